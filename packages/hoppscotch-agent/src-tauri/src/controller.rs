@@ -1,9 +1,10 @@
-use std::sync::Arc;
+use std::{convert::Infallible, sync::Arc, time::Duration};
 
 use axum::{
     body::Bytes,
     extract::{Path, State},
     http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
     Json,
 };
 use axum_extra::{
@@ -11,21 +12,24 @@ use axum_extra::{
     TypedHeader,
 };
 use chrono::Utc;
+use futures_util::Stream;
 use rand::Rng;
 use serde_json::json;
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc::WeakUnboundedSender;
 use uuid::Uuid;
 use x25519_dalek::{EphemeralSecret, PublicKey};
 
 use crate::{
     error::{AgentError, AgentResult},
-    global::NONCE,
+    global::{CASSETTE_DIR, NONCE},
     model::{
         AuthKeyResponse, ConfirmedRegistrationRequest, HandshakeResponse, LogEntry, LogLevel,
-        MaskedRegistration, Registration,
+        MaskedRegistration, MqttSubscribe, MqttUnsubscribe, Registration, SnippetRequest,
+        WebSocketClose,
     },
     state::AppState,
-    util::{generate_auth_key_hash, EncryptedJson},
+    util::{encrypt_json, generate_auth_key_hash, EncryptedJson},
 };
 
 #[tracing::instrument]
@@ -254,6 +258,923 @@ pub async fn execute(
         })?)
 }
 
+/// Runs a batch of requests in one round trip, see `relay::batch::execute`.
+/// The whole batch is cancelled through `/cancel/:req_id` with its id.
+#[tracing::instrument(skip(state, body, _app_handle), fields(batch_id))]
+pub async fn execute_batch(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<EncryptedJson<Vec<relay::batch::BatchResult>>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let batch = match state.validate_access_and_get_data::<relay::batch::Batch>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(b) => b,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    tracing::Span::current().record("batch_id", batch.id);
+
+    let reg_info = match state.get_registration(auth_header.token()) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Registration info not found");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let results = tokio::task::spawn_blocking(move || relay::batch::execute(batch))
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Batch thread panicked");
+            AgentError::InternalServerError
+        })??;
+
+    Ok(EncryptedJson {
+        key_b16: reg_info.shared_secret_b16,
+        data: results,
+    })
+}
+
+fn encrypted_event<T: serde::Serialize>(key_b16: &str, name: &str, data: &T) -> Event {
+    let (nonce_b16, encrypted) = encrypt_json(key_b16, data);
+    Event::default().event(name).data(format!(
+        "{}:{}",
+        nonce_b16,
+        base16::encode_lower(&encrypted)
+    ))
+}
+
+/// How often an idle stream checks whether its client is still there.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Calls `on_closed` once the client dropped the stream fed by `sender`.
+/// Relay callbacks only notice that on their next event, which a quiet
+/// connection may never produce. Stops by itself once the session ended and
+/// dropped its sender.
+fn watch_stream<T: Send + 'static>(
+    sender: WeakUnboundedSender<T>,
+    on_closed: impl FnOnce() + Send + 'static,
+) {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(IDLE_CHECK_INTERVAL);
+        loop {
+            ticks.tick().await;
+            match sender.upgrade() {
+                Some(sender) if sender.is_closed() => {
+                    on_closed();
+                    return;
+                }
+                Some(_) => {}
+                None => return,
+            }
+        }
+    });
+}
+
+/// Opens an event stream through the relay and forwards it as SSE until the
+/// upstream stream ends or is cancelled through `/cancel/:req_id`. Each
+/// `message` event's data is `<nonce_b16>:<ciphertext_b16>`, a
+/// `relay::SseMessage` encrypted the same way as `EncryptedJson` responses.
+/// A failed stream ends with an `error` event carrying the `RelayError`.
+#[tracing::instrument(skip(state, body, _app_handle), fields(req_id))]
+pub async fn subscribe(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let request = match state.validate_access_and_get_data::<relay::Request>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    tracing::Span::current().record("req_id", &request.id);
+
+    let reg_info = match state.get_registration(auth_header.token()) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Registration info not found");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let id = request.id;
+    let key_b16 = reg_info.shared_secret_b16;
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let watched = sender.downgrade();
+
+    tokio::task::spawn_blocking(move || {
+        // NOTE: A closed client connection drops the receiver, which stops
+        // the subscription at the next message.
+        let result = relay::subscribe(request, |message| {
+            sender
+                .send(encrypted_event(&key_b16, "message", &message))
+                .is_ok()
+        });
+
+        if let Err(error) = result {
+            tracing::error!(?error, "Event stream failed");
+            let _ = sender.send(encrypted_event(&key_b16, "error", &error));
+        }
+    });
+
+    watch_stream(watched, move || {
+        tracing::info!(request_id = id, "Client went away, cancelling event stream");
+        tokio::spawn(async move {
+            let _ = relay::cancel(id).await;
+        });
+    });
+
+    let stream =
+        futures_util::stream::poll_fn(move |cx| receiver.poll_recv(cx).map(|event| event.map(Ok)));
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Runs a load test, see `relay::load_test::run`, streaming interim
+/// statistics as `message` events encrypted the same way as `/subscribe`.
+/// The stream ends with a `finished` event carrying the final statistics, or
+/// an `error` event with the `RelayError`. The test is stopped through
+/// `/cancel/:req_id` with its id, or by closing the stream.
+#[tracing::instrument(skip(state, body, _app_handle), fields(test_id))]
+pub async fn load_test(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let test = match state.validate_access_and_get_data::<relay::load_test::LoadTest>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(t) => t,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    tracing::Span::current().record("test_id", test.id);
+
+    let reg_info = match state.get_registration(auth_header.token()) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Registration info not found");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    // NOTE: Checked before streaming so a bad test is a regular error
+    // response.
+    test.validate()?;
+
+    let key_b16 = reg_info.shared_secret_b16;
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    tokio::task::spawn_blocking(move || {
+        // NOTE: A closed client connection drops the receiver, which stops
+        // the test at the next report.
+        let result = relay::load_test::run(test, |stats| {
+            sender
+                .send(encrypted_event(&key_b16, "message", stats))
+                .is_ok()
+        });
+
+        let event = match result {
+            Ok(stats) => encrypted_event(&key_b16, "finished", &stats),
+            Err(error) => {
+                tracing::error!(?error, "Load test failed");
+                encrypted_event(&key_b16, "error", &error)
+            }
+        };
+        let _ = sender.send(event);
+    });
+
+    let stream =
+        futures_util::stream::poll_fn(move |cx| receiver.poll_recv(cx).map(|event| event.map(Ok)));
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Opens a WebSocket through the relay and forwards its events as SSE,
+/// encrypted the same way as `/subscribe`. Responds only once the handshake
+/// is done, so a failed upgrade is a regular error response. The stream ends
+/// after the `close` event, frames are sent through `/websocket/:req_id/send`.
+#[tracing::instrument(skip(state, body, _app_handle), fields(req_id))]
+pub async fn websocket_open(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let request = match state.validate_access_and_get_data::<relay::Request>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    tracing::Span::current().record("req_id", &request.id);
+
+    let reg_info = match state.get_registration(auth_header.token()) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Registration info not found");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let id = request.id;
+    let key_b16 = reg_info.shared_secret_b16;
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let watched = sender.downgrade();
+
+    tokio::task::spawn_blocking(move || {
+        // NOTE: A closed client connection drops the receiver, which closes
+        // the socket at the next event.
+        relay::websocket::open(request, move |event| {
+            sender
+                .send(encrypted_event(&key_b16, "message", &event))
+                .is_ok()
+        })
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!(error = %e, "WebSocket thread panicked");
+        AgentError::InternalServerError
+    })??;
+
+    watch_stream(watched, move || {
+        tracing::info!(request_id = id, "Client went away, closing WebSocket");
+        // NOTE: 1001 is "Going Away", see RFC 6455 section 7.4.1.
+        let _ = relay::websocket::close(id, Some(1001), None);
+    });
+
+    let stream =
+        futures_util::stream::poll_fn(move |cx| receiver.poll_recv(cx).map(|event| event.map(Ok)));
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[tracing::instrument(skip(state, body, _app_handle), fields(request_id = %request_id))]
+pub async fn websocket_send(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    Path(request_id): Path<i64>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<Json<serde_json::Value>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let frame = match state.validate_access_and_get_data::<relay::WsFrame>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(f) => f,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    relay::websocket::send(request_id, frame)?;
+
+    Ok(Json(json!({ "message": "Frame queued" })))
+}
+
+#[tracing::instrument(skip(state, body, _app_handle), fields(request_id = %request_id))]
+pub async fn websocket_close(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    Path(request_id): Path<i64>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<Json<serde_json::Value>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let close = match state.validate_access_and_get_data::<WebSocketClose>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(c) => c,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    relay::websocket::close(request_id, close.code, close.reason)?;
+
+    Ok(Json(json!({ "message": "WebSocket closing" })))
+}
+
+/// Connects to an MQTT broker through the relay and forwards the session's
+/// events as SSE, encrypted the same way as `/subscribe`. Responds only once
+/// the broker accepted the connection, the stream ends after the
+/// `disconnected` event.
+#[tracing::instrument(skip(state, body, _app_handle), fields(req_id))]
+pub async fn mqtt_connect(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let request = match state.validate_access_and_get_data::<relay::MqttRequest>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    tracing::Span::current().record("req_id", &request.id);
+
+    let reg_info = match state.get_registration(auth_header.token()) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Registration info not found");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let id = request.id;
+    let key_b16 = reg_info.shared_secret_b16;
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let watched = sender.downgrade();
+
+    tokio::task::spawn_blocking(move || {
+        // NOTE: A closed client connection drops the receiver, which ends
+        // the session at the next event.
+        relay::mqtt::connect(request, move |event| {
+            sender
+                .send(encrypted_event(&key_b16, "message", &event))
+                .is_ok()
+        })
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!(error = %e, "MQTT thread panicked");
+        AgentError::InternalServerError
+    })??;
+
+    watch_stream(watched, move || {
+        tracing::info!(request_id = id, "Client went away, disconnecting MQTT");
+        let _ = relay::mqtt::disconnect(id);
+    });
+
+    let stream =
+        futures_util::stream::poll_fn(move |cx| receiver.poll_recv(cx).map(|event| event.map(Ok)));
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[tracing::instrument(skip(state, body, _app_handle), fields(request_id = %request_id))]
+pub async fn mqtt_subscribe(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    Path(request_id): Path<i64>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<Json<serde_json::Value>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let subscribe = match state.validate_access_and_get_data::<MqttSubscribe>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(b) => b,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    relay::mqtt::subscribe(request_id, subscribe.topic, subscribe.qos)?;
+
+    Ok(Json(json!({ "message": "Subscribe queued" })))
+}
+
+#[tracing::instrument(skip(state, body, _app_handle), fields(request_id = %request_id))]
+pub async fn mqtt_unsubscribe(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    Path(request_id): Path<i64>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<Json<serde_json::Value>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let unsubscribe = match state.validate_access_and_get_data::<MqttUnsubscribe>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(b) => b,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    relay::mqtt::unsubscribe(request_id, unsubscribe.topic)?;
+
+    Ok(Json(json!({ "message": "Unsubscribe queued" })))
+}
+
+#[tracing::instrument(skip(state, body, _app_handle), fields(request_id = %request_id))]
+pub async fn mqtt_publish(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    Path(request_id): Path<i64>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<Json<serde_json::Value>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let message = match state.validate_access_and_get_data::<relay::MqttMessage>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(b) => b,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    relay::mqtt::publish(request_id, message)?;
+
+    Ok(Json(json!({ "message": "Message queued" })))
+}
+
+#[tracing::instrument(skip(state, _app_handle), fields(request_id = %request_id))]
+pub async fn mqtt_disconnect(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    Path(request_id): Path<i64>,
+) -> AgentResult<Json<serde_json::Value>> {
+    if !state.validate_access(auth_header.token()) {
+        tracing::warn!("Unauthorized MQTT disconnect attempt");
+        return Err(AgentError::Unauthorized);
+    }
+
+    relay::mqtt::disconnect(request_id)?;
+
+    Ok(Json(json!({ "message": "MQTT disconnecting" })))
+}
+
+#[tracing::instrument(skip(state, _app_handle))]
+pub async fn host_overrides(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+) -> AgentResult<EncryptedJson<relay::HostOverrides>> {
+    let reg_info = match state.get_registration(auth_header.token()) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Unauthorized attempt to read host overrides");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    Ok(EncryptedJson {
+        key_b16: reg_info.shared_secret_b16,
+        data: relay::host_overrides(),
+    })
+}
+
+#[tracing::instrument(skip(state, body, app_handle))]
+pub async fn set_host_overrides(
+    State((state, app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<Json<serde_json::Value>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let overrides = match state.validate_access_and_get_data::<relay::HostOverrides>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(o) => o,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    state.update_host_overrides(app_handle, overrides)?;
+
+    tracing::info!("Host overrides updated");
+    Ok(Json(
+        json!({ "message": "Host overrides updated successfully" }),
+    ))
+}
+
+#[tracing::instrument(skip(state, _app_handle))]
+pub async fn host_limits(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+) -> AgentResult<EncryptedJson<Vec<relay::HostLimit>>> {
+    let reg_info = match state.get_registration(auth_header.token()) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Unauthorized attempt to read host limits");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    Ok(EncryptedJson {
+        key_b16: reg_info.shared_secret_b16,
+        data: relay::host_limits(),
+    })
+}
+
+#[tracing::instrument(skip(state, body, app_handle))]
+pub async fn set_host_limits(
+    State((state, app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<Json<serde_json::Value>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let limits = match state.validate_access_and_get_data::<Vec<relay::HostLimit>>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(l) => l,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    state.update_host_limits(app_handle, limits)?;
+
+    tracing::info!("Host limits updated");
+    Ok(Json(
+        json!({ "message": "Host limits updated successfully" }),
+    ))
+}
+
+/// Writes the request out as code for the requested client, see
+/// `relay::snippet::generate`. Nothing is sent.
+#[tracing::instrument(skip(state, body, _app_handle))]
+pub async fn snippet(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<EncryptedJson<String>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let snippet = match state.validate_access_and_get_data::<SnippetRequest>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(s) => s,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let reg_info = match state.get_registration(auth_header.token()) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Registration info not found");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    Ok(EncryptedJson {
+        key_b16: reg_info.shared_secret_b16,
+        data: relay::snippet::generate(&snippet.request, snippet.target),
+    })
+}
+
+/// Converts the posted request and response pairs into a HAR 1.2 log, see
+/// `relay::har::export`.
+#[tracing::instrument(skip(state, body, _app_handle))]
+pub async fn export_har(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<EncryptedJson<relay::har::Har>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let exchanges = match state.validate_access_and_get_data::<Vec<relay::har::Exchange>>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(e) => e,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let reg_info = match state.get_registration(auth_header.token()) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Registration info not found");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    Ok(EncryptedJson {
+        key_b16: reg_info.shared_secret_b16,
+        data: relay::har::export(&exchanges),
+    })
+}
+
+#[tracing::instrument(skip(state, _app_handle))]
+pub async fn cassette(
+    State((state, _app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+) -> AgentResult<EncryptedJson<Option<relay::CassetteConfig>>> {
+    let reg_info = match state.get_registration(auth_header.token()) {
+        Some(r) => r,
+        None => {
+            tracing::warn!("Unauthorized attempt to read cassette");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    Ok(EncryptedJson {
+        key_b16: reg_info.shared_secret_b16,
+        data: relay::cassette(),
+    })
+}
+
+/// Starts recording to or replaying from a cassette on this machine, a
+/// `null` body goes back to sending requests as usual. Unlike host
+/// overrides this isn't persisted, a restarted agent always hits the network.
+///
+/// Cassette paths are taken from the agent's cassette directory, with the
+/// same rules as saved bodies.
+#[tracing::instrument(skip(state, body, app_handle))]
+pub async fn set_cassette(
+    State((state, app_handle)): State<(Arc<AppState>, AppHandle)>,
+    TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AgentResult<Json<serde_json::Value>> {
+    let nonce = match headers.get(NONCE) {
+        Some(n) => match n.to_str() {
+            Ok(n) => n,
+            Err(_) => {
+                tracing::warn!("Invalid nonce header");
+                return Err(AgentError::Unauthorized);
+            }
+        },
+        None => {
+            tracing::warn!("Missing nonce header");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let cassette = match state.validate_access_and_get_data::<Option<relay::CassetteConfig>>(
+        auth_header.token(),
+        nonce,
+        &body,
+    ) {
+        Some(c) => c,
+        None => {
+            tracing::warn!("Invalid access or data");
+            return Err(AgentError::Unauthorized);
+        }
+    };
+
+    let cassette = match cassette {
+        Some(mut config) => {
+            let dir = app_handle.path().app_data_dir()?.join(CASSETTE_DIR);
+            config.path = relay::resolve_within(&dir, &config.path)
+                .map_err(|e| AgentError::BadRequest(e.to_string()))?
+                .to_string_lossy()
+                .into_owned();
+            Some(config)
+        }
+        None => None,
+    };
+
+    relay::set_cassette(cassette)?;
+
+    tracing::info!("Cassette updated");
+    Ok(Json(json!({ "message": "Cassette updated successfully" })))
+}
+
 /// Provides a way for registered clients to check if their
 /// registration still holds, this route is supposed to return
 /// an encrypted `true` value if the given auth_key is good.
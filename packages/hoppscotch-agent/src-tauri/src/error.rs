@@ -82,6 +82,15 @@ impl IntoResponse for AgentError {
             AgentError::InvalidHeaders => (StatusCode::BAD_REQUEST, self.to_string()),
             AgentError::RequestRunError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
             AgentError::RequestCancelled => (StatusCode::BAD_REQUEST, self.to_string()),
+            AgentError::Relay(ref e @ relay::error::RelayError::CassetteMiss { .. }) => {
+                (StatusCode::NOT_FOUND, e.to_string())
+            }
+            AgentError::Relay(ref e @ relay::error::RelayError::InvalidBatch { .. }) => {
+                (StatusCode::BAD_REQUEST, e.to_string())
+            }
+            AgentError::Relay(ref e @ relay::error::RelayError::InvalidLoadTest { .. }) => {
+                (StatusCode::BAD_REQUEST, e.to_string())
+            }
             _ => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Internal Server Error".to_string(),
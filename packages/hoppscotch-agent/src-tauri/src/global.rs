@@ -1,3 +1,7 @@
 pub const AGENT_STORE: &str = "app_data.bin";
 pub const REGISTRATIONS: &str = "registrations";
 pub const NONCE: &str = "X-Hopp-Nonce";
+pub const HOST_OVERRIDES: &str = "host_overrides";
+pub const HOST_LIMITS: &str = "host_limits";
+pub const SAVE_DIR: &str = "downloads";
+pub const CASSETTE_DIR: &str = "cassettes";
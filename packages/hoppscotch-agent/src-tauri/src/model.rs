@@ -108,3 +108,32 @@ pub enum LogLevel {
     Warn,
     Error,
 }
+
+/// Body of `/websocket/:req_id/close`, both fields are optional
+/// so an empty object sends a close frame without a status.
+#[derive(Debug, Default, Deserialize)]
+pub struct WebSocketClose {
+    pub code: Option<u16>,
+    pub reason: Option<String>,
+}
+
+/// Body of `/mqtt/:req_id/subscribe`, `qos` defaults to 0.
+#[derive(Debug, Deserialize)]
+pub struct MqttSubscribe {
+    pub topic: String,
+    #[serde(default)]
+    pub qos: u8,
+}
+
+/// Body of `/mqtt/:req_id/unsubscribe`.
+#[derive(Debug, Deserialize)]
+pub struct MqttUnsubscribe {
+    pub topic: String,
+}
+
+/// Body of `/snippet`, the request to write out and the client to write it for.
+#[derive(Debug, Deserialize)]
+pub struct SnippetRequest {
+    pub request: relay::Request,
+    pub target: relay::SnippetTarget,
+}
@@ -28,7 +28,41 @@ pub fn route(state: Arc<AppState>, app_handle: AppHandle) -> Router {
             delete(controller::delete_registration),
         )
         .route("/execute", post(controller::execute))
+        .route("/batch", post(controller::execute_batch))
+        .route("/subscribe", post(controller::subscribe))
+        .route("/load-test", post(controller::load_test))
+        .route("/websocket/open", post(controller::websocket_open))
+        .route("/websocket/:req_id/send", post(controller::websocket_send))
+        .route(
+            "/websocket/:req_id/close",
+            post(controller::websocket_close),
+        )
+        .route("/mqtt/connect", post(controller::mqtt_connect))
+        .route("/mqtt/:req_id/subscribe", post(controller::mqtt_subscribe))
+        .route(
+            "/mqtt/:req_id/unsubscribe",
+            post(controller::mqtt_unsubscribe),
+        )
+        .route("/mqtt/:req_id/publish", post(controller::mqtt_publish))
+        .route(
+            "/mqtt/:req_id/disconnect",
+            post(controller::mqtt_disconnect),
+        )
         .route("/cancel/:req_id", post(controller::cancel))
+        .route(
+            "/host-overrides",
+            get(controller::host_overrides).post(controller::set_host_overrides),
+        )
+        .route(
+            "/host-limits",
+            get(controller::host_limits).post(controller::set_host_limits),
+        )
+        .route("/snippet", post(controller::snippet))
+        .route("/har", post(controller::export_har))
+        .route(
+            "/cassette",
+            get(controller::cassette).post(controller::set_cassette),
+        )
         .route("/log-sink", post(controller::log_sink))
         .with_state((state, app_handle))
 }
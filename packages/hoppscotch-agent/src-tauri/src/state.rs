@@ -2,13 +2,14 @@ use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit};
 use axum::body::Bytes;
 use dashmap::DashMap;
 use serde::de::DeserializeOwned;
+use tauri::Manager;
 use tauri_plugin_store::StoreExt;
 use tokio::sync::RwLock;
 use tokio_util::sync::CancellationToken;
 
 use crate::{
     error::{AgentError, AgentResult},
-    global::{AGENT_STORE, REGISTRATIONS},
+    global::{AGENT_STORE, CASSETTE_DIR, HOST_LIMITS, HOST_OVERRIDES, REGISTRATIONS, SAVE_DIR},
     model::Registration,
 };
 
@@ -50,6 +51,37 @@ impl AppState {
         // Try to save the latest registrations list
         let _ = store.set(REGISTRATIONS, serde_json::to_value(&registrations)?);
 
+        // Host overrides live in the relay itself, so they only need to be
+        // handed over once on startup
+        if let Some(overrides) = store
+            .get(HOST_OVERRIDES)
+            .and_then(|val| serde_json::from_value::<relay::HostOverrides>(val.clone()).ok())
+        {
+            tracing::debug!("Restoring host overrides from store");
+            relay::set_host_overrides(overrides);
+        }
+
+        if let Some(limits) = store
+            .get(HOST_LIMITS)
+            .and_then(|val| serde_json::from_value::<Vec<relay::HostLimit>>(val.clone()).ok())
+        {
+            tracing::debug!("Restoring host limits from store");
+            relay::set_host_limits(limits);
+        }
+
+        // Clients can only save bodies in here, a path from a request must
+        // never reach arbitrary files the agent can write.
+        let save_dir = app_handle.path().app_data_dir()?.join(SAVE_DIR);
+        std::fs::create_dir_all(&save_dir)?;
+        relay::set_save_dir(Some(save_dir));
+        // Cassettes are confined the same way, see `controller::set_cassette`.
+        std::fs::create_dir_all(app_handle.path().app_data_dir()?.join(CASSETTE_DIR))?;
+        // TLS key logging is for whoever started the agent, clients can't
+        // turn it on.
+        if let Some(path) = std::env::var_os("SSLKEYLOGFILE") {
+            relay::set_key_log_file(Some(path.into()));
+        }
+
         if let Err(e) = store.save() {
             tracing::error!("Failed to persist store changes: {}", e);
             return Err(e.into());
@@ -129,6 +161,84 @@ impl AppState {
         Ok(())
     }
 
+    /// Replaces the relay's global host overrides and persists them so they
+    /// survive restarts.
+    #[tracing::instrument(skip(self, app_handle, overrides))]
+    pub fn update_host_overrides(
+        &self,
+        app_handle: tauri::AppHandle,
+        overrides: relay::HostOverrides,
+    ) -> Result<(), AgentError> {
+        tracing::info!("Updating host overrides");
+
+        let store = match app_handle.store(AGENT_STORE) {
+            Ok(store) => store,
+            Err(e) => {
+                tracing::error!("Failed to access app store: {}", e);
+                return Err(e.into());
+            }
+        };
+
+        match serde_json::to_value(&overrides) {
+            Ok(value) => {
+                let _ = store.set(HOST_OVERRIDES, value);
+            }
+            Err(e) => {
+                tracing::error!("Failed to serialize host overrides: {}", e);
+                return Err(e.into());
+            }
+        }
+
+        if let Err(e) = store.save() {
+            tracing::error!("Failed to persist store changes: {}", e);
+            return Err(e.into());
+        }
+
+        relay::set_host_overrides(overrides);
+
+        tracing::info!("Host overrides updated successfully");
+        Ok(())
+    }
+
+    /// Replaces the relay's global host limits and persists them so they
+    /// survive restarts.
+    #[tracing::instrument(skip(self, app_handle, limits))]
+    pub fn update_host_limits(
+        &self,
+        app_handle: tauri::AppHandle,
+        limits: Vec<relay::HostLimit>,
+    ) -> Result<(), AgentError> {
+        tracing::info!("Updating host limits");
+
+        let store = match app_handle.store(AGENT_STORE) {
+            Ok(store) => store,
+            Err(e) => {
+                tracing::error!("Failed to access app store: {}", e);
+                return Err(e.into());
+            }
+        };
+
+        match serde_json::to_value(&limits) {
+            Ok(value) => {
+                let _ = store.set(HOST_LIMITS, value);
+            }
+            Err(e) => {
+                tracing::error!("Failed to serialize host limits: {}", e);
+                return Err(e.into());
+            }
+        }
+
+        if let Err(e) = store.save() {
+            tracing::error!("Failed to persist store changes: {}", e);
+            return Err(e.into());
+        }
+
+        relay::set_host_limits(limits);
+
+        tracing::info!("Host limits updated successfully");
+        Ok(())
+    }
+
     /// Clear all the registrations
     #[tracing::instrument(skip(self, app_handle))]
     pub fn clear_registrations(&self, app_handle: tauri::AppHandle) -> Result<(), AgentError> {
@@ -55,6 +55,25 @@ pub fn open_link(link: &str) -> Option<()> {
     }
 }
 
+/// Serializes `data` to JSON and encrypts it with the registration's shared
+/// secret, returning the base16 nonce alongside the ciphertext.
+pub fn encrypt_json<T: Serialize>(key_b16: &str, data: &T) -> (String, Vec<u8>) {
+    let serialized =
+        serde_json::to_vec(data).expect("Failed serializing data to vec for encryption");
+
+    let key: [u8; 32] = base16::decode(key_b16).unwrap()[0..32].try_into().unwrap();
+
+    let cipher = Aes256Gcm::new(&key.into());
+
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let encrypted = cipher
+        .encrypt(&nonce, serialized.as_slice())
+        .expect("Failed encrypting data");
+
+    (base16::encode_lower(&nonce), encrypted)
+}
+
 #[derive(Debug)]
 pub struct EncryptedJson<T: Serialize> {
     pub key_b16: String,
@@ -66,22 +85,7 @@ where
     T: Serialize,
 {
     fn into_response(self) -> Response {
-        let serialized_response = serde_json::to_vec(&self.data)
-            .expect("Failed serializing response to vec for encryption");
-
-        let key: [u8; 32] = base16::decode(&self.key_b16).unwrap()[0..32]
-            .try_into()
-            .unwrap();
-
-        let cipher = Aes256Gcm::new(&key.into());
-
-        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
-
-        let nonce_b16 = base16::encode_lower(&nonce);
-
-        let encrypted_response = cipher
-            .encrypt(&nonce, serialized_response.as_slice())
-            .expect("Failed encrypting response");
+        let (nonce_b16, encrypted_response) = encrypt_json(&self.key_b16, &self.data);
 
         let mut response = Response::new(Body::from(encrypted_response));
         let response_headers = response.headers_mut();
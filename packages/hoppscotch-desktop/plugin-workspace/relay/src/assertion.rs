@@ -0,0 +1,372 @@
+use std::borrow::Cow;
+
+use encoding_rs::{Encoding, UTF_8};
+use jsonpath_rust::JsonPath;
+use regex::Regex;
+use serde_json::Value;
+
+use crate::{
+    charset,
+    interop::{Assertion, AssertionResult, Request, Response},
+};
+
+/// Characters of the body reported as `actual` for `BodyContains`.
+const BODY_PREVIEW_CHARS: usize = 256;
+
+/// Checks the request's `RequestOptions.assertions` against `response` and
+/// stores the outcomes in its meta.
+pub(crate) fn evaluate(request: &Request, response: &mut Response) {
+    let Some(assertions) = request
+        .meta
+        .as_ref()
+        .and_then(|meta| meta.options.as_ref())
+        .and_then(|options| options.assertions.as_ref())
+    else {
+        return;
+    };
+
+    let results: Vec<AssertionResult> = assertions
+        .iter()
+        .map(|assertion| check(assertion, response))
+        .collect();
+
+    tracing::info!(
+        total = results.len(),
+        failed = results.iter().filter(|result| !result.passed).count(),
+        "Evaluated assertions"
+    );
+    response.meta.assertions = Some(results);
+}
+
+fn check(assertion: &Assertion, response: &Response) -> AssertionResult {
+    let outcome = match assertion {
+        Assertion::Status { equals } => {
+            let status = response.status.as_u16();
+            Ok((status == *equals, Some(status.into())))
+        }
+        Assertion::StatusRange { min, max } => {
+            let status = response.status.as_u16();
+            Ok(((*min..=*max).contains(&status), Some(status.into())))
+        }
+        Assertion::Header { name, matches } => header(response, name, matches.as_deref()),
+        Assertion::ResponseTime { below } => {
+            let timing = &response.meta.timing;
+            let elapsed = timing.end.saturating_sub(timing.start);
+            Ok((elapsed < *below, Some(elapsed.into())))
+        }
+        Assertion::BodyContains { text } => {
+            let body = body_text(response);
+            let preview: String = body.chars().take(BODY_PREVIEW_CHARS).collect();
+            Ok((body.contains(text.as_str()), Some(preview.into())))
+        }
+        Assertion::JsonPath { path, equals } => json_path(response, path).map(|actual| {
+            let actual = match actual.len() {
+                0 => None,
+                1 => actual.into_iter().next(),
+                _ => Some(Value::Array(actual)),
+            };
+            (actual.as_ref() == Some(equals), actual)
+        }),
+        Assertion::ArrayLength { path, equals } => array_length(response, path)
+            .map(|length| (length == Some(*equals), length.map(Value::from))),
+        Assertion::Xpath { path, equals } => {
+            xpath(response, path).map(|actual| (actual == *equals, Some(actual.into())))
+        }
+    };
+
+    match outcome {
+        Ok((passed, actual)) => AssertionResult {
+            assertion: assertion.clone(),
+            passed,
+            actual,
+            error: None,
+        },
+        Err(error) => {
+            tracing::warn!(?assertion, %error, "Failed to evaluate assertion");
+            AssertionResult {
+                assertion: assertion.clone(),
+                passed: false,
+                actual: None,
+                error: Some(error),
+            }
+        }
+    }
+}
+
+type Outcome = std::result::Result<(bool, Option<Value>), String>;
+
+/// The body's text view when the request asked for one, otherwise the body
+/// decoded with its detected charset, or as UTF-8 without one.
+fn body_text(response: &Response) -> Cow<'_, str> {
+    if let Some(ref text) = response.body.text {
+        return Cow::Borrowed(text);
+    }
+
+    let encoding = response
+        .body
+        .charset
+        .as_deref()
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .unwrap_or(UTF_8);
+    Cow::Owned(charset::decode(&response.body.body, encoding))
+}
+
+fn header(response: &Response, name: &str, matches: Option<&str>) -> Outcome {
+    let value = response
+        .headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value);
+
+    let passed = match (value, matches) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some(value), Some(pattern)) => Regex::new(pattern)
+            .map_err(|e| format!("Invalid regex '{}': {}", pattern, e))?
+            .is_match(value),
+    };
+    Ok((passed, value.cloned().map(Value::String)))
+}
+
+fn json_path(response: &Response, path: &str) -> std::result::Result<Vec<Value>, String> {
+    let body: Value = serde_json::from_str(&body_text(response))
+        .map_err(|e| format!("Body is not JSON: {}", e))?;
+    let matches = body
+        .query(path)
+        .map_err(|e| format!("Invalid JSONPath '{}': {}", path, e))?;
+    Ok(matches.into_iter().cloned().collect())
+}
+
+fn array_length(response: &Response, path: &str) -> std::result::Result<Option<usize>, String> {
+    match json_path(response, path)?.as_slice() {
+        [] => Ok(None),
+        [Value::Array(items)] => Ok(Some(items.len())),
+        [_] => Err(format!("Value at '{}' is not an array", path)),
+        _ => Err(format!("'{}' matches more than one value", path)),
+    }
+}
+
+fn xpath(response: &Response, path: &str) -> std::result::Result<String, String> {
+    let body = body_text(response);
+    let package =
+        sxd_document::parser::parse(&body).map_err(|e| format!("Body is not XML: {}", e))?;
+    let document = package.as_document();
+    let value = sxd_xpath::evaluate_xpath(&document, path)
+        .map_err(|e| format!("Invalid XPath '{}': {}", path, e))?;
+    Ok(value.string())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http::{Method, StatusCode};
+    use serde_json::json;
+
+    use super::*;
+    use crate::interop::{RequestMeta, RequestOptions, TimingInfo};
+
+    fn request(assertions: Vec<Assertion>) -> Request {
+        Request {
+            meta: Some(RequestMeta {
+                options: Some(RequestOptions {
+                    assertions: Some(assertions),
+                    ..Default::default()
+                }),
+            }),
+            ..Request::test(Method::GET, "https://example.com")
+        }
+    }
+
+    fn response(headers: &[(&str, &str)], body: &str) -> Response {
+        let mut response = Response::test(StatusCode::CREATED, headers, body.as_bytes());
+        response.meta.timing = TimingInfo {
+            start: 1000,
+            end: 1120,
+        };
+        response
+    }
+
+    fn outcomes(assertions: Vec<Assertion>, response: &mut Response) -> Vec<(bool, Value)> {
+        evaluate(&request(assertions), response);
+        response
+            .meta
+            .assertions
+            .take()
+            .unwrap()
+            .into_iter()
+            .map(|result| (result.passed, result.actual.unwrap_or(Value::Null)))
+            .collect()
+    }
+
+    #[test]
+    fn checks_status_headers_and_timing() {
+        let mut response = response(&[("Content-Type", "application/json; charset=utf-8")], "");
+        let results = outcomes(
+            vec![
+                Assertion::Status { equals: 200 },
+                Assertion::StatusRange { min: 200, max: 299 },
+                Assertion::Header {
+                    name: "content-type".into(),
+                    matches: Some("^application/json".into()),
+                },
+                Assertion::Header {
+                    name: "ETag".into(),
+                    matches: None,
+                },
+                Assertion::ResponseTime { below: 100 },
+            ],
+            &mut response,
+        );
+
+        assert_eq!(
+            results,
+            [
+                (false, json!(201)),
+                (true, json!(201)),
+                (true, json!("application/json; charset=utf-8")),
+                (false, Value::Null),
+                (false, json!(120)),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_json_bodies() {
+        let body = r#"{"user": {"name": "ada", "roles": ["admin", "dev"]}, "items": [{"id": 1}, {"id": 2}]}"#;
+        let mut response = response(&[], body);
+        let results = outcomes(
+            vec![
+                Assertion::BodyContains {
+                    text: "\"ada\"".into(),
+                },
+                Assertion::JsonPath {
+                    path: "$.user.name".into(),
+                    equals: json!("ada"),
+                },
+                Assertion::JsonPath {
+                    path: "$.items[*].id".into(),
+                    equals: json!([1, 2]),
+                },
+                Assertion::JsonPath {
+                    path: "$.missing".into(),
+                    equals: json!(null),
+                },
+                Assertion::ArrayLength {
+                    path: "$.user.roles".into(),
+                    equals: 3,
+                },
+            ],
+            &mut response,
+        );
+
+        assert_eq!(
+            results,
+            [
+                (true, json!(body)),
+                (true, json!("ada")),
+                (true, json!([1, 2])),
+                (false, Value::Null),
+                (false, json!(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_xml_bodies() {
+        let mut response = response(
+            &[],
+            "<?xml version=\"1.0\"?><order id=\"7\"><item/><item/></order>",
+        );
+        let results = outcomes(
+            vec![
+                Assertion::Xpath {
+                    path: "/order/@id".into(),
+                    equals: "7".into(),
+                },
+                Assertion::Xpath {
+                    path: "count(//item)".into(),
+                    equals: "2".into(),
+                },
+            ],
+            &mut response,
+        );
+
+        assert_eq!(results, [(true, json!("7")), (true, json!("2"))]);
+    }
+
+    #[test]
+    fn checks_decoded_bodies() {
+        let mut response = response(&[], "");
+        response.body.body = Bytes::from_static(b"<?xml version=\"1.0\"?><name>caf\xe9</name>");
+        response.body.charset = Some("windows-1252".into());
+        let assertions = || {
+            vec![
+                Assertion::BodyContains {
+                    text: "caf\u{e9}".into(),
+                },
+                Assertion::Xpath {
+                    path: "/name".into(),
+                    equals: "caf\u{e9}".into(),
+                },
+            ]
+        };
+
+        let results = outcomes(assertions(), &mut response);
+        assert!(results.iter().all(|(passed, _)| *passed));
+
+        // NOTE: The text view is what the request asked to be checked.
+        response.body.text = Some("<name>tea</name>".into());
+        let results = outcomes(assertions(), &mut response);
+        assert_eq!(results[1], (false, json!("tea")));
+    }
+
+    #[test]
+    fn reports_errors_as_failures() {
+        let mut response = response(&[("X-Id", "1")], "not json");
+        evaluate(
+            &request(vec![
+                Assertion::Header {
+                    name: "X-Id".into(),
+                    matches: Some("(".into()),
+                },
+                Assertion::JsonPath {
+                    path: "$.id".into(),
+                    equals: json!(1),
+                },
+                Assertion::Xpath {
+                    path: "/id".into(),
+                    equals: "1".into(),
+                },
+            ]),
+            &mut response,
+        );
+
+        let results = response.meta.assertions.unwrap();
+        assert!(results.iter().all(|result| !result.passed));
+        assert!(results[0]
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with("Invalid regex"));
+        assert!(results[1]
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with("Body is not JSON"));
+        assert!(results[2]
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with("Body is not XML"));
+    }
+
+    #[test]
+    fn skips_requests_without_assertions() {
+        let mut response = response(&[], "");
+        let mut request = request(Vec::new());
+        request.meta = None;
+
+        evaluate(&request, &mut response);
+        assert!(response.meta.assertions.is_none());
+    }
+}
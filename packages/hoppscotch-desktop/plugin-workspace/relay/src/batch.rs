@@ -0,0 +1,517 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{RelayError, Result},
+    interop::{Request, Response},
+    relay::{self, ACTIVE_REQUESTS},
+};
+
+const DEFAULT_CONCURRENCY: u32 = 6;
+
+/// How often the scheduler checks for cancellation while requests run.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Batch {
+    /// Cancels the whole batch with `relay::cancel`, so it must not clash
+    /// with the ids of its requests or of other requests in flight.
+    pub id: i64,
+    pub items: Vec<BatchItem>,
+    /// Requests in flight at once. Defaults to 6.
+    pub concurrency: Option<u32>,
+    /// Starts no further requests once one fails, requests already in
+    /// flight still finish. Defaults to `false`.
+    pub stop_on_failure: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchItem {
+    pub request: Request,
+    /// `Request.id`s that have to succeed before this request starts.
+    pub depends_on: Option<Vec<i64>>,
+}
+
+/// Outcome of one request, in the order of `Batch.items`. Only relay
+/// errors count as failures, a response is a success whatever its status.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum BatchResult {
+    Success { id: i64, response: Box<Response> },
+    Error { id: i64, error: RelayError },
+    Skipped { id: i64, reason: SkipReason },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SkipReason {
+    /// A request this one depends on failed or was skipped.
+    DependencyFailed,
+    /// An earlier request failed with `stop_on_failure` set.
+    Stopped,
+    /// The batch was cancelled before this request started.
+    Cancelled,
+}
+
+impl BatchResult {
+    pub fn id(&self) -> i64 {
+        match self {
+            BatchResult::Success { id, .. }
+            | BatchResult::Error { id, .. }
+            | BatchResult::Skipped { id, .. } => *id,
+        }
+    }
+
+    fn succeeded(&self) -> bool {
+        matches!(self, BatchResult::Success { .. })
+    }
+}
+
+/// Runs every request of the batch and returns their results in order.
+///
+/// Requests whose dependencies all succeeded start as soon as a slot is
+/// free, in the order they were given. Fails upfront with
+/// `RelayError::InvalidBatch` on duplicate ids, unknown dependencies or
+/// dependency cycles.
+pub fn execute(batch: Batch) -> Result<Vec<BatchResult>> {
+    let dependencies = validate(&batch.items)?;
+    let concurrency = batch.concurrency.unwrap_or(DEFAULT_CONCURRENCY).max(1) as usize;
+    let stop_on_failure = batch.stop_on_failure.unwrap_or(false);
+
+    tracing::info!(
+        batch_id = batch.id,
+        requests = batch.items.len(),
+        concurrency,
+        "Starting batch"
+    );
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    ACTIVE_REQUESTS.insert(batch.id, Arc::clone(&cancelled));
+
+    let ids: Vec<i64> = batch.items.iter().map(|item| item.request.id).collect();
+    let mut requests: Vec<Option<Request>> = batch
+        .items
+        .into_iter()
+        .map(|item| Some(item.request))
+        .collect();
+    let mut results: Vec<Option<BatchResult>> = ids.iter().map(|_| None).collect();
+    let mut running: HashSet<usize> = HashSet::new();
+    let mut stopped = false;
+
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+
+        loop {
+            let is_cancelled = cancelled.load(Ordering::SeqCst);
+            if is_cancelled {
+                for index in &running {
+                    if let Some(flag) = ACTIVE_REQUESTS.get(&ids[*index]) {
+                        flag.store(true, Ordering::SeqCst);
+                    }
+                }
+            }
+
+            for index in 0..ids.len() {
+                if results[index].is_some() || running.contains(&index) {
+                    continue;
+                }
+
+                let skip = if is_cancelled {
+                    Some(SkipReason::Cancelled)
+                } else if stopped {
+                    Some(SkipReason::Stopped)
+                } else if dependencies[index].iter().any(|dependency| {
+                    results[*dependency]
+                        .as_ref()
+                        .is_some_and(|result| !result.succeeded())
+                }) {
+                    Some(SkipReason::DependencyFailed)
+                } else {
+                    None
+                };
+                if let Some(reason) = skip {
+                    tracing::debug!(request_id = ids[index], ?reason, "Skipping batch request");
+                    results[index] = Some(BatchResult::Skipped {
+                        id: ids[index],
+                        reason,
+                    });
+                    continue;
+                }
+
+                let ready = dependencies[index]
+                    .iter()
+                    .all(|dependency| results[*dependency].is_some());
+                if !ready || running.len() >= concurrency {
+                    continue;
+                }
+
+                let Some(request) = requests[index].take() else {
+                    continue;
+                };
+                running.insert(index);
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let result = relay::run(request);
+                    let _ = sender.send((index, result));
+                });
+            }
+
+            // NOTE: A skip can unblock items earlier in the list, which the
+            // next pass picks up.
+            if running.is_empty() {
+                if results.iter().all(Option::is_some) {
+                    break;
+                }
+                continue;
+            }
+
+            let Ok((index, result)) = receiver.recv_timeout(CANCEL_POLL_INTERVAL) else {
+                continue;
+            };
+            running.remove(&index);
+
+            let id = ids[index];
+            results[index] = Some(match result {
+                Ok(response) => BatchResult::Success {
+                    id,
+                    response: Box::new(response),
+                },
+                Err(error) => {
+                    tracing::warn!(request_id = id, error = %error, "Batch request failed");
+                    if stop_on_failure && !stopped {
+                        tracing::info!("Stopping batch after a failed request");
+                        stopped = true;
+                    }
+                    BatchResult::Error { id, error }
+                }
+            });
+        }
+    });
+
+    ACTIVE_REQUESTS.remove(&batch.id);
+    tracing::info!(batch_id = batch.id, "Batch finished");
+
+    // NOTE: The loop only ends once every request has a result.
+    Ok(results.into_iter().flatten().collect())
+}
+
+/// Maps each item to the indices of the items it depends on, checking that
+/// ids are unique, dependencies exist and there are no cycles.
+fn validate(items: &[BatchItem]) -> Result<Vec<Vec<usize>>> {
+    let mut indices = HashMap::new();
+    for (index, item) in items.iter().enumerate() {
+        if indices.insert(item.request.id, index).is_some() {
+            return Err(RelayError::InvalidBatch {
+                message: format!("Request id {} appears more than once", item.request.id),
+            });
+        }
+    }
+
+    let dependencies = items
+        .iter()
+        .map(|item| {
+            item.depends_on
+                .iter()
+                .flatten()
+                .map(|dependency| {
+                    indices
+                        .get(dependency)
+                        .copied()
+                        .ok_or_else(|| RelayError::InvalidBatch {
+                            message: format!(
+                                "Request {} depends on {}, which isn't in the batch",
+                                item.request.id, dependency
+                            ),
+                        })
+                })
+                .collect::<Result<Vec<usize>>>()
+        })
+        .collect::<Result<Vec<Vec<usize>>>>()?;
+
+    // NOTE: Kahn's algorithm, whatever can't be ordered is part of a cycle.
+    let mut remaining: Vec<usize> = dependencies.iter().map(Vec::len).collect();
+    let mut dependents = vec![Vec::new(); items.len()];
+    for (index, item_dependencies) in dependencies.iter().enumerate() {
+        for dependency in item_dependencies {
+            dependents[*dependency].push(index);
+        }
+    }
+    let mut ready: Vec<usize> = (0..items.len()).filter(|i| remaining[*i] == 0).collect();
+    let mut ordered = 0;
+    while let Some(index) = ready.pop() {
+        ordered += 1;
+        for dependent in &dependents[index] {
+            remaining[*dependent] -= 1;
+            if remaining[*dependent] == 0 {
+                ready.push(*dependent);
+            }
+        }
+    }
+
+    if ordered < items.len() {
+        let cycle: Vec<String> = (0..items.len())
+            .filter(|i| remaining[*i] > 0)
+            .map(|i| items[i].request.id.to_string())
+            .collect();
+        return Err(RelayError::InvalidBatch {
+            message: format!("Dependency cycle between requests {}", cycle.join(", ")),
+        });
+    }
+
+    Ok(dependencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::atomic::AtomicUsize,
+        time::Instant,
+    };
+
+    use http::Method;
+
+    use super::*;
+
+    fn item(id: i64, url: &str, depends_on: &[i64]) -> BatchItem {
+        BatchItem {
+            request: Request {
+                id,
+                ..Request::test(Method::GET, url)
+            },
+            depends_on: (!depends_on.is_empty()).then(|| depends_on.to_vec()),
+        }
+    }
+
+    /// Answers every connection with an empty 200 after `delay`, counting
+    /// how many were open at once.
+    fn serve(delay: Duration) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let open = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let peak_clone = Arc::clone(&peak);
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let open = Arc::clone(&open);
+                let peak = Arc::clone(&peak_clone);
+                std::thread::spawn(move || {
+                    let mut stream = stream;
+                    let now = open.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    let mut buffer = [0; 4096];
+                    let _ = stream.read(&mut buffer);
+                    std::thread::sleep(delay);
+                    open.fetch_sub(1, Ordering::SeqCst);
+                    let _ = stream.write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    );
+                });
+            }
+        });
+
+        (url, peak)
+    }
+
+    fn kinds(results: &[BatchResult]) -> Vec<(i64, &'static str)> {
+        results
+            .iter()
+            .map(|result| {
+                let kind = match result {
+                    BatchResult::Success { .. } => "success",
+                    BatchResult::Error { .. } => "error",
+                    BatchResult::Skipped {
+                        reason: SkipReason::DependencyFailed,
+                        ..
+                    } => "dependencyFailed",
+                    BatchResult::Skipped {
+                        reason: SkipReason::Stopped,
+                        ..
+                    } => "stopped",
+                    BatchResult::Skipped {
+                        reason: SkipReason::Cancelled,
+                        ..
+                    } => "cancelled",
+                };
+                (result.id(), kind)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rejects_invalid_batches() {
+        let url = "http://127.0.0.1:9";
+        let duplicate = validate(&[item(1, url, &[]), item(1, url, &[])]);
+        assert!(matches!(duplicate, Err(RelayError::InvalidBatch { .. })));
+
+        let unknown = validate(&[item(1, url, &[2])]);
+        assert!(matches!(unknown, Err(RelayError::InvalidBatch { .. })));
+
+        let cycle = validate(&[
+            item(1, url, &[3]),
+            item(2, url, &[1]),
+            item(3, url, &[2]),
+            item(4, url, &[]),
+        ]);
+        let Err(RelayError::InvalidBatch { message }) = cycle else {
+            panic!("expected a cycle error");
+        };
+        assert_eq!(message, "Dependency cycle between requests 1, 2, 3");
+
+        assert_eq!(
+            validate(&[item(1, url, &[]), item(2, url, &[1]), item(3, url, &[1, 2])]).unwrap(),
+            vec![vec![], vec![0], vec![0, 1]]
+        );
+    }
+
+    #[test]
+    fn runs_in_parallel_up_to_concurrency() {
+        let (url, peak) = serve(Duration::from_millis(200));
+        let batch = Batch {
+            id: 9001,
+            items: (1..=4).map(|id| item(id, &url, &[])).collect(),
+            concurrency: Some(2),
+            stop_on_failure: None,
+        };
+
+        let started = Instant::now();
+        let results = execute(batch).unwrap();
+
+        assert_eq!(
+            kinds(&results),
+            [
+                (1, "success"),
+                (2, "success"),
+                (3, "success"),
+                (4, "success")
+            ]
+        );
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert!(started.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[test]
+    fn skips_dependents_of_failed_requests() {
+        let (url, _) = serve(Duration::ZERO);
+        // NOTE: Nothing listens on the discard port, so this fails to connect.
+        let unreachable = "http://127.0.0.1:9";
+        let batch = Batch {
+            id: 9002,
+            items: vec![
+                item(3, &url, &[2]),
+                item(1, unreachable, &[]),
+                item(2, &url, &[1]),
+                item(4, &url, &[]),
+            ],
+            concurrency: None,
+            stop_on_failure: None,
+        };
+
+        assert_eq!(
+            kinds(&execute(batch).unwrap()),
+            [
+                (3, "dependencyFailed"),
+                (1, "error"),
+                (2, "dependencyFailed"),
+                (4, "success")
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_on_first_failure() {
+        let (url, _) = serve(Duration::ZERO);
+        let batch = Batch {
+            id: 9003,
+            items: vec![
+                item(1, "http://127.0.0.1:9", &[]),
+                item(2, &url, &[]),
+                item(3, &url, &[]),
+            ],
+            concurrency: Some(1),
+            stop_on_failure: Some(true),
+        };
+
+        assert_eq!(
+            kinds(&execute(batch).unwrap()),
+            [(1, "error"), (2, "stopped"), (3, "stopped")]
+        );
+    }
+
+    #[test]
+    fn cancels_the_whole_batch() {
+        let (url, _) = serve(Duration::from_millis(300));
+        let batch = Batch {
+            id: 9004,
+            items: vec![item(1, &url, &[]), item(2, &url, &[1])],
+            concurrency: None,
+            stop_on_failure: None,
+        };
+
+        let canceller = std::thread::spawn(|| {
+            std::thread::sleep(Duration::from_millis(100));
+            ACTIVE_REQUESTS
+                .get(&9004)
+                .expect("batch is running")
+                .store(true, Ordering::SeqCst);
+        });
+        let results = execute(batch).unwrap();
+        canceller.join().unwrap();
+
+        assert!(matches!(
+            results[0],
+            BatchResult::Error {
+                error: RelayError::Abort { .. },
+                ..
+            }
+        ));
+        assert_eq!(kinds(&results)[1], (2, "cancelled"));
+        assert!(!ACTIVE_REQUESTS.contains_key(&9004));
+    }
+
+    #[test]
+    fn cancelling_stops_running_transfers() {
+        let (url, _) = serve(Duration::from_secs(30));
+        let batch = Batch {
+            id: 9005,
+            items: vec![item(5, &url, &[])],
+            concurrency: None,
+            stop_on_failure: None,
+        };
+
+        let canceller = std::thread::spawn(|| {
+            while !ACTIVE_REQUESTS.contains_key(&5) {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+            ACTIVE_REQUESTS
+                .get(&9005)
+                .expect("batch is running")
+                .store(true, Ordering::SeqCst);
+        });
+        let started = Instant::now();
+        let results = execute(batch).unwrap();
+        canceller.join().unwrap();
+
+        assert!(matches!(
+            results[0],
+            BatchResult::Error {
+                error: RelayError::Abort { .. },
+                ..
+            }
+        ));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
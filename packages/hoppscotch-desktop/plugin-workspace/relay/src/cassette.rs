@@ -0,0 +1,568 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    io::{ErrorKind, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    curl_command,
+    error::{RelayError, Result},
+    interop::{CassetteConfig, CassetteMatching, CassetteMode, Request, Response},
+    output::PendingFile,
+};
+
+const CASSETTE_VERSION: u32 = 1;
+const REDACTED: &str = "[REDACTED]";
+const SENSITIVE_HEADERS: [&str; 4] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+
+lazy_static::lazy_static! {
+    /// The cassette set by the host app with its interactions, and which of
+    /// them were replayed so far. Writes hold the lock so concurrent
+    /// requests don't drop each other's interactions.
+    static ref ACTIVE: Mutex<Option<Active>> = Mutex::new(None);
+}
+
+struct Active {
+    config: CassetteConfig,
+    cassette: Cassette,
+    replayed: HashSet<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Cassette {
+    version: u32,
+    interactions: Vec<Interaction>,
+}
+
+/// A recorded exchange. Auth, client certificates and proxy credentials
+/// are left out of `request`, and sensitive headers are redacted.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Interaction {
+    #[serde(with = "time::serde::rfc3339")]
+    recorded_at: OffsetDateTime,
+    request: Request,
+    response: Response,
+}
+
+/// Starts recording to or replaying from a cassette, or with `None` goes
+/// back to sending requests as usual.
+///
+/// The cassette is read here once, so a broken file fails here rather than
+/// on the first request, and so does a missing one in replay mode. Record
+/// mode appends to what the file already holds. Setting a cassette again
+/// starts its replay from the first interaction.
+pub fn set_cassette(config: Option<CassetteConfig>) -> Result<()> {
+    let active = match config {
+        Some(config) => {
+            tracing::info!(mode = ?config.mode, path = %config.path, "Setting cassette");
+            let cassette = match config.mode {
+                CassetteMode::Replay => load(&config.path)?,
+                CassetteMode::Record => load_or_default(&config.path)?,
+            };
+            tracing::debug!(
+                interactions = cassette.interactions.len(),
+                "Loaded cassette"
+            );
+            Some(Active {
+                config,
+                cassette,
+                replayed: HashSet::new(),
+            })
+        }
+        None => {
+            tracing::info!("Clearing cassette");
+            None
+        }
+    };
+    match ACTIVE.lock() {
+        Ok(mut guard) => *guard = active,
+        Err(poisoned) => *poisoned.into_inner() = active,
+    }
+    Ok(())
+}
+
+/// Returns a copy of the cassette settings, `None` when requests go out as
+/// usual.
+pub fn cassette() -> Option<CassetteConfig> {
+    match ACTIVE.lock() {
+        Ok(guard) => guard.as_ref().map(|active| active.config.clone()),
+        Err(poisoned) => poisoned
+            .into_inner()
+            .as_ref()
+            .map(|active| active.config.clone()),
+    }
+}
+
+/// Answers `request` from the cassette in replay mode, `None` in any other
+/// mode.
+///
+/// Identical requests get their recorded responses in order, the last one
+/// repeating once they run out.
+pub(crate) fn replay(request: &Request) -> Option<Result<Response>> {
+    let mut guard = ACTIVE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let active = guard
+        .as_mut()
+        .filter(|active| active.config.mode == CassetteMode::Replay)?;
+
+    let ignored = ignored_headers(&active.config);
+    let candidates: Vec<usize> = active
+        .cassette
+        .interactions
+        .iter()
+        .enumerate()
+        .filter(|(_, interaction)| {
+            matches(
+                &active.config.matching,
+                &ignored,
+                &interaction.request,
+                request,
+            )
+        })
+        .map(|(index, _)| index)
+        .collect();
+
+    let Some(index) = candidates
+        .iter()
+        .find(|index| !active.replayed.contains(index))
+        .or(candidates.last())
+        .copied()
+    else {
+        let url = normalized_url(request);
+        tracing::warn!(method = %request.method, url = %url, "No cassette interaction matches");
+        return Some(Err(RelayError::CassetteMiss {
+            message: format!(
+                "{} {} matches no interaction in {}",
+                request.method, url, active.config.path
+            ),
+            method: request.method.to_string(),
+            url,
+        }));
+    };
+    active.replayed.insert(index);
+
+    tracing::info!(index, "Replaying response from cassette");
+    let mut response = active.cassette.interactions[index].response.clone();
+    response.id = request.id;
+    response.meta.replayed = Some(true);
+    Some(Ok(response))
+}
+
+/// Appends the exchange to the cassette in record mode, redacted, and
+/// replaces the file with the updated cassette.
+///
+/// A failed write is logged rather than failing the request, whose response
+/// is fine either way. The interaction stays in memory and is written with
+/// the next one.
+pub(crate) fn record(request: &Request, response: &Response) {
+    let mut guard = ACTIVE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let Some(active) = guard
+        .as_mut()
+        .filter(|active| active.config.mode == CassetteMode::Record)
+    else {
+        return;
+    };
+
+    let sensitive = redacted_headers(&active.config);
+    let interaction = Interaction {
+        recorded_at: OffsetDateTime::now_utc(),
+        request: redact_request(request, &sensitive),
+        response: redact_response(response, &sensitive),
+    };
+
+    active.cassette.version = CASSETTE_VERSION;
+    active.cassette.interactions.push(interaction);
+    match save(&active.config.path, &active.cassette) {
+        Ok(()) => tracing::debug!(path = %active.config.path, "Recorded interaction"),
+        Err(e) => {
+            tracing::error!(error = %e, path = %active.config.path, "Failed to record interaction")
+        }
+    }
+}
+
+fn load(path: &str) -> Result<Cassette> {
+    let data = fs::read(path).map_err(|e| {
+        tracing::error!(error = %e, path, "Failed to read cassette");
+        RelayError::Parse {
+            message: format!("Failed to read cassette {}", path),
+            cause: Some(e.to_string()),
+        }
+    })?;
+    parse(path, &data)
+}
+
+fn load_or_default(path: &str) -> Result<Cassette> {
+    match fs::read(path) {
+        Ok(data) if data.iter().all(u8::is_ascii_whitespace) => Ok(Cassette::default()),
+        Ok(data) => parse(path, &data),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Cassette::default()),
+        Err(e) => Err(RelayError::Parse {
+            message: format!("Failed to read cassette {}", path),
+            cause: Some(e.to_string()),
+        }),
+    }
+}
+
+fn parse(path: &str, data: &[u8]) -> Result<Cassette> {
+    serde_json::from_slice(data).map_err(|e| {
+        tracing::error!(error = %e, path, "Failed to parse cassette");
+        RelayError::Parse {
+            message: format!("Failed to parse cassette {}", path),
+            cause: Some(e.to_string()),
+        }
+    })
+}
+
+/// Writes the cassette next to `path` and renames it into place, so the
+/// file is never left half written.
+fn save(path: &str, cassette: &Cassette) -> Result<()> {
+    let data = serde_json::to_vec_pretty(cassette).map_err(|e| RelayError::Parse {
+        message: "Failed to serialize cassette".into(),
+        cause: Some(e.to_string()),
+    })?;
+    let (pending, mut file) = PendingFile::create(PathBuf::from(path))?;
+    file.write_all(&data)
+        .and_then(|()| file.sync_all())
+        .map_err(|e| RelayError::Parse {
+            message: format!("Failed to write cassette {}", path),
+            cause: Some(e.to_string()),
+        })?;
+    drop(file);
+    pending.persist()
+}
+
+/// Lowercase names of the headers redacted on write.
+fn redacted_headers(config: &CassetteConfig) -> HashSet<String> {
+    SENSITIVE_HEADERS
+        .iter()
+        .map(|name| name.to_string())
+        .chain(
+            config
+                .redact_headers
+                .iter()
+                .flatten()
+                .map(|name| name.to_ascii_lowercase()),
+        )
+        .collect()
+}
+
+/// Lowercase names of the headers left out of header matching.
+fn ignored_headers(config: &CassetteConfig) -> HashSet<String> {
+    let mut ignored = redacted_headers(config);
+    ignored.extend(
+        config
+            .matching
+            .ignore_headers
+            .iter()
+            .map(|name| name.to_ascii_lowercase()),
+    );
+    ignored
+}
+
+fn redact_request(request: &Request, sensitive: &HashSet<String>) -> Request {
+    let mut request = request.clone();
+    if let Some(ref mut headers) = request.headers {
+        redact_headers(headers.iter_mut(), sensitive);
+    }
+    request.auth = None;
+    if let Some(ref mut security) = request.security {
+        security.certificates = None;
+    }
+    if let Some(ref mut proxy) = request.proxy {
+        proxy.auth = None;
+    }
+    request
+}
+
+fn redact_response(response: &Response, sensitive: &HashSet<String>) -> Response {
+    let mut response = response.clone();
+    redact_headers(response.headers.iter_mut(), sensitive);
+    if sensitive.contains("set-cookie") {
+        for cookie in response.cookies.iter_mut().flatten() {
+            cookie.value = REDACTED.into();
+        }
+    }
+    response
+}
+
+fn redact_headers<'a>(
+    headers: impl Iterator<Item = (&'a String, &'a mut String)>,
+    sensitive: &HashSet<String>,
+) {
+    for (name, value) in headers {
+        if sensitive.contains(&name.to_ascii_lowercase()) {
+            *value = REDACTED.into();
+        }
+    }
+}
+
+fn matches(
+    rules: &CassetteMatching,
+    ignored: &HashSet<String>,
+    recorded: &Request,
+    request: &Request,
+) -> bool {
+    recorded.method == request.method
+        && normalized_url(recorded) == normalized_url(request)
+        && (!rules.body
+            || serde_json::to_value(&recorded.content).ok()
+                == serde_json::to_value(&request.content).ok())
+        && (!rules.headers || header_map(recorded, ignored) == header_map(request, ignored))
+}
+
+/// The URL with `params` applied, its query sorted and no fragment.
+fn normalized_url(request: &Request) -> String {
+    let params: Vec<(String, String)> = request
+        .params
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let url = curl_command::with_query(&request.url, &params);
+
+    let Ok(mut parsed) = url::Url::parse(&url) else {
+        return url;
+    };
+    let mut query: Vec<(String, String)> = parsed.query_pairs().into_owned().collect();
+    query.sort();
+    parsed.set_query(None);
+    parsed.set_fragment(None);
+    if !query.is_empty() {
+        parsed.query_pairs_mut().extend_pairs(query);
+    }
+    parsed.to_string()
+}
+
+fn header_map(request: &Request, ignored: &HashSet<String>) -> BTreeMap<String, String> {
+    request
+        .headers
+        .iter()
+        .flatten()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.clone()))
+        .filter(|(name, _)| !ignored.contains(name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bytes::Bytes;
+    use http::{Method, StatusCode};
+
+    use super::*;
+    use crate::interop::{AuthType, ContentType, MediaType};
+
+    fn request(url: &str, headers: &[(&str, &str)], body: Option<&str>) -> Request {
+        Request {
+            id: 7,
+            headers: Some(
+                headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            ),
+            content: body.map(|body| ContentType::Text {
+                content: body.into(),
+                media_type: MediaType::TextPlain,
+            }),
+            auth: Some(AuthType::Bearer {
+                token: "secret-token".into(),
+            }),
+            ..Request::test(Method::POST, url)
+        }
+    }
+
+    fn response(body: &str) -> Response {
+        Response::test(
+            StatusCode::OK,
+            &[
+                ("Set-Cookie", "session=abc"),
+                ("Content-Type", "text/plain"),
+            ],
+            body.as_bytes(),
+        )
+    }
+
+    fn config(mode: CassetteMode, path: &str, matching: CassetteMatching) -> CassetteConfig {
+        CassetteConfig {
+            mode,
+            path: path.into(),
+            matching,
+            redact_headers: Some(vec!["X-Api-Key".into()]),
+        }
+    }
+
+    #[test]
+    fn matches_url_with_query_in_any_order() {
+        let rules = CassetteMatching::default();
+        let ignored = HashSet::new();
+        let recorded = request("https://example.com/a?b=2&a=1#top", &[], None);
+        let mut same = request("https://example.com/a?a=1", &[], Some("other body"));
+        same.params = Some(HashMap::from([("b".into(), "2".into())]));
+
+        assert!(matches(&rules, &ignored, &recorded, &same));
+        assert!(!matches(
+            &rules,
+            &ignored,
+            &recorded,
+            &request("https://example.com/a?a=1&b=3", &[], None)
+        ));
+
+        let mut get = recorded.clone();
+        get.method = Method::GET;
+        assert!(!matches(&rules, &ignored, &recorded, &get));
+    }
+
+    #[test]
+    fn matches_body_and_headers_when_asked() {
+        let cassette = config(
+            CassetteMode::Replay,
+            "unused.json",
+            CassetteMatching {
+                body: true,
+                headers: true,
+                ignore_headers: vec!["X-Request-Id".into()],
+            },
+        );
+        let ignored = ignored_headers(&cassette);
+        let recorded = request(
+            "https://example.com",
+            &[
+                ("Accept", "text/plain"),
+                ("X-Request-Id", "1"),
+                ("X-Api-Key", REDACTED),
+            ],
+            Some("hello"),
+        );
+
+        let same = request(
+            "https://example.com",
+            &[
+                ("accept", "text/plain"),
+                ("X-Request-Id", "2"),
+                ("X-Api-Key", "live-key"),
+            ],
+            Some("hello"),
+        );
+        assert!(matches(&cassette.matching, &ignored, &recorded, &same));
+
+        let other_body = request(
+            "https://example.com",
+            &[("Accept", "text/plain")],
+            Some("bye"),
+        );
+        assert!(!matches(
+            &cassette.matching,
+            &ignored,
+            &recorded,
+            &other_body
+        ));
+
+        let other_header = request("https://example.com", &[("Accept", "*/*")], Some("hello"));
+        assert!(!matches(
+            &cassette.matching,
+            &ignored,
+            &recorded,
+            &other_header
+        ));
+    }
+
+    #[test]
+    fn redacts_sensitive_headers_and_credentials() {
+        let sensitive = redacted_headers(&config(
+            CassetteMode::Record,
+            "unused.json",
+            CassetteMatching::default(),
+        ));
+        let request = redact_request(
+            &request(
+                "https://example.com",
+                &[
+                    ("Authorization", "Bearer abc"),
+                    ("x-api-key", "k"),
+                    ("Accept", "*/*"),
+                ],
+                None,
+            ),
+            &sensitive,
+        );
+        let headers = request.headers.unwrap();
+
+        assert_eq!(headers["Authorization"], REDACTED);
+        assert_eq!(headers["x-api-key"], REDACTED);
+        assert_eq!(headers["Accept"], "*/*");
+        assert!(request.auth.is_none());
+
+        let response = redact_response(&response("ok"), &sensitive);
+        assert_eq!(response.headers["Set-Cookie"], REDACTED);
+        assert_eq!(response.headers["Content-Type"], "text/plain");
+    }
+
+    #[test]
+    fn records_then_replays_in_order() {
+        let path = std::env::temp_dir().join(format!("relay-cassette-{}.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let _ = fs::remove_file(&path);
+
+        let first = request("https://example.com/items", &[], None);
+        set_cassette(Some(config(
+            CassetteMode::Record,
+            &path,
+            CassetteMatching::default(),
+        )))
+        .unwrap();
+        record(&first, &response("one"));
+        set_cassette(Some(config(
+            CassetteMode::Record,
+            &path,
+            CassetteMatching::default(),
+        )))
+        .unwrap();
+        record(&first, &response("two"));
+
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(!written.contains("secret-token"));
+        assert!(!written.contains("session=abc"));
+
+        set_cassette(Some(config(
+            CassetteMode::Replay,
+            &path,
+            CassetteMatching::default(),
+        )))
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+        let bodies: Vec<Bytes> = (0..3)
+            .map(|_| replay(&first).unwrap().unwrap().body.body)
+            .collect();
+        assert_eq!(bodies, ["one", "two", "two"]);
+
+        let replayed = replay(&first).unwrap().unwrap();
+        assert_eq!(replayed.id, 7);
+        assert_eq!(replayed.meta.replayed, Some(true));
+
+        let miss = replay(&request("https://example.com/other", &[], None)).unwrap();
+        assert!(matches!(
+            miss,
+            Err(RelayError::CassetteMiss { ref url, .. }) if url == "https://example.com/other"
+        ));
+
+        set_cassette(None).unwrap();
+        assert!(replay(&first).is_none());
+        assert!(cassette().is_none());
+    }
+}
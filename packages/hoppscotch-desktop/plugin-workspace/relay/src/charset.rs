@@ -0,0 +1,171 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use mime::Mime;
+
+use crate::interop::MediaKind;
+
+/// How far into the body the XML declaration and HTML `<meta>` are looked
+/// for, the same limit as the HTML encoding sniffing algorithm's prescan.
+const PRESCAN_LIMIT: usize = 1024;
+
+/// Detects the body's character encoding from, in order of precedence, a
+/// byte order mark, the `Content-Type` charset parameter, an XML
+/// declaration, then an HTML `<meta>` charset. `None` when nothing declares
+/// one.
+pub(crate) fn detect(content_type: Option<&str>, body: &[u8]) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = Encoding::for_bom(body) {
+        tracing::trace!(charset = encoding.name(), "Charset from byte order mark");
+        return Some(encoding);
+    }
+
+    let mime = content_type.and_then(|value| value.parse::<Mime>().ok());
+
+    if let Some(encoding) = mime
+        .as_ref()
+        .and_then(|mime| mime.get_param(mime::CHARSET))
+        .and_then(|charset| Encoding::for_label(charset.as_str().as_bytes()))
+    {
+        tracing::trace!(charset = encoding.name(), "Charset from Content-Type");
+        return Some(encoding);
+    }
+
+    let head = &body[..body.len().min(PRESCAN_LIMIT)];
+
+    if let Some(encoding) = xml_declaration(head) {
+        tracing::trace!(charset = encoding.name(), "Charset from XML declaration");
+        return Some(encoding);
+    }
+
+    let html = mime
+        .as_ref()
+        .is_none_or(|mime| mime.subtype() == mime::HTML);
+
+    if html {
+        if let Some(encoding) = html_meta(head) {
+            tracing::trace!(charset = encoding.name(), "Charset from HTML meta");
+            return Some(encoding);
+        }
+    }
+
+    None
+}
+
+/// Decodes to UTF-8, replacing malformed sequences. A BOM overrides
+/// `encoding` and is stripped.
+pub(crate) fn decode(body: &[u8], encoding: &'static Encoding) -> String {
+    let (text, actual, had_errors) = encoding.decode(body);
+
+    if had_errors {
+        tracing::warn!(
+            charset = actual.name(),
+            "Body contained malformed sequences, replaced with U+FFFD"
+        );
+    }
+
+    text.into_owned()
+}
+
+/// Media kinds worth a text view even when no charset was declared, in
+/// which case UTF-8 is assumed.
+pub(crate) fn is_textual(kind: MediaKind) -> bool {
+    matches!(kind, MediaKind::Json | MediaKind::Xml | MediaKind::Text)
+}
+
+/// `<?xml version="1.0" encoding="..."?>`
+fn xml_declaration(head: &[u8]) -> Option<&'static Encoding> {
+    let rest = head.strip_prefix(b"<?xml")?;
+    let end = find(rest, b"?>")?;
+    let declaration = String::from_utf8_lossy(&rest[..end]).to_ascii_lowercase();
+
+    let value = declaration.split("encoding").nth(1)?;
+    let value = value.trim_start().strip_prefix('=')?.trim_start();
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let label = value[1..].split(quote).next()?;
+
+    Encoding::for_label(label.as_bytes())
+}
+
+/// `<meta charset="...">` or `<meta http-equiv="Content-Type"
+/// content="text/html; charset=...">`. A UTF-16 label here means UTF-8,
+/// since a document that really was UTF-16 couldn't have been read this far.
+fn html_meta(head: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+
+    let encoding = head.split("<meta").skip(1).find_map(|tag| {
+        let tag = tag.split('>').next().unwrap_or_default();
+        let value = tag.split("charset").nth(1)?;
+        let value = value.trim_start().strip_prefix('=')?.trim_start();
+        let label = value
+            .trim_start_matches(['"', '\''])
+            .split(|c: char| c == '"' || c == '\'' || c == ';' || c.is_whitespace())
+            .next()?;
+
+        Encoding::for_label(label.as_bytes())
+    })?;
+
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        return Some(UTF_8);
+    }
+
+    Some(encoding)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{EUC_KR, SHIFT_JIS, UTF_16LE, WINDOWS_1252};
+
+    use super::*;
+
+    #[test]
+    fn test_detect_precedence() {
+        assert_eq!(
+            detect(Some("text/plain; charset=Shift_JIS"), b"abc"),
+            Some(SHIFT_JIS)
+        );
+        assert_eq!(
+            detect(Some("text/plain; charset=Shift_JIS"), b"\xFF\xFEa\x00"),
+            Some(UTF_16LE)
+        );
+        assert_eq!(detect(Some("application/json"), b"{}"), None);
+    }
+
+    #[test]
+    fn test_detect_xml_and_html() {
+        assert_eq!(
+            detect(
+                Some("text/xml"),
+                b"<?xml version=\"1.0\" encoding='ISO-8859-1'?><a/>"
+            ),
+            Some(WINDOWS_1252)
+        );
+        assert_eq!(
+            detect(
+                Some("text/html"),
+                b"<html><head><META http-equiv=\"Content-Type\" content=\"text/html; charset=euc-kr\">"
+            ),
+            Some(EUC_KR)
+        );
+        assert_eq!(
+            detect(None, b"<!doctype html><meta charset=utf-16>"),
+            Some(UTF_8)
+        );
+        assert_eq!(
+            detect(
+                Some("application/json"),
+                b"{\"a\": \"<meta charset=euc-kr>\"}"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"caf\xE9", WINDOWS_1252), "café");
+        assert_eq!(decode(b"\x82\xA0", SHIFT_JIS), "あ");
+    }
+}
@@ -0,0 +1,91 @@
+use std::io::Write;
+
+use flate2::{
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+
+use crate::{
+    error::{RelayError, Result},
+    interop::BodyCompression,
+};
+
+/// Brotli quality and window size, the `brotli` CLI defaults.
+const BROTLI_QUALITY: u32 = 11;
+const BROTLI_LG_WINDOW_SIZE: u32 = 22;
+
+#[tracing::instrument(skip(data), fields(data_length = data.len()), level = "debug")]
+pub(crate) fn compress(data: &[u8], encoding: BodyCompression) -> Result<Vec<u8>> {
+    let compressed = match encoding {
+        BodyCompression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).and_then(|_| encoder.finish())
+        }
+        // NOTE: `deflate` in HTTP means the zlib format, not raw deflate,
+        // see: https://www.rfc-editor.org/rfc/rfc9110#name-deflate-coding
+        BodyCompression::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).and_then(|_| encoder.finish())
+        }
+        BodyCompression::Br => {
+            let mut compressed = Vec::new();
+            {
+                let mut encoder = brotli::CompressorWriter::new(
+                    &mut compressed,
+                    4096,
+                    BROTLI_QUALITY,
+                    BROTLI_LG_WINDOW_SIZE,
+                );
+                encoder.write_all(data).and_then(|_| encoder.flush())
+            }
+            .map(|_| compressed)
+        }
+        BodyCompression::Zstd => zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL),
+    };
+
+    compressed.map_err(|e| {
+        tracing::error!(error = %e, encoding = %encoding, "Failed to compress body");
+        RelayError::Network {
+            message: format!("Failed to compress body with {}", encoding),
+            cause: Some(e.to_string()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn test_compress_round_trip() {
+        let data = br#"{"level":"info","message":"hello"}"#.repeat(64);
+
+        let gzip = compress(&data, BodyCompression::Gzip).unwrap();
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(gzip.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let deflate = compress(&data, BodyCompression::Deflate).unwrap();
+        let mut decoded = Vec::new();
+        flate2::read::ZlibDecoder::new(deflate.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let br = compress(&data, BodyCompression::Br).unwrap();
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new(br.as_slice(), 4096)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let zstd = compress(&data, BodyCompression::Zstd).unwrap();
+        assert_eq!(zstd::decode_all(zstd.as_slice()).unwrap(), data);
+
+        assert!(gzip.len() < data.len() && br.len() < data.len() && zstd.len() < data.len());
+    }
+}
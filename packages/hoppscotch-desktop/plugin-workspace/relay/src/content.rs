@@ -1,21 +1,35 @@
-use curl::easy::Easy;
+use curl::easy::{Easy, Form, List};
 use http::HeaderName;
-use std::{collections::HashMap, path::Path};
+use std::{borrow::Cow, collections::HashMap, path::Path};
 
 use crate::{
+    compression,
     error::{RelayError, Result},
-    interop::{ContentType, FormValue, MediaType},
+    interop::{BodyCompression, CompressionInfo, ContentType, FormData, FormValue, MediaType},
+    multipart,
 };
 
 pub(crate) struct ContentHandler<'a> {
     handle: &'a mut Easy,
     headers: &'a mut HashMap<String, String>,
+    compression: Option<BodyCompression>,
+    compression_info: Option<CompressionInfo>,
 }
 
 impl<'a> ContentHandler<'a> {
     pub(crate) fn new(handle: &'a mut Easy, headers: &'a mut HashMap<String, String>) -> Self {
         tracing::debug!("Creating new ContentHandler with headers: {:?}", headers);
-        Self { handle, headers }
+        Self {
+            handle,
+            headers,
+            compression: None,
+            compression_info: None,
+        }
+    }
+
+    pub(crate) fn with_compression(mut self, compression: Option<BodyCompression>) -> Self {
+        self.compression = compression;
+        self
     }
 
     fn merge_headers(&mut self, new_headers: HashMap<String, String>) {
@@ -47,9 +61,49 @@ impl<'a> ContentHandler<'a> {
         tracing::trace!(merged_headers = ?self.headers, "Headers after merge");
     }
 
+    /// Derives `Content-Type` from the body's media type. Headers the user
+    /// set explicitly replace it later, see `CurlRequest::prepare`.
+    ///
+    /// NOTE: For `multipart/*` bodies curl appends the boundary it generates
+    /// to this header, see: https://curl.se/libcurl/c/CURLOPT_HTTPPOST.html
+    fn set_content_type(&mut self, media_type: &MediaType) {
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), media_type.to_string());
+        self.merge_headers(headers);
+    }
+
+    /// Compresses a serialized body when `with_compression` asked for it,
+    /// setting `Content-Encoding` and recording both sizes.
+    fn encode_body<'b>(&mut self, body: &'b [u8]) -> Result<Cow<'b, [u8]>> {
+        let Some(encoding) = self.compression else {
+            return Ok(Cow::Borrowed(body));
+        };
+
+        let compressed = compression::compress(body, encoding)?;
+        tracing::debug!(
+            encoding = %encoding,
+            original_size = body.len(),
+            compressed_size = compressed.len(),
+            "Compressed request body"
+        );
+
+        let mut headers = HashMap::new();
+        headers.insert("Content-Encoding".to_string(), encoding.to_string());
+        self.merge_headers(headers);
+
+        self.compression_info = Some(CompressionInfo {
+            encoding,
+            original_size: body.len() as u64,
+            compressed_size: compressed.len() as u64,
+        });
+
+        Ok(Cow::Owned(compressed))
+    }
+
+    /// Sets the body, returning how it was compressed if it was.
     #[tracing::instrument(skip(self), level = "debug")]
-    pub(crate) fn set_content(&mut self, content: &ContentType) -> Result<()> {
-        match content {
+    pub(crate) fn set_content(&mut self, content: &ContentType) -> Result<Option<CompressionInfo>> {
+        let result = match content {
             ContentType::Text {
                 content,
                 media_type,
@@ -68,8 +122,8 @@ impl<'a> ContentHandler<'a> {
                 content,
                 media_type,
             } => {
-                tracing::info!(field_count = content.len(), "Setting form content");
-                self.set_form_content(content, media_type)
+                tracing::info!(field_count = content.len(), media_type = %media_type, "Setting form content");
+                self.set_form_content(content)
             }
             ContentType::Binary {
                 content,
@@ -86,9 +140,10 @@ impl<'a> ContentHandler<'a> {
             ContentType::Multipart {
                 content,
                 media_type,
+                boundary,
             } => {
                 tracing::info!(field_count = content.len(), "Setting multipart content");
-                self.set_multipart_content(content, media_type)
+                self.set_multipart_content(content, media_type, boundary.as_deref())
             }
             ContentType::Xml {
                 content,
@@ -104,24 +159,22 @@ impl<'a> ContentHandler<'a> {
                 tracing::info!(field_count = content.len(), "Setting URL-encoded content");
                 self.set_urlencoded_content(content, media_type)
             }
-        }
+        };
+
+        result.map(|_| self.compression_info.take())
     }
 
     fn set_text_content(&mut self, content: &str, media_type: &MediaType) -> Result<()> {
-        /* TODO: Look into reintroducing this when auth handling is done by kernel */
-        // let mut headers = HashMap::new();
-        // headers.insert("content-type".to_string(), media_type.to_string());
-        // self.merge_headers(headers);
-
-        self.handle
-            .post_fields_copy(content.as_bytes())
-            .map_err(|e| {
-                tracing::error!(error = %e, "Failed to set text content");
-                RelayError::Network {
-                    message: "Failed to set text content".into(),
-                    cause: Some(e.to_string()),
-                }
-            })?;
+        self.set_content_type(media_type);
+        let body = self.encode_body(content.as_bytes())?;
+
+        self.handle.post_fields_copy(&body).map_err(|e| {
+            tracing::error!(error = %e, "Failed to set text content");
+            RelayError::Network {
+                message: "Failed to set text content".into(),
+                cause: Some(e.to_string()),
+            }
+        })?;
 
         tracing::debug!("Text content set successfully");
         Ok(())
@@ -140,20 +193,16 @@ impl<'a> ContentHandler<'a> {
             }
         })?;
 
-        /* TODO: Look into reintroducing this when auth handling is done by kernel */
-        // let mut headers = HashMap::new();
-        // headers.insert("content-type".to_string(), media_type.to_string());
-        // self.merge_headers(headers);
+        self.set_content_type(media_type);
+        let body = self.encode_body(json_str.as_bytes())?;
 
-        self.handle
-            .post_fields_copy(json_str.as_bytes())
-            .map_err(|e| {
-                tracing::error!(error = %e, "Failed to set JSON content");
-                RelayError::Network {
-                    message: "Failed to set JSON content".into(),
-                    cause: Some(e.to_string()),
-                }
-            })?;
+        self.handle.post_fields_copy(&body).map_err(|e| {
+            tracing::error!(error = %e, "Failed to set JSON content");
+            RelayError::Network {
+                message: "Failed to set JSON content".into(),
+                cause: Some(e.to_string()),
+            }
+        })?;
 
         tracing::debug!("JSON content set successfully");
         Ok(())
@@ -166,6 +215,7 @@ impl<'a> ContentHandler<'a> {
         filename: Option<&str>,
     ) -> Result<()> {
         let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), media_type.to_string());
 
         if let Some(name) = filename {
             let safe_name = Path::new(name)
@@ -173,20 +223,16 @@ impl<'a> ContentHandler<'a> {
                 .and_then(|n| n.to_str())
                 .unwrap_or(name);
 
-            /* TODO: Look into reintroducing this when auth handling is done by kernel */
-            // headers.insert("content-type".to_string(), media_type.to_string());
             headers.insert(
                 "Content-Disposition".to_string(),
                 format!("attachment; filename=\"{}\"", safe_name),
             );
-        } else {
-            /* TODO: Look into reintroducing this when auth handling is done by kernel */
-            // headers.insert("content-type".to_string(), media_type.to_string());
         }
 
-        // self.merge_headers(headers);
+        self.merge_headers(headers);
+        let body = self.encode_body(content)?;
 
-        self.handle.post_fields_copy(content).map_err(|e| {
+        self.handle.post_fields_copy(&body).map_err(|e| {
             tracing::error!(error = %e, "Failed to set binary content");
             RelayError::Network {
                 message: "Failed to set binary content".into(),
@@ -198,38 +244,57 @@ impl<'a> ContentHandler<'a> {
         Ok(())
     }
 
-    fn set_form_content(
-        &mut self,
-        content: &Vec<(String, Vec<FormValue>)>,
-        media_type: &MediaType,
-    ) -> Result<()> {
-        /* TODO: Look into reintroducing this when auth handling is done by kernel */
-        // let mut headers = HashMap::new();
-        // headers.insert("content-type".to_string(), media_type.to_string());
-        // self.merge_headers(headers);
+    fn set_form_content(&mut self, content: &FormData) -> Result<()> {
+        // NOTE: curl's form API always builds `multipart/form-data`, whatever
+        // media type the body was tagged with (`form` bodies default to
+        // `application/x-www-form-urlencoded` in the kernel).
+        let media_type = MediaType::MultipartFormData;
 
-        let mut form = curl::easy::Form::new();
+        // NOTE: Bodies curl builds itself can't be compressed, so compressed
+        // forms are encoded here instead.
+        if self.compression.is_some() {
+            return self.set_encoded_multipart(
+                content,
+                &media_type,
+                multipart::generate_boundary(),
+            );
+        }
+
+        self.set_content_type(&media_type);
+
+        let mut form = Form::new();
 
         for (key, values) in content {
             for value in values {
                 match value {
-                    FormValue::Text { value: text } => {
+                    FormValue::Text {
+                        value: text,
+                        content_type,
+                        headers,
+                    } => {
                         tracing::debug!(key = %key, text_length = text.len(), "Adding form text field");
-                        form.part(key)
-                            .contents(text.as_bytes())
-                            .add()
-                            .map_err(|e| {
-                                tracing::error!(error = %e, key = %key, "Failed to add form text field");
-                                RelayError::Network {
-                                    message: format!("Failed to add form text field: {}", key),
-                                    cause: Some(e.to_string()),
-                                }
-                            })?;
+                        let (header_type, header_list) = Self::part_headers(key, headers)?;
+                        let mut part = form.part(key);
+                        part.contents(text.as_bytes());
+                        if let Some(content_type) = header_type.or(content_type.as_deref()) {
+                            part.content_type(content_type);
+                        }
+                        if let Some(list) = header_list {
+                            part.content_header(list);
+                        }
+                        part.add().map_err(|e| {
+                            tracing::error!(error = %e, key = %key, "Failed to add form text field");
+                            RelayError::Network {
+                                message: format!("Failed to add form text field: {}", key),
+                                cause: Some(e.to_string()),
+                            }
+                        })?;
                     }
                     FormValue::File {
                         filename,
                         content_type,
                         data,
+                        headers,
                     } => {
                         tracing::debug!(
                             key = %key,
@@ -238,25 +303,29 @@ impl<'a> ContentHandler<'a> {
                             data_length = data.len(),
                             "Adding form file field"
                         );
-                        form.part(key)
-                            .buffer(&filename, data.to_vec())
-                            .content_type(&content_type.to_string())
-                            .add()
-                            .map_err(|e| {
-                                tracing::error!(
-                                    error = %e,
-                                    key = %key,
-                                    filename = %filename,
-                                    "Failed to add form file field"
-                                );
-                                RelayError::Network {
-                                    message: format!(
-                                        "Failed to add form file field: {} ({})",
-                                        key, filename
-                                    ),
-                                    cause: Some(e.to_string()),
-                                }
-                            })?;
+                        let content_type = content_type.to_string();
+                        let (header_type, header_list) = Self::part_headers(key, headers)?;
+                        let mut part = form.part(key);
+                        part.buffer(filename, data.to_vec())
+                            .content_type(header_type.unwrap_or(&content_type));
+                        if let Some(list) = header_list {
+                            part.content_header(list);
+                        }
+                        part.add().map_err(|e| {
+                            tracing::error!(
+                                error = %e,
+                                key = %key,
+                                filename = %filename,
+                                "Failed to add form file field"
+                            );
+                            RelayError::Network {
+                                message: format!(
+                                    "Failed to add form file field: {} ({})",
+                                    key, filename
+                                ),
+                                cause: Some(e.to_string()),
+                            }
+                        })?;
                     }
                 }
             }
@@ -274,33 +343,301 @@ impl<'a> ContentHandler<'a> {
         Ok(())
     }
 
+    /// Plain `multipart/form-data` goes through curl's form API. A custom
+    /// boundary or another subtype isn't something curl can produce, so
+    /// those bodies are encoded here and sent as-is.
     fn set_multipart_content(
         &mut self,
-        content: &Vec<(String, Vec<FormValue>)>,
+        content: &FormData,
         media_type: &MediaType,
+        boundary: Option<&str>,
     ) -> Result<()> {
-        self.set_form_content(content, media_type)
+        if boundary.is_none() && *media_type == MediaType::MultipartFormData {
+            return self.set_form_content(content);
+        }
+
+        let boundary = match boundary {
+            Some(boundary) => {
+                multipart::validate_boundary(boundary)?;
+                boundary.to_string()
+            }
+            None => multipart::generate_boundary(),
+        };
+
+        self.set_encoded_multipart(content, media_type, boundary)
     }
 
-    fn set_urlencoded_content(&mut self, content: &String, media_type: &MediaType) -> Result<()> {
-        /* TODO: Look into reintroducing this when auth handling is done by kernel */
-        // let mut headers = HashMap::new();
-        // headers.insert("content-type".to_string(), media_type.to_string());
-        // self.merge_headers(headers);
+    fn set_encoded_multipart(
+        &mut self,
+        content: &FormData,
+        media_type: &MediaType,
+        boundary: String,
+    ) -> Result<()> {
+        tracing::debug!(media_type = %media_type, boundary = %boundary, "Encoding multipart body");
+        let body = multipart::encode(content, media_type, &boundary)?;
 
-        tracing::debug!(content_length = content.len(), "URL-encoded form data");
+        let mut headers = HashMap::new();
+        headers.insert(
+            "Content-Type".to_string(),
+            multipart::content_type(content, media_type, &boundary),
+        );
+        self.merge_headers(headers);
+        let body = self.encode_body(&body)?;
 
-        self.handle
-            .post_fields_copy(content.as_bytes())
-            .map_err(|e| {
-                tracing::error!(error = %e, "Failed to set urlencoded content");
+        self.handle.post_fields_copy(&body).map_err(|e| {
+            tracing::error!(error = %e, "Failed to set multipart content");
+            RelayError::Network {
+                message: "Failed to set multipart content".into(),
+                cause: Some(e.to_string()),
+            }
+        })?;
+
+        tracing::debug!("Multipart content set successfully");
+        Ok(())
+    }
+
+    /// Splits a part's extra headers for curl's form API, which leaves out
+    /// its own `Content-Disposition` when there is one but sends the part's
+    /// content type instead of a `Content-Type` header. That header is
+    /// returned on its own so it can become the content type.
+    fn part_headers<'h>(
+        key: &str,
+        headers: &'h Option<HashMap<String, String>>,
+    ) -> Result<(Option<&'h str>, Option<List>)> {
+        let Some(headers) = headers else {
+            return Ok((None, None));
+        };
+
+        let mut content_type = None;
+        let mut list = List::new();
+        for (name, value) in headers {
+            if name.eq_ignore_ascii_case("content-type") {
+                content_type = Some(value.as_str());
+                continue;
+            }
+            list.append(&format!("{}: {}", name, value)).map_err(|e| {
+                tracing::error!(error = %e, key = %key, header = %name, "Failed to add part header");
                 RelayError::Network {
-                    message: "Failed to set urlencoded content".into(),
+                    message: format!("Failed to add header '{}' to form field: {}", name, key),
                     cause: Some(e.to_string()),
                 }
             })?;
+        }
+        Ok((content_type, Some(list)))
+    }
+
+    fn set_urlencoded_content(&mut self, content: &String, media_type: &MediaType) -> Result<()> {
+        self.set_content_type(media_type);
+
+        tracing::debug!(content_length = content.len(), "URL-encoded form data");
+        let body = self.encode_body(content.as_bytes())?;
+
+        self.handle.post_fields_copy(&body).map_err(|e| {
+            tracing::error!(error = %e, "Failed to set urlencoded content");
+            RelayError::Network {
+                message: "Failed to set urlencoded content".into(),
+                cause: Some(e.to_string()),
+            }
+        })?;
 
         tracing::debug!("URL-encoded content set successfully");
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+    };
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::*;
+    use crate::interop::Request;
+
+    /// Sends `content` with the `Content-Type` header given, returning the
+    /// request's `Content-Type` and body as the server got them.
+    fn send_multipart(content: serde_json::Value, content_type: &str) -> (String, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/upload", listener.local_addr().unwrap());
+
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let (mut content_type, mut length) = (String::new(), 0);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                if let Some((name, value)) = line.split_once(':') {
+                    match name.to_ascii_lowercase().as_str() {
+                        "content-type" => content_type = value.trim().to_string(),
+                        "content-length" => length = value.trim().parse().unwrap(),
+                        _ => {}
+                    }
+                }
+                line.clear();
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            (content_type, String::from_utf8(body).unwrap())
+        });
+
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "url": url,
+            "method": "POST",
+            "version": "HTTP/1.1",
+            "headers": { "Content-Type": content_type },
+            "content": content,
+        }))
+        .unwrap();
+        crate::relay::execute_request(&request, &CancellationToken::new()).unwrap();
+        server.join().unwrap()
+    }
+
+    #[test]
+    fn test_content_type_from_media_type() {
+        let mut handle = Easy::new();
+        let mut headers = HashMap::new();
+
+        ContentHandler::new(&mut handle, &mut headers)
+            .set_content(&ContentType::Json {
+                content: serde_json::json!({ "ok": true }),
+                media_type: MediaType::Json,
+            })
+            .unwrap();
+
+        assert_eq!(
+            headers.get("content-type").map(String::as_str),
+            Some("application/json")
+        );
+
+        let content: ContentType = serde_json::from_value(serde_json::json!({
+            "kind": "json",
+            "content": { "title": "Not Found" },
+            "mediaType": "application/problem+json; charset=utf-8",
+        }))
+        .unwrap();
+        let mut headers = HashMap::new();
+
+        ContentHandler::new(&mut handle, &mut headers)
+            .set_content(&content)
+            .unwrap();
+
+        assert_eq!(
+            headers.get("content-type").map(String::as_str),
+            Some("application/problem+json; charset=utf-8")
+        );
+    }
+
+    #[test]
+    fn test_compressed_content() {
+        let mut handle = Easy::new();
+        let mut headers = HashMap::new();
+        let content = serde_json::json!({ "message": "x".repeat(1024) });
+
+        let info = ContentHandler::new(&mut handle, &mut headers)
+            .with_compression(Some(BodyCompression::Gzip))
+            .set_content(&ContentType::Json {
+                content: content.clone(),
+                media_type: MediaType::Json,
+            })
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            headers.get("content-encoding").map(String::as_str),
+            Some("gzip")
+        );
+        assert_eq!(info.encoding, BodyCompression::Gzip);
+        assert_eq!(info.original_size, content.to_string().len() as u64);
+        assert!(info.compressed_size < info.original_size);
+    }
+
+    #[test]
+    fn test_binary_content_disposition() {
+        let mut handle = Easy::new();
+        let mut headers = HashMap::new();
+
+        ContentHandler::new(&mut handle, &mut headers)
+            .set_content(&ContentType::Binary {
+                content: bytes::Bytes::from_static(b"%PDF-1.7"),
+                media_type: MediaType::ApplicationPdf,
+                filename: Some("../reports/q3.pdf".into()),
+            })
+            .unwrap();
+
+        assert_eq!(
+            headers.get("content-type").map(String::as_str),
+            Some("application/pdf")
+        );
+        assert_eq!(
+            headers.get("content-disposition").map(String::as_str),
+            Some("attachment; filename=\"q3.pdf\"")
+        );
+    }
+
+    #[test]
+    fn test_multipart_on_the_wire() {
+        let parts = serde_json::json!([
+            ["a", [{
+                "kind": "text",
+                "value": "{}",
+                "contentType": "text/plain",
+                "headers": {
+                    "Content-Type": "application/json",
+                    "Content-Disposition": "form-data; name=\"renamed\""
+                }
+            }]],
+            ["f", [{
+                "kind": "file",
+                "filename": "x.bin",
+                "contentType": "application/octet-stream",
+                "data": [65],
+                "headers": { "content-type": "image/png", "X-Extra": "1" }
+            }]]
+        ]);
+
+        let (content_type, body) = send_multipart(
+            serde_json::json!({
+                "kind": "multipart",
+                "mediaType": "multipart/form-data",
+                "content": parts,
+            }),
+            "multipart/form-data; boundary=stale; charset=utf-8",
+        );
+        let boundary = multipart::boundary(&content_type).unwrap();
+        assert_eq!(
+            content_type,
+            format!("multipart/form-data; charset=utf-8; boundary={}", boundary)
+        );
+        assert!(body.starts_with(&format!("--{}\r\n", boundary)));
+        for header in [
+            "Content-Type: application/json\r\n",
+            "Content-Disposition: form-data; name=\"renamed\"\r\n",
+            "Content-Type: image/png\r\n",
+            "X-Extra: 1\r\n",
+        ] {
+            assert_eq!(body.matches(header).count(), 1, "{}", header);
+        }
+        assert_eq!(body.matches("Content-Type:").count(), 2);
+        assert_eq!(body.matches("Content-Disposition:").count(), 2);
+
+        let (content_type, body) = send_multipart(
+            serde_json::json!({
+                "kind": "multipart",
+                "mediaType": "multipart/mixed",
+                "boundary": "b0undary",
+                "content": parts,
+            }),
+            "multipart/mixed; boundary=stale",
+        );
+        assert_eq!(content_type, "multipart/mixed; boundary=b0undary");
+        assert!(body.starts_with("--b0undary\r\n"));
+    }
+}
@@ -0,0 +1,1922 @@
+use std::{collections::HashMap, path::Path};
+
+use bytes::Bytes;
+use http::{Method, Version};
+
+use crate::{
+    error::{RelayError, Result},
+    interop::{
+        ApiKeyLocation, AuthType, CertificateConfig, CertificateType, ConnectToEntry, ContentType,
+        FormData, FormValue, MediaKind, MediaType, ProxyAuth, ProxyAuthScheme, ProxyConfig,
+        Request, RequestMeta, RequestOptions, ResolveEntry, SecurityConfig, VersionMode,
+    },
+    media,
+};
+
+/// Separates the options of an exported command, one per line.
+const LINE_BREAK: &str = " \\\n  ";
+
+// NOTE: Certificates and keys live in the request as data while curl reads
+// them from files, so exported commands refer to these names instead.
+pub(crate) const CLIENT_CERT_FILE: &str = "client.pem";
+pub(crate) const CLIENT_KEY_FILE: &str = "client.key";
+pub(crate) const CLIENT_P12_FILE: &str = "client.p12";
+pub(crate) const CA_CERT_FILE: &str = "ca.pem";
+const PROXY_CLIENT_CERT_FILE: &str = "proxy-client.pem";
+const PROXY_CLIENT_KEY_FILE: &str = "proxy-client.key";
+const PROXY_CLIENT_P12_FILE: &str = "proxy-client.p12";
+const PROXY_CA_CERT_FILE: &str = "proxy-ca.pem";
+/// Binary bodies with NUL bytes, which no shell can pass in an argument.
+const BODY_FILE: &str = "body.bin";
+
+/// Renders `request` as a curl command line for POSIX shells that sends
+/// the same request the relay would.
+///
+/// Headers are sorted by name since `Request.headers` has no order.
+/// Certificates and binary bodies with a `filename` or NUL bytes refer to
+/// files next to the command (`client.pem`, `ca.pem`, `body.bin`, ...)
+/// rather than embedding them.
+/// OAuth2 is only exported once it has an access token, and options curl
+/// has no flag for, like `body_compression`, are left out.
+pub fn export(request: &Request) -> String {
+    let mut args: Vec<String> = Vec::new();
+    let mut headers: Vec<(String, String)> = request
+        .headers
+        .iter()
+        .flatten()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    let has_header = |headers: &[(String, String)], name: &str| {
+        headers.iter().any(|(n, _)| n.eq_ignore_ascii_case(name))
+    };
+
+    let has_body = request.content.is_some();
+    match request.method {
+        Method::HEAD if !has_body => args.push("-I".into()),
+        Method::GET if !has_body => {}
+        Method::POST if has_body => {}
+        ref method => args.push(format!("-X {}", quote(method.as_str()))),
+    }
+
+    let mut query: Vec<(String, String)> = request
+        .params
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    query.sort();
+    if let Some(AuthType::ApiKey {
+        key,
+        value,
+        location: ApiKeyLocation::Query,
+    }) = &request.auth
+    {
+        query.push((key.clone(), value.clone()));
+    }
+    args.push(quote(&with_query(&request.url, &query)));
+
+    let options = request
+        .meta
+        .as_ref()
+        .and_then(|meta| meta.options.clone())
+        .unwrap_or_default();
+
+    match (request.version, options.version_mode.unwrap_or_default()) {
+        (Version::HTTP_10, _) => args.push("--http1.0".into()),
+        (Version::HTTP_2, VersionMode::Fallback) => args.push("--http2".into()),
+        (Version::HTTP_2, VersionMode::Only) => args.push("--http2-prior-knowledge".into()),
+        (Version::HTTP_3, VersionMode::Fallback) => args.push("--http3".into()),
+        (Version::HTTP_3, VersionMode::Only) => args.push("--http3-only".into()),
+        _ => {}
+    }
+
+    match &request.auth {
+        Some(AuthType::Basic { username, password }) => {
+            args.push(format!(
+                "-u {}",
+                quote(&format!("{}:{}", username, password))
+            ));
+        }
+        Some(AuthType::Digest {
+            username, password, ..
+        }) => {
+            args.push("--digest".into());
+            args.push(format!(
+                "-u {}",
+                quote(&format!("{}:{}", username, password))
+            ));
+        }
+        Some(AuthType::Bearer { token })
+        | Some(AuthType::OAuth2 {
+            access_token: Some(token),
+            ..
+        }) => args.push(format!("--oauth2-bearer {}", quote(token))),
+        Some(AuthType::ApiKey {
+            key,
+            value,
+            location: ApiKeyLocation::Header,
+        }) => headers.push((key.clone(), value.clone())),
+        Some(AuthType::Aws {
+            access_key,
+            secret_key,
+            region,
+            service,
+            session_token,
+            ..
+        }) => {
+            args.push(format!(
+                "--aws-sigv4 {}",
+                quote(&format!("aws:amz:{}:{}", region, service))
+            ));
+            args.push(format!(
+                "-u {}",
+                quote(&format!("{}:{}", access_key, secret_key))
+            ));
+            if let Some(token) = session_token {
+                headers.push(("x-amz-security-token".into(), token.clone()));
+            }
+        }
+        _ => {}
+    }
+
+    let mut body = Vec::new();
+    if let Some(ref content) = request.content {
+        let (media_type, curl_default) = match content {
+            ContentType::Text {
+                content,
+                media_type,
+            }
+            | ContentType::Xml {
+                content,
+                media_type,
+            } => {
+                body.push(format!("--data-raw {}", quote(content)));
+                (media_type, false)
+            }
+            ContentType::Json {
+                content,
+                media_type,
+            } => {
+                let json = serde_json::to_string(content).unwrap_or_default();
+                body.push(format!("--data-raw {}", quote(&json)));
+                (media_type, false)
+            }
+            ContentType::Urlencoded {
+                content,
+                media_type,
+            } => {
+                body.push(format!("--data-raw {}", quote(content)));
+                (media_type, *media_type == MediaType::FormUrlEncoded)
+            }
+            ContentType::Binary {
+                content,
+                media_type,
+                filename,
+            } => {
+                match filename {
+                    Some(filename) => body.push(format!(
+                        "--data-binary {}",
+                        quote(&format!("@{}", filename))
+                    )),
+                    None if content.contains(&0) => {
+                        body.push(format!("--data-binary @{}", BODY_FILE))
+                    }
+                    // NOTE: `--data-binary @...` would read a file instead,
+                    // `--data-raw` sends the same bytes without that.
+                    None if content.starts_with(b"@") => {
+                        body.push(format!("--data-raw {}", quote_bytes(content)))
+                    }
+                    None => body.push(format!("--data-binary {}", quote_bytes(content))),
+                }
+                (media_type, false)
+            }
+            ContentType::Form {
+                content,
+                media_type,
+            } => {
+                body.extend(form_args(content));
+                // NOTE: Sent as `multipart/form-data` whatever it's tagged
+                // with, see `ContentHandler::set_form_content`.
+                (media_type, true)
+            }
+            ContentType::Multipart {
+                content,
+                media_type,
+                ..
+            } => {
+                body.extend(form_args(content));
+                (media_type, *media_type == MediaType::MultipartFormData)
+            }
+        };
+
+        if !curl_default && !has_header(&headers, "content-type") {
+            headers.push(("Content-Type".into(), media_type.to_string()));
+        }
+    }
+
+    headers.sort_by(|(a, a_value), (b, b_value)| {
+        a.to_ascii_lowercase()
+            .cmp(&b.to_ascii_lowercase())
+            .then_with(|| a_value.cmp(b_value))
+    });
+    args.extend(
+        headers
+            .iter()
+            .map(|(name, value)| format!("-H {}", quote(&format!("{}: {}", name, value)))),
+    );
+    args.extend(body);
+
+    if let Some(ref proxy) = request.proxy {
+        args.extend(proxy_args(proxy));
+    }
+    if let Some(ref security) = request.security {
+        args.extend(security_args(security, false));
+    }
+    args.extend(option_args(&options));
+
+    let mut command = String::from("curl");
+    for (index, arg) in args.iter().enumerate() {
+        // NOTE: The method and URL stay on the first line.
+        let first_line = index == 0
+            || (index == 1 && args[0].starts_with("-X "))
+            || (index == 1 && args[0] == "-I");
+        command.push_str(if first_line { " " } else { LINE_BREAK });
+        command.push_str(arg);
+    }
+    command
+}
+
+pub(crate) fn with_query(url: &str, query: &[(String, String)]) -> String {
+    if query.is_empty() {
+        return url.to_string();
+    }
+
+    let encoded = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(query)
+        .finish();
+    let (base, fragment) = match url.split_once('#') {
+        Some((base, fragment)) => (base, Some(fragment)),
+        None => (url, None),
+    };
+    let separator = match base.find('?') {
+        Some(index) if index + 1 < base.len() && !base.ends_with('&') => "&",
+        Some(_) => "",
+        None => "?",
+    };
+
+    let mut url = format!("{}{}{}", base, separator, encoded);
+    if let Some(fragment) = fragment {
+        url.push('#');
+        url.push_str(fragment);
+    }
+    url
+}
+
+fn form_args(content: &FormData) -> Vec<String> {
+    let mut args = Vec::new();
+    for (name, values) in content {
+        for value in values {
+            args.push(match value {
+                FormValue::Text {
+                    value,
+                    content_type,
+                    headers,
+                } => {
+                    if content_type.is_none() && headers.is_none() && !form_special(value) {
+                        format!("-F {}", quote(&format!("{}={}", name, value)))
+                    } else if content_type.is_none() && headers.is_none() {
+                        format!("--form-string {}", quote(&format!("{}={}", name, value)))
+                    } else {
+                        let mut field = format!("{}={}", name, form_quote(value));
+                        if let Some(content_type) = content_type {
+                            field.push_str(&format!(";type={}", content_type));
+                        }
+                        field.push_str(&part_headers(headers.as_ref()));
+                        format!("-F {}", quote(&field))
+                    }
+                }
+                FormValue::File {
+                    filename,
+                    content_type,
+                    headers,
+                    ..
+                } => {
+                    let path = if form_special(filename) || filename.contains(',') {
+                        form_quote(filename)
+                    } else {
+                        filename.clone()
+                    };
+                    let mut field = format!("{}=@{};type={}", name, path, content_type);
+                    field.push_str(&part_headers(headers.as_ref()));
+                    format!("-F {}", quote(&field))
+                }
+            });
+        }
+    }
+    args
+}
+
+/// Whether curl's `-F` would read more into a value than its text.
+fn form_special(value: &str) -> bool {
+    value.starts_with(['@', '<', '"']) || value.contains(';')
+}
+
+fn form_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn part_headers(headers: Option<&HashMap<String, String>>) -> String {
+    let mut headers: Vec<_> = headers.into_iter().flatten().collect();
+    headers.sort();
+    headers
+        .into_iter()
+        .map(|(name, value)| format!(";headers={}", form_quote(&format!("{}: {}", name, value))))
+        .collect()
+}
+
+fn proxy_args(proxy: &ProxyConfig) -> Vec<String> {
+    let mut args = Vec::new();
+
+    if let Some(ref url) = proxy.url {
+        let url = match (proxy.remote_dns, url.split_once("://")) {
+            (Some(true), Some(("socks5", rest))) => format!("socks5h://{}", rest),
+            (Some(true), Some(("socks4", rest))) => format!("socks4a://{}", rest),
+            _ => url.clone(),
+        };
+        args.push(format!("-x {}", quote(&url)));
+    }
+
+    if let Some(ProxyAuth {
+        ref username,
+        ref password,
+        scheme,
+    }) = proxy.auth
+    {
+        args.push(format!(
+            "-U {}",
+            quote(&format!("{}:{}", username, password))
+        ));
+        match scheme {
+            Some(ProxyAuthScheme::Any) => args.push("--proxy-anyauth".into()),
+            Some(ProxyAuthScheme::Digest) => args.push("--proxy-digest".into()),
+            Some(ProxyAuthScheme::Ntlm) => args.push("--proxy-ntlm".into()),
+            Some(ProxyAuthScheme::Basic) | None => {}
+        }
+    }
+
+    if let Some(ref no_proxy) = proxy.no_proxy {
+        args.push(format!("--noproxy {}", quote(&no_proxy.join(","))));
+    }
+
+    if let Some(ref security) = proxy.security {
+        args.extend(security_args(security, true));
+    }
+
+    args
+}
+
+fn security_args(security: &SecurityConfig, proxy: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    let prefix = if proxy { "--proxy-" } else { "--" };
+
+    // NOTE: curl can't skip only the host name check.
+    if security.verify_peer == Some(false) || security.verify_host == Some(false) {
+        args.push(if proxy { "--proxy-insecure" } else { "-k" }.into());
+    }
+
+    let Some(CertificateConfig { ref client, ref ca }) = security.certificates else {
+        return args;
+    };
+
+    match client {
+        Some(CertificateType::Pem { .. }) => {
+            let (cert, key) = if proxy {
+                (PROXY_CLIENT_CERT_FILE, PROXY_CLIENT_KEY_FILE)
+            } else {
+                (CLIENT_CERT_FILE, CLIENT_KEY_FILE)
+            };
+            args.push(format!("{}cert {}", prefix, cert));
+            args.push(format!("{}key {}", prefix, key));
+        }
+        Some(CertificateType::Pfx { password, .. }) => {
+            args.push(format!("{}cert-type P12", prefix));
+            let file = if proxy {
+                PROXY_CLIENT_P12_FILE
+            } else {
+                CLIENT_P12_FILE
+            };
+            let cert = if password.is_empty() {
+                file.to_string()
+            } else {
+                format!("{}:{}", file, password)
+            };
+            args.push(format!("{}cert {}", prefix, quote(&cert)));
+        }
+        None => {}
+    }
+
+    if ca.as_ref().is_some_and(|ca| !ca.is_empty()) {
+        let file = if proxy {
+            PROXY_CA_CERT_FILE
+        } else {
+            CA_CERT_FILE
+        };
+        args.push(format!("{}cacert {}", prefix, file));
+    }
+
+    args
+}
+
+fn option_args(options: &RequestOptions) -> Vec<String> {
+    let mut args = Vec::new();
+
+    if options.follow_redirects == Some(true) {
+        args.push("-L".into());
+    }
+    if let Some(max) = options.max_redirects {
+        args.push(format!("--max-redirs {}", max));
+    }
+    if let Some(timeout) = options.timeout {
+        args.push(format!("--max-time {}", seconds(timeout)));
+    }
+    if options.decompress == Some(true) {
+        args.push("--compressed".into());
+    }
+    if options.cookies == Some(true) {
+        args.push("-b ''".into());
+    }
+    if options.keep_alive == Some(false) {
+        args.push("--no-keepalive".into());
+    }
+    for entry in options.resolve.iter().flatten() {
+        let addresses: Vec<String> = entry
+            .addresses
+            .iter()
+            .map(|address| {
+                if address.contains(':') && !address.starts_with('[') {
+                    format!("[{}]", address)
+                } else {
+                    address.clone()
+                }
+            })
+            .collect();
+        args.push(format!(
+            "--resolve {}",
+            quote(&format!(
+                "{}:{}:{}",
+                entry.host,
+                entry.port,
+                addresses.join(",")
+            ))
+        ));
+    }
+    for entry in options.connect_to.iter().flatten() {
+        let port = |port: Option<u16>| port.map(|p| p.to_string()).unwrap_or_default();
+        args.push(format!(
+            "--connect-to {}",
+            quote(&format!(
+                "{}:{}:{}:{}",
+                entry.host.as_deref().unwrap_or_default(),
+                port(entry.port),
+                entry.connect_host.as_deref().unwrap_or_default(),
+                port(entry.connect_port)
+            ))
+        ));
+    }
+    if let Some(ref name) = options.abstract_unix_socket {
+        args.push(format!("--abstract-unix-socket {}", quote(name)));
+    } else if let Some(ref path) = options.unix_socket {
+        args.push(format!("--unix-socket {}", quote(path)));
+    }
+    if let Some(ref path) = options.save_to {
+        args.push(format!("-o {}", quote(path)));
+    }
+
+    args
+}
+
+/// Formats milliseconds as the seconds `--max-time` takes, e.g. `2.5`.
+fn seconds(millis: u64) -> String {
+    (millis as f64 / 1000.0).to_string()
+}
+
+/// Single-quotes `value` for a POSIX shell unless it is plain enough not to
+/// need it.
+fn quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+%".contains(c));
+    if plain {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Like `quote`, but falls back to `$'...'` with `\xHH` escapes for bytes
+/// that aren't printable text.
+fn quote_bytes(value: &[u8]) -> String {
+    if let Ok(text) = std::str::from_utf8(value) {
+        if !text
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+        {
+            return quote(text);
+        }
+    }
+
+    let mut quoted = String::from("$'");
+    for &byte in value {
+        match byte {
+            b'\'' => quoted.push_str("\\'"),
+            b'\\' => quoted.push_str("\\\\"),
+            0x20..=0x7e => quoted.push(byte as char),
+            _ => quoted.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Turns a curl command line, as copied from browser dev tools, API docs
+/// or a shell history, into a request with `id` 0.
+///
+/// Understands POSIX shell quoting including `$'...'` and line
+/// continuations. Files curl would read (`-d @file`, `-F name=@file`) keep
+/// only their name with empty data, and options that only change curl's
+/// own output like `-s` or `-v` are ignored. Options that would change the
+/// request but have no equivalent, like `-T` or `--cert`, are an error.
+#[tracing::instrument(skip(command), level = "debug")]
+pub fn parse(command: &str) -> Result<Request> {
+    let words = split_words(command)?;
+    let mut words = words.into_iter();
+
+    let program = words.next().map(lossy).unwrap_or_default();
+    let program = Path::new(&program)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    if program != "curl" {
+        return Err(RelayError::Parse {
+            message: "Not a curl command".into(),
+            cause: Some(program.to_string()),
+        });
+    }
+
+    let mut parser = Parser::default();
+    let mut only_urls = false;
+    while let Some(word) = words.next() {
+        let text = lossy(word.clone());
+
+        if only_urls || !text.starts_with('-') || text == "-" {
+            parser.url(text)?;
+        } else if text == "--" {
+            only_urls = true;
+        } else if let Some(name) = text.strip_prefix("--") {
+            let value = if takes_value(name) {
+                Some(words.next().ok_or_else(|| missing_value(&text))?)
+            } else {
+                None
+            };
+            parser.option(name, value)?;
+        } else {
+            // Short options can be grouped (`-sSL`) and take their value
+            // from the rest of the word (`-XPOST`) or the next one.
+            let flags: Vec<char> = text[1..].chars().collect();
+            for (index, flag) in flags.iter().enumerate() {
+                let name =
+                    long_name(*flag).ok_or_else(|| unsupported_option(&format!("-{}", flag)))?;
+                if !takes_value(name) {
+                    parser.option(name, None)?;
+                    continue;
+                }
+
+                let rest: String = flags[index + 1..].iter().collect();
+                let value = if rest.is_empty() {
+                    words.next().ok_or_else(|| missing_value(&text))?
+                } else {
+                    rest.into_bytes()
+                };
+                parser.option(name, Some(value))?;
+                break;
+            }
+        }
+    }
+
+    parser.finish()
+}
+
+fn lossy(word: Vec<u8>) -> String {
+    String::from_utf8(word).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+fn missing_value(option: &str) -> RelayError {
+    RelayError::Parse {
+        message: "curl option is missing its value".into(),
+        cause: Some(option.to_string()),
+    }
+}
+
+fn unsupported_option(option: &str) -> RelayError {
+    RelayError::Parse {
+        message: "Unsupported curl option".into(),
+        cause: Some(option.to_string()),
+    }
+}
+
+fn long_name(flag: char) -> Option<&'static str> {
+    Some(match flag {
+        'X' => "request",
+        'H' => "header",
+        'd' => "data",
+        'F' => "form",
+        'u' => "user",
+        'x' => "proxy",
+        'U' => "proxy-user",
+        'o' => "output",
+        'm' => "max-time",
+        'e' => "referer",
+        'A' => "user-agent",
+        'b' => "cookie",
+        'c' => "cookie-jar",
+        'D' => "dump-header",
+        'w' => "write-out",
+        'E' => "cert",
+        'r' => "range",
+        'T' => "upload-file",
+        'K' => "config",
+        'G' => "get",
+        'I' => "head",
+        'k' => "insecure",
+        'L' => "location",
+        's' => "silent",
+        'S' => "show-error",
+        'v' => "verbose",
+        'i' => "include",
+        'f' => "fail",
+        'N' => "no-buffer",
+        'g' => "globoff",
+        '#' => "progress-bar",
+        '0' => "http1.0",
+        '4' => "ipv4",
+        '6' => "ipv6",
+        _ => return None,
+    })
+}
+
+/// Long options that take a value.
+const WITH_VALUE: &[&str] = &[
+    "request",
+    "header",
+    "data",
+    "data-ascii",
+    "data-raw",
+    "data-binary",
+    "data-urlencode",
+    "json",
+    "url-query",
+    "form",
+    "form-string",
+    "user",
+    "oauth2-bearer",
+    "aws-sigv4",
+    "url",
+    "proxy",
+    "proxy-user",
+    "noproxy",
+    "socks4",
+    "socks4a",
+    "socks5",
+    "socks5-hostname",
+    "output",
+    "max-time",
+    "max-redirs",
+    "referer",
+    "user-agent",
+    "cookie",
+    "cookie-jar",
+    "dump-header",
+    "write-out",
+    "range",
+    "resolve",
+    "connect-to",
+    "unix-socket",
+    "abstract-unix-socket",
+    "cert",
+    "cert-type",
+    "key",
+    "key-type",
+    "pass",
+    "cacert",
+    "capath",
+    "proxy-cert",
+    "proxy-cert-type",
+    "proxy-key",
+    "proxy-key-type",
+    "proxy-pass",
+    "proxy-cacert",
+    "connect-timeout",
+    "retry",
+    "retry-delay",
+    "retry-max-time",
+    "limit-rate",
+    "interface",
+    "max-filesize",
+    "trace",
+    "trace-ascii",
+    "stderr",
+    "upload-file",
+    "config",
+];
+
+fn takes_value(name: &str) -> bool {
+    WITH_VALUE.contains(&name)
+}
+
+/// A body piece from `-d` and friends, already encoded the way curl sends
+/// it.
+enum Data {
+    Bytes(Vec<u8>),
+    File(String),
+}
+
+#[derive(Default)]
+struct Parser {
+    url: Option<String>,
+    method: Option<Method>,
+    head: bool,
+    get: bool,
+    version: Option<(Version, VersionMode)>,
+    headers: Vec<(String, String)>,
+    data: Vec<Data>,
+    json: bool,
+    query: Vec<String>,
+    form: Vec<(String, FormValue)>,
+    user: Option<String>,
+    digest: bool,
+    bearer: Option<String>,
+    aws_sigv4: Option<String>,
+    proxy: Option<ProxyConfig>,
+    insecure: bool,
+    options: RequestOptions,
+}
+
+impl Parser {
+    fn url(&mut self, url: String) -> Result<()> {
+        if self.url.is_some() {
+            return Err(RelayError::Parse {
+                message: "curl commands with several URLs aren't supported".into(),
+                cause: Some(url),
+            });
+        }
+        self.url = Some(url);
+        Ok(())
+    }
+
+    fn proxy(&mut self) -> &mut ProxyConfig {
+        self.proxy.get_or_insert(ProxyConfig {
+            url: None,
+            auth: None,
+            no_proxy: None,
+            from_env: None,
+            pac: None,
+            remote_dns: None,
+            security: None,
+        })
+    }
+
+    fn option(&mut self, name: &str, value: Option<Vec<u8>>) -> Result<()> {
+        let raw = value.unwrap_or_default();
+        let value = lossy(raw.clone());
+
+        match name {
+            "request" => {
+                self.method =
+                    Some(
+                        Method::from_bytes(value.as_bytes()).map_err(|e| RelayError::Parse {
+                            message: "Invalid request method".into(),
+                            cause: Some(e.to_string()),
+                        })?,
+                    )
+            }
+            "url" => self.url(value)?,
+            "head" => self.head = true,
+            "get" => self.get = true,
+
+            "header" => match value.split_once(':') {
+                Some((name, value)) if !value.trim().is_empty() => {
+                    self.headers
+                        .push((name.trim().to_string(), value.trim().to_string()));
+                }
+                // `Name:` removes a header curl adds, `Name;` sends it empty.
+                Some(_) => {}
+                None => {
+                    if let Some(name) = value.strip_suffix(';') {
+                        self.headers.push((name.trim().to_string(), String::new()));
+                    }
+                }
+            },
+            "user-agent" => self.headers.push(("User-Agent".into(), value)),
+            "referer" => self.headers.push(("Referer".into(), value)),
+            "range" => self
+                .headers
+                .push(("Range".into(), format!("bytes={}", value))),
+            "cookie" => {
+                if value.contains('=') {
+                    self.headers.push(("Cookie".into(), value));
+                } else {
+                    // An empty value or a file name turns the cookie engine on.
+                    self.options.cookies = Some(true);
+                }
+            }
+
+            "data" | "data-ascii" => match value.strip_prefix('@') {
+                Some(file) => self.data.push(Data::File(file.to_string())),
+                None => self.data.push(Data::Bytes(raw)),
+            },
+            "data-binary" => match value.strip_prefix('@') {
+                Some(file) => self.data.push(Data::File(file.to_string())),
+                None => self.data.push(Data::Bytes(raw)),
+            },
+            "data-raw" => self.data.push(Data::Bytes(raw)),
+            "data-urlencode" => self
+                .data
+                .push(Data::Bytes(urlencode_data(&value)?.into_bytes())),
+            "url-query" => self.query.push(urlencode_data(&value)?),
+            "json" => {
+                self.json = true;
+                match value.strip_prefix('@') {
+                    Some(file) => self.data.push(Data::File(file.to_string())),
+                    None => self.data.push(Data::Bytes(raw)),
+                }
+            }
+            "form" => {
+                let field = parse_form(&value)?;
+                self.form.push(field);
+            }
+            "form-string" => {
+                let (name, text) = value.split_once('=').ok_or_else(|| invalid_form(&value))?;
+                self.form.push((
+                    name.to_string(),
+                    FormValue::Text {
+                        value: text.to_string(),
+                        content_type: None,
+                        headers: None,
+                    },
+                ));
+            }
+
+            "user" => self.user = Some(value),
+            "basic" | "anyauth" => self.digest = false,
+            "digest" => self.digest = true,
+            "ntlm" | "negotiate" | "ntlm-wb" => {
+                return Err(RelayError::UnsupportedFeature {
+                    feature: name.into(),
+                    message: "Only basic, digest, bearer and AWS SigV4 auth can be imported".into(),
+                    relay: "curl".into(),
+                })
+            }
+            "oauth2-bearer" => self.bearer = Some(value),
+            "aws-sigv4" => self.aws_sigv4 = Some(value),
+
+            "proxy" => self.proxy().url = Some(value),
+            "socks4" | "socks4a" | "socks5" => {
+                self.proxy().url = Some(format!("{}://{}", name, value))
+            }
+            "socks5-hostname" => self.proxy().url = Some(format!("socks5h://{}", value)),
+            "proxy-user" => {
+                let (username, password) = value.split_once(':').unwrap_or((&value, ""));
+                let scheme = self.proxy().auth.as_ref().and_then(|auth| auth.scheme);
+                self.proxy().auth = Some(ProxyAuth {
+                    username: username.to_string(),
+                    password: password.to_string(),
+                    scheme,
+                });
+            }
+            "proxy-anyauth" | "proxy-basic" | "proxy-digest" | "proxy-ntlm" => {
+                let scheme = match name {
+                    "proxy-anyauth" => ProxyAuthScheme::Any,
+                    "proxy-digest" => ProxyAuthScheme::Digest,
+                    "proxy-ntlm" => ProxyAuthScheme::Ntlm,
+                    _ => ProxyAuthScheme::Basic,
+                };
+                let auth = self.proxy().auth.get_or_insert(ProxyAuth {
+                    username: String::new(),
+                    password: String::new(),
+                    scheme: None,
+                });
+                auth.scheme = Some(scheme);
+            }
+            "noproxy" => {
+                self.proxy().no_proxy = Some(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|entry| !entry.is_empty())
+                        .map(String::from)
+                        .collect(),
+                )
+            }
+            "proxy-insecure" => {
+                self.proxy().security = Some(SecurityConfig {
+                    certificates: None,
+                    verify_host: Some(false),
+                    verify_peer: Some(false),
+                })
+            }
+            "insecure" => self.insecure = true,
+            "cert" | "key" | "cacert" | "capath" | "proxy-cert" | "proxy-key" | "proxy-cacert" => {
+                return Err(RelayError::UnsupportedFeature {
+                    feature: name.into(),
+                    message: "Certificate files can't be imported, add them to the request instead"
+                        .into(),
+                    relay: "curl".into(),
+                })
+            }
+
+            "http1.0" => self.version = Some((Version::HTTP_10, VersionMode::Fallback)),
+            "http1.1" => self.version = Some((Version::HTTP_11, VersionMode::Fallback)),
+            "http2" => self.version = Some((Version::HTTP_2, VersionMode::Fallback)),
+            "http2-prior-knowledge" => self.version = Some((Version::HTTP_2, VersionMode::Only)),
+            "http3" => self.version = Some((Version::HTTP_3, VersionMode::Fallback)),
+            "http3-only" => self.version = Some((Version::HTTP_3, VersionMode::Only)),
+
+            "location" | "location-trusted" => self.options.follow_redirects = Some(true),
+            "max-redirs" => self.options.max_redirects = Some(number(name, &value)?),
+            "max-time" => {
+                let seconds: f64 = number(name, &value)?;
+                self.options.timeout = Some((seconds * 1000.0).round() as u64);
+            }
+            "compressed" => self.options.decompress = Some(true),
+            "no-keepalive" => self.options.keep_alive = Some(false),
+            "resolve" => self
+                .options
+                .resolve
+                .get_or_insert_with(Vec::new)
+                .push(parse_resolve(&value)?),
+            "connect-to" => self
+                .options
+                .connect_to
+                .get_or_insert_with(Vec::new)
+                .push(parse_connect_to(&value)?),
+            "unix-socket" => self.options.unix_socket = Some(value),
+            "abstract-unix-socket" => self.options.abstract_unix_socket = Some(value),
+            // NOTE: Relative paths land in the host app's save directory
+            // rather than wherever curl would have run.
+            "output" => self.options.save_to = Some(value),
+
+            // Options that only concern curl's own output, or that read
+            // local files the request can't refer to.
+            "silent" | "show-error" | "verbose" | "include" | "fail" | "fail-with-body"
+            | "no-buffer" | "globoff" | "progress-bar" | "no-progress-meter" | "ipv4" | "ipv6"
+            | "path-as-is" | "tcp-nodelay" | "tlsv1" | "tlsv1.0" | "tlsv1.1" | "tlsv1.2"
+            | "tlsv1.3" | "ssl" | "ssl-reqd" | "ssl-no-revoke" | "remote-name"
+            | "remote-name-all" | "cookie-jar" | "dump-header" | "write-out" | "cert-type"
+            | "key-type" | "pass" | "proxy-cert-type" | "proxy-key-type" | "proxy-pass"
+            | "connect-timeout" | "retry" | "retry-delay" | "retry-max-time"
+            | "retry-connrefused" | "retry-all-errors" | "limit-rate" | "interface"
+            | "max-filesize" | "trace" | "trace-ascii" | "stderr" => {
+                tracing::debug!(option = name, "Ignoring curl option");
+            }
+
+            _ => return Err(unsupported_option(&format!("--{}", name))),
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Request> {
+        let mut url = self.url.take().ok_or_else(|| RelayError::Parse {
+            message: "curl command has no URL".into(),
+            cause: None,
+        })?;
+        if !url.contains("://") {
+            url = format!("http://{}", url);
+        }
+
+        let has_body = !self.data.is_empty() || !self.form.is_empty();
+        let method = match self.method.take() {
+            Some(method) => method,
+            None if self.head => Method::HEAD,
+            None if self.get || !has_body => Method::GET,
+            None => Method::POST,
+        };
+
+        if self.json {
+            for (name, value) in [
+                ("Content-Type", "application/json"),
+                ("Accept", "application/json"),
+            ] {
+                if !self
+                    .headers
+                    .iter()
+                    .any(|(n, _)| n.eq_ignore_ascii_case(name))
+                {
+                    self.headers.push((name.into(), value.into()));
+                }
+            }
+        }
+
+        let content = if !self.form.is_empty() {
+            if !self.data.is_empty() {
+                return Err(RelayError::Parse {
+                    message: "curl can't send -F form fields together with -d data".into(),
+                    cause: None,
+                });
+            }
+            let media_type = self
+                .take_header("content-type")
+                .map(|value| media_type(&value))
+                .unwrap_or(MediaType::MultipartFormData);
+            let mut content: FormData = Vec::new();
+            for (name, value) in self.form.drain(..) {
+                match content.iter_mut().find(|(existing, _)| *existing == name) {
+                    Some((_, values)) => values.push(value),
+                    None => content.push((name, vec![value])),
+                }
+            }
+            Some(ContentType::Multipart {
+                content,
+                media_type,
+                boundary: None,
+            })
+        } else if self.get && !self.data.is_empty() {
+            let data = self.joined_data()?;
+            self.query.push(lossy(data));
+            None
+        } else if let [Data::File(_)] = self.data.as_slice() {
+            let Some(Data::File(filename)) = self.data.pop() else {
+                unreachable!()
+            };
+            let media_type = self
+                .take_header("content-type")
+                .map(|value| media_type(&value))
+                .unwrap_or(MediaType::OctetStream);
+            Some(ContentType::Binary {
+                content: Bytes::new(),
+                media_type,
+                filename: Some(filename),
+            })
+        } else if !self.data.is_empty() {
+            let data = self.joined_data()?;
+            let media_type = self
+                .take_header("content-type")
+                .map(|value| media_type(&value))
+                .unwrap_or(MediaType::FormUrlEncoded);
+            Some(body(data, media_type))
+        } else {
+            None
+        };
+
+        if !self.query.is_empty() {
+            let separator = if url.contains('?') { "&" } else { "?" };
+            url = format!("{}{}{}", url, separator, self.query.join("&"));
+        }
+
+        let auth = self.auth()?;
+        let security = self.insecure.then(|| SecurityConfig {
+            certificates: None,
+            verify_host: Some(false),
+            verify_peer: Some(false),
+        });
+        let (version, version_mode) = self
+            .version
+            .unwrap_or((Version::HTTP_11, VersionMode::Fallback));
+        if version_mode == VersionMode::Only {
+            self.options.version_mode = Some(version_mode);
+        }
+
+        let mut headers: HashMap<String, String> = HashMap::new();
+        for (name, value) in self.headers {
+            match headers
+                .iter_mut()
+                .find(|(existing, _)| existing.eq_ignore_ascii_case(&name))
+            {
+                Some((existing, joined)) => {
+                    let separator = if existing.eq_ignore_ascii_case("cookie") {
+                        "; "
+                    } else {
+                        ", "
+                    };
+                    joined.push_str(separator);
+                    joined.push_str(&value);
+                }
+                None => {
+                    headers.insert(name, value);
+                }
+            }
+        }
+
+        let options = self.options;
+        let has_options = serde_json::to_value(&options)
+            .map(|value| {
+                value
+                    .as_object()
+                    .is_some_and(|o| o.values().any(|v| !v.is_null()))
+            })
+            .unwrap_or(false);
+
+        Ok(Request {
+            id: 0,
+            url,
+            method,
+            version,
+            headers: (!headers.is_empty()).then_some(headers),
+            params: None,
+            content,
+            auth,
+            security,
+            proxy: self.proxy,
+            meta: has_options.then_some(RequestMeta {
+                options: Some(options),
+            }),
+        })
+    }
+
+    fn take_header(&mut self, name: &str) -> Option<String> {
+        let index = self
+            .headers
+            .iter()
+            .rposition(|(n, _)| n.eq_ignore_ascii_case(name))?;
+        let (_, value) = self.headers.remove(index);
+        self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        Some(value)
+    }
+
+    /// Joins `-d` pieces with `&` the way curl does, `--json` pieces
+    /// without a separator.
+    fn joined_data(&self) -> Result<Vec<u8>> {
+        let mut joined = Vec::new();
+        for (index, piece) in self.data.iter().enumerate() {
+            let Data::Bytes(bytes) = piece else {
+                return Err(RelayError::Parse {
+                    message: "Body data read from a file can't be combined with other data".into(),
+                    cause: None,
+                });
+            };
+            if index > 0 && !self.json {
+                joined.push(b'&');
+            }
+            joined.extend_from_slice(bytes);
+        }
+        Ok(joined)
+    }
+
+    fn auth(&mut self) -> Result<Option<AuthType>> {
+        let credentials = self.user.take().map(|user| match user.split_once(':') {
+            Some((username, password)) => (username.to_string(), password.to_string()),
+            None => (user, String::new()),
+        });
+
+        if let Some(ref sigv4) = self.aws_sigv4 {
+            // `aws:amz:region:service`, both parts may be left to curl to
+            // guess from the host name.
+            let mut parts = sigv4.split(':').skip(2);
+            let region = parts.next().unwrap_or_default().to_string();
+            let service = parts.next().unwrap_or_default().to_string();
+            let (access_key, secret_key) = credentials.unwrap_or_default();
+            return Ok(Some(AuthType::Aws {
+                access_key,
+                secret_key,
+                region,
+                service,
+                session_token: self.take_header("x-amz-security-token"),
+                location: ApiKeyLocation::Header,
+            }));
+        }
+
+        if let Some(token) = self.bearer.take() {
+            return Ok(Some(AuthType::Bearer { token }));
+        }
+
+        Ok(credentials.map(|(username, password)| {
+            if self.digest {
+                AuthType::Digest {
+                    username,
+                    password,
+                    realm: None,
+                    nonce: None,
+                    opaque: None,
+                    algorithm: None,
+                    qop: None,
+                    nc: None,
+                    cnonce: None,
+                }
+            } else {
+                AuthType::Basic { username, password }
+            }
+        }))
+    }
+}
+
+fn number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T> {
+    value.trim().parse().map_err(|_| RelayError::Parse {
+        message: format!("Invalid number for --{}", option),
+        cause: Some(value.to_string()),
+    })
+}
+
+fn media_type(value: &str) -> MediaType {
+    value.parse().unwrap_or(MediaType::Other(value.to_string()))
+}
+
+/// Picks the content kind from the media type, keeping bodies that
+/// wouldn't survive a round trip through `serde_json::Value` as text.
+fn body(data: Vec<u8>, media_type: MediaType) -> ContentType {
+    let text = match String::from_utf8(data) {
+        Ok(text) => text,
+        Err(e) => {
+            return ContentType::Binary {
+                content: Bytes::from(e.into_bytes()),
+                media_type,
+                filename: None,
+            }
+        }
+    };
+
+    if media_type == MediaType::FormUrlEncoded {
+        return ContentType::Urlencoded {
+            content: text,
+            media_type,
+        };
+    }
+
+    match media::classify(&media_type) {
+        MediaKind::Json => match serde_json::from_str::<serde_json::Value>(&text) {
+            Ok(json) if serde_json::to_string(&json).ok().as_deref() == Some(text.as_str()) => {
+                ContentType::Json {
+                    content: json,
+                    media_type,
+                }
+            }
+            _ => ContentType::Text {
+                content: text,
+                media_type,
+            },
+        },
+        MediaKind::Xml => ContentType::Xml {
+            content: text,
+            media_type,
+        },
+        MediaKind::Text => ContentType::Text {
+            content: text,
+            media_type,
+        },
+        MediaKind::Image | MediaKind::Binary => ContentType::Binary {
+            content: Bytes::from(text.into_bytes()),
+            media_type,
+            filename: None,
+        },
+    }
+}
+
+/// Encodes a `--data-urlencode` value: `content`, `=content` or
+/// `name=content`, of which only the content is encoded.
+fn urlencode_data(value: &str) -> Result<String> {
+    let encode = |content: &str| {
+        url::form_urlencoded::byte_serialize(content.as_bytes()).collect::<String>()
+    };
+
+    match value.split_once('=') {
+        Some(("", content)) => Ok(encode(content)),
+        Some((name, content)) => Ok(format!("{}={}", name, encode(content))),
+        None if value.contains('@') => Err(RelayError::Parse {
+            message: "--data-urlencode can't read files here".into(),
+            cause: Some(value.to_string()),
+        }),
+        None => Ok(encode(value)),
+    }
+}
+
+fn invalid_form(field: &str) -> RelayError {
+    RelayError::Parse {
+        message: "Invalid form field, expected name=value".into(),
+        cause: Some(field.to_string()),
+    }
+}
+
+/// Parses `-F name=value`, `name=@file` and `name="quoted"` with
+/// `;type=`, `;filename=` and `;headers=` parameters.
+fn parse_form(field: &str) -> Result<(String, FormValue)> {
+    let (name, rest) = field.split_once('=').ok_or_else(|| invalid_form(field))?;
+
+    if rest.starts_with('<') {
+        return Err(RelayError::Parse {
+            message: "Form fields read from files (name=<file) aren't supported".into(),
+            cause: Some(field.to_string()),
+        });
+    }
+
+    let (file, rest) = match rest.strip_prefix('@') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let (value, mut params) = form_token(rest);
+
+    let mut content_type = None;
+    let mut filename = None;
+    let mut headers = HashMap::new();
+    while let Some(param) = params.strip_prefix(';') {
+        let (key, rest) = param.split_once('=').unwrap_or((param, ""));
+        let (param_value, rest) = form_token(rest);
+        match key.trim() {
+            "type" => content_type = Some(param_value),
+            "filename" => filename = Some(param_value),
+            "headers" => {
+                if let Some((name, value)) = param_value.split_once(':') {
+                    headers.insert(name.trim().to_string(), value.trim().to_string());
+                }
+            }
+            _ => {}
+        }
+        params = rest;
+    }
+    let headers = (!headers.is_empty()).then_some(headers);
+
+    let value = if file {
+        FormValue::File {
+            filename: filename.unwrap_or(value),
+            content_type: content_type
+                .map(|value| media_type(&value))
+                .unwrap_or(MediaType::OctetStream),
+            data: Bytes::new(),
+            headers,
+        }
+    } else {
+        FormValue::Text {
+            value,
+            content_type,
+            headers,
+        }
+    };
+
+    Ok((name.to_string(), value))
+}
+
+/// Reads a `-F` value or parameter up to the next `;`, unquoting it if it
+/// is in double quotes.
+fn form_token(input: &str) -> (String, &str) {
+    let Some(quoted) = input.strip_prefix('"') else {
+        let end = input.find(';').unwrap_or(input.len());
+        return (input[..end].to_string(), &input[end..]);
+    };
+
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    value.push(escaped);
+                }
+            }
+            '"' => {
+                let rest = &quoted[index + 1..];
+                let end = rest.find(';').unwrap_or(rest.len());
+                return (value, &rest[end..]);
+            }
+            c => value.push(c),
+        }
+    }
+    (value, "")
+}
+
+/// Splits an address list on commas outside of IPv6 brackets.
+fn parse_resolve(value: &str) -> Result<ResolveEntry> {
+    let invalid = || RelayError::Parse {
+        message: "Invalid --resolve entry, expected host:port:address".into(),
+        cause: Some(value.to_string()),
+    };
+
+    let (host, rest) = value
+        .trim_start_matches('+')
+        .split_once(':')
+        .ok_or_else(invalid)?;
+    let (port, addresses) = rest.split_once(':').ok_or_else(invalid)?;
+    let port = port.parse().map_err(|_| invalid())?;
+    let addresses = addresses
+        .split(',')
+        .map(|address| {
+            address
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string()
+        })
+        .filter(|address| !address.is_empty())
+        .collect();
+
+    Ok(ResolveEntry {
+        host: host.to_string(),
+        port,
+        addresses,
+    })
+}
+
+fn parse_connect_to(value: &str) -> Result<ConnectToEntry> {
+    let invalid = || RelayError::Parse {
+        message: "Invalid --connect-to entry, expected host:port:connect-host:connect-port".into(),
+        cause: Some(value.to_string()),
+    };
+
+    // NOTE: IPv6 hosts are bracketed, so colons inside brackets don't split.
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut bracketed = false;
+    for c in value.chars() {
+        match c {
+            '[' => bracketed = true,
+            ']' => bracketed = false,
+            ':' if !bracketed => {
+                parts.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    parts.push(current);
+
+    let [host, port, connect_host, connect_port] = parts.as_slice() else {
+        return Err(invalid());
+    };
+    let text = |value: &String| (!value.is_empty()).then(|| value.clone());
+    let parse_port = |value: &String| -> Result<Option<u16>> {
+        if value.is_empty() {
+            return Ok(None);
+        }
+        value.parse().map(Some).map_err(|_| invalid())
+    };
+
+    Ok(ConnectToEntry {
+        host: text(host),
+        port: parse_port(port)?,
+        connect_host: text(connect_host),
+        connect_port: parse_port(connect_port)?,
+    })
+}
+
+/// Splits a command line into words following POSIX shell quoting, plus
+/// bash's `$'...'`. Words are bytes since `\xHH` escapes needn't be UTF-8.
+fn split_words(command: &str) -> Result<Vec<Vec<u8>>> {
+    let unterminated = |quote: &str| RelayError::Parse {
+        message: format!("Unterminated {} quote in curl command", quote),
+        cause: None,
+    };
+
+    let mut words = Vec::new();
+    let mut word: Option<Vec<u8>> = None;
+    let mut chars = command.chars().peekable();
+    let push = |word: &mut Option<Vec<u8>>, c: char| {
+        let mut buffer = [0; 4];
+        word.get_or_insert_with(Vec::new)
+            .extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            }
+            '\\' => match chars.next() {
+                // Line continuation.
+                Some('\n') => {}
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                }
+                Some(c) => push(&mut word, c),
+                None => {}
+            },
+            '\'' => {
+                word.get_or_insert_with(Vec::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => push(&mut word, c),
+                        None => return Err(unterminated("single")),
+                    }
+                }
+            }
+            '"' => {
+                word.get_or_insert_with(Vec::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('\n') => {}
+                            Some(c @ ('$' | '`' | '"' | '\\')) => push(&mut word, c),
+                            Some(c) => {
+                                push(&mut word, '\\');
+                                push(&mut word, c);
+                            }
+                            None => return Err(unterminated("double")),
+                        },
+                        Some(c) => push(&mut word, c),
+                        None => return Err(unterminated("double")),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                let bytes = word.get_or_insert_with(Vec::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some('\\') => {
+                            let Some(escape) = chars.next() else {
+                                return Err(unterminated("$'"));
+                            };
+                            match escape {
+                                'n' => bytes.push(b'\n'),
+                                't' => bytes.push(b'\t'),
+                                'r' => bytes.push(b'\r'),
+                                'a' => bytes.push(0x07),
+                                'b' => bytes.push(0x08),
+                                'e' | 'E' => bytes.push(0x1b),
+                                'f' => bytes.push(0x0c),
+                                'v' => bytes.push(0x0b),
+                                'x' => {
+                                    let mut digits = String::new();
+                                    while digits.len() < 2
+                                        && chars.peek().is_some_and(|c| c.is_ascii_hexdigit())
+                                    {
+                                        digits.extend(chars.next());
+                                    }
+                                    bytes.push(u8::from_str_radix(&digits, 16).unwrap_or(b'x'));
+                                }
+                                '0'..='7' => {
+                                    let mut digits = escape.to_string();
+                                    while digits.len() < 3
+                                        && chars.peek().is_some_and(|c| ('0'..='7').contains(c))
+                                    {
+                                        digits.extend(chars.next());
+                                    }
+                                    bytes.push(u8::from_str_radix(&digits, 8).unwrap_or(0));
+                                }
+                                c => {
+                                    let mut buffer = [0; 4];
+                                    bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                                }
+                            }
+                        }
+                        Some(c) => {
+                            let mut buffer = [0; 4];
+                            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                        }
+                        None => return Err(unterminated("$'")),
+                    }
+                }
+            }
+            c => push(&mut word, c),
+        }
+    }
+
+    if let Some(word) = word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exports, parses the result and exports again, which must not
+    /// change anything.
+    fn assert_round_trip(request: &Request) -> String {
+        let command = export(request);
+        let parsed = parse(&command).unwrap();
+        assert_eq!(export(&parsed), command, "round trip changed the command");
+        command
+    }
+
+    #[test]
+    fn exports_a_plain_get() {
+        let command = assert_round_trip(&Request::test(Method::GET, "https://example.com/users"));
+        assert_eq!(command, "curl https://example.com/users");
+    }
+
+    #[test]
+    fn exports_json_with_params_headers_and_auth() {
+        let mut request = Request::test(Method::POST, "https://api.example.com/v1/items?draft=1");
+        request.version = Version::HTTP_2;
+        request.params = Some(HashMap::from([
+            ("tag".into(), "a b".into()),
+            ("limit".into(), "10".into()),
+        ]));
+        request.headers = Some(HashMap::from([
+            ("X-Trace".into(), "it's".into()),
+            ("accept".into(), "application/json".into()),
+        ]));
+        request.content = Some(ContentType::Json {
+            content: serde_json::json!({ "name": "widget", "tags": ["x"] }),
+            media_type: MediaType::Json,
+        });
+        request.auth = Some(AuthType::Basic {
+            username: "alice".into(),
+            password: "s3cret:pass".into(),
+        });
+        request.security = Some(SecurityConfig {
+            certificates: None,
+            verify_host: None,
+            verify_peer: Some(false),
+        });
+        request.meta = Some(RequestMeta {
+            options: Some(RequestOptions {
+                follow_redirects: Some(true),
+                timeout: Some(2500),
+                ..Default::default()
+            }),
+        });
+
+        let command = assert_round_trip(&request);
+        assert_eq!(
+            command,
+            "curl 'https://api.example.com/v1/items?draft=1&limit=10&tag=a+b' \\
+  --http2 \\
+  -u alice:s3cret:pass \\
+  -H 'accept: application/json' \\
+  -H 'Content-Type: application/json' \\
+  -H 'X-Trace: it'\\''s' \\
+  --data-raw '{\"name\":\"widget\",\"tags\":[\"x\"]}' \\
+  -k \\
+  -L \\
+  --max-time 2.5"
+        );
+    }
+
+    #[test]
+    fn exports_multipart_and_files() {
+        let mut request = Request::test(Method::PUT, "https://example.com/upload");
+        request.content = Some(ContentType::Multipart {
+            content: vec![
+                (
+                    "note".into(),
+                    vec![FormValue::Text {
+                        value: "a;b".into(),
+                        content_type: None,
+                        headers: None,
+                    }],
+                ),
+                (
+                    "meta".into(),
+                    vec![FormValue::Text {
+                        value: "{\"a\":1}".into(),
+                        content_type: Some("application/json".into()),
+                        headers: Some(HashMap::from([("X-Part".into(), "1".into())])),
+                    }],
+                ),
+                (
+                    "file".into(),
+                    vec![FormValue::File {
+                        filename: "photo.png".into(),
+                        content_type: MediaType::ImagePng,
+                        data: Bytes::new(),
+                        headers: None,
+                    }],
+                ),
+            ],
+            media_type: MediaType::MultipartFormData,
+            boundary: None,
+        });
+
+        let command = assert_round_trip(&request);
+        assert_eq!(
+            command,
+            "curl -X PUT https://example.com/upload \\
+  --form-string 'note=a;b' \\
+  -F 'meta=\"{\\\"a\\\":1}\";type=application/json;headers=\"X-Part: 1\"' \\
+  -F 'file=@photo.png;type=image/png'"
+        );
+    }
+
+    #[test]
+    fn exports_binary_bodies() {
+        let mut request = Request::test(Method::POST, "https://example.com/blob");
+        request.content = Some(ContentType::Binary {
+            content: Bytes::from_static(b"\x00\x01'"),
+            media_type: MediaType::OctetStream,
+            filename: None,
+        });
+        let command = assert_round_trip(&request);
+        assert!(command.ends_with("--data-binary @body.bin"));
+
+        request.content = Some(ContentType::Binary {
+            content: Bytes::from_static(b"@passwd\x01"),
+            media_type: MediaType::OctetStream,
+            filename: None,
+        });
+        let command = assert_round_trip(&request);
+        assert!(command.ends_with("--data-raw $'@passwd\\x01'"));
+        assert!(matches!(
+            parse(&command).unwrap().content,
+            Some(ContentType::Binary { content, filename: None, .. }) if content == "@passwd\x01"
+        ));
+
+        request.content = Some(ContentType::Binary {
+            content: Bytes::new(),
+            media_type: MediaType::ApplicationPdf,
+            filename: Some("report.pdf".into()),
+        });
+        let command = assert_round_trip(&request);
+        assert_eq!(
+            command,
+            "curl https://example.com/blob \\
+  -H 'Content-Type: application/pdf' \\
+  --data-binary @report.pdf"
+        );
+    }
+
+    #[test]
+    fn exports_proxy_certificates_and_options() {
+        let mut request = Request::test(Method::HEAD, "https://example.com");
+        request.version = Version::HTTP_2;
+        request.auth = Some(AuthType::Bearer {
+            token: "abc".into(),
+        });
+        request.proxy = Some(ProxyConfig {
+            url: Some("socks5://proxy:1080".into()),
+            auth: Some(ProxyAuth {
+                username: "p".into(),
+                password: "q".into(),
+                scheme: Some(ProxyAuthScheme::Digest),
+            }),
+            no_proxy: Some(vec!["localhost".into(), ".internal".into()]),
+            from_env: None,
+            pac: None,
+            remote_dns: Some(true),
+            security: Some(SecurityConfig {
+                certificates: Some(CertificateConfig {
+                    client: Some(CertificateType::Pfx {
+                        data: Bytes::new(),
+                        password: String::new(),
+                    }),
+                    ca: None,
+                }),
+                verify_host: None,
+                verify_peer: None,
+            }),
+        });
+        request.security = Some(SecurityConfig {
+            certificates: Some(CertificateConfig {
+                client: Some(CertificateType::Pfx {
+                    data: Bytes::new(),
+                    password: "pw".into(),
+                }),
+                ca: Some(vec![Bytes::new()]),
+            }),
+            verify_host: None,
+            verify_peer: None,
+        });
+        request.meta = Some(RequestMeta {
+            options: Some(RequestOptions {
+                version_mode: Some(VersionMode::Only),
+                resolve: Some(vec![ResolveEntry {
+                    host: "example.com".into(),
+                    port: 443,
+                    addresses: vec!["127.0.0.1".into(), "::1".into()],
+                }]),
+                connect_to: Some(vec![ConnectToEntry {
+                    host: None,
+                    port: None,
+                    connect_host: Some("backend".into()),
+                    connect_port: Some(8443),
+                }]),
+                ..Default::default()
+            }),
+        });
+
+        let command = export(&request);
+        assert_eq!(
+            command,
+            "curl -I https://example.com \\
+  --http2-prior-knowledge \\
+  --oauth2-bearer abc \\
+  -x socks5h://proxy:1080 \\
+  -U p:q \\
+  --proxy-digest \\
+  --noproxy localhost,.internal \\
+  --proxy-cert-type P12 \\
+  --proxy-cert proxy-client.p12 \\
+  --cert-type P12 \\
+  --cert client.p12:pw \\
+  --cacert ca.pem \\
+  --resolve 'example.com:443:127.0.0.1,[::1]' \\
+  --connect-to ::backend:8443"
+        );
+        assert!(matches!(
+            parse(&command),
+            Err(RelayError::UnsupportedFeature { feature, .. }) if feature == "proxy-cert"
+        ));
+    }
+
+    #[test]
+    fn parses_browser_copies() {
+        let request = parse(
+            r#"curl 'https://api.example.com/graphql' \
+  -H 'accept: */*' \
+  -H 'content-type: application/json' \
+  -b 'session=abc; theme=dark' \
+  -H $'x-note: it\'s' \
+  --data-raw '{"query":"{ me { id } }"}' \
+  --compressed"#,
+        )
+        .unwrap();
+
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.url, "https://api.example.com/graphql");
+        let headers = request.headers.unwrap();
+        assert_eq!(headers["accept"], "*/*");
+        assert_eq!(headers["Cookie"], "session=abc; theme=dark");
+        assert_eq!(headers["x-note"], "it's");
+        assert!(!headers.contains_key("content-type"));
+        assert!(matches!(
+            request.content,
+            Some(ContentType::Json { ref content, .. }) if content["query"] == "{ me { id } }"
+        ));
+        assert_eq!(
+            request.meta.unwrap().options.unwrap().decompress,
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn parses_short_options_and_data() {
+        let request = parse(
+            "curl -sSL -XPATCH -HAccept:text/plain -d a=1 -d 'b=2 3' --data-urlencode 'c=x y' example.com:8080/path -m 3 -uuser",
+        )
+        .unwrap();
+
+        assert_eq!(request.method, Method::PATCH);
+        assert_eq!(request.url, "http://example.com:8080/path");
+        assert_eq!(request.headers.unwrap()["Accept"], "text/plain");
+        assert!(matches!(
+            request.content,
+            Some(ContentType::Urlencoded { ref content, .. }) if content == "a=1&b=2 3&c=x+y"
+        ));
+        assert!(matches!(
+            request.auth,
+            Some(AuthType::Basic { ref username, ref password }) if username == "user" && password.is_empty()
+        ));
+        let options = request.meta.unwrap().options.unwrap();
+        assert_eq!(options.follow_redirects, Some(true));
+        assert_eq!(options.timeout, Some(3000));
+    }
+
+    #[test]
+    fn parses_get_data_into_the_query() {
+        let request =
+            parse("curl -G https://example.com/search?x=1 -d q=rust --url-query 'page=2 3'")
+                .unwrap();
+        assert_eq!(request.method, Method::GET);
+        assert_eq!(
+            request.url,
+            "https://example.com/search?x=1&page=2+3&q=rust"
+        );
+        assert!(request.content.is_none());
+    }
+
+    #[test]
+    fn parses_json_option_and_aws() {
+        let request = parse(
+            "curl --json '{\"a\": 1}' https://s3.amazonaws.com/bucket --aws-sigv4 aws:amz:us-east-1:s3 -u AK:SK -H 'x-amz-security-token: tok'",
+        )
+        .unwrap();
+
+        // Not compact JSON, so kept as written.
+        assert!(matches!(
+            request.content,
+            Some(ContentType::Text { ref content, ref media_type }) if content == "{\"a\": 1}" && *media_type == MediaType::Json
+        ));
+        assert_eq!(request.headers.unwrap()["Accept"], "application/json");
+        assert!(matches!(
+            request.auth,
+            Some(AuthType::Aws { ref region, ref service, ref session_token, .. })
+                if region == "us-east-1" && service == "s3" && session_token.as_deref() == Some("tok")
+        ));
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_represent() {
+        assert!(parse("wget https://example.com").is_err());
+        assert!(parse("curl").is_err());
+        assert!(parse("curl 'https://example.com").is_err());
+        assert!(parse("curl -T file.bin https://example.com").is_err());
+        assert!(parse("curl -E client.pem https://example.com").is_err());
+        assert!(parse("curl --frobnicate https://example.com").is_err());
+        assert!(parse("curl -F a=1 -d b=2 https://example.com").is_err());
+        assert!(parse("curl -H").is_err());
+    }
+
+    #[test]
+    fn splits_shell_words() {
+        let words: Vec<String> =
+            split_words("a 'b c' \"d \\\"e\\\" \\n\" $'\\x41\\n\\'' f\\ g \\\n h")
+                .unwrap()
+                .into_iter()
+                .map(lossy)
+                .collect();
+        assert_eq!(words, ["a", "b c", "d \"e\" \\n", "A\n'", "f g", "h"]);
+    }
+}
@@ -0,0 +1,192 @@
+use std::sync::RwLock;
+
+use curl::easy::{Easy, List};
+
+use crate::{
+    error::{RelayError, Result},
+    interop::{ConnectToEntry, HostOverrides, RequestOptions, ResolveEntry},
+};
+
+lazy_static::lazy_static! {
+    /// Overrides shared by every request, set by the host app (the agent or
+    /// the desktop app) from its settings.
+    static ref GLOBAL_OVERRIDES: RwLock<HostOverrides> = RwLock::new(HostOverrides::default());
+}
+
+/// Replaces the global override table.
+pub fn set_host_overrides(overrides: HostOverrides) {
+    tracing::info!(
+        resolve = overrides.resolve.as_ref().map_or(0, Vec::len),
+        connect_to = overrides.connect_to.as_ref().map_or(0, Vec::len),
+        "Updating global host overrides"
+    );
+
+    match GLOBAL_OVERRIDES.write() {
+        Ok(mut guard) => *guard = overrides,
+        Err(poisoned) => *poisoned.into_inner() = overrides,
+    }
+}
+
+/// Returns a copy of the global override table.
+pub fn host_overrides() -> HostOverrides {
+    match GLOBAL_OVERRIDES.read() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+pub(crate) struct DnsHandler<'a> {
+    handle: &'a mut Easy,
+}
+
+impl<'a> DnsHandler<'a> {
+    pub(crate) fn new(handle: &'a mut Easy) -> Self {
+        Self { handle }
+    }
+
+    /// Applies the request's `resolve` and `connect_to` entries followed by
+    /// the global ones. Request entries come first so they win: curl uses
+    /// the first matching `connect_to` entry, and global `resolve` entries
+    /// for a `host:port` the request already covers are dropped.
+    #[tracing::instrument(skip(self, options), level = "debug")]
+    pub(crate) fn configure(&mut self, options: Option<&RequestOptions>) -> Result<()> {
+        let global = host_overrides();
+
+        let request_resolve = options
+            .and_then(|o| o.resolve.as_deref())
+            .unwrap_or_default();
+        let global_resolve = global.resolve.as_deref().unwrap_or_default();
+
+        let resolve: Vec<&ResolveEntry> = request_resolve
+            .iter()
+            .chain(global_resolve.iter().filter(|entry| {
+                !request_resolve
+                    .iter()
+                    .any(|r| r.host.eq_ignore_ascii_case(&entry.host) && r.port == entry.port)
+            }))
+            .collect();
+
+        if !resolve.is_empty() {
+            let list = Self::build_list(resolve.iter().map(|entry| resolve_line(entry)))?;
+            tracing::debug!(count = resolve.len(), "Setting resolve overrides");
+            self.handle.resolve(list).map_err(|e| {
+                tracing::error!(error = %e, "Failed to set resolve overrides");
+                RelayError::Network {
+                    message: "Failed to set resolve overrides".into(),
+                    cause: Some(e.to_string()),
+                }
+            })?;
+        }
+
+        let connect_to: Vec<&ConnectToEntry> = options
+            .and_then(|o| o.connect_to.as_deref())
+            .unwrap_or_default()
+            .iter()
+            .chain(global.connect_to.as_deref().unwrap_or_default())
+            .collect();
+
+        if !connect_to.is_empty() {
+            let list = Self::build_list(connect_to.iter().map(|entry| connect_to_line(entry)))?;
+            tracing::debug!(count = connect_to.len(), "Setting connect-to overrides");
+            self.handle.connect_to(list).map_err(|e| {
+                tracing::error!(error = %e, "Failed to set connect-to overrides");
+                RelayError::Network {
+                    message: "Failed to set connect-to overrides".into(),
+                    cause: Some(e.to_string()),
+                }
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn build_list(mut lines: impl Iterator<Item = String>) -> Result<List> {
+        lines.try_fold(List::new(), |mut list, line| {
+            tracing::trace!(line = %line, "Adding host override");
+            list.append(&line).map_err(|e| RelayError::Network {
+                message: format!("Failed to add host override: {}", line),
+                cause: Some(e.to_string()),
+            })?;
+            Ok(list)
+        })
+    }
+}
+
+/// `HOST:PORT:ADDRESS[,ADDRESS]...`, see `CURLOPT_RESOLVE`.
+fn resolve_line(entry: &ResolveEntry) -> String {
+    let addresses = entry
+        .addresses
+        .iter()
+        .map(|address| bracket_ipv6(address))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!("{}:{}:{}", entry.host, entry.port, addresses)
+}
+
+/// `HOST:PORT:CONNECT-TO-HOST:CONNECT-TO-PORT`, see `CURLOPT_CONNECT_TO`.
+/// Empty fields match any host or port, or keep the original one.
+fn connect_to_line(entry: &ConnectToEntry) -> String {
+    let port = |port: Option<u16>| port.map(|p| p.to_string()).unwrap_or_default();
+
+    format!(
+        "{}:{}:{}:{}",
+        entry.host.as_deref().map(bracket_ipv6).unwrap_or_default(),
+        port(entry.port),
+        entry
+            .connect_host
+            .as_deref()
+            .map(bracket_ipv6)
+            .unwrap_or_default(),
+        port(entry.connect_port),
+    )
+}
+
+fn bracket_ipv6(address: &str) -> String {
+    if address.contains(':') && !address.starts_with('[') {
+        format!("[{}]", address)
+    } else {
+        address.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_line() {
+        let entry = ResolveEntry {
+            host: "api.example.com".into(),
+            port: 443,
+            addresses: vec!["203.0.113.10".into(), "2001:db8::1".into()],
+        };
+
+        assert_eq!(
+            resolve_line(&entry),
+            "api.example.com:443:203.0.113.10,[2001:db8::1]"
+        );
+    }
+
+    #[test]
+    fn test_connect_to_line() {
+        let entry = ConnectToEntry {
+            host: Some("api.example.com".into()),
+            port: Some(443),
+            connect_host: Some("staging.internal".into()),
+            connect_port: None,
+        };
+        assert_eq!(
+            connect_to_line(&entry),
+            "api.example.com:443:staging.internal:"
+        );
+
+        let any = ConnectToEntry {
+            host: None,
+            port: None,
+            connect_host: Some("::1".into()),
+            connect_port: Some(8443),
+        };
+        assert_eq!(connect_to_line(&any), "::[::1]:8443");
+    }
+}
@@ -38,8 +38,58 @@ pub enum RelayError {
         cause: Option<String>,
     },
 
+    #[error("Proxy error: {message}")]
+    Proxy {
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cause: Option<String>,
+    },
+
+    #[error("Proxy authentication failed: {message}")]
+    ProxyAuth {
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cause: Option<String>,
+    },
+
+    #[error("Response body too large: {message}")]
+    BodyTooLarge { message: String, limit: u64 },
+
     #[error("Request aborted: {message}")]
     Abort { message: String },
+
+    #[error("No recorded response: {message}")]
+    CassetteMiss {
+        message: String,
+        method: String,
+        url: String,
+    },
+
+    #[error("Invalid batch: {message}")]
+    InvalidBatch { message: String },
+
+    #[error("Invalid load test: {message}")]
+    InvalidLoadTest { message: String },
+}
+
+impl RelayError {
+    /// The `kind` tag the error serializes with.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RelayError::UnsupportedFeature { .. } => "unsupported_feature",
+            RelayError::Network { .. } => "network",
+            RelayError::Timeout { .. } => "timeout",
+            RelayError::Certificate { .. } => "certificate",
+            RelayError::Parse { .. } => "parse",
+            RelayError::Proxy { .. } => "proxy",
+            RelayError::ProxyAuth { .. } => "proxy_auth",
+            RelayError::BodyTooLarge { .. } => "body_too_large",
+            RelayError::Abort { .. } => "abort",
+            RelayError::CassetteMiss { .. } => "cassette_miss",
+            RelayError::InvalidBatch { .. } => "invalid_batch",
+            RelayError::InvalidLoadTest { .. } => "invalid_load_test",
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
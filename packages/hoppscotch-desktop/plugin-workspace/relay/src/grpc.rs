@@ -0,0 +1,886 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Read,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use curl::{
+    easy::{Easy, ReadError},
+    multi::Multi,
+};
+use dashmap::DashMap;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use http::{Method, Version};
+use prost::Message;
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, MessageDescriptor, MethodDescriptor, SerializeOptions,
+};
+
+use crate::{
+    error::{RelayError, Result},
+    interop::{
+        DescriptorSource, GrpcMethod, GrpcRequest, GrpcResponse, GrpcService, GrpcStatus, Request,
+        RequestMeta, VersionMode,
+    },
+    proxy, reflection,
+    relay::ACTIVE_REQUESTS,
+    request::CurlRequest,
+};
+
+/// How long a transfer waits before the outgoing queue is checked again.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Compressed flag and big-endian length in front of every message.
+const FRAME_HEADER_SIZE: usize = 5;
+
+// NOTE: The status codes the relay reports itself, see
+// https://grpc.github.io/grpc/core/md_doc_statuscodes.html
+const CANCELLED: u32 = 1;
+const UNKNOWN: u32 = 2;
+const DEADLINE_EXCEEDED: u32 = 4;
+const PERMISSION_DENIED: u32 = 7;
+const UNIMPLEMENTED: u32 = 12;
+const INTERNAL: u32 = 13;
+const UNAVAILABLE: u32 = 14;
+const UNAUTHENTICATED: u32 = 16;
+
+struct Session {
+    input: MessageDescriptor,
+    /// `None` half-closes the request stream.
+    sender: Sender<Option<Bytes>>,
+}
+
+lazy_static::lazy_static! {
+    static ref SESSIONS: DashMap<i64, Session> = DashMap::new();
+}
+
+/// Lists the services of `request.descriptors`, only `request.service`
+/// when set.
+#[tracing::instrument(skip(request), fields(request_id = request.id), level = "debug")]
+pub fn describe(request: &GrpcRequest) -> Result<Vec<GrpcService>> {
+    let pool = load_descriptors(request)?;
+
+    Ok(pool
+        .services()
+        .filter(|service| request.service.is_empty() || service.full_name() == request.service)
+        .map(|service| GrpcService {
+            name: service.full_name().to_string(),
+            methods: service
+                .methods()
+                .map(|method| GrpcMethod {
+                    name: method.name().to_string(),
+                    input_type: method.input().full_name().to_string(),
+                    output_type: method.output().full_name().to_string(),
+                    client_streaming: method.is_client_streaming(),
+                    server_streaming: method.is_server_streaming(),
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Calls `request.method` with the request's metadata, auth, security and
+/// proxy settings, passing every response message to `on_message` as it
+/// arrives. Returning `false` cancels the call, as does `relay::cancel`,
+/// both ending with a `CANCELLED` status.
+///
+/// Blocks until the call ends. A non-OK status from the server is part of
+/// the response, errors are for calls that couldn't be made or understood.
+#[tracing::instrument(skip(request, on_message), fields(request_id = request.id), level = "debug")]
+pub fn call<F>(request: GrpcRequest, mut on_message: F) -> Result<GrpcResponse>
+where
+    F: FnMut(&serde_json::Value) -> bool,
+{
+    tracing::info!(
+        url = %request.url,
+        service = %request.service,
+        method = %request.method,
+        "Starting gRPC call"
+    );
+
+    let pool = load_descriptors(&request)?;
+    let method = find_method(&pool, &request.service, &request.method)?;
+
+    if !method.is_client_streaming() && (request.messages.len() != 1 || request.keep_open) {
+        return Err(RelayError::Parse {
+            message: format!("{} takes exactly one request message", method.full_name()),
+            cause: None,
+        });
+    }
+
+    let input = method.input();
+    let output = method.output();
+    let frames = request
+        .messages
+        .iter()
+        .map(|message| encode_message(&input, message))
+        .collect::<Result<Vec<_>>>()?;
+
+    let id = request.id;
+    let (sender, receiver) = mpsc::channel();
+    if request.keep_open {
+        SESSIONS.insert(id, Session { input, sender });
+    } else {
+        drop(sender);
+    }
+    let cancelled = Arc::new(AtomicBool::new(false));
+    ACTIVE_REQUESTS.insert(id, Arc::clone(&cancelled));
+
+    let path = format!("/{}/{}", method.parent_service().full_name(), method.name());
+    let mut messages = Vec::new();
+    let result = exchange(&request, &path, frames, receiver, &cancelled, |frame| {
+        let message = decode_message(&output, frame)?;
+        let keep_going = on_message(&message);
+        messages.push(message);
+        Ok(keep_going)
+    });
+
+    SESSIONS.remove(&id);
+    ACTIVE_REQUESTS.remove(&id);
+
+    let exchange = result?;
+    tracing::info!(
+        code = exchange.status.code,
+        messages = messages.len(),
+        "gRPC call completed"
+    );
+
+    Ok(GrpcResponse {
+        id,
+        status: exchange.status,
+        headers: exchange.headers,
+        trailers: exchange.trailers,
+        messages,
+    })
+}
+
+/// Sends one more message on a call opened with `keep_open`.
+#[tracing::instrument(skip(message), level = "debug")]
+pub fn send(id: i64, message: serde_json::Value) -> Result<()> {
+    let Some(session) = SESSIONS.get(&id) else {
+        tracing::warn!("gRPC call not found");
+        return Err(RelayError::Network {
+            message: "gRPC call not found or not open for sending".into(),
+            cause: None,
+        });
+    };
+
+    let frame = encode_message(&session.input, &message)?;
+    session
+        .sender
+        .send(Some(frame))
+        .map_err(|_| RelayError::Network {
+            message: "gRPC call already ended".into(),
+            cause: None,
+        })
+}
+
+/// Ends the request stream of a call opened with `keep_open`, the response
+/// keeps streaming until the server ends it.
+#[tracing::instrument(level = "debug")]
+pub fn close_send(id: i64) -> Result<()> {
+    let Some((_, session)) = SESSIONS.remove(&id) else {
+        tracing::warn!("gRPC call not found");
+        return Err(RelayError::Network {
+            message: "gRPC call not found or not open for sending".into(),
+            cause: None,
+        });
+    };
+
+    let _ = session.sender.send(None);
+    Ok(())
+}
+
+fn load_descriptors(request: &GrpcRequest) -> Result<DescriptorPool> {
+    match &request.descriptors {
+        DescriptorSource::Reflection => reflection::load(request),
+        DescriptorSource::FileDescriptorSet { data } => DescriptorPool::decode(data.as_ref())
+            .map_err(|e| {
+                tracing::error!(error = %e, "Failed to load FileDescriptorSet");
+                RelayError::Parse {
+                    message: "Invalid FileDescriptorSet".into(),
+                    cause: Some(e.to_string()),
+                }
+            }),
+    }
+}
+
+fn find_method(pool: &DescriptorPool, service: &str, method: &str) -> Result<MethodDescriptor> {
+    pool.get_service_by_name(service)
+        .and_then(|service| service.methods().find(|m| m.name() == method))
+        .ok_or_else(|| {
+            tracing::error!(service, method, "gRPC method not found");
+            RelayError::Parse {
+                message: format!("Method {}/{} not found in the descriptors", service, method),
+                cause: None,
+            }
+        })
+}
+
+fn encode_message(input: &MessageDescriptor, message: &serde_json::Value) -> Result<Bytes> {
+    let message = DynamicMessage::deserialize(input.clone(), message).map_err(|e| {
+        tracing::error!(error = %e, message_type = input.full_name(), "Invalid request message");
+        RelayError::Parse {
+            message: format!("Invalid {} message", input.full_name()),
+            cause: Some(e.to_string()),
+        }
+    })?;
+
+    Ok(frame(&message.encode_to_vec()))
+}
+
+fn decode_message(output: &MessageDescriptor, data: Bytes) -> Result<serde_json::Value> {
+    let parse_error = |e: &dyn std::fmt::Display| RelayError::Parse {
+        message: format!("Invalid {} message", output.full_name()),
+        cause: Some(e.to_string()),
+    };
+
+    let message = DynamicMessage::decode(output.clone(), data).map_err(|e| parse_error(&e))?;
+    message
+        .serialize_with_options(
+            serde_json::value::Serializer,
+            &SerializeOptions::new().skip_default_fields(false),
+        )
+        .map_err(|e| parse_error(&e))
+}
+
+/// Prefixes an encoded message with its length.
+pub(crate) fn frame(message: &[u8]) -> Bytes {
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + message.len());
+    frame.push(0);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    Bytes::from(frame)
+}
+
+/// Splits whole messages off the front of `buffer`, decompressing them
+/// with the response's `grpc-encoding`.
+fn take_messages(buffer: &mut Vec<u8>, encoding: Option<&str>) -> Result<Vec<Bytes>> {
+    let mut messages = Vec::new();
+    let mut offset = 0;
+
+    while buffer.len() - offset >= FRAME_HEADER_SIZE {
+        let header = &buffer[offset..offset + FRAME_HEADER_SIZE];
+        let compressed = header[0] == 1;
+        let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        let start = offset + FRAME_HEADER_SIZE;
+        if buffer.len() - start < len {
+            break;
+        }
+
+        let data = &buffer[start..start + len];
+        messages.push(if compressed {
+            decompress(data, encoding)?
+        } else {
+            Bytes::copy_from_slice(data)
+        });
+        offset = start + len;
+    }
+
+    buffer.drain(..offset);
+    Ok(messages)
+}
+
+fn decompress(data: &[u8], encoding: Option<&str>) -> Result<Bytes> {
+    let mut decompressed = Vec::new();
+    let result = match encoding {
+        Some("gzip") => GzDecoder::new(data).read_to_end(&mut decompressed),
+        Some("deflate") => ZlibDecoder::new(data).read_to_end(&mut decompressed),
+        _ => {
+            return Err(RelayError::UnsupportedFeature {
+                feature: format!("grpc-encoding {}", encoding.unwrap_or("unset")),
+                message: "Only gzip and deflate compressed messages are supported".into(),
+                relay: "curl".into(),
+            })
+        }
+    };
+
+    result.map(|_| Bytes::from(decompressed)).map_err(|e| {
+        tracing::error!(error = %e, "Failed to decompress gRPC message");
+        RelayError::Parse {
+            message: "Failed to decompress gRPC message".into(),
+            cause: Some(e.to_string()),
+        }
+    })
+}
+
+/// What `exchange` learned about a call besides its messages.
+pub(crate) struct Exchange {
+    pub(crate) status: GrpcStatus,
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) trailers: HashMap<String, String>,
+}
+
+#[derive(Default)]
+struct Shared {
+    outgoing: VecDeque<u8>,
+    /// No more frames will be queued, the request stream ends once
+    /// `outgoing` is drained.
+    finished: bool,
+    paused: bool,
+    incoming: Vec<u8>,
+    headers: HashMap<String, String>,
+    trailers: HashMap<String, String>,
+    in_trailers: bool,
+}
+
+/// Runs one call on `path` with already framed messages, queueing frames
+/// from `more` until it sends `None` or is dropped. Each response message is
+/// passed to `on_message`, which returns `false` to cancel.
+pub(crate) fn exchange<F>(
+    request: &GrpcRequest,
+    path: &str,
+    frames: Vec<Bytes>,
+    more: Receiver<Option<Bytes>>,
+    cancelled: &AtomicBool,
+    mut on_message: F,
+) -> Result<Exchange>
+where
+    F: FnMut(Bytes) -> Result<bool>,
+{
+    let http_request = http_request(request, path);
+    let mut handle = Easy::new();
+    let mut curl_request = CurlRequest::new(&mut handle, &http_request);
+    curl_request.prepare()?;
+    let proxied = curl_request.proxy().is_some();
+
+    let shared = Arc::new(Mutex::new(Shared {
+        outgoing: frames
+            .iter()
+            .flat_map(|frame| frame.iter().copied())
+            .collect(),
+        ..Default::default()
+    }));
+    configure_callbacks(&mut handle, &shared)?;
+
+    let multi = Multi::new();
+    let easy = multi.add(handle).map_err(|e| {
+        tracing::error!(error = %e, "Failed to start gRPC transfer");
+        RelayError::Network {
+            message: "Failed to start gRPC transfer".into(),
+            cause: Some(e.to_string()),
+        }
+    })?;
+    let transfer_error = |e: curl::MultiError| RelayError::Network {
+        message: "gRPC transfer failed".into(),
+        cause: Some(e.to_string()),
+    };
+
+    let mut stopped = None;
+    loop {
+        if cancelled.load(Ordering::SeqCst) {
+            stopped = Some("Cancelled");
+            break;
+        }
+
+        let unpause = {
+            let mut state = shared.lock().unwrap();
+            while !state.finished {
+                match more.try_recv() {
+                    Ok(Some(frame)) => state.outgoing.extend(frame.iter()),
+                    Ok(None) | Err(TryRecvError::Disconnected) => state.finished = true,
+                    Err(TryRecvError::Empty) => break,
+                }
+            }
+            let unpause = state.paused && (state.finished || !state.outgoing.is_empty());
+            if unpause {
+                state.paused = false;
+            }
+            unpause
+        };
+        if unpause {
+            easy.unpause_read().map_err(|e| RelayError::Network {
+                message: "Failed to resume gRPC request stream".into(),
+                cause: Some(e.to_string()),
+            })?;
+        }
+
+        let running = multi.perform().map_err(transfer_error)?;
+
+        let messages = {
+            let mut state = shared.lock().unwrap();
+            let encoding = state.headers.get("grpc-encoding").cloned();
+            take_messages(&mut state.incoming, encoding.as_deref())?
+        };
+        for message in messages {
+            if !on_message(message)? {
+                stopped = Some("Cancelled by the client");
+                break;
+            }
+        }
+        if stopped.is_some() || running == 0 {
+            break;
+        }
+
+        multi.wait(&mut [], POLL_INTERVAL).map_err(transfer_error)?;
+    }
+
+    let mut result = Ok(());
+    multi.messages(|message| {
+        if let Some(transfer) = message.result_for(&easy) {
+            result = transfer;
+        }
+    });
+    let mut handle = multi.remove(easy).map_err(transfer_error)?;
+
+    let state = std::mem::take(&mut *shared.lock().unwrap());
+    let exchange = |status| Exchange {
+        status,
+        headers: state.headers.clone(),
+        trailers: state.trailers.clone(),
+    };
+
+    if let Some(reason) = stopped {
+        tracing::info!(reason, "gRPC call cancelled");
+        return Ok(exchange(GrpcStatus {
+            code: CANCELLED,
+            message: Some(reason.into()),
+        }));
+    }
+
+    if let Err(e) = result {
+        if e.is_operation_timedout() {
+            tracing::warn!(error = %e, "gRPC call timed out");
+            return Ok(exchange(GrpcStatus {
+                code: DEADLINE_EXCEEDED,
+                message: Some(e.to_string()),
+            }));
+        }
+
+        tracing::error!(error = %e, "gRPC transfer failed");
+        return Err(proxied
+            .then(|| proxy::classify_error(&mut handle, &e))
+            .flatten()
+            .unwrap_or_else(|| RelayError::Network {
+                message: "gRPC transfer failed".into(),
+                cause: Some(e.to_string()),
+            }));
+    }
+
+    if !state.incoming.is_empty() {
+        tracing::error!(
+            bytes = state.incoming.len(),
+            "gRPC response ended mid-message"
+        );
+        return Err(RelayError::Parse {
+            message: "gRPC response ended in the middle of a message".into(),
+            cause: None,
+        });
+    }
+
+    let http_status = handle.response_code().unwrap_or_default();
+    Ok(exchange(status(
+        http_status,
+        &state.headers,
+        &state.trailers,
+    )))
+}
+
+/// The HTTP/2 request carrying a call, so it goes through `CurlRequest` like
+/// any other.
+fn http_request(request: &GrpcRequest, path: &str) -> Request {
+    let mut options = request
+        .meta
+        .as_ref()
+        .and_then(|meta| meta.options.clone())
+        .unwrap_or_default();
+    options.version_mode = Some(VersionMode::Only);
+
+    let mut headers = request.metadata.clone().unwrap_or_default();
+    headers.insert("content-type".into(), "application/grpc".into());
+    headers.insert("te".into(), "trailers".into());
+    headers.insert("grpc-accept-encoding".into(), "gzip, deflate".into());
+    if let Some(timeout) = options.timeout {
+        headers.insert("grpc-timeout".into(), grpc_timeout(timeout));
+    }
+
+    Request {
+        id: request.id,
+        url: format!("{}{}", request.url.trim_end_matches('/'), path),
+        method: Method::POST,
+        version: Version::HTTP_2,
+        headers: Some(headers),
+        params: None,
+        content: None,
+        auth: request.auth.clone(),
+        security: request.security.clone(),
+        proxy: request.proxy.clone(),
+        meta: Some(RequestMeta {
+            options: Some(options),
+        }),
+    }
+}
+
+/// `grpc-timeout` for `millis`, which allows at most 8 digits. Longer
+/// timeouts move to coarser units, rounded up so the deadline is never
+/// earlier than asked.
+fn grpc_timeout(millis: u64) -> String {
+    const MAX_VALUE: u64 = 99_999_999;
+
+    let mut value = millis;
+    // NOTE: Each unit with how many of it make up the next one.
+    for (unit, per_next) in [("m", 1000), ("S", 60), ("M", 60)] {
+        if value <= MAX_VALUE {
+            return format!("{}{}", value, unit);
+        }
+        value = value.div_ceil(per_next);
+    }
+    format!("{}H", value.min(MAX_VALUE))
+}
+
+fn configure_callbacks(handle: &mut Easy, shared: &Arc<Mutex<Shared>>) -> Result<()> {
+    let callback_error = |e: curl::Error| {
+        tracing::error!(error = %e, "Failed to set gRPC transfer callback");
+        RelayError::Network {
+            message: "Failed to set gRPC transfer callback".into(),
+            cause: Some(e.to_string()),
+        }
+    };
+
+    handle.post(true).map_err(callback_error)?;
+
+    let state = Arc::clone(shared);
+    handle
+        .read_function(move |buf| {
+            let mut state = state.lock().unwrap();
+            if state.outgoing.is_empty() {
+                if state.finished {
+                    return Ok(0);
+                }
+                state.paused = true;
+                return Err(ReadError::Pause);
+            }
+
+            let len = buf.len().min(state.outgoing.len());
+            for (slot, byte) in buf.iter_mut().zip(state.outgoing.drain(..len)) {
+                *slot = byte;
+            }
+            Ok(len)
+        })
+        .map_err(callback_error)?;
+
+    let state = Arc::clone(shared);
+    handle
+        .write_function(move |data| {
+            state.lock().unwrap().incoming.extend_from_slice(data);
+            Ok(data.len())
+        })
+        .map_err(callback_error)?;
+
+    let state = Arc::clone(shared);
+    handle
+        .header_function(move |line| {
+            let line = String::from_utf8_lossy(line);
+            let line = line.trim();
+            let mut state = state.lock().unwrap();
+
+            // NOTE: Trailers come through here too, after the blank line
+            // that ends the headers.
+            if line.is_empty() {
+                state.in_trailers = true;
+            } else if line.starts_with("HTTP/") {
+                state.in_trailers = false;
+                state.headers.clear();
+            } else if let Some((name, value)) = line.split_once(':') {
+                let (name, value) = (name.trim().to_lowercase(), value.trim().to_string());
+                if state.in_trailers {
+                    state.trailers.insert(name, value);
+                } else {
+                    state.headers.insert(name, value);
+                }
+            }
+            true
+        })
+        .map_err(callback_error)
+}
+
+/// Reads the status from the trailers, or the headers of a trailers-only
+/// response, falling back to the HTTP status mapping in
+/// https://github.com/grpc/grpc/blob/master/doc/http-grpc-status-mapping.md
+fn status(
+    http_status: u32,
+    headers: &HashMap<String, String>,
+    trailers: &HashMap<String, String>,
+) -> GrpcStatus {
+    let fields = if trailers.contains_key("grpc-status") {
+        trailers
+    } else {
+        headers
+    };
+
+    if let Some(code) = fields.get("grpc-status").and_then(|c| c.parse().ok()) {
+        return GrpcStatus {
+            code,
+            message: fields.get("grpc-message").map(|message| {
+                urlencoding::decode(message)
+                    .map(|m| m.into_owned())
+                    .unwrap_or_else(|_| message.clone())
+            }),
+        };
+    }
+
+    let code = match http_status {
+        200 => INTERNAL,
+        400 => INTERNAL,
+        401 => UNAUTHENTICATED,
+        403 => PERMISSION_DENIED,
+        404 => UNIMPLEMENTED,
+        429 | 502 | 503 | 504 => UNAVAILABLE,
+        _ => UNKNOWN,
+    };
+    let message = if http_status == 200 {
+        "Response ended without a grpc-status".to_string()
+    } else {
+        format!("HTTP status {}", http_status)
+    };
+
+    GrpcStatus {
+        code,
+        message: Some(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU64;
+
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic_health::ServingStatus;
+
+    use super::*;
+
+    /// Gives every test's calls their own id, sessions are looked up by it.
+    static NEXT_ID: AtomicU64 = AtomicU64::new(4000);
+
+    /// A tonic server with the health service, for unary and server
+    /// streaming calls, and reflection, which is also a bidi method.
+    fn serve() -> (
+        String,
+        tonic_health::server::HealthReporter,
+        tokio::runtime::Runtime,
+    ) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime
+            .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let (reporter, health) = tonic_health::server::health_reporter();
+        runtime.block_on(reporter.set_service_status("test.Echo", ServingStatus::Serving));
+        let reflection = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+            .register_encoded_file_descriptor_set(tonic_reflection::pb::v1::FILE_DESCRIPTOR_SET)
+            .build_v1()
+            .unwrap();
+
+        runtime.spawn(
+            tonic::transport::Server::builder()
+                .add_service(health)
+                .add_service(reflection)
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        (format!("http://{}", address), reporter, runtime)
+    }
+
+    fn request(url: &str, method: &str, messages: serde_json::Value) -> GrpcRequest {
+        serde_json::from_value(serde_json::json!({
+            "id": NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            "url": url,
+            "service": "grpc.health.v1.Health",
+            "method": method,
+            "descriptors": { "kind": "reflection" },
+            "messages": messages
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_grpc_reflection_and_unary() {
+        let (url, _reporter, _runtime) = serve();
+
+        let services = describe(&request(&url, "", serde_json::json!([]))).unwrap();
+        assert_eq!(services.len(), 1);
+        assert_eq!(
+            services[0].methods,
+            vec![
+                GrpcMethod {
+                    name: "Check".into(),
+                    input_type: "grpc.health.v1.HealthCheckRequest".into(),
+                    output_type: "grpc.health.v1.HealthCheckResponse".into(),
+                    client_streaming: false,
+                    server_streaming: false,
+                },
+                GrpcMethod {
+                    name: "Watch".into(),
+                    input_type: "grpc.health.v1.HealthCheckRequest".into(),
+                    output_type: "grpc.health.v1.HealthCheckResponse".into(),
+                    client_streaming: false,
+                    server_streaming: true,
+                },
+            ]
+        );
+
+        let response = call(
+            request(
+                &url,
+                "Check",
+                serde_json::json!([{ "service": "test.Echo" }]),
+            ),
+            |_| true,
+        )
+        .unwrap();
+        assert_eq!(response.status.code, 0);
+        assert_eq!(
+            response.headers.get("content-type").map(String::as_str),
+            Some("application/grpc")
+        );
+        assert_eq!(
+            response.trailers.get("grpc-status").map(String::as_str),
+            Some("0")
+        );
+        assert_eq!(
+            response.messages,
+            vec![serde_json::json!({ "status": "SERVING" })]
+        );
+
+        let response = call(
+            request(&url, "Check", serde_json::json!([{ "service": "missing" }])),
+            |_| true,
+        )
+        .unwrap();
+        assert_eq!(
+            response.status,
+            GrpcStatus {
+                code: 5,
+                message: Some("service not registered".into()),
+            }
+        );
+        assert!(response.messages.is_empty());
+
+        let error = call(
+            request(&url, "Check", serde_json::json!([{ "unknown": 1 }])),
+            |_| true,
+        )
+        .unwrap_err();
+        assert!(matches!(error, RelayError::Parse { .. }));
+    }
+
+    #[test]
+    fn test_grpc_server_streaming_with_descriptor_set() {
+        let (url, reporter, runtime) = serve();
+
+        let mut request = request(
+            &url,
+            "Watch",
+            serde_json::json!([{ "service": "test.Echo" }]),
+        );
+        request.descriptors = DescriptorSource::FileDescriptorSet {
+            data: Bytes::from_static(tonic_health::pb::FILE_DESCRIPTOR_SET),
+        };
+
+        let response = call(request, |message| {
+            if message["status"] == "SERVING" {
+                runtime
+                    .block_on(reporter.set_service_status("test.Echo", ServingStatus::NotServing));
+                return true;
+            }
+            false
+        })
+        .unwrap();
+
+        assert_eq!(response.status.code, CANCELLED);
+        assert_eq!(
+            response.messages,
+            vec![
+                serde_json::json!({ "status": "SERVING" }),
+                serde_json::json!({ "status": "NOT_SERVING" }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grpc_bidi_streaming() {
+        let (url, _reporter, _runtime) = serve();
+
+        let mut request = request(&url, "", serde_json::json!([{ "listServices": "" }]));
+        request.service = "grpc.reflection.v1.ServerReflection".into();
+        request.method = "ServerReflectionInfo".into();
+        request.keep_open = true;
+        let id = request.id;
+
+        let mut replies = 0;
+        let response = call(request, |_| {
+            replies += 1;
+            if replies == 1 {
+                send(
+                    id,
+                    serde_json::json!({ "fileContainingSymbol": "grpc.health.v1.Health" }),
+                )
+                .unwrap();
+                close_send(id).unwrap();
+            }
+            true
+        })
+        .unwrap();
+
+        assert_eq!(response.status.code, 0);
+        assert_eq!(response.messages.len(), 2);
+        let services = response.messages[0]["listServicesResponse"]["service"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|service| service["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert!(services.contains(&"grpc.health.v1.Health"));
+        assert!(
+            response.messages[1]["fileDescriptorResponse"]["fileDescriptorProto"]
+                .as_array()
+                .is_some_and(|files| !files.is_empty())
+        );
+        assert!(send(id, serde_json::json!({})).is_err());
+    }
+
+    #[test]
+    fn test_status_fallbacks() {
+        let trailers = HashMap::from([
+            ("grpc-status".to_string(), "3".to_string()),
+            (
+                "grpc-message".to_string(),
+                "bad%20input%3A%20%E2%9C%93".to_string(),
+            ),
+        ]);
+        assert_eq!(
+            status(200, &HashMap::new(), &trailers),
+            GrpcStatus {
+                code: 3,
+                message: Some("bad input: ✓".into()),
+            }
+        );
+        assert_eq!(
+            status(404, &HashMap::new(), &HashMap::new()).code,
+            UNIMPLEMENTED
+        );
+        assert_eq!(
+            status(503, &HashMap::new(), &HashMap::new()).code,
+            UNAVAILABLE
+        );
+        assert_eq!(status(200, &HashMap::new(), &HashMap::new()).code, INTERNAL);
+    }
+
+    #[test]
+    fn test_grpc_timeout_fits_eight_digits() {
+        assert_eq!(grpc_timeout(1500), "1500m");
+        assert_eq!(grpc_timeout(99_999_999), "99999999m");
+        assert_eq!(grpc_timeout(100_000_000), "100000S");
+        assert_eq!(grpc_timeout(99_999_998_001), "99999999S");
+        assert_eq!(grpc_timeout(200_000_000_000), "3333334M");
+        assert_eq!(grpc_timeout(100_000_000_000_000), "27777778H");
+        assert_eq!(grpc_timeout(u64::MAX), "99999999H");
+    }
+}
@@ -0,0 +1,836 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http::Version;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    curl_command,
+    interop::{
+        ApiKeyLocation, AuthType, ContentType, FormData, FormValue, MediaKind, PhaseTimings,
+        Request, Response,
+    },
+};
+
+const HAR_VERSION: &str = "1.2";
+
+/// An HTTP Archive, see http://www.softwareishard.com/blog/har-12-spec/.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Har {
+    pub log: Log,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Log {
+    pub version: String,
+    pub creator: Creator,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Creator {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    #[serde(with = "time::serde::rfc3339")]
+    pub started_date_time: OffsetDateTime,
+    /// Total milliseconds, the sum of `timings` but `ssl`.
+    pub time: f64,
+    pub request: HarRequest,
+    pub response: HarResponse,
+    pub cache: Cache,
+    pub timings: Timings,
+    #[serde(rename = "serverIPAddress", skip_serializing_if = "Option::is_none")]
+    pub server_ip_address: Option<String>,
+    /// The port connected to, HAR's connection id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarRequest {
+    pub method: String,
+    pub url: String,
+    pub http_version: String,
+    pub cookies: Vec<HarCookie>,
+    pub headers: Vec<NameValue>,
+    pub query_string: Vec<NameValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_data: Option<PostData>,
+    pub headers_size: i64,
+    pub body_size: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarResponse {
+    pub status: u16,
+    pub status_text: String,
+    pub http_version: String,
+    pub cookies: Vec<HarCookie>,
+    pub headers: Vec<NameValue>,
+    pub content: Content,
+    #[serde(rename = "redirectURL")]
+    pub redirect_url: String,
+    pub headers_size: i64,
+    pub body_size: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NameValue {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarCookie {
+    pub name: String,
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub expires: Option<OffsetDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secure: Option<bool>,
+}
+
+/// Either `params` (form bodies) or `text` (everything else), both for
+/// `application/x-www-form-urlencoded` like browsers write them.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PostData {
+    pub mime_type: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub params: Vec<Param>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Param {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Content {
+    pub size: i64,
+    pub mime_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// `base64` when `text` holds binary content.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Cache {}
+
+/// Milliseconds per phase, `-1` where a phase doesn't apply or isn't
+/// known. `connect` includes `ssl`, as HAR asks.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Timings {
+    pub blocked: f64,
+    pub dns: f64,
+    pub connect: f64,
+    pub send: f64,
+    pub wait: f64,
+    pub receive: f64,
+    pub ssl: f64,
+}
+
+/// A request together with the response the relay got for it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Exchange {
+    pub request: Request,
+    pub response: Response,
+}
+
+/// A HAR log with an entry per exchange, in order.
+pub fn export(exchanges: &[Exchange]) -> Har {
+    Har {
+        log: Log {
+            version: HAR_VERSION.into(),
+            creator: Creator {
+                name: env!("CARGO_PKG_NAME").into(),
+                version: env!("CARGO_PKG_VERSION").into(),
+            },
+            entries: exchanges
+                .iter()
+                .map(|exchange| entry(&exchange.request, &exchange.response))
+                .collect(),
+        },
+    }
+}
+
+/// The HAR entry for `request` and the `response` it got.
+///
+/// Headers are the ones on `request` plus what its auth and body add, curl's
+/// own defaults like `User-Agent` aren't known here. Phase timings are only
+/// filled in when the response carries them.
+pub fn entry(request: &Request, response: &Response) -> Entry {
+    let started_date_time =
+        OffsetDateTime::from_unix_timestamp_nanos(response.meta.timing.start as i128 * 1_000_000)
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH);
+    let elapsed = response
+        .meta
+        .timing
+        .end
+        .saturating_sub(response.meta.timing.start) as f64;
+    let timings = match response.meta.phases {
+        Some(ref phases) => phase_timings(phases),
+        None => Timings {
+            blocked: -1.0,
+            dns: -1.0,
+            connect: -1.0,
+            send: 0.0,
+            wait: elapsed,
+            receive: 0.0,
+            ssl: -1.0,
+        },
+    };
+    let time = [
+        timings.blocked,
+        timings.dns,
+        timings.connect,
+        timings.send,
+        timings.wait,
+        timings.receive,
+    ]
+    .iter()
+    .filter(|time| **time > 0.0)
+    .sum();
+
+    Entry {
+        started_date_time,
+        time,
+        request: har_request(request),
+        response: har_response(response),
+        cache: Cache::default(),
+        timings,
+        server_ip_address: response.meta.primary_ip.clone(),
+        connection: response.meta.primary_port.map(|port| port.to_string()),
+    }
+}
+
+/// Turns curl's cumulative times into HAR's per-phase ones.
+fn phase_timings(phases: &PhaseTimings) -> Timings {
+    // NOTE: Phases that didn't happen are reported as `0`, so every end is
+    // clamped to the one before to keep the differences non-negative.
+    let dns_end = phases.namelookup;
+    let tcp_end = phases.connect.max(dns_end);
+    let connect_end = phases.appconnect.max(tcp_end);
+    let send_end = phases.pretransfer.max(connect_end);
+    let wait_end = phases.starttransfer.max(send_end);
+    let total = (phases.total - phases.redirect).max(wait_end);
+
+    Timings {
+        blocked: if phases.redirect > 0.0 {
+            phases.redirect
+        } else {
+            -1.0
+        },
+        dns: if phases.namelookup > 0.0 {
+            dns_end
+        } else {
+            -1.0
+        },
+        connect: if phases.connect > 0.0 {
+            connect_end - dns_end
+        } else {
+            -1.0
+        },
+        send: send_end - connect_end,
+        wait: wait_end - send_end,
+        receive: total - wait_end,
+        ssl: if phases.appconnect > 0.0 {
+            connect_end - tcp_end
+        } else {
+            -1.0
+        },
+    }
+}
+
+fn http_version(version: Version) -> String {
+    format!("{:?}", version)
+}
+
+fn har_request(request: &Request) -> HarRequest {
+    let mut query: Vec<(String, String)> = request
+        .params
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    query.sort();
+
+    let mut headers: Vec<NameValue> = request
+        .headers
+        .iter()
+        .flatten()
+        .map(|(name, value)| NameValue {
+            name: name.clone(),
+            value: value.clone(),
+        })
+        .collect();
+    let has_header = |headers: &[NameValue], name: &str| {
+        headers
+            .iter()
+            .any(|header| header.name.eq_ignore_ascii_case(name))
+    };
+
+    let authorization = match request.auth {
+        Some(AuthType::Basic {
+            ref username,
+            ref password,
+        }) => Some(format!(
+            "Basic {}",
+            STANDARD.encode(format!("{}:{}", username, password))
+        )),
+        Some(AuthType::Bearer { ref token })
+        | Some(AuthType::OAuth2 {
+            access_token: Some(ref token),
+            ..
+        }) => Some(format!("Bearer {}", token)),
+        Some(AuthType::ApiKey {
+            ref key,
+            ref value,
+            ref location,
+        }) => {
+            match location {
+                ApiKeyLocation::Header if !has_header(&headers, key) => headers.push(NameValue {
+                    name: key.clone(),
+                    value: value.clone(),
+                }),
+                ApiKeyLocation::Header => {}
+                ApiKeyLocation::Query => query.push((key.clone(), value.clone())),
+            }
+            None
+        }
+        _ => None,
+    };
+    if let Some(value) = authorization.filter(|_| !has_header(&headers, "authorization")) {
+        headers.push(NameValue {
+            name: "Authorization".into(),
+            value,
+        });
+    }
+
+    let post_data = request.content.as_ref().map(post_data);
+    if let Some(ref post_data) = post_data {
+        if !has_header(&headers, "content-type") {
+            headers.push(NameValue {
+                name: "Content-Type".into(),
+                value: post_data.mime_type.clone(),
+            });
+        }
+    }
+    headers.sort_by(|a, b| {
+        a.name
+            .to_ascii_lowercase()
+            .cmp(&b.name.to_ascii_lowercase())
+    });
+
+    let url = curl_command::with_query(&request.url, &query);
+    let query_string = url::Url::parse(&url)
+        .map(|url| {
+            url.query_pairs()
+                .map(|(name, value)| NameValue {
+                    name: name.into_owned(),
+                    value: value.into_owned(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let cookies = headers
+        .iter()
+        .filter(|header| header.name.eq_ignore_ascii_case("cookie"))
+        .flat_map(|header| header.value.split(';'))
+        .filter_map(|pair| {
+            let (name, value) = pair.trim().split_once('=')?;
+            Some(HarCookie {
+                name: name.into(),
+                value: value.into(),
+                path: None,
+                domain: None,
+                expires: None,
+                http_only: None,
+                secure: None,
+            })
+        })
+        .collect();
+
+    let body_size = match request.content {
+        None => 0,
+        Some(ContentType::Text { ref content, .. })
+        | Some(ContentType::Xml { ref content, .. })
+        | Some(ContentType::Urlencoded { ref content, .. }) => content.len() as i64,
+        Some(ContentType::Json { ref content, .. }) => content.to_string().len() as i64,
+        Some(ContentType::Binary { ref content, .. }) => content.len() as i64,
+        // NOTE: Multipart sizes depend on the boundary curl picks.
+        Some(ContentType::Form { .. }) | Some(ContentType::Multipart { .. }) => -1,
+    };
+
+    HarRequest {
+        method: request.method.to_string(),
+        url,
+        http_version: http_version(request.version),
+        cookies,
+        headers,
+        query_string,
+        post_data,
+        headers_size: -1,
+        body_size,
+    }
+}
+
+fn post_data(content: &ContentType) -> PostData {
+    let text = |mime_type: String, text: String| PostData {
+        mime_type,
+        params: Vec::new(),
+        text: Some(text),
+        comment: None,
+    };
+
+    match content {
+        ContentType::Text {
+            content,
+            media_type,
+        }
+        | ContentType::Xml {
+            content,
+            media_type,
+        } => text(media_type.to_string(), content.clone()),
+        ContentType::Json {
+            content,
+            media_type,
+        } => text(media_type.to_string(), content.to_string()),
+        ContentType::Urlencoded {
+            content,
+            media_type,
+        } => PostData {
+            mime_type: media_type.to_string(),
+            params: url::form_urlencoded::parse(content.as_bytes())
+                .map(|(name, value)| Param {
+                    name: name.into_owned(),
+                    value: Some(value.into_owned()),
+                    file_name: None,
+                    content_type: None,
+                })
+                .collect(),
+            text: Some(content.clone()),
+            comment: None,
+        },
+        ContentType::Binary {
+            content,
+            media_type,
+            ..
+        } => match std::str::from_utf8(content) {
+            Ok(content) => text(media_type.to_string(), content.into()),
+            Err(_) => PostData {
+                comment: Some("text is base64 encoded".into()),
+                ..text(media_type.to_string(), STANDARD.encode(content))
+            },
+        },
+        ContentType::Form { content, .. } => form_post_data(content, None),
+        ContentType::Multipart {
+            content, boundary, ..
+        } => form_post_data(content, boundary.as_deref()),
+    }
+}
+
+fn form_post_data(content: &FormData, boundary: Option<&str>) -> PostData {
+    let mime_type = match boundary {
+        Some(boundary) => format!("multipart/form-data; boundary={}", boundary),
+        None => "multipart/form-data".into(),
+    };
+    let params = content
+        .iter()
+        .flat_map(|(name, values)| values.iter().map(move |value| (name, value)))
+        .map(|(name, value)| match value {
+            FormValue::Text {
+                value,
+                content_type,
+                ..
+            } => Param {
+                name: name.clone(),
+                value: Some(value.clone()),
+                file_name: None,
+                content_type: content_type.clone(),
+            },
+            FormValue::File {
+                filename,
+                content_type,
+                ..
+            } => Param {
+                name: name.clone(),
+                value: None,
+                file_name: Some(filename.clone()),
+                content_type: Some(content_type.to_string()),
+            },
+        })
+        .collect();
+
+    PostData {
+        mime_type,
+        params,
+        text: None,
+        comment: None,
+    }
+}
+
+fn har_response(response: &Response) -> HarResponse {
+    let mut headers: Vec<NameValue> = response
+        .headers
+        .iter()
+        .flat_map(|(name, value)| {
+            // NOTE: Repeated headers like `Set-Cookie` arrive joined with
+            // `\n`, see `TransferHandler`.
+            value.split('\n').map(move |value| NameValue {
+                name: name.clone(),
+                value: value.to_string(),
+            })
+        })
+        .collect();
+    headers.sort_by(|a, b| {
+        a.name
+            .to_ascii_lowercase()
+            .cmp(&b.name.to_ascii_lowercase())
+            .then_with(|| a.value.cmp(&b.value))
+    });
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|header| header.name.eq_ignore_ascii_case(name))
+            .map(|header| header.value.clone())
+    };
+
+    let cookies = response
+        .cookies
+        .iter()
+        .flatten()
+        .map(|cookie| HarCookie {
+            name: cookie.name.clone(),
+            value: cookie.value.clone(),
+            path: cookie.path.clone(),
+            domain: cookie.domain.clone(),
+            expires: cookie.expires,
+            http_only: cookie.http_only,
+            secure: cookie.secure,
+        })
+        .collect();
+
+    let body = &response.body;
+    let mime_type = body
+        .mime
+        .clone()
+        .unwrap_or_else(|| body.media_type.to_string());
+    let (text, encoding) = match (&body.text, std::str::from_utf8(&body.body)) {
+        (Some(text), _) => (text.clone(), None),
+        (None, Ok(text)) if !matches!(body.kind, MediaKind::Image | MediaKind::Binary) => {
+            (text.to_string(), None)
+        }
+        (None, _) => (STANDARD.encode(&body.body), Some("base64".to_string())),
+    };
+    let comment = match (&response.meta.saved_to, response.meta.truncated) {
+        (Some(saved), _) => Some(format!("Body saved to {}, text is a preview", saved.path)),
+        (None, Some(true)) => Some("Body truncated".to_string()),
+        _ => None,
+    };
+
+    // NOTE: With `Content-Encoding` the bytes on the wire differ from the
+    // decoded body, and curl doesn't report how many there were.
+    let body_size = match header("content-encoding") {
+        Some(_) => -1,
+        None => response.meta.size.body as i64,
+    };
+
+    HarResponse {
+        status: response.status.as_u16(),
+        status_text: response
+            .status
+            .canonical_reason()
+            .unwrap_or_default()
+            .to_string(),
+        http_version: http_version(response.version),
+        cookies,
+        content: Content {
+            size: response.meta.size.body as i64,
+            mime_type,
+            text: Some(text),
+            encoding,
+            comment,
+        },
+        redirect_url: header("location").unwrap_or_default(),
+        headers,
+        headers_size: response.meta.size.headers as i64,
+        body_size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bytes::Bytes;
+    use http::{Method, StatusCode};
+
+    use super::*;
+    use crate::interop::{Cookie, MediaType, ResponseBody, ResponseMeta, SizeInfo, TimingInfo};
+
+    fn request(content: Option<ContentType>) -> Request {
+        Request {
+            headers: Some(HashMap::from([(
+                "Cookie".into(),
+                "session=abc; theme=dark".into(),
+            )])),
+            params: Some(HashMap::from([("q".into(), "a b".into())])),
+            content,
+            auth: Some(AuthType::Basic {
+                username: "alice".into(),
+                password: "s3cret".into(),
+            }),
+            ..Request::test(Method::POST, "https://example.com/items?page=2")
+        }
+    }
+
+    fn response(body: &'static [u8], media_type: MediaType, kind: MediaKind) -> Response {
+        let response = Response::test(
+            StatusCode::CREATED,
+            &[
+                ("content-type", &media_type.to_string()),
+                ("set-cookie", "a=1\nb=2"),
+            ],
+            body,
+        );
+        Response {
+            version: Version::HTTP_2,
+            cookies: Some(vec![Cookie {
+                name: "a".into(),
+                value: "1".into(),
+                domain: None,
+                path: Some("/".into()),
+                expires: None,
+                secure: Some(true),
+                http_only: None,
+                same_site: None,
+            }]),
+            body: ResponseBody {
+                media_type,
+                kind,
+                ..response.body
+            },
+            meta: ResponseMeta {
+                timing: TimingInfo {
+                    start: 1_700_000_000_000,
+                    end: 1_700_000_000_120,
+                },
+                size: SizeInfo {
+                    headers: 80,
+                    body: body.len() as u64,
+                    total: 80 + body.len() as u64,
+                },
+                primary_ip: Some("93.184.216.34".into()),
+                primary_port: Some(443),
+                ..Default::default()
+            },
+            ..response
+        }
+    }
+
+    #[test]
+    fn request_carries_auth_query_cookies_and_params() {
+        let request = request(Some(ContentType::Urlencoded {
+            content: "a=1&b=x%20y".into(),
+            media_type: MediaType::FormUrlEncoded,
+        }));
+        let entry = entry(&request, &response(b"{}", MediaType::Json, MediaKind::Json));
+
+        assert_eq!(entry.request.url, "https://example.com/items?page=2&q=a+b");
+        assert_eq!(
+            entry.request.query_string,
+            vec![
+                NameValue {
+                    name: "page".into(),
+                    value: "2".into()
+                },
+                NameValue {
+                    name: "q".into(),
+                    value: "a b".into()
+                },
+            ]
+        );
+        let names: Vec<&str> = entry
+            .request
+            .headers
+            .iter()
+            .map(|header| header.name.as_str())
+            .collect();
+        assert_eq!(names, ["Authorization", "Content-Type", "Cookie"]);
+        assert_eq!(entry.request.headers[0].value, "Basic YWxpY2U6czNjcmV0");
+        assert_eq!(entry.request.cookies.len(), 2);
+        assert_eq!(entry.request.cookies[1].value, "dark");
+
+        let post_data = entry.request.post_data.unwrap();
+        assert_eq!(post_data.text.as_deref(), Some("a=1&b=x%20y"));
+        assert_eq!(post_data.params[1].value.as_deref(), Some("x y"));
+        assert_eq!(entry.request.body_size, 11);
+    }
+
+    #[test]
+    fn multipart_params_name_files() {
+        let request = request(Some(ContentType::Multipart {
+            content: vec![
+                (
+                    "note".into(),
+                    vec![FormValue::Text {
+                        value: "hi".into(),
+                        content_type: None,
+                        headers: None,
+                    }],
+                ),
+                (
+                    "file".into(),
+                    vec![FormValue::File {
+                        filename: "photo.png".into(),
+                        content_type: MediaType::ImagePng,
+                        data: Bytes::from_static(b"\x89PNG"),
+                        headers: None,
+                    }],
+                ),
+            ],
+            media_type: MediaType::MultipartFormData,
+            boundary: Some("xyz".into()),
+        }));
+        let post_data = entry(
+            &request,
+            &response(b"", MediaType::TextPlain, MediaKind::Text),
+        )
+        .request
+        .post_data
+        .unwrap();
+
+        assert_eq!(post_data.mime_type, "multipart/form-data; boundary=xyz");
+        assert!(post_data.text.is_none());
+        assert_eq!(
+            post_data.params[1],
+            Param {
+                name: "file".into(),
+                value: None,
+                file_name: Some("photo.png".into()),
+                content_type: Some("image/png".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn response_content_and_headers() {
+        let entry = entry(
+            &request(None),
+            &response(b"\x89PNG\x00", MediaType::ImagePng, MediaKind::Image),
+        );
+        let response = entry.response;
+
+        assert_eq!(response.status, 201);
+        assert_eq!(response.status_text, "Created");
+        assert_eq!(response.http_version, "HTTP/2.0");
+        assert_eq!(response.content.encoding.as_deref(), Some("base64"));
+        assert_eq!(response.content.text.as_deref(), Some("iVBORwA="));
+        assert_eq!(response.content.size, 5);
+        assert_eq!(
+            response
+                .headers
+                .iter()
+                .filter(|header| header.name == "set-cookie")
+                .count(),
+            2
+        );
+        assert_eq!(response.cookies[0].path.as_deref(), Some("/"));
+        assert_eq!(entry.server_ip_address.as_deref(), Some("93.184.216.34"));
+        assert_eq!(entry.time, 120.0);
+        assert_eq!(entry.timings.dns, -1.0);
+    }
+
+    #[test]
+    fn phase_timings_become_per_phase() {
+        let timings = phase_timings(&PhaseTimings {
+            namelookup: 5.0,
+            connect: 15.0,
+            appconnect: 40.0,
+            pretransfer: 41.0,
+            starttransfer: 90.0,
+            total: 100.0,
+            redirect: 0.0,
+        });
+        assert_eq!(
+            timings,
+            Timings {
+                blocked: -1.0,
+                dns: 5.0,
+                connect: 35.0,
+                send: 1.0,
+                wait: 49.0,
+                receive: 10.0,
+                ssl: 25.0,
+            }
+        );
+
+        // A reused plain HTTP connection skips DNS, connect and TLS.
+        let timings = phase_timings(&PhaseTimings {
+            pretransfer: 1.0,
+            starttransfer: 30.0,
+            total: 32.0,
+            ..Default::default()
+        });
+        assert_eq!(
+            (timings.dns, timings.connect, timings.ssl),
+            (-1.0, -1.0, -1.0)
+        );
+        assert_eq!(
+            (timings.send, timings.wait, timings.receive),
+            (1.0, 29.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn export_serializes_har_field_names() {
+        let har = export(&[Exchange {
+            request: request(None),
+            response: response(b"ok", MediaType::TextPlain, MediaKind::Text),
+        }]);
+        let json = serde_json::to_value(&har).unwrap();
+
+        assert_eq!(json["log"]["version"], "1.2");
+        let entry = &json["log"]["entries"][0];
+        assert_eq!(entry["startedDateTime"], "2023-11-14T22:13:20Z");
+        assert_eq!(entry["serverIPAddress"], "93.184.216.34");
+        assert_eq!(entry["response"]["redirectURL"], "");
+        assert_eq!(entry["response"]["content"]["text"], "ok");
+        assert!(entry["request"].get("postData").is_none());
+    }
+}
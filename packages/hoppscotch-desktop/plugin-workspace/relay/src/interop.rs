@@ -6,135 +6,144 @@ use serde::{Deserialize, Serialize};
 use strum::{Display, EnumString};
 use time::OffsetDateTime;
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Display, EnumString)]
+/// A body's media type. Types without a variant are kept verbatim in
+/// `Other`, parameters included, so e.g. `application/problem+json` or
+/// `application/json; charset=utf-8` round-trip unchanged.
+#[derive(Debug, Clone, PartialEq, Display, EnumString)]
 pub enum MediaType {
     // Text
-    #[serde(rename = "text/plain")]
     #[strum(to_string = "text/plain")]
     TextPlain,
-    #[serde(rename = "text/html")]
     #[strum(to_string = "text/html")]
     TextHtml,
-    #[serde(rename = "text/css")]
     #[strum(to_string = "text/css")]
     TextCss,
-    #[serde(rename = "text/csv")]
     #[strum(to_string = "text/csv")]
     TextCsv,
-    #[serde(rename = "text/xml")]
     #[strum(to_string = "text/xml")]
     TextXml,
 
     // Application
-    #[serde(rename = "application/json")]
     #[strum(to_string = "application/json")]
     Json,
-    #[serde(rename = "application/ld+json")]
     #[strum(to_string = "application/ld+json")]
     JsonLd,
-    #[serde(rename = "application/xml")]
     #[strum(to_string = "application/xml")]
     Xml,
-    #[serde(rename = "application/x-www-form-urlencoded")]
     #[strum(to_string = "application/x-www-form-urlencoded")]
     FormUrlEncoded,
-    #[serde(rename = "multipart/form-data")]
     #[strum(to_string = "multipart/form-data")]
     MultipartFormData,
-    #[serde(rename = "application/octet-stream")]
+    #[strum(to_string = "multipart/mixed")]
+    MultipartMixed,
+    #[strum(to_string = "multipart/related")]
+    MultipartRelated,
     #[strum(to_string = "application/octet-stream")]
     OctetStream,
-    #[serde(rename = "application/pdf")]
     #[strum(to_string = "application/pdf")]
     ApplicationPdf,
-    #[serde(rename = "application/zip")]
     #[strum(to_string = "application/zip")]
     ApplicationZip,
-    #[serde(rename = "application/javascript")]
     #[strum(to_string = "application/javascript")]
     ApplicationJavascript,
 
     // Audio
-    #[serde(rename = "audio/mpeg")]
     #[strum(to_string = "audio/mpeg")]
     AudioMpeg,
-    #[serde(rename = "audio/mp4")]
     #[strum(to_string = "audio/mp4")]
     AudioMp4,
-    #[serde(rename = "audio/x-m4a")]
     #[strum(to_string = "audio/x-m4a")]
     AudioXM4a,
-    #[serde(rename = "audio/wav")]
     #[strum(to_string = "audio/wav")]
     AudioWav,
-    #[serde(rename = "audio/ogg")]
     #[strum(to_string = "audio/ogg")]
     AudioOgg,
-    #[serde(rename = "audio/aac")]
     #[strum(to_string = "audio/aac")]
     AudioAac,
-    #[serde(rename = "audio/flac")]
     #[strum(to_string = "audio/flac")]
     AudioFlac,
 
     // Video
-    #[serde(rename = "video/mp4")]
     #[strum(to_string = "video/mp4")]
     VideoMp4,
-    #[serde(rename = "video/avi")]
     #[strum(to_string = "video/avi")]
     VideoAvi,
-    #[serde(rename = "video/quicktime")]
     #[strum(to_string = "video/quicktime")]
     VideoQuicktime,
-    #[serde(rename = "video/x-msvideo")]
     #[strum(to_string = "video/x-msvideo")]
     VideoXMsvideo,
-    #[serde(rename = "video/webm")]
     #[strum(to_string = "video/webm")]
     VideoWebm,
-    #[serde(rename = "video/x-flv")]
     #[strum(to_string = "video/x-flv")]
     VideoXFlv,
 
     // Image
-    #[serde(rename = "image/png")]
     #[strum(to_string = "image/png")]
     ImagePng,
-    #[serde(rename = "image/jpeg")]
     #[strum(to_string = "image/jpeg")]
     ImageJpeg,
-    #[serde(rename = "image/gif")]
     #[strum(to_string = "image/gif")]
     ImageGif,
-    #[serde(rename = "image/svg+xml")]
     #[strum(to_string = "image/svg+xml")]
     ImageSvgXml,
-    #[serde(rename = "image/webp")]
     #[strum(to_string = "image/webp")]
     ImageWebp,
-    #[serde(rename = "image/bmp")]
     #[strum(to_string = "image/bmp")]
     ImageBmp,
-    #[serde(rename = "image/x-icon")]
     #[strum(to_string = "image/x-icon")]
     ImageXIcon,
 
     // Fallback for unknown
-    #[serde(other)]
-    Other,
+    #[strum(default)]
+    Other(String),
+}
+
+impl Serialize for MediaType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MediaType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        // NOTE: Infallible, unknown types fall back to `Other`.
+        Ok(value.parse().unwrap_or(MediaType::Other(value)))
+    }
+}
+
+/// Coarse classification of a media type, for deciding how to render a body.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum MediaKind {
+    /// `application/json` and `+json` suffixed types.
+    Json,
+    /// `application/xml`, `text/xml` and `+xml` suffixed types.
+    Xml,
+    /// Other `text/*` types plus textual application types.
+    Text,
+    Image,
+    Binary,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "kind", rename_all = "camelCase")]
 pub enum FormValue {
     #[serde(rename_all = "camelCase")]
-    Text { value: String },
+    Text {
+        value: String,
+        /// Sent as the part's `Content-Type`, charset included, e.g.
+        /// `application/json; charset=utf-8`. Parts without one have none.
+        content_type: Option<String>,
+        /// Extra part headers, replacing generated ones of the same name.
+        headers: Option<HashMap<String, String>>,
+    },
     #[serde(rename_all = "camelCase")]
     File {
         filename: String,
         content_type: MediaType,
         data: Bytes,
+        headers: Option<HashMap<String, String>>,
     },
 }
 
@@ -173,6 +182,8 @@ pub enum ContentType {
     Multipart {
         content: FormData,
         media_type: MediaType,
+        /// Boundary to use instead of a generated one.
+        boundary: Option<String>,
     },
     #[serde(rename_all = "camelCase")]
     Urlencoded {
@@ -318,7 +329,7 @@ pub struct RequestMeta {
     pub options: Option<RequestOptions>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct RequestOptions {
     pub timeout: Option<u64>,
@@ -327,6 +338,210 @@ pub struct RequestOptions {
     pub decompress: Option<bool>,
     pub cookies: Option<bool>,
     pub keep_alive: Option<bool>,
+    /// Pins host names to addresses, like `curl --resolve`. SNI and the
+    /// `Host` header still use the original name.
+    pub resolve: Option<Vec<ResolveEntry>>,
+    /// Redirects connections for a host and port elsewhere, like
+    /// `curl --connect-to`.
+    pub connect_to: Option<Vec<ConnectToEntry>>,
+    /// Path of a Unix domain socket to connect through instead of TCP. The
+    /// URL still provides the `Host` header and path, e.g.
+    /// `http://localhost/v1.43/containers/json` over `/var/run/docker.sock`.
+    pub unix_socket: Option<String>,
+    /// Name of a Linux abstract socket, without the leading NUL byte. Takes
+    /// precedence over `unix_socket`.
+    pub abstract_unix_socket: Option<String>,
+    /// Whether `Request.version` may fall back to an older version.
+    pub version_mode: Option<VersionMode>,
+    /// Compresses the serialized body and sets `Content-Encoding`.
+    pub body_compression: Option<BodyCompression>,
+    /// Adds a UTF-8 view of textual response bodies as `ResponseBody.text`.
+    pub decode_text: Option<bool>,
+    /// Caps the decoded response body in bytes, see `body_limit_mode`.
+    pub max_body_size: Option<u64>,
+    pub body_limit_mode: Option<BodyLimitMode>,
+    /// File to write the response body to, inside the directory the host
+    /// app set with `relay::set_save_dir`, either relative to it or
+    /// absolute. The response then carries only the first few KiB of it.
+    /// The file is replaced only once the whole response arrived.
+    pub save_to: Option<String>,
+    /// Sends the request again on transient failures, see `RetryPolicy`.
+    pub retry: Option<RetryPolicy>,
+    /// Checked against the final response, with the outcomes in
+    /// `ResponseMeta.assertions`.
+    pub assertions: Option<Vec<Assertion>>,
+}
+
+/// When and how often a request is sent again. Unset fields take the
+/// defaults below, so `{}` retries a GET up to twice on 408, 429 and 5xx
+/// gateway errors, connection failures and timeouts.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryPolicy {
+    /// Attempts in total, the first one included. Defaults to 3.
+    pub max_attempts: Option<u32>,
+    /// Defaults to 408, 429, 500, 502, 503 and 504.
+    pub retry_on_status: Option<Vec<u16>>,
+    /// Defaults to `network` and `timeout`. Cancelled requests are never
+    /// retried.
+    pub retry_on_errors: Option<Vec<RetryErrorKind>>,
+    /// Milliseconds before the second attempt, multiplied by `multiplier`
+    /// for each one after. Defaults to 500.
+    pub initial_delay: Option<u64>,
+    /// Upper bound on the delay in milliseconds. Defaults to 30000.
+    pub max_delay: Option<u64>,
+    /// Defaults to 2.
+    pub multiplier: Option<f64>,
+    /// Waits a random 50-100% of each delay so clients that failed together
+    /// don't retry together. Defaults to `true`.
+    pub jitter: Option<bool>,
+    /// Waits as long as a `Retry-After` header asks instead of the backoff
+    /// delay, or stops retrying when that is beyond `max_delay`. Defaults
+    /// to `true`.
+    pub respect_retry_after: Option<bool>,
+    /// Retries POST, PATCH and other methods that aren't idempotent, which
+    /// can apply the same change twice. Defaults to `false`.
+    pub retry_non_idempotent: Option<bool>,
+}
+
+/// `RelayError` kinds a request can be retried on.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryErrorKind {
+    Network,
+    Timeout,
+    Certificate,
+    Parse,
+    Proxy,
+    ProxyAuth,
+    BodyTooLarge,
+}
+
+/// A check on a response. Body checks see the body as returned, which is
+/// only a preview with `RequestOptions.save_to`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Assertion {
+    Status {
+        equals: u16,
+    },
+    /// Both bounds inclusive, e.g. 200 to 299.
+    StatusRange {
+        min: u16,
+        max: u16,
+    },
+    /// The header is present, and its value matches the `matches` regex
+    /// when given. Names are case-insensitive.
+    Header {
+        name: String,
+        matches: Option<String>,
+    },
+    /// Milliseconds from sending the request to receiving the whole
+    /// response, exclusive.
+    ResponseTime {
+        below: u64,
+    },
+    BodyContains {
+        text: String,
+    },
+    /// The value at a JSONPath, e.g. `$.user.name`. A path matching several
+    /// values is compared as an array of them.
+    JsonPath {
+        path: String,
+        equals: serde_json::Value,
+    },
+    /// The length of the array at a JSONPath.
+    ArrayLength {
+        path: String,
+        equals: usize,
+    },
+    /// The string value of an XPath 1.0 expression, e.g. `count(//item)`.
+    Xpath {
+        path: String,
+        equals: String,
+    },
+}
+
+/// What happens when a response body exceeds `RequestOptions.max_body_size`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum BodyLimitMode {
+    /// Stops reading and returns what arrived so far, flagged with
+    /// `ResponseMeta.truncated`.
+    #[default]
+    Truncate,
+    /// Fails the request with `RelayError::BodyTooLarge`.
+    Error,
+}
+
+/// `Content-Encoding` tokens the relay can compress request bodies with.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum BodyCompression {
+    Gzip,
+    Deflate,
+    Br,
+    Zstd,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum VersionMode {
+    /// HTTP/2 is negotiated through ALPN or an `Upgrade`, HTTP/3 falls back
+    /// to HTTP/2 or HTTP/1.1 if QUIC fails.
+    #[default]
+    Fallback,
+    /// HTTP/2 is spoken from the start, which is h2c on cleartext
+    /// connections, and HTTP/3 fails instead of falling back.
+    Only,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolveEntry {
+    pub host: String,
+    pub port: u16,
+    pub addresses: Vec<String>,
+}
+
+/// `None` fields match any host or port on the left side, and keep the
+/// original host or port on the right side.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectToEntry {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub connect_host: Option<String>,
+    pub connect_port: Option<u16>,
+}
+
+/// Resolve and connect-to entries applied to every request, see
+/// `relay::set_host_overrides`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HostOverrides {
+    pub resolve: Option<Vec<ResolveEntry>>,
+    pub connect_to: Option<Vec<ConnectToEntry>>,
+}
+
+/// A rate and concurrency limit shared by every request `relay::execute`
+/// sends to matching hosts, see `relay::set_host_limits`. Requests over
+/// the limit wait their turn.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HostLimit {
+    /// Host name pattern, matched case-insensitively with `*` standing for
+    /// any run of characters, e.g. `api.example.com`, `*.example.com` or
+    /// `*`. Only the first matching limit applies.
+    pub host: String,
+    /// Requests per second, fractions allowed. Unlimited when unset.
+    pub rate: Option<f64>,
+    /// Requests that can go out at once after a quiet period, the size of
+    /// the token bucket. Defaults to `rate` rounded up.
+    pub burst: Option<u32>,
+    /// Requests in flight at once. Unlimited when unset.
+    pub max_concurrent: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -346,11 +561,288 @@ pub struct Request {
     pub meta: Option<RequestMeta>,
 }
 
+#[cfg(test)]
+impl Request {
+    /// A bare HTTP/1.1 request with id 1, for tests to fill in.
+    pub(crate) fn test(method: Method, url: &str) -> Self {
+        Self {
+            id: 1,
+            url: url.into(),
+            method,
+            version: Version::HTTP_11,
+            headers: None,
+            params: None,
+            content: None,
+            auth: None,
+            security: None,
+            proxy: None,
+            meta: None,
+        }
+    }
+}
+
+/// A language and HTTP client to write a `Request` out for, see
+/// `relay::snippet::generate`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SnippetTarget {
+    Curl,
+    PythonRequests,
+    PythonHttpx,
+    JavascriptFetch,
+    JavascriptAxios,
+    GoNetHttp,
+    RustReqwest,
+    JavaHttpClient,
+    PowershellRestMethod,
+}
+
+/// One `text/event-stream` event.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SseEvent {
+    /// The `event:` field, `message` when the server sent none.
+    pub event: String,
+    pub data: String,
+    /// The last event ID at dispatch, which `id:` sets and later events
+    /// without one inherit.
+    pub id: Option<String>,
+}
+
+/// What an event stream subscription reports to its subscriber.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum SseMessage {
+    /// The server accepted the (re)connection.
+    Open {
+        status: u16,
+        headers: HashMap<String, String>,
+    },
+    Event(SseEvent),
+    /// The connection dropped and is reopened after `delay_ms`.
+    #[serde(rename_all = "camelCase")]
+    Reconnecting {
+        delay_ms: u64,
+        last_event_id: Option<String>,
+        cause: Option<String>,
+    },
+}
+
+/// A frame to send on an open WebSocket.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum WsFrame {
+    Text {
+        data: String,
+    },
+    Binary {
+        data: Bytes,
+    },
+    Ping {
+        #[serde(default)]
+        data: Bytes,
+    },
+    Pong {
+        #[serde(default)]
+        data: Bytes,
+    },
+    /// Starts the closing handshake. `reason` is only sent with a `code`.
+    Close {
+        code: Option<u16>,
+        #[serde(default)]
+        reason: String,
+    },
+}
+
+/// What a WebSocket session reports to its subscriber.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum WsEvent {
+    /// The upgrade succeeded, `protocol` is the subprotocol the server
+    /// picked.
+    Open {
+        status: u16,
+        headers: HashMap<String, String>,
+        protocol: Option<String>,
+    },
+    Text {
+        data: String,
+    },
+    Binary {
+        data: Bytes,
+    },
+    /// Pings are answered automatically.
+    Ping {
+        data: Bytes,
+    },
+    Pong {
+        data: Bytes,
+    },
+    /// Always the last event. Code 1006 means the connection ended without
+    /// a close frame, with `reason` describing why.
+    Close {
+        code: Option<u16>,
+        reason: String,
+    },
+}
+
+/// The MQTT protocol level to connect with.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum MqttVersion {
+    #[default]
+    #[serde(rename = "3.1.1")]
+    V311,
+    #[serde(rename = "5")]
+    V5,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MqttMessage {
+    pub topic: String,
+    pub payload: Bytes,
+    /// 0, 1 or 2.
+    #[serde(default)]
+    pub qos: u8,
+    #[serde(default)]
+    pub retain: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MqttRequest {
+    pub id: i64,
+    /// `mqtt://` or `tcp://` for plain TCP, `mqtts://` or `ssl://` for TLS.
+    /// The port defaults to 1883 and 8883 respectively.
+    pub url: String,
+    #[serde(default)]
+    pub version: MqttVersion,
+    /// Defaults to `hoppscotch-<id>`.
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// In seconds, defaults to 60.
+    pub keep_alive: Option<u64>,
+    /// Clean session in 3.1.1 and clean start in 5, defaults to true.
+    pub clean_start: Option<bool>,
+    pub last_will: Option<MqttMessage>,
+    pub security: Option<SecurityConfig>,
+}
+
+/// What an MQTT session reports to its subscriber.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum MqttEvent {
+    #[serde(rename_all = "camelCase")]
+    Connected {
+        session_present: bool,
+    },
+    /// The QoS granted for each filter of a subscribe, `None` where the
+    /// broker refused it.
+    Subscribed {
+        granted: Vec<Option<u8>>,
+    },
+    Unsubscribed,
+    Message(MqttMessage),
+    /// Always the last event, `reason` is only set when the connection
+    /// failed rather than being closed.
+    Disconnected {
+        reason: Option<String>,
+    },
+}
+
+/// Where a gRPC call finds its service definitions.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum DescriptorSource {
+    /// Asks the server through `grpc.reflection.v1`, or `v1alpha` on older
+    /// servers.
+    Reflection,
+    /// An encoded `google.protobuf.FileDescriptorSet` with all imports, e.g.
+    /// from `protoc --include_imports --descriptor_set_out`.
+    FileDescriptorSet { data: Bytes },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GrpcRequest {
+    pub id: i64,
+    /// The server, `http://` for cleartext HTTP/2 and `https://` for TLS.
+    pub url: String,
+    /// Fully qualified, e.g. `helloworld.Greeter`. Narrows what
+    /// `grpc::describe` fetches when set.
+    #[serde(default)]
+    pub service: String,
+    #[serde(default)]
+    pub method: String,
+    pub descriptors: DescriptorSource,
+    /// Sent as is, so values of `-bin` keys must already be base64.
+    pub metadata: Option<HashMap<String, String>>,
+    /// Request messages in the protobuf JSON mapping. Unary and server
+    /// streaming methods take exactly one.
+    #[serde(default)]
+    pub messages: Vec<serde_json::Value>,
+    /// Keeps the request stream open after `messages`, so a client or bidi
+    /// streaming call can go on with `grpc::send` until `grpc::close_send`.
+    #[serde(default)]
+    pub keep_open: bool,
+    pub auth: Option<AuthType>,
+    pub security: Option<SecurityConfig>,
+    pub proxy: Option<ProxyConfig>,
+    pub meta: Option<RequestMeta>,
+}
+
+/// How a call ended, `code` is one of the gRPC status codes with 0 for
+/// `OK`. Failures reported by the server are statuses, not errors.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GrpcStatus {
+    pub code: u32,
+    /// The percent-decoded `grpc-message`.
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GrpcResponse {
+    pub id: i64,
+    pub status: GrpcStatus,
+    pub headers: HashMap<String, String>,
+    pub trailers: HashMap<String, String>,
+    /// Response messages in the protobuf JSON mapping, default values
+    /// included.
+    pub messages: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GrpcMethod {
+    pub name: String,
+    pub input_type: String,
+    pub output_type: String,
+    pub client_streaming: bool,
+    pub server_streaming: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GrpcService {
+    /// Fully qualified, e.g. `helloworld.Greeter`.
+    pub name: String,
+    pub methods: Vec<GrpcMethod>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ResponseBody {
     pub body: Bytes,
     pub media_type: MediaType,
+    /// The `Content-Type` header as received, parameters included.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    pub kind: MediaKind,
+    /// Character encoding detected from a BOM, the `Content-Type` charset,
+    /// an XML declaration or an HTML `<meta>`, e.g. `Shift_JIS`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charset: Option<String>,
+    /// The body transcoded to UTF-8, only with `RequestOptions.decode_text`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -368,16 +860,86 @@ pub struct Response {
     pub meta: ResponseMeta,
 }
 
+#[cfg(test)]
+impl Response {
+    /// An HTTP/1.1 `text/plain` response with id 1, for tests to fill in.
+    pub(crate) fn test(status: StatusCode, headers: &[(&str, &str)], body: &[u8]) -> Self {
+        Self {
+            id: 1,
+            status,
+            status_text: status.to_string(),
+            version: Version::HTTP_11,
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            cookies: None,
+            body: ResponseBody {
+                body: Bytes::copy_from_slice(body),
+                media_type: MediaType::TextPlain,
+                mime: None,
+                kind: MediaKind::Text,
+                charset: None,
+                text: None,
+            },
+            meta: ResponseMeta {
+                size: SizeInfo {
+                    headers: 0,
+                    body: body.len() as u64,
+                    total: body.len() as u64,
+                },
+                ..Default::default()
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct ProxyConfig {
-    pub url: String,
+    pub url: Option<String>,
     pub auth: Option<ProxyAuth>,
+    /// NO_PROXY-style bypass entries: `*`, host names (matching their
+    /// subdomains too), `.suffix` domains, IP addresses and CIDR ranges.
+    pub no_proxy: Option<Vec<String>>,
+    /// Honour `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` from
+    /// the environment when neither `url` nor `pac` picks a proxy.
+    pub from_env: Option<bool>,
+    pub pac: Option<PacSource>,
+    /// Let SOCKS proxies resolve host names (`socks4a`/`socks5h`) instead
+    /// of resolving them locally.
+    pub remote_dns: Option<bool>,
+    /// TLS settings for the connection to an `https://` proxy, separate
+    /// from the ones used for the target server.
+    pub security: Option<SecurityConfig>,
+}
+
+/// Where to load a proxy auto-config script from. The script's
+/// `FindProxyForURL` runs locally for every request.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PacSource {
+    Url { url: String },
+    Script { script: String },
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProxyAuth {
     pub username: String,
+    /// Optional, some proxies take a token as the username only.
+    #[serde(default)]
     pub password: String,
+    pub scheme: Option<ProxyAuthScheme>,
+}
+
+/// Proxy authentication scheme, `Any` lets curl negotiate.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyAuthScheme {
+    Any,
+    Basic,
+    Digest,
+    Ntlm,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -402,19 +964,160 @@ pub enum SameSite {
     None,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
 pub struct ResponseMeta {
     pub timing: TimingInfo,
     pub size: SizeInfo,
+    /// Set to the key log path when TLS secrets for this request were
+    /// written to disk, so the frontend can flag the response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_key_log: Option<String>,
+    /// The proxy the request was sent through, `None` when it connected
+    /// directly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Address and port of the last connection curl used, the proxy's
+    /// when going through one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_port: Option<u16>,
+    /// Present when the request body was compressed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionInfo>,
+    /// Set when the body was cut off at `RequestOptions.max_body_size`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<bool>,
+    /// Where the body was written with `RequestOptions.save_to`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub saved_to: Option<SavedBody>,
+    /// Connection and transfer phases of the last transfer, as reported by
+    /// curl.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phases: Option<PhaseTimings>,
+    /// Set when the response came from a cassette instead of the network.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replayed: Option<bool>,
+    /// Every attempt in order, the last one being this response. Only set
+    /// when the request had a `RequestOptions.retry` policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attempts: Option<Vec<RetryAttempt>>,
+    /// Milliseconds the request waited for a `HostLimit` before going out,
+    /// which `timing` doesn't include.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queued: Option<u64>,
+    /// One per `RequestOptions.assertions` entry, in the same order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assertions: Option<Vec<AssertionResult>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryAttempt {
+    /// Response status, `None` when the attempt failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Why the attempt failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Milliseconds the attempt took.
+    pub duration: u64,
+    /// Milliseconds waited before the next attempt, `None` on the last one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay: Option<u64>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResult {
+    pub assertion: Assertion,
+    pub passed: bool,
+    /// What the response had, `None` when there was nothing to compare,
+    /// e.g. a missing header or a JSONPath without matches.
+    pub actual: Option<serde_json::Value>,
+    /// Why the assertion couldn't be checked, e.g. an invalid regex or a
+    /// body that isn't JSON. The assertion fails then.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Milliseconds from the start of the transfer until each phase ended,
+/// `0` for phases that didn't happen, e.g. the DNS lookup and connect on a
+/// reused connection or the TLS handshake over plain HTTP. Time spent on
+/// redirects is only in `redirect` and `total`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseTimings {
+    pub namelookup: f64,
+    pub connect: f64,
+    pub appconnect: f64,
+    pub pretransfer: f64,
+    pub starttransfer: f64,
+    pub total: f64,
+    pub redirect: f64,
+}
+
+/// Records exchanges to, or answers requests from, a cassette file, see
+/// `relay::set_cassette`.
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CassetteConfig {
+    pub mode: CassetteMode,
+    /// The JSON file exchanges are appended to or replayed from.
+    pub path: String,
+    #[serde(default)]
+    pub matching: CassetteMatching,
+    /// Headers written as `[REDACTED]`, on top of `Authorization`,
+    /// `Proxy-Authorization`, `Cookie` and `Set-Cookie`.
+    pub redact_headers: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CassetteMode {
+    /// Sends requests as usual and appends each exchange to the cassette.
+    Record,
+    /// Answers requests from the cassette without touching the network.
+    Replay,
+}
+
+/// What a recorded request has to share with a new one to answer it, on top
+/// of the method and the URL with its query in any order.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CassetteMatching {
+    #[serde(default)]
+    pub body: bool,
+    #[serde(default)]
+    pub headers: bool,
+    /// Headers left out when comparing headers, matched case-insensitively.
+    /// Redacted headers are always left out.
+    #[serde(default)]
+    pub ignore_headers: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SavedBody {
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressionInfo {
+    pub encoding: BodyCompression,
+    pub original_size: u64,
+    pub compressed_size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct TimingInfo {
     pub start: u64,
     pub end: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct SizeInfo {
     pub headers: u64,
     pub body: u64,
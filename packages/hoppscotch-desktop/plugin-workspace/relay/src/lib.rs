@@ -1,14 +1,48 @@
+mod assertion;
 mod auth;
+pub mod batch;
+mod cassette;
+mod charset;
+mod compression;
 mod content;
+pub mod curl_command;
+mod dns;
 pub mod error;
+pub mod grpc;
+pub mod har;
 mod header;
 mod interop;
+mod limit;
+pub mod load_test;
+mod media;
+pub mod mqtt;
+mod multipart;
+mod output;
+mod pac;
+mod proxy;
+mod reflection;
 mod relay;
 mod request;
 mod response;
+mod retry;
 mod security;
+pub mod snippet;
+mod sse;
 mod transfer;
 mod util;
+pub mod websocket;
 
-pub use interop::{Request, Response};
+pub use cassette::{cassette, set_cassette};
+pub use dns::{host_overrides, set_host_overrides};
+pub use interop::{
+    Assertion, AssertionResult, CassetteConfig, CassetteMatching, CassetteMode, ConnectToEntry,
+    DescriptorSource, GrpcMethod, GrpcRequest, GrpcResponse, GrpcService, GrpcStatus, HostLimit,
+    HostOverrides, MqttEvent, MqttMessage, MqttRequest, MqttVersion, PhaseTimings, Request,
+    ResolveEntry, Response, RetryAttempt, RetryErrorKind, RetryPolicy, SnippetTarget, SseEvent,
+    SseMessage, WsEvent, WsFrame,
+};
+pub use limit::{host_limits, set_host_limits};
+pub use output::{resolve_within, save_dir, set_save_dir};
 pub use relay::{cancel, execute};
+pub use security::{key_log_file, set_key_log_file};
+pub use sse::subscribe;
@@ -0,0 +1,304 @@
+use std::{
+    sync::{atomic::AtomicBool, Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{RelayError, Result},
+    interop::HostLimit,
+    util,
+};
+
+/// How often a request waiting on `max_concurrent` checks for a free slot.
+const SLOT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+lazy_static::lazy_static! {
+    /// Limits shared by every request, set by the host app (the agent or
+    /// the desktop app) from its settings.
+    static ref GLOBAL_LIMITS: RwLock<Vec<Arc<Limiter>>> = RwLock::new(Vec::new());
+}
+
+/// Replaces the global limits. Requests already queued keep waiting on the
+/// limits they matched, and requests in flight count against those.
+pub fn set_host_limits(limits: Vec<HostLimit>) {
+    tracing::info!(count = limits.len(), "Updating global host limits");
+
+    let limiters = limits.into_iter().map(Limiter::new).map(Arc::new).collect();
+    match GLOBAL_LIMITS.write() {
+        Ok(mut guard) => *guard = limiters,
+        Err(poisoned) => *poisoned.into_inner() = limiters,
+    }
+}
+
+/// Returns a copy of the global limits.
+pub fn host_limits() -> Vec<HostLimit> {
+    let limiters = match GLOBAL_LIMITS.read() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    };
+    limiters
+        .iter()
+        .map(|limiter| limiter.limit.clone())
+        .collect()
+}
+
+/// Waits until the first limit matching the URL's host lets the request
+/// go out, or fails with `RelayError::Abort` once `cancelled` is set.
+///
+/// The request counts against `max_concurrent` until the returned permit
+/// is dropped. The duration is how long it queued, `None` when no limit
+/// matched.
+pub(crate) fn acquire(url: &str, cancelled: &AtomicBool) -> Result<(Permit, Option<Duration>)> {
+    let Some(limiter) = find(url) else {
+        return Ok((Permit { limiter: None }, None));
+    };
+
+    let started = Instant::now();
+    loop {
+        let wait = match limiter.try_acquire(Instant::now()) {
+            Ok(()) => {
+                let queued = started.elapsed();
+                tracing::debug!(
+                    host = %limiter.limit.host,
+                    queued_ms = queued.as_millis() as u64,
+                    "Acquired host limit"
+                );
+                return Ok((
+                    Permit {
+                        limiter: Some(limiter),
+                    },
+                    Some(queued),
+                ));
+            }
+            Err(wait) => wait,
+        };
+
+        if util::wait(wait, cancelled) {
+            tracing::info!(host = %limiter.limit.host, "Request cancelled while queued");
+            return Err(RelayError::Abort {
+                message: "Request cancelled while waiting for a host limit".into(),
+            });
+        }
+    }
+}
+
+fn find(url: &str) -> Option<Arc<Limiter>> {
+    let url = url::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+
+    let limiters = match GLOBAL_LIMITS.read() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    limiters
+        .iter()
+        .find(|limiter| host_matches(&limiter.limit.host, host))
+        .cloned()
+}
+
+/// Glob match where `*` stands for any run of characters, ignoring case.
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    let host = host.to_ascii_lowercase();
+
+    let mut parts = pattern.split('*');
+    // NOTE: `split` always yields at least one part.
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = host.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Held by a request while it is in flight, freeing its concurrency slot
+/// when dropped.
+pub(crate) struct Permit {
+    limiter: Option<Arc<Limiter>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(ref limiter) = self.limiter {
+            let mut bucket = limiter.bucket();
+            bucket.active = bucket.active.saturating_sub(1);
+        }
+    }
+}
+
+struct Limiter {
+    limit: HostLimit,
+    /// Requests per second, `None` when only concurrency is limited.
+    rate: Option<f64>,
+    capacity: f64,
+    state: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    active: u32,
+}
+
+impl Limiter {
+    fn new(limit: HostLimit) -> Self {
+        let rate = limit.rate.filter(|rate| rate.is_finite() && *rate > 0.0);
+        let capacity = limit
+            .burst
+            .map(f64::from)
+            .or(rate.map(f64::ceil))
+            .unwrap_or(1.0)
+            .max(1.0);
+
+        Self {
+            limit,
+            rate,
+            capacity,
+            state: Mutex::new(Bucket {
+                tokens: capacity,
+                refilled: Instant::now(),
+                active: 0,
+            }),
+        }
+    }
+
+    fn bucket(&self) -> std::sync::MutexGuard<'_, Bucket> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Takes a token and a concurrency slot, or says how long to wait
+    /// before trying again.
+    fn try_acquire(&self, now: Instant) -> std::result::Result<(), Duration> {
+        let mut bucket = self.bucket();
+
+        if let Some(rate) = self.rate {
+            let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rate).min(self.capacity);
+            bucket.refilled = now;
+        }
+
+        if let Some(max) = self.limit.max_concurrent {
+            if bucket.active >= max.max(1) {
+                return Err(SLOT_POLL_INTERVAL);
+            }
+        }
+
+        if let Some(rate) = self.rate {
+            if bucket.tokens < 1.0 {
+                return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate));
+            }
+            bucket.tokens -= 1.0;
+        }
+
+        bucket.active += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+
+    fn limit(host: &str, rate: Option<f64>, burst: Option<u32>, max: Option<u32>) -> HostLimit {
+        HostLimit {
+            host: host.into(),
+            rate,
+            burst,
+            max_concurrent: max,
+        }
+    }
+
+    #[test]
+    fn matches_host_patterns() {
+        assert!(host_matches("api.example.com", "API.example.com"));
+        assert!(!host_matches("api.example.com", "api.example.com.evil"));
+        assert!(host_matches("*.example.com", "a.b.example.com"));
+        assert!(!host_matches("*.example.com", "example.com"));
+        assert!(host_matches("*", "localhost"));
+        assert!(host_matches("api-*.example.*", "api-eu.example.org"));
+        assert!(!host_matches("api-*.example.*", "web-eu.example.org"));
+    }
+
+    #[test]
+    fn refills_tokens_at_rate_up_to_burst() {
+        let limiter = Limiter::new(limit("*", Some(2.0), Some(3), None));
+        let start = limiter.bucket().refilled;
+
+        for _ in 0..3 {
+            assert!(limiter.try_acquire(start).is_ok());
+        }
+        assert_eq!(limiter.try_acquire(start), Err(Duration::from_millis(500)));
+
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.try_acquire(later).is_ok());
+        assert!(limiter.try_acquire(later).is_err());
+
+        // A long pause only refills up to the burst size.
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.try_acquire(much_later).is_ok());
+        }
+        assert!(limiter.try_acquire(much_later).is_err());
+    }
+
+    #[test]
+    fn burst_defaults_to_rate() {
+        assert_eq!(
+            Limiter::new(limit("*", Some(2.5), None, None)).capacity,
+            3.0
+        );
+        assert_eq!(
+            Limiter::new(limit("*", Some(0.2), None, None)).capacity,
+            1.0
+        );
+    }
+
+    #[test]
+    fn limits_concurrency_until_permits_drop() {
+        set_host_limits(vec![
+            limit("limits.test", None, None, Some(1)),
+            limit("*.test", Some(1000.0), None, None),
+        ]);
+        let cancelled = AtomicBool::new(false);
+
+        let (first, queued) = acquire("http://limits.test/a", &cancelled).unwrap();
+        assert!(queued.is_some());
+
+        let (_, unlimited) = acquire("http://other.example/a", &cancelled).unwrap();
+        assert!(unlimited.is_none());
+
+        let waiter = std::thread::spawn(|| {
+            let cancelled = AtomicBool::new(false);
+            acquire("http://LIMITS.test/b", &cancelled).map(|(_, queued)| queued)
+        });
+        std::thread::sleep(Duration::from_millis(100));
+        drop(first);
+        let queued = waiter.join().unwrap().unwrap().unwrap();
+        assert!(queued >= Duration::from_millis(50), "{:?}", queued);
+
+        let (_held, _) = acquire("http://limits.test/c", &cancelled).unwrap();
+        cancelled.store(true, Ordering::SeqCst);
+        assert!(matches!(
+            acquire("http://limits.test/d", &cancelled),
+            Err(RelayError::Abort { .. })
+        ));
+
+        assert_eq!(host_limits().len(), 2);
+        set_host_limits(Vec::new());
+        assert!(host_limits().is_empty());
+    }
+}
@@ -0,0 +1,649 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use curl::easy::Easy;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::{RelayError, Result},
+    interop::Request,
+    relay::{self, ACTIVE_REQUESTS},
+    util,
+};
+
+const DEFAULT_CONCURRENCY: u32 = 1;
+const DEFAULT_REPORT_INTERVAL: u64 = 1000;
+const HISTOGRAM_BUCKETS: usize = 10;
+/// Latencies are counted in 2^7 linear steps per power of two, keeping each
+/// within 1/128 of its real value.
+const SUB_BUCKET_BITS: u32 = 7;
+
+/// How often the test checks for cancellation between reports.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Sends one request over and over to measure how the server holds up.
+/// At least one of `iterations` and `duration` is required, with both the
+/// test ends at whichever comes first.
+///
+/// Requests go straight to the network on reused connections, skipping
+/// cassettes, host limits and retries so they don't skew the numbers.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadTest {
+    /// Cancels the test with `relay::cancel`.
+    pub id: i64,
+    pub request: Request,
+    /// Requests to send in total.
+    pub iterations: Option<u64>,
+    /// Milliseconds to keep starting requests for.
+    pub duration: Option<u64>,
+    /// Requests in flight at once, each on its own connection. Defaults
+    /// to 1.
+    pub concurrency: Option<u32>,
+    /// Requests started per second across all connections, as fast as
+    /// they complete when unset.
+    pub rate: Option<f64>,
+    /// Milliseconds between interim statistics. Defaults to 1000.
+    pub report_interval: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadTestStats {
+    /// Requests that got a response or failed so far.
+    pub completed: u64,
+    /// Requests that failed without a response.
+    pub failed: u64,
+    /// Milliseconds since the test started.
+    pub elapsed: u64,
+    /// Completed requests per second.
+    pub throughput: f64,
+    /// Milliseconds until the whole response arrived, over requests that
+    /// got one. `None` until the first response. With a `rate` this counts
+    /// from when the request was due, so time spent queued behind a slow
+    /// response is included.
+    pub latency: Option<LatencySummary>,
+    /// Latencies split into equal ranges between the fastest and the
+    /// slowest response.
+    pub histogram: Vec<HistogramBucket>,
+    /// Responses per status code.
+    pub statuses: BTreeMap<u16, u64>,
+    /// Failures per `RelayError` kind, e.g. `network` or `timeout`.
+    pub errors: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencySummary {
+    pub min: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistogramBucket {
+    /// Upper bound in milliseconds, inclusive.
+    pub le: f64,
+    pub count: u64,
+}
+
+/// Runs the test, handing interim statistics to `on_progress` every
+/// `report_interval`, and returns the final ones.
+///
+/// The test stops early, with the statistics gathered so far, when it is
+/// cancelled or `on_progress` returns `false`. Requests in flight are then
+/// aborted and left out.
+pub fn run<F>(test: LoadTest, mut on_progress: F) -> Result<LoadTestStats>
+where
+    F: FnMut(&LoadTestStats) -> bool,
+{
+    test.validate()?;
+    let concurrency = test.concurrency.unwrap_or(DEFAULT_CONCURRENCY).max(1);
+    let report_interval = Duration::from_millis(
+        test.report_interval
+            .unwrap_or(DEFAULT_REPORT_INTERVAL)
+            .max(1),
+    );
+
+    tracing::info!(
+        id = test.id,
+        url = %test.request.url,
+        iterations = ?test.iterations,
+        duration_ms = ?test.duration,
+        concurrency,
+        rate = ?test.rate,
+        "Starting load test"
+    );
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    ACTIVE_REQUESTS.insert(test.id, Arc::clone(&cancelled));
+
+    let schedule = Schedule::new(&test, Instant::now());
+    let stopped = AtomicBool::new(false);
+    let cancel_token = CancellationToken::new();
+    let mut recorder = Recorder::default();
+
+    let stop = || {
+        stopped.store(true, Ordering::SeqCst);
+        cancel_token.cancel();
+    };
+
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..concurrency {
+            let sender = sender.clone();
+            let (request, schedule, stopped, cancel_token) =
+                (&test.request, &schedule, &stopped, &cancel_token);
+            scope.spawn(move || {
+                let mut handle = Easy::new();
+                while let Some(at) = schedule.claim() {
+                    if util::wait(at.saturating_duration_since(Instant::now()), stopped) {
+                        break;
+                    }
+
+                    // NOTE: Measured from when the request was due rather
+                    // than when it went out, otherwise a stalled server
+                    // delays the requests that would have seen the stall
+                    // and hides it, see "coordinated omission".
+                    let result = relay::send(&mut handle, request, cancel_token);
+                    let latency = at.elapsed();
+
+                    // NOTE: Requests cut off by stopping failed because of
+                    // it, not because of the server.
+                    if result.is_err() && stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let sample = result.map(|response| (response.status.as_u16(), latency));
+                    if sender.send(sample).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut next_report = schedule.started + report_interval;
+        loop {
+            if cancelled.load(Ordering::SeqCst) && !stopped.load(Ordering::SeqCst) {
+                tracing::info!(id = test.id, "Load test cancelled");
+                stop();
+            }
+
+            let timeout = next_report
+                .saturating_duration_since(Instant::now())
+                .min(CANCEL_POLL_INTERVAL);
+            match receiver.recv_timeout(timeout) {
+                Ok(sample) => recorder.record(sample),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if Instant::now() >= next_report {
+                next_report += report_interval;
+                let stats = recorder.stats(schedule.started.elapsed());
+                if !stopped.load(Ordering::SeqCst) && !on_progress(&stats) {
+                    tracing::info!(id = test.id, "Load test stopped by the listener");
+                    stop();
+                }
+            }
+        }
+    });
+
+    ACTIVE_REQUESTS.remove(&test.id);
+
+    let stats = recorder.stats(schedule.started.elapsed());
+    tracing::info!(
+        id = test.id,
+        completed = stats.completed,
+        failed = stats.failed,
+        throughput = stats.throughput,
+        "Load test finished"
+    );
+    Ok(stats)
+}
+
+impl LoadTest {
+    /// Checks the test can end and its rate makes sense, `run` does this
+    /// first too.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: &str| {
+            Err(RelayError::InvalidLoadTest {
+                message: message.into(),
+            })
+        };
+
+        if self.iterations.is_none() && self.duration.is_none() {
+            return invalid("Either iterations or duration is required");
+        }
+        if self
+            .rate
+            .is_some_and(|rate| !rate.is_finite() || rate <= 0.0)
+        {
+            return invalid("Rate has to be a positive number of requests per second");
+        }
+        Ok(())
+    }
+}
+
+/// Hands out request slots to the workers, spacing their start times out
+/// when the test has a rate.
+struct Schedule {
+    started: Instant,
+    iterations: Option<u64>,
+    deadline: Option<Instant>,
+    rate: Option<f64>,
+    next: AtomicU64,
+}
+
+impl Schedule {
+    fn new(test: &LoadTest, started: Instant) -> Self {
+        Self {
+            started,
+            iterations: test.iterations,
+            deadline: test
+                .duration
+                .map(|duration| started + Duration::from_millis(duration)),
+            rate: test.rate,
+            next: AtomicU64::new(0),
+        }
+    }
+
+    /// When the next request should start, `None` once the test is over.
+    fn claim(&self) -> Option<Instant> {
+        let index = self.next.fetch_add(1, Ordering::SeqCst);
+        if self
+            .iterations
+            .is_some_and(|iterations| index >= iterations)
+        {
+            return None;
+        }
+
+        let at = match self.rate {
+            Some(rate) => self.started + Duration::from_secs_f64(index as f64 / rate),
+            None => Instant::now(),
+        };
+        match self.deadline {
+            Some(deadline) if at >= deadline => None,
+            _ => Some(at),
+        }
+    }
+}
+
+/// Collects outcomes, either a status with its latency or an error.
+#[derive(Default)]
+struct Recorder {
+    latencies: Histogram,
+    statuses: BTreeMap<u16, u64>,
+    errors: BTreeMap<String, u64>,
+    failed: u64,
+}
+
+impl Recorder {
+    fn record(&mut self, sample: Result<(u16, Duration)>) {
+        match sample {
+            Ok((status, latency)) => {
+                self.latencies.record(latency.as_micros() as u64);
+                *self.statuses.entry(status).or_default() += 1;
+            }
+            Err(error) => {
+                self.failed += 1;
+                *self.errors.entry(error.kind().to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    fn stats(&self, elapsed: Duration) -> LoadTestStats {
+        let completed = self.latencies.total + self.failed;
+        let throughput = if elapsed.is_zero() {
+            0.0
+        } else {
+            completed as f64 / elapsed.as_secs_f64()
+        };
+
+        LoadTestStats {
+            completed,
+            failed: self.failed,
+            elapsed: elapsed.as_millis() as u64,
+            throughput,
+            latency: self.latencies.summary(),
+            histogram: self.latencies.buckets(),
+            statuses: self.statuses.clone(),
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+/// Latencies in microseconds, counted the way HdrHistogram does: linear
+/// sub-buckets within each power of two. Recording is constant time and
+/// the counts stay small however long the test runs, at the cost of
+/// percentiles being within 1/128 of the real value. Min, max and mean are
+/// exact.
+#[derive(Default)]
+struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    fn record(&mut self, micros: u64) {
+        let index = Self::index(micros);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+
+        self.min = if self.total == 0 {
+            micros
+        } else {
+            self.min.min(micros)
+        };
+        self.max = self.max.max(micros);
+        self.total += 1;
+        self.sum = self.sum.saturating_add(micros);
+    }
+
+    /// Values below `2^(SUB_BUCKET_BITS + 1)` get a bucket each, above that
+    /// every power of two is split into `2^SUB_BUCKET_BITS` equal buckets.
+    fn index(micros: u64) -> usize {
+        let shift = (u64::BITS - micros.leading_zeros()).saturating_sub(SUB_BUCKET_BITS + 1);
+        ((u64::from(shift) << SUB_BUCKET_BITS) + (micros >> shift)) as usize
+    }
+
+    /// The largest value that lands in bucket `index`.
+    fn value(index: usize) -> u64 {
+        let shift = (index >> SUB_BUCKET_BITS).saturating_sub(1);
+        let sub_bucket = (index - (shift << SUB_BUCKET_BITS)) as u64;
+        (sub_bucket << shift) + ((1 << shift) - 1)
+    }
+
+    /// Non-empty buckets with their value, clamped to what was recorded.
+    fn values(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(index, count)| (Self::value(index).clamp(self.min, self.max), *count))
+    }
+
+    fn summary(&self) -> Option<LatencySummary> {
+        if self.total == 0 {
+            return None;
+        }
+
+        Some(LatencySummary {
+            min: millis(self.min),
+            mean: millis(self.sum) / self.total as f64,
+            p50: millis(self.percentile(50.0)),
+            p90: millis(self.percentile(90.0)),
+            p99: millis(self.percentile(99.0)),
+            max: millis(self.max),
+        })
+    }
+
+    /// Nearest-rank percentile, the histogram must not be empty.
+    fn percentile(&self, percent: f64) -> u64 {
+        let rank = ((percent / 100.0 * self.total as f64).ceil() as u64).clamp(1, self.total);
+        let mut seen = 0;
+        self.values()
+            .find(|(_, count)| {
+                seen += count;
+                seen >= rank
+            })
+            .map_or(self.max, |(value, _)| value)
+    }
+
+    fn buckets(&self) -> Vec<HistogramBucket> {
+        if self.total == 0 {
+            return Vec::new();
+        }
+        if self.max == self.min {
+            return vec![HistogramBucket {
+                le: millis(self.max),
+                count: self.total,
+            }];
+        }
+
+        let width = (self.max - self.min) as f64 / HISTOGRAM_BUCKETS as f64;
+        let mut buckets: Vec<HistogramBucket> = (1..=HISTOGRAM_BUCKETS)
+            .map(|bucket| HistogramBucket {
+                le: millis(self.min) + width * bucket as f64 / 1000.0,
+                count: 0,
+            })
+            .collect();
+        // NOTE: Pin the last bound so rounding can't leave `max` out.
+        buckets[HISTOGRAM_BUCKETS - 1].le = millis(self.max);
+
+        for (value, count) in self.values() {
+            let index = (((value - self.min) as f64 / width).ceil() as usize)
+                .clamp(1, HISTOGRAM_BUCKETS)
+                - 1;
+            buckets[index].count += count;
+        }
+        buckets
+    }
+}
+
+fn millis(micros: u64) -> f64 {
+    micros as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use http::Method;
+
+    use super::*;
+
+    /// Answers every request with an empty 200 after `delay`.
+    fn serve(delay: Duration) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                std::thread::spawn(move || {
+                    let mut stream = stream;
+                    let mut buffer = [0; 4096];
+                    while matches!(stream.read(&mut buffer), Ok(n) if n > 0) {
+                        std::thread::sleep(delay);
+                        if stream
+                            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        url
+    }
+
+    fn test(id: i64, url: &str) -> LoadTest {
+        LoadTest {
+            id,
+            request: Request {
+                id,
+                ..Request::test(Method::GET, url)
+            },
+            iterations: None,
+            duration: None,
+            concurrency: None,
+            rate: None,
+            report_interval: None,
+        }
+    }
+
+    #[test]
+    fn summarizes_latencies() {
+        let mut recorder = Recorder::default();
+        for latency in 1..=100 {
+            recorder.record(Ok((200, Duration::from_millis(latency))));
+        }
+        recorder.record(Ok((503, Duration::from_millis(1))));
+        recorder.record(Err(RelayError::Timeout {
+            message: "Timed out".into(),
+            phase: None,
+        }));
+
+        let stats = recorder.stats(Duration::from_secs(2));
+        let latency = stats.latency.unwrap();
+
+        assert_eq!(stats.completed, 102);
+        assert_eq!(stats.failed, 1);
+        assert_eq!(stats.throughput, 51.0);
+        assert_eq!((latency.min, latency.max), (1.0, 100.0));
+        let close = |value: f64, expected: f64| (value - expected).abs() <= expected / 128.0;
+        assert!(close(latency.p50, 50.0));
+        assert!(close(latency.p90, 90.0));
+        assert!(close(latency.p99, 99.0));
+        assert_eq!(stats.statuses, BTreeMap::from([(200, 100), (503, 1)]));
+        assert_eq!(stats.errors, BTreeMap::from([("timeout".to_string(), 1)]));
+
+        assert_eq!(stats.histogram.len(), HISTOGRAM_BUCKETS);
+        assert_eq!(stats.histogram.iter().map(|b| b.count).sum::<u64>(), 101);
+        assert_eq!(stats.histogram[0].count, 11);
+        assert_eq!(stats.histogram[9].le, 100.0);
+    }
+
+    #[test]
+    fn buckets_keep_latencies_within_precision() {
+        let values = (0..20_000)
+            .chain((0..62).map(|power| 3u64 << power))
+            .chain([u64::MAX]);
+        for value in values {
+            let bucketed = Histogram::value(Histogram::index(value));
+            assert!(bucketed >= value);
+            assert!(bucketed - value <= value >> SUB_BUCKET_BITS, "{}", value);
+        }
+        assert_eq!(Histogram::index(u64::MAX), (58 << SUB_BUCKET_BITS) - 1);
+    }
+
+    #[test]
+    fn reports_nothing_before_the_first_response() {
+        let stats = Recorder::default().stats(Duration::ZERO);
+        assert_eq!(stats, LoadTestStats::default());
+    }
+
+    #[test]
+    fn spaces_out_requests_at_the_rate() {
+        let started = Instant::now();
+        let mut load = test(1, "http://127.0.0.1:9");
+        load.rate = Some(10.0);
+        load.duration = Some(250);
+        let schedule = Schedule::new(&load, started);
+
+        let starts: Vec<Duration> = std::iter::from_fn(|| schedule.claim())
+            .map(|at| at - started)
+            .collect();
+        assert_eq!(starts, [0, 100, 200].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn rejects_tests_without_an_end() {
+        let mut load = test(1, "http://127.0.0.1:9");
+        assert!(matches!(
+            run(load.clone(), |_| true),
+            Err(RelayError::InvalidLoadTest { .. })
+        ));
+
+        load.iterations = Some(1);
+        load.rate = Some(0.0);
+        assert!(matches!(
+            run(load, |_| true),
+            Err(RelayError::InvalidLoadTest { .. })
+        ));
+    }
+
+    #[test]
+    fn runs_iterations_and_streams_progress() {
+        let url = serve(Duration::from_millis(10));
+        let mut load = test(9101, &url);
+        load.iterations = Some(40);
+        load.concurrency = Some(4);
+        load.report_interval = Some(20);
+
+        let mut reports = Vec::new();
+        let stats = run(load, |stats| {
+            reports.push(stats.completed);
+            true
+        })
+        .unwrap();
+
+        assert_eq!(stats.completed, 40);
+        assert_eq!(stats.statuses, BTreeMap::from([(200, 40)]));
+        assert!(stats.latency.unwrap().min >= 10.0);
+        assert!(!reports.is_empty());
+        assert!(reports.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(!ACTIVE_REQUESTS.contains_key(&9101));
+    }
+
+    #[test]
+    fn counts_queueing_behind_slow_responses_at_a_rate() {
+        let url = serve(Duration::from_millis(200));
+        let mut load = test(9104, &url);
+        load.iterations = Some(4);
+        load.rate = Some(20.0);
+
+        // Due every 50ms but served one by one every 200ms, so the last
+        // request waited 450ms before it could go out.
+        let latency = run(load, |_| true).unwrap().latency.unwrap();
+        assert!(latency.min >= 200.0);
+        assert!(latency.max >= 600.0);
+    }
+
+    #[test]
+    fn counts_failures_by_kind() {
+        // NOTE: Nothing listens on the discard port.
+        let mut load = test(9102, "http://127.0.0.1:9");
+        load.iterations = Some(3);
+
+        let stats = run(load, |_| true).unwrap();
+        assert_eq!(stats.failed, 3);
+        assert_eq!(stats.errors.values().sum::<u64>(), 3);
+        assert!(stats.latency.is_none());
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let url = serve(Duration::from_millis(20));
+        let mut load = test(9103, &url);
+        load.duration = Some(60_000);
+        load.concurrency = Some(2);
+
+        let canceller = std::thread::spawn(|| {
+            std::thread::sleep(Duration::from_millis(200));
+            ACTIVE_REQUESTS
+                .get(&9103)
+                .expect("load test is running")
+                .store(true, Ordering::SeqCst);
+        });
+        let started = Instant::now();
+        let stats = run(load, |_| true).unwrap();
+        canceller.join().unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(stats.completed > 0);
+        assert_eq!(stats.failed, 0);
+    }
+}
@@ -0,0 +1,75 @@
+use mime::Mime;
+
+use crate::interop::{MediaKind, MediaType};
+
+/// Application types that are text despite not being `text/*`.
+const TEXTUAL_APPLICATION_TYPES: &[&str] = &[
+    "javascript",
+    "ecmascript",
+    "x-javascript",
+    "x-www-form-urlencoded",
+    "graphql",
+    "yaml",
+    "x-yaml",
+    "toml",
+    "x-sh",
+    "sql",
+];
+
+/// Classifies by structured syntax suffix first, see RFC 6839, so e.g.
+/// `application/problem+json` is JSON and `application/atom+xml` is XML.
+/// `image/svg+xml` stays an image.
+pub(crate) fn classify(media_type: &MediaType) -> MediaKind {
+    let Ok(mime) = media_type.to_string().parse::<Mime>() else {
+        return MediaKind::Binary;
+    };
+
+    if mime.type_() == mime::IMAGE {
+        return MediaKind::Image;
+    }
+
+    let subtype = mime.subtype();
+    let suffix = mime.suffix();
+
+    if subtype == mime::JSON || suffix == Some(mime::JSON) {
+        return MediaKind::Json;
+    }
+
+    if subtype == mime::XML || suffix == Some(mime::XML) {
+        return MediaKind::Xml;
+    }
+
+    if mime.type_() == mime::TEXT
+        || (mime.type_() == mime::APPLICATION
+            && TEXTUAL_APPLICATION_TYPES.contains(&subtype.as_str()))
+    {
+        return MediaKind::Text;
+    }
+
+    MediaKind::Binary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let kind = |value: &str| classify(&value.parse().unwrap());
+
+        assert_eq!(kind("application/json"), MediaKind::Json);
+        assert_eq!(kind("application/problem+json"), MediaKind::Json);
+        assert_eq!(
+            kind("application/vnd.api+json; charset=utf-8"),
+            MediaKind::Json
+        );
+        assert_eq!(kind("text/xml"), MediaKind::Xml);
+        assert_eq!(kind("application/atom+xml"), MediaKind::Xml);
+        assert_eq!(kind("image/svg+xml"), MediaKind::Image);
+        assert_eq!(kind("image/avif"), MediaKind::Image);
+        assert_eq!(kind("text/markdown"), MediaKind::Text);
+        assert_eq!(kind("application/x-www-form-urlencoded"), MediaKind::Text);
+        assert_eq!(kind("application/grpc"), MediaKind::Binary);
+        assert_eq!(kind("not a media type"), MediaKind::Binary);
+    }
+}
@@ -0,0 +1,823 @@
+use std::time::Duration;
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use rumqttc::{
+    v5::{
+        self,
+        mqttbytes::{v5::Packet as V5Packet, QoS as V5QoS},
+    },
+    Outgoing, Packet, QoS, TlsConfiguration, Transport,
+};
+
+use crate::{
+    error::{RelayError, Result},
+    interop::{MqttEvent, MqttMessage, MqttRequest, MqttVersion},
+    security::tls_connector,
+};
+
+const DEFAULT_KEEP_ALIVE: u64 = 60;
+/// Requests `subscribe`, `publish` and friends can queue before they block.
+const REQUEST_CAPACITY: usize = 64;
+/// The client refuses larger packets, in either direction. rumqttc's own
+/// default of 10 KiB is too small for payloads people test with.
+const MAX_PACKET_SIZE: usize = 16 * 1024 * 1024;
+
+lazy_static::lazy_static! {
+    static ref SESSIONS: DashMap<i64, Client> = DashMap::new();
+}
+
+/// Connects to the broker at `request.url` and hands the connection to a
+/// background thread that passes everything the broker sends to `on_event`.
+/// The session is addressed by `request.id` in `subscribe`, `publish` and
+/// `disconnect`, and ends with a `MqttEvent::Disconnected`.
+///
+/// Blocks until the broker accepts or refuses the connection.
+#[tracing::instrument(skip(request, on_event), fields(request_id = request.id), level = "debug")]
+pub fn connect<F>(request: MqttRequest, mut on_event: F) -> Result<()>
+where
+    F: FnMut(MqttEvent) -> bool + Send + 'static,
+{
+    tracing::info!(url = %request.url, version = ?request.version, "Connecting to MQTT broker");
+
+    let id = request.id;
+    let (client, mut connection) = open(&request)?;
+
+    // NOTE: Reserved up front so a concurrent `connect` with the same id
+    // fails instead of replacing this session, and registered before
+    // `Connected` is reported so the callback can already subscribe.
+    match SESSIONS.entry(id) {
+        Entry::Occupied(_) => {
+            return Err(RelayError::Network {
+                message: format!("MQTT session {} is already open", id),
+                cause: None,
+            });
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(client);
+        }
+    }
+
+    let connected = match wait_connected(&mut connection) {
+        Ok(connected) => connected,
+        Err(e) => {
+            SESSIONS.remove(&id);
+            return Err(e);
+        }
+    };
+
+    tracing::info!("MQTT connected");
+    if !on_event(connected) {
+        if let Some((_, client)) = SESSIONS.remove(&id) {
+            let _ = client.disconnect();
+            connection.flush();
+        }
+        return Ok(());
+    }
+
+    std::thread::spawn(move || {
+        let reason = loop {
+            match connection.next() {
+                Step::Event(event) => {
+                    if !on_event(event) {
+                        if let Some((_, client)) = SESSIONS.remove(&id) {
+                            let _ = client.disconnect();
+                            connection.flush();
+                        }
+                        break Some("Subscriber went away".to_string());
+                    }
+                }
+                Step::Idle => {}
+                Step::Closed => break None,
+                Step::Failed { cause, .. } => break Some(cause),
+            }
+        };
+
+        SESSIONS.remove(&id);
+        tracing::info!(reason = ?reason, "MQTT disconnected");
+        on_event(MqttEvent::Disconnected { reason });
+    });
+
+    Ok(())
+}
+
+/// Drives `connection` until the broker answers the CONNECT.
+fn wait_connected(connection: &mut Connection) -> Result<MqttEvent> {
+    loop {
+        match connection.next() {
+            Step::Event(event @ MqttEvent::Connected { .. }) => return Ok(event),
+            Step::Event(_) | Step::Idle => continue,
+            Step::Closed => {
+                return Err(RelayError::Network {
+                    message: "MQTT connection closed before the broker answered".into(),
+                    cause: None,
+                })
+            }
+            Step::Failed { tls, cause } => {
+                tracing::error!(error = %cause, "MQTT connection failed");
+                let message = "MQTT connection failed".to_string();
+                return Err(if tls {
+                    RelayError::Certificate {
+                        message,
+                        cause: Some(cause),
+                    }
+                } else {
+                    RelayError::Network {
+                        message,
+                        cause: Some(cause),
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Subscribes to a topic filter, the broker's answer arrives as
+/// `MqttEvent::Subscribed`.
+#[tracing::instrument(level = "debug")]
+pub fn subscribe(id: i64, topic: String, qos: u8) -> Result<()> {
+    let qos = check_qos(qos)?;
+    with_session(id, |client| client.subscribe(topic, qos))
+}
+
+#[tracing::instrument(level = "debug")]
+pub fn unsubscribe(id: i64, topic: String) -> Result<()> {
+    with_session(id, |client| client.unsubscribe(topic))
+}
+
+#[tracing::instrument(skip(message), fields(topic = %message.topic), level = "debug")]
+pub fn publish(id: i64, message: MqttMessage) -> Result<()> {
+    check_qos(message.qos)?;
+    with_session(id, |client| client.publish(message))
+}
+
+/// Sends DISCONNECT, the session ends once it is out.
+#[tracing::instrument(level = "debug")]
+pub fn disconnect(id: i64) -> Result<()> {
+    with_session(id, Client::disconnect)
+}
+
+fn with_session<T>(id: i64, f: impl FnOnce(&Client) -> Result<T>) -> Result<T> {
+    match SESSIONS.get(&id) {
+        Some(client) => f(&client),
+        None => {
+            tracing::warn!("MQTT session not found");
+            Err(RelayError::Network {
+                message: "MQTT session not found".into(),
+                cause: None,
+            })
+        }
+    }
+}
+
+fn check_qos(qos: u8) -> Result<u8> {
+    if qos > 2 {
+        return Err(RelayError::Parse {
+            message: "MQTT QoS must be 0, 1 or 2".into(),
+            cause: Some(qos.to_string()),
+        });
+    }
+    Ok(qos)
+}
+
+/// Splits an `mqtt://`, `mqtts://`, `tcp://` or `ssl://` URL into host,
+/// port and whether it uses TLS.
+fn broker_address(url: &str) -> Result<(String, u16, bool)> {
+    let invalid = |cause: String| RelayError::Parse {
+        message: "Invalid MQTT broker URL".into(),
+        cause: Some(cause),
+    };
+
+    let parsed = url::Url::parse(url).map_err(|e| invalid(e.to_string()))?;
+    let tls = match parsed.scheme() {
+        "mqtt" | "tcp" => false,
+        "mqtts" | "ssl" => true,
+        scheme => {
+            return Err(invalid(format!(
+                "Unsupported scheme {}, expected mqtt, mqtts, tcp or ssl",
+                scheme
+            )))
+        }
+    };
+    let host = parsed
+        .host_str()
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| invalid(format!("{} has no host", url)))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = parsed.port().unwrap_or(if tls { 8883 } else { 1883 });
+
+    Ok((host, port, tls))
+}
+
+fn open(request: &MqttRequest) -> Result<(Client, Connection)> {
+    let (host, port, tls) = broker_address(&request.url)?;
+    let client_id = request
+        .client_id
+        .clone()
+        .filter(|client_id| !client_id.is_empty())
+        .unwrap_or_else(|| format!("hoppscotch-{}", request.id));
+    let keep_alive = Duration::from_secs(request.keep_alive.unwrap_or(DEFAULT_KEEP_ALIVE));
+    let clean_start = request.clean_start.unwrap_or(true);
+    let transport = if tls {
+        let connector = tls_connector(request.security.as_ref())?;
+        Transport::tls_with_config(TlsConfiguration::NativeConnector(connector))
+    } else {
+        Transport::tcp()
+    };
+    let last_will = request
+        .last_will
+        .as_ref()
+        .map(|will| check_qos(will.qos).map(|qos| (will, qos)))
+        .transpose()?;
+
+    tracing::debug!(host = %host, port, tls, client_id = %client_id, "Opening MQTT connection");
+
+    match request.version {
+        MqttVersion::V311 => {
+            let mut options = rumqttc::MqttOptions::new(client_id, host, port);
+            options
+                .set_keep_alive(keep_alive)
+                .set_clean_session(clean_start)
+                .set_max_packet_size(MAX_PACKET_SIZE, MAX_PACKET_SIZE)
+                .set_transport(transport);
+            if let Some(ref username) = request.username {
+                options.set_credentials(username, request.password.clone().unwrap_or_default());
+            }
+            if let Some((will, qos)) = last_will {
+                options.set_last_will(rumqttc::LastWill::new(
+                    &will.topic,
+                    will.payload.to_vec(),
+                    v4_qos(qos),
+                    will.retain,
+                ));
+            }
+
+            let (client, connection) = rumqttc::Client::new(options, REQUEST_CAPACITY);
+            Ok((Client::V4(client), Connection::V4(Box::new(connection))))
+        }
+        MqttVersion::V5 => {
+            let mut options = v5::MqttOptions::new(client_id, host, port);
+            options
+                .set_keep_alive(keep_alive)
+                .set_clean_start(clean_start)
+                .set_max_packet_size(Some(MAX_PACKET_SIZE as u32))
+                .set_transport(transport);
+            if let Some(ref username) = request.username {
+                options.set_credentials(username, request.password.clone().unwrap_or_default());
+            }
+            if let Some((will, qos)) = last_will {
+                options.set_last_will(v5::mqttbytes::v5::LastWill::new(
+                    &will.topic,
+                    will.payload.to_vec(),
+                    v5_qos(qos),
+                    will.retain,
+                    None,
+                ));
+            }
+
+            let (client, connection) = v5::Client::new(options, REQUEST_CAPACITY);
+            Ok((Client::V5(client), Connection::V5(Box::new(connection))))
+        }
+    }
+}
+
+fn v4_qos(qos: u8) -> QoS {
+    rumqttc::qos(qos).unwrap_or(QoS::AtMostOnce)
+}
+
+fn v5_qos(qos: u8) -> V5QoS {
+    v5::mqttbytes::qos(qos).unwrap_or(V5QoS::AtMostOnce)
+}
+
+enum Client {
+    V4(rumqttc::Client),
+    V5(v5::Client),
+}
+
+impl Client {
+    fn subscribe(&self, topic: String, qos: u8) -> Result<()> {
+        match self {
+            Self::V4(client) => client.subscribe(topic, v4_qos(qos)).map_err(queue_error),
+            Self::V5(client) => client.subscribe(topic, v5_qos(qos)).map_err(queue_error),
+        }
+    }
+
+    fn unsubscribe(&self, topic: String) -> Result<()> {
+        match self {
+            Self::V4(client) => client.unsubscribe(topic).map_err(queue_error),
+            Self::V5(client) => client.unsubscribe(topic).map_err(queue_error),
+        }
+    }
+
+    fn publish(&self, message: MqttMessage) -> Result<()> {
+        match self {
+            Self::V4(client) => client
+                .publish(
+                    message.topic,
+                    v4_qos(message.qos),
+                    message.retain,
+                    message.payload.to_vec(),
+                )
+                .map_err(queue_error),
+            Self::V5(client) => client
+                .publish(
+                    message.topic,
+                    v5_qos(message.qos),
+                    message.retain,
+                    message.payload,
+                )
+                .map_err(queue_error),
+        }
+    }
+
+    fn disconnect(&self) -> Result<()> {
+        match self {
+            Self::V4(client) => client.disconnect().map_err(queue_error),
+            Self::V5(client) => client.disconnect().map_err(queue_error),
+        }
+    }
+}
+
+fn queue_error(e: impl std::fmt::Display) -> RelayError {
+    tracing::error!(error = %e, "Failed to queue MQTT request");
+    RelayError::Network {
+        message: "Failed to queue MQTT request".into(),
+        cause: Some(e.to_string()),
+    }
+}
+
+/// What one turn of the event loop produced.
+enum Step {
+    Event(MqttEvent),
+    /// Traffic the subscriber doesn't see, like acks and pings.
+    Idle,
+    /// The client sent DISCONNECT.
+    Closed,
+    Failed {
+        tls: bool,
+        cause: String,
+    },
+}
+
+enum Connection {
+    V4(Box<rumqttc::Connection>),
+    V5(Box<v5::Connection>),
+}
+
+impl Connection {
+    /// Drives the event loop until a queued DISCONNECT is out, dropping the
+    /// connection before that would cut it off.
+    fn flush(&mut self) {
+        while !matches!(self.next(), Step::Closed | Step::Failed { .. }) {}
+    }
+
+    fn next(&mut self) -> Step {
+        match self {
+            Self::V4(connection) => match connection.recv() {
+                Err(_) => Step::Closed,
+                Ok(Err(e)) => Step::Failed {
+                    tls: matches!(e, rumqttc::ConnectionError::Tls(_)),
+                    cause: e.to_string(),
+                },
+                Ok(Ok(rumqttc::Event::Outgoing(Outgoing::Disconnect))) => Step::Closed,
+                Ok(Ok(rumqttc::Event::Outgoing(_))) => Step::Idle,
+                Ok(Ok(rumqttc::Event::Incoming(packet))) => match packet {
+                    Packet::ConnAck(ack) => Step::Event(MqttEvent::Connected {
+                        session_present: ack.session_present,
+                    }),
+                    Packet::SubAck(ack) => Step::Event(MqttEvent::Subscribed {
+                        granted: ack
+                            .return_codes
+                            .into_iter()
+                            .map(|code| match code {
+                                rumqttc::SubscribeReasonCode::Success(qos) => Some(qos as u8),
+                                rumqttc::SubscribeReasonCode::Failure => None,
+                            })
+                            .collect(),
+                    }),
+                    Packet::UnsubAck(_) => Step::Event(MqttEvent::Unsubscribed),
+                    Packet::Publish(publish) => Step::Event(MqttEvent::Message(MqttMessage {
+                        topic: publish.topic,
+                        payload: publish.payload,
+                        qos: publish.qos as u8,
+                        retain: publish.retain,
+                    })),
+                    _ => Step::Idle,
+                },
+            },
+            Self::V5(connection) => match connection.recv() {
+                Err(_) => Step::Closed,
+                Ok(Err(e)) => Step::Failed {
+                    tls: matches!(e, v5::ConnectionError::Tls(_)),
+                    cause: e.to_string(),
+                },
+                Ok(Ok(v5::Event::Outgoing(Outgoing::Disconnect))) => Step::Closed,
+                Ok(Ok(v5::Event::Outgoing(_))) => Step::Idle,
+                Ok(Ok(v5::Event::Incoming(packet))) => match packet {
+                    V5Packet::ConnAck(ack) => Step::Event(MqttEvent::Connected {
+                        session_present: ack.session_present,
+                    }),
+                    V5Packet::SubAck(ack) => Step::Event(MqttEvent::Subscribed {
+                        granted: ack
+                            .return_codes
+                            .into_iter()
+                            .map(|code| match code {
+                                v5::mqttbytes::v5::SubscribeReasonCode::Success(qos) => {
+                                    Some(qos as u8)
+                                }
+                                _ => None,
+                            })
+                            .collect(),
+                    }),
+                    V5Packet::UnsubAck(_) => Step::Event(MqttEvent::Unsubscribed),
+                    V5Packet::Publish(publish) => Step::Event(MqttEvent::Message(MqttMessage {
+                        topic: String::from_utf8_lossy(&publish.topic).into_owned(),
+                        payload: publish.payload,
+                        qos: publish.qos as u8,
+                        retain: publish.retain,
+                    })),
+                    V5Packet::Disconnect(disconnect) => Step::Failed {
+                        tls: false,
+                        cause: format!("Broker disconnected: {:?}", disconnect.reason_code),
+                    },
+                    _ => Step::Idle,
+                },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+    };
+
+    use bytes::Bytes;
+    use openssl::ssl::{SslAcceptor, SslMethod, SslVerifyMode};
+
+    use super::*;
+    use crate::{
+        interop::{CertificateConfig, CertificateType, SecurityConfig},
+        security::tests::certificate,
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Just enough of a broker to answer one client: acks CONNECT,
+    /// SUBSCRIBE, UNSUBSCRIBE and QoS 1 publishes, and echoes every publish
+    /// back at QoS 0. Returns the packet types it received.
+    fn broker(tls: Option<SslAcceptor>) -> (u16, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+            match tls {
+                Some(acceptor) => acceptor.accept(stream).map(serve).unwrap_or_default(),
+                None => serve(stream),
+            }
+        });
+
+        (port, handle)
+    }
+
+    fn serve(mut stream: impl Read + Write) -> Vec<u8> {
+        let mut received = Vec::new();
+        let mut v5 = false;
+
+        while let Some((header, body)) = read_packet(&mut stream) {
+            let kind = header >> 4;
+            received.push(kind);
+            match kind {
+                1 => {
+                    // Protocol level follows the "MQTT" protocol name.
+                    v5 = body[6] == 5;
+                    let ack: &[u8] = if v5 { &[0, 0, 0] } else { &[0, 0] };
+                    write_packet(&mut stream, 0x20, ack);
+                }
+                3 => {
+                    let qos = (header >> 1) & 3;
+                    let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                    let topic = &body[2..2 + topic_len];
+                    let mut rest = &body[2 + topic_len..];
+                    if qos > 0 {
+                        write_packet(&mut stream, 0x40, &rest[..2]);
+                        rest = &rest[2..];
+                    }
+                    if v5 {
+                        rest = &rest[1 + rest[0] as usize..];
+                    }
+
+                    let mut echo = (topic_len as u16).to_be_bytes().to_vec();
+                    echo.extend_from_slice(topic);
+                    if v5 {
+                        echo.push(0);
+                    }
+                    echo.extend_from_slice(rest);
+                    write_packet(&mut stream, 0x30, &echo);
+                }
+                8 | 10 => {
+                    let mut ack = body[..2].to_vec();
+                    let mut filters = &body[2..];
+                    if v5 {
+                        ack.push(0);
+                        filters = &filters[1 + filters[0] as usize..];
+                    }
+                    while filters.len() >= 2 {
+                        let len = u16::from_be_bytes([filters[0], filters[1]]) as usize;
+                        if kind == 8 {
+                            ack.push(filters[2 + len] & 3);
+                            filters = &filters[3 + len..];
+                        } else {
+                            if v5 {
+                                ack.push(0);
+                            }
+                            filters = &filters[2 + len..];
+                        }
+                    }
+                    write_packet(&mut stream, if kind == 8 { 0x90 } else { 0xB0 }, &ack);
+                }
+                12 => write_packet(&mut stream, 0xD0, &[]),
+                14 => break,
+                _ => {}
+            }
+        }
+
+        received
+    }
+
+    fn read_packet(stream: &mut impl Read) -> Option<(u8, Vec<u8>)> {
+        let mut byte = [0u8];
+        stream.read_exact(&mut byte).ok()?;
+        let header = byte[0];
+
+        let (mut length, mut shift) = (0usize, 0);
+        loop {
+            stream.read_exact(&mut byte).ok()?;
+            length |= ((byte[0] & 0x7F) as usize) << shift;
+            shift += 7;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).ok()?;
+        Some((header, body))
+    }
+
+    fn write_packet(stream: &mut impl Write, header: u8, body: &[u8]) {
+        let mut packet = vec![header];
+        let mut length = body.len();
+        loop {
+            let mut byte = (length % 128) as u8;
+            length /= 128;
+            if length > 0 {
+                byte |= 0x80;
+            }
+            packet.push(byte);
+            if length == 0 {
+                break;
+            }
+        }
+        packet.extend_from_slice(body);
+        stream.write_all(&packet).unwrap();
+    }
+
+    /// An acceptor that requires a client certificate, and the client side
+    /// settings that satisfy it.
+    fn mutual_tls() -> (SslAcceptor, SecurityConfig) {
+        let (server_cert, server_key) = certificate("broker");
+        let (client_cert, client_key) = certificate("client");
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&server_cert).unwrap();
+        acceptor.set_private_key(&server_key).unwrap();
+        acceptor
+            .cert_store_mut()
+            .add_cert(client_cert.clone())
+            .unwrap();
+        acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+
+        let security = SecurityConfig {
+            certificates: Some(CertificateConfig {
+                client: Some(CertificateType::Pem {
+                    cert: client_cert.to_pem().unwrap().into(),
+                    // Traditional RSA encoding, which `native-tls` can't
+                    // read without conversion.
+                    key: client_key
+                        .rsa()
+                        .unwrap()
+                        .private_key_to_pem()
+                        .unwrap()
+                        .into(),
+                }),
+                ca: Some(vec![server_cert.to_pem().unwrap().into()]),
+            }),
+            verify_host: None,
+            verify_peer: None,
+        };
+
+        (acceptor.build(), security)
+    }
+
+    fn round_trip(id: i64, version: MqttVersion, tls: Option<(SslAcceptor, SecurityConfig)>) {
+        let (acceptor, security) = tls.unzip();
+        let scheme = if security.is_some() { "mqtts" } else { "mqtt" };
+        let (port, broker) = broker(acceptor);
+        let (sender, events) = mpsc::channel();
+
+        connect(
+            MqttRequest {
+                id,
+                url: format!("{}://127.0.0.1:{}", scheme, port),
+                version,
+                client_id: None,
+                username: Some("user".into()),
+                password: Some("secret".into()),
+                keep_alive: None,
+                clean_start: None,
+                last_will: None,
+                security,
+            },
+            move |event| sender.send(event).is_ok(),
+        )
+        .unwrap();
+        let next = || events.recv_timeout(TIMEOUT).unwrap();
+
+        assert_eq!(
+            next(),
+            MqttEvent::Connected {
+                session_present: false
+            }
+        );
+
+        subscribe(id, "sensors/#".into(), 1).unwrap();
+        assert_eq!(
+            next(),
+            MqttEvent::Subscribed {
+                granted: vec![Some(1)]
+            }
+        );
+
+        publish(
+            id,
+            MqttMessage {
+                topic: "sensors/temperature".into(),
+                payload: Bytes::from_static(b"21.5"),
+                qos: 1,
+                retain: false,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            next(),
+            MqttEvent::Message(MqttMessage {
+                topic: "sensors/temperature".into(),
+                payload: Bytes::from_static(b"21.5"),
+                qos: 0,
+                retain: false,
+            })
+        );
+
+        unsubscribe(id, "sensors/#".into()).unwrap();
+        assert_eq!(next(), MqttEvent::Unsubscribed);
+
+        disconnect(id).unwrap();
+        assert_eq!(next(), MqttEvent::Disconnected { reason: None });
+        assert!(publish(
+            id,
+            MqttMessage {
+                topic: "sensors/temperature".into(),
+                payload: Bytes::new(),
+                qos: 0,
+                retain: false,
+            }
+        )
+        .is_err());
+
+        assert_eq!(broker.join().unwrap(), vec![1, 8, 3, 10, 14]);
+    }
+
+    #[test]
+    fn round_trip_v311() {
+        round_trip(9001, MqttVersion::V311, None);
+    }
+
+    #[test]
+    fn round_trip_v5() {
+        round_trip(9002, MqttVersion::V5, None);
+    }
+
+    #[test]
+    fn round_trip_with_client_certificate() {
+        round_trip(9004, MqttVersion::V311, Some(mutual_tls()));
+    }
+
+    #[test]
+    fn refusing_the_session_still_disconnects() {
+        let (port, broker) = broker(None);
+
+        connect(
+            MqttRequest {
+                id: 9006,
+                url: format!("mqtt://127.0.0.1:{}", port),
+                version: MqttVersion::V311,
+                client_id: None,
+                username: None,
+                password: None,
+                keep_alive: None,
+                clean_start: None,
+                last_will: None,
+                security: None,
+            },
+            |_| false,
+        )
+        .unwrap();
+
+        assert_eq!(broker.join().unwrap(), vec![1, 14]);
+        assert!(disconnect(9006).is_err());
+    }
+
+    #[test]
+    fn untrusted_broker_is_a_certificate_error() {
+        let (acceptor, _) = mutual_tls();
+        let (port, _broker) = broker(Some(acceptor));
+
+        let result = connect(
+            MqttRequest {
+                id: 9005,
+                url: format!("ssl://127.0.0.1:{}", port),
+                version: MqttVersion::V5,
+                client_id: None,
+                username: None,
+                password: None,
+                keep_alive: None,
+                clean_start: None,
+                last_will: None,
+                security: None,
+            },
+            |_| true,
+        );
+
+        assert!(matches!(result, Err(RelayError::Certificate { .. })));
+        assert!(!SESSIONS.contains_key(&9005));
+    }
+
+    #[test]
+    fn refused_connection_is_an_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_packet(&mut stream).unwrap();
+            // 3.1.1 CONNACK "bad user name or password".
+            write_packet(&mut stream, 0x20, &[0, 4]);
+        });
+
+        let result = connect(
+            MqttRequest {
+                id: 9003,
+                url: format!("tcp://127.0.0.1:{}", port),
+                version: MqttVersion::V311,
+                client_id: Some("client".into()),
+                username: None,
+                password: None,
+                keep_alive: Some(5),
+                clean_start: Some(false),
+                last_will: None,
+                security: None,
+            },
+            |_| true,
+        );
+
+        assert!(matches!(result, Err(RelayError::Network { .. })));
+        // The reserved id is released again.
+        assert!(!SESSIONS.contains_key(&9003));
+        broker.join().unwrap();
+    }
+
+    #[test]
+    fn broker_urls() {
+        assert_eq!(
+            broker_address("mqtt://broker.local").unwrap(),
+            ("broker.local".into(), 1883, false)
+        );
+        assert_eq!(
+            broker_address("mqtts://broker.local").unwrap(),
+            ("broker.local".into(), 8883, true)
+        );
+        assert_eq!(
+            broker_address("ssl://[::1]:9000").unwrap(),
+            ("::1".into(), 9000, true)
+        );
+        assert!(broker_address("http://broker.local").is_err());
+        assert!(check_qos(3).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    error::{RelayError, Result},
+    interop::{FormData, FormValue, MediaType},
+};
+
+/// Characters RFC 2046 allows in a boundary, besides alphanumerics.
+const BOUNDARY_SPECIALS: &str = "'()+_,-./:=? ";
+
+/// A boundary in the same shape curl generates, 24 dashes and 32 random
+/// characters.
+pub(crate) fn generate_boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    let random = || {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        hasher.finish()
+    };
+
+    format!("{}{:016x}{:016x}", "-".repeat(24), random(), random())
+}
+
+pub(crate) fn validate_boundary(boundary: &str) -> Result<()> {
+    let valid = (1..=70).contains(&boundary.len())
+        && !boundary.ends_with(' ')
+        && boundary
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || BOUNDARY_SPECIALS.contains(c));
+
+    if valid {
+        return Ok(());
+    }
+
+    tracing::error!(boundary = %boundary, "Invalid multipart boundary");
+    Err(RelayError::Parse {
+        message: "Invalid multipart boundary".into(),
+        cause: Some("Boundaries are 1 to 70 characters, see RFC 2046 section 5.1.1".into()),
+    })
+}
+
+/// The body's `Content-Type`. `multipart/related` also gets the `type`
+/// parameter naming the root (first) part's media type.
+pub(crate) fn content_type(content: &FormData, media_type: &MediaType, boundary: &str) -> String {
+    let mut value = media_type.to_string();
+
+    if *media_type == MediaType::MultipartRelated {
+        let root_type = content
+            .iter()
+            .flat_map(|(_, values)| values)
+            .next()
+            .and_then(part_content_type);
+
+        if let Some(root_type) = root_type {
+            let essence = root_type.split(';').next().unwrap_or_default().trim();
+            value.push_str(&format!("; type=\"{}\"", essence));
+        }
+    }
+
+    value.push_str(&format!("; boundary={}", quote_boundary(boundary)));
+    value
+}
+
+/// The `boundary` parameter of a `Content-Type` value, unquoted.
+pub(crate) fn boundary(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// `content_type` with its `boundary` parameter set to `boundary`, or
+/// removed with `None`. The media type and other parameters are kept.
+pub(crate) fn with_boundary(content_type: &str, boundary: Option<&str>) -> String {
+    let mut value = content_type
+        .split(';')
+        .filter(|param| {
+            param
+                .split_once('=')
+                .is_none_or(|(name, _)| !name.trim().eq_ignore_ascii_case("boundary"))
+        })
+        .collect::<Vec<_>>()
+        .join(";");
+
+    if let Some(boundary) = boundary {
+        value.push_str(&format!("; boundary={}", quote_boundary(boundary)));
+    }
+    value
+}
+
+/// Serializes the parts in order. `multipart/form-data` parts are named
+/// through `Content-Disposition`, other subtypes carry the key as
+/// `Content-ID` instead, which is what `multipart/related` and batch APIs
+/// reference parts by.
+pub(crate) fn encode(
+    content: &FormData,
+    media_type: &MediaType,
+    boundary: &str,
+) -> Result<Vec<u8>> {
+    let delimiter = format!("--{}", boundary);
+    let form_data = *media_type == MediaType::MultipartFormData;
+    let mut body = Vec::new();
+
+    for (key, values) in content {
+        for value in values {
+            let (data, custom_headers) = match value {
+                FormValue::Text { value, headers, .. } => (value.as_bytes(), headers),
+                FormValue::File { data, headers, .. } => (data.as_ref(), headers),
+            };
+
+            if contains(data, delimiter.as_bytes()) {
+                tracing::error!(key = %key, "Multipart part contains the boundary");
+                return Err(RelayError::Parse {
+                    message: format!("Multipart part '{}' contains the boundary", key),
+                    cause: None,
+                });
+            }
+
+            let mut headers = generated_headers(key, value, form_data);
+
+            if let Some(custom_headers) = custom_headers {
+                let mut custom: Vec<_> = custom_headers.iter().collect();
+                custom.sort();
+
+                for (name, header_value) in custom {
+                    headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+                    headers.push((name.clone(), header_value.clone()));
+                }
+            }
+
+            body.extend_from_slice(delimiter.as_bytes());
+            body.extend_from_slice(b"\r\n");
+            for (name, header_value) in headers {
+                body.extend_from_slice(format!("{}: {}\r\n", name, header_value).as_bytes());
+            }
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n");
+        }
+    }
+
+    body.extend_from_slice(delimiter.as_bytes());
+    body.extend_from_slice(b"--\r\n");
+
+    tracing::debug!(body_length = body.len(), "Encoded multipart body");
+    Ok(body)
+}
+
+fn generated_headers(key: &str, value: &FormValue, form_data: bool) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    let filename = match value {
+        FormValue::File { filename, .. } => Some(filename.as_str()),
+        FormValue::Text { .. } => None,
+    };
+
+    if form_data {
+        let mut disposition = format!("form-data; name=\"{}\"", escape_quoted(key));
+        if let Some(filename) = filename {
+            disposition.push_str(&format!("; filename=\"{}\"", escape_quoted(filename)));
+        }
+        headers.push(("Content-Disposition".to_string(), disposition));
+    } else {
+        if !key.is_empty() {
+            headers.push(("Content-ID".to_string(), format!("<{}>", key)));
+        }
+        if let Some(filename) = filename {
+            headers.push((
+                "Content-Disposition".to_string(),
+                format!("attachment; filename=\"{}\"", escape_quoted(filename)),
+            ));
+        }
+    }
+
+    if let Some(content_type) = part_content_type(value) {
+        headers.push(("Content-Type".to_string(), content_type));
+    }
+
+    headers
+}
+
+fn part_content_type(value: &FormValue) -> Option<String> {
+    match value {
+        FormValue::Text { content_type, .. } => content_type.clone(),
+        FormValue::File { content_type, .. } => Some(content_type.to_string()),
+    }
+}
+
+/// Escapes names and filenames the way browsers do for form-data, see the
+/// HTML "multipart/form-data encoding algorithm".
+fn escape_quoted(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn quote_boundary(boundary: &str) -> String {
+    if boundary
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "'+_-.".contains(c))
+    {
+        boundary.to_string()
+    } else {
+        format!("\"{}\"", boundary)
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_form_data() {
+        let content: FormData = vec![
+            (
+                "policy".into(),
+                vec![FormValue::Text {
+                    value: "{}".into(),
+                    content_type: Some("application/json; charset=utf-8".into()),
+                    headers: None,
+                }],
+            ),
+            (
+                "file".into(),
+                vec![FormValue::File {
+                    filename: "a\"b.txt".into(),
+                    content_type: MediaType::TextPlain,
+                    data: bytes::Bytes::from_static(b"hello"),
+                    headers: Some(HashMap::from([(
+                        "X-Amz-Meta-Tag".to_string(),
+                        "one".to_string(),
+                    )])),
+                }],
+            ),
+        ];
+
+        let body = encode(&content, &MediaType::MultipartFormData, "b0undary").unwrap();
+
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "--b0undary\r\n\
+             Content-Disposition: form-data; name=\"policy\"\r\n\
+             Content-Type: application/json; charset=utf-8\r\n\
+             \r\n\
+             {}\r\n\
+             --b0undary\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"a%22b.txt\"\r\n\
+             Content-Type: text/plain\r\n\
+             X-Amz-Meta-Tag: one\r\n\
+             \r\n\
+             hello\r\n\
+             --b0undary--\r\n"
+        );
+    }
+
+    #[test]
+    fn test_encode_related_with_header_override() {
+        let content: FormData = vec![(
+            "root".into(),
+            vec![FormValue::Text {
+                value: "{}".into(),
+                content_type: Some("application/json".into()),
+                headers: Some(HashMap::from([(
+                    "content-id".to_string(),
+                    "<item-1>".to_string(),
+                )])),
+            }],
+        )];
+
+        let body = encode(&content, &MediaType::MultipartRelated, "x").unwrap();
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "--x\r\nContent-Type: application/json\r\ncontent-id: <item-1>\r\n\r\n{}\r\n--x--\r\n"
+        );
+
+        assert_eq!(
+            content_type(&content, &MediaType::MultipartRelated, "a b"),
+            "multipart/related; type=\"application/json\"; boundary=\"a b\""
+        );
+    }
+
+    #[test]
+    fn test_boundary() {
+        let generated = generate_boundary();
+        assert_eq!(generated.len(), 56);
+        assert!(validate_boundary(&generated).is_ok());
+
+        assert!(validate_boundary("simple-boundary").is_ok());
+        assert!(validate_boundary("").is_err());
+        assert!(validate_boundary("trailing ").is_err());
+        assert!(validate_boundary("semi;colon").is_err());
+        assert!(validate_boundary(&"a".repeat(71)).is_err());
+
+        let content: FormData = vec![(
+            "a".into(),
+            vec![FormValue::Text {
+                value: "--x inside".into(),
+                content_type: None,
+                headers: None,
+            }],
+        )];
+        assert!(encode(&content, &MediaType::MultipartMixed, "x").is_err());
+    }
+
+    #[test]
+    fn test_replace_boundary_parameter() {
+        let value = "multipart/mixed; charset=utf-8; Boundary=\"old one\"";
+        assert_eq!(boundary(value).as_deref(), Some("old one"));
+        assert_eq!(boundary("multipart/mixed"), None);
+
+        assert_eq!(
+            with_boundary(value, Some("new one")),
+            "multipart/mixed; charset=utf-8; boundary=\"new one\""
+        );
+        assert_eq!(
+            with_boundary("multipart/form-data", Some("b0undary")),
+            "multipart/form-data; boundary=b0undary"
+        );
+        assert_eq!(with_boundary(value, None), "multipart/mixed; charset=utf-8");
+    }
+}
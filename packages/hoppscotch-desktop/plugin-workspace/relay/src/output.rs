@@ -0,0 +1,228 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+};
+
+use crate::error::{RelayError, Result};
+
+lazy_static::lazy_static! {
+    /// Where `RequestOptions.save_to` may write, set by the host app. Saving
+    /// bodies is refused while it is unset.
+    static ref SAVE_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Tells temp files written at the same time apart.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Sets the directory response bodies are saved to, or with `None` stops
+/// requests from saving bodies at all.
+pub fn set_save_dir(dir: Option<PathBuf>) {
+    tracing::info!(dir = ?dir, "Updating save directory");
+
+    match SAVE_DIR.write() {
+        Ok(mut guard) => *guard = dir,
+        Err(poisoned) => *poisoned.into_inner() = dir,
+    }
+}
+
+/// Returns the directory response bodies are saved to.
+pub fn save_dir() -> Option<PathBuf> {
+    match SAVE_DIR.read() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+/// Resolves `path` to a file directly or indirectly inside `dir`. Relative
+/// paths are taken from `dir`, absolute ones have to point into it, and
+/// `..` is rejected either way. The file's parent directory has to exist.
+pub fn resolve_within(dir: &Path, path: &str) -> Result<PathBuf> {
+    let outside = |reason: &str| {
+        tracing::warn!(dir = %dir.display(), path, reason, "Rejected output path");
+        RelayError::Parse {
+            message: format!("Output path has to be inside {}: {}", dir.display(), reason),
+            cause: Some(path.to_string()),
+        }
+    };
+
+    let requested = Path::new(path);
+    if requested
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        return Err(outside("'..' is not allowed"));
+    }
+    let Some(name) = requested.file_name() else {
+        return Err(outside("no file name"));
+    };
+
+    let dir = dir.canonicalize().map_err(|e| RelayError::Network {
+        message: format!("Failed to open directory {}", dir.display()),
+        cause: Some(e.to_string()),
+    })?;
+    let joined = dir.join(requested);
+    let parent = joined
+        .parent()
+        .and_then(|parent| parent.canonicalize().ok())
+        .ok_or_else(|| outside("the parent directory doesn't exist"))?;
+
+    // NOTE: Canonicalizing resolves symlinked directories, so a link inside
+    // `dir` can't lead writes out of it.
+    if !parent.starts_with(&dir) {
+        return Err(outside("it is outside"));
+    }
+    Ok(parent.join(name))
+}
+
+/// A file written under a temporary name next to `target` and moved into
+/// place by `persist`. Dropping it unpersisted removes what was written, so
+/// failures never leave an empty or partial file behind.
+#[derive(Debug)]
+pub(crate) struct PendingFile {
+    temp: PathBuf,
+    target: PathBuf,
+    persisted: bool,
+}
+
+impl PendingFile {
+    pub(crate) fn create(target: PathBuf) -> Result<(Self, File)> {
+        let name = target
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let temp = target.with_file_name(format!(
+            ".{}.{}-{}.part",
+            name,
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .map_err(|e| {
+                tracing::error!(error = %e, path = %temp.display(), "Failed to create temp file");
+                RelayError::Network {
+                    message: format!("Failed to create output file '{}'", target.display()),
+                    cause: Some(e.to_string()),
+                }
+            })?;
+
+        Ok((
+            Self {
+                temp,
+                target,
+                persisted: false,
+            },
+            file,
+        ))
+    }
+
+    pub(crate) fn target(&self) -> &Path {
+        &self.target
+    }
+
+    /// Replaces `target` with the written file. The file handle has to be
+    /// closed by then.
+    pub(crate) fn persist(mut self) -> Result<()> {
+        fs::rename(&self.temp, &self.target).map_err(|e| {
+            tracing::error!(error = %e, path = %self.target.display(), "Failed to move output file into place");
+            RelayError::Network {
+                message: format!("Failed to write output file '{}'", self.target.display()),
+                cause: Some(e.to_string()),
+            }
+        })?;
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for PendingFile {
+    fn drop(&mut self) {
+        if self.persisted {
+            return;
+        }
+        if let Err(e) = fs::remove_file(&self.temp) {
+            tracing::warn!(error = %e, path = %self.temp.display(), "Failed to remove temp file");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("relay-output-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn resolves_paths_inside_the_directory() {
+        let dir = temp_dir("resolve");
+
+        assert_eq!(resolve_within(&dir, "a.json").unwrap(), dir.join("a.json"));
+        assert_eq!(
+            resolve_within(&dir, "nested/b.json").unwrap(),
+            dir.join("nested/b.json")
+        );
+        let absolute = dir.join("c.json");
+        assert_eq!(
+            resolve_within(&dir, absolute.to_str().unwrap()).unwrap(),
+            absolute
+        );
+
+        for path in [
+            "../escape.json",
+            "nested/../../escape.json",
+            "/etc/passwd",
+            "missing/d.json",
+        ] {
+            assert!(resolve_within(&dir, path).is_err(), "{}", path);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_out_of_the_directory() {
+        let dir = temp_dir("symlink");
+        std::os::unix::fs::symlink(std::env::temp_dir(), dir.join("out")).unwrap();
+
+        assert!(resolve_within(&dir, "out/escape.json").is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn only_persisted_files_remain() {
+        let dir = temp_dir("pending");
+        let target = dir.join("body.bin");
+        fs::write(&target, b"old").unwrap();
+
+        let (pending, mut file) = PendingFile::create(target.clone()).unwrap();
+        file.write_all(b"partial").unwrap();
+        drop(file);
+        drop(pending);
+        assert_eq!(fs::read(&target).unwrap(), b"old");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        let (pending, mut file) = PendingFile::create(target.clone()).unwrap();
+        file.write_all(b"new").unwrap();
+        drop(file);
+        pending.persist().unwrap();
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
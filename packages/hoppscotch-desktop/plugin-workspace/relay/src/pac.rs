@@ -0,0 +1,589 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    net::{ToSocketAddrs, UdpSocket},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use curl::easy::Easy;
+use rquickjs::{Context, Function, Runtime};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::{RelayError, Result},
+    interop::PacSource,
+};
+
+/// Upper bound on a single `FindProxyForURL` evaluation, PAC files are
+/// untrusted input and a runaway loop must not hang the request thread.
+const PAC_EVAL_TIMEOUT: Duration = Duration::from_secs(2);
+const PAC_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+const PAC_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a fetched PAC script is used before it is fetched again.
+const PAC_CACHE_TTL: Duration = Duration::from_secs(300);
+
+lazy_static::lazy_static! {
+    /// Fetched PAC scripts by URL, with when they were fetched.
+    static ref SCRIPTS: Mutex<HashMap<String, (Instant, String)>> = Mutex::new(HashMap::new());
+}
+
+thread_local! {
+    /// The runtime the last script evaluated on this thread was loaded into,
+    /// reused for as long as requests keep using that script. Per thread so
+    /// a slow `dnsResolve` only holds up its own request.
+    static EVALUATOR: RefCell<Option<Evaluator>> = const { RefCell::new(None) };
+}
+
+/// The standard PAC helper functions that can be expressed in plain
+/// JavaScript. `dnsResolve` and `myIpAddress` need the host and are
+/// registered natively before this runs.
+const PAC_PRELUDE: &str = r#"
+function isPlainHostName(host) {
+    return host.indexOf('.') == -1;
+}
+
+function dnsDomainIs(host, domain) {
+    return host.length >= domain.length &&
+        host.substring(host.length - domain.length) == domain;
+}
+
+function localHostOrDomainIs(host, hostdom) {
+    return host == hostdom || hostdom.lastIndexOf(host + '.', 0) == 0;
+}
+
+function isResolvable(host) {
+    return dnsResolve(host) != null;
+}
+
+function dnsDomainLevels(host) {
+    return host.split('.').length - 1;
+}
+
+function __ipToInt(ip) {
+    var parts = String(ip).split('.');
+    if (parts.length != 4) return null;
+    var value = 0;
+    for (var i = 0; i < 4; i++) {
+        var part = parseInt(parts[i], 10);
+        if (isNaN(part) || part < 0 || part > 255) return null;
+        value = value * 256 + part;
+    }
+    return value;
+}
+
+function isInNet(host, pattern, mask) {
+    var ip = __ipToInt(host);
+    if (ip == null) {
+        var resolved = dnsResolve(host);
+        if (resolved == null) return false;
+        ip = __ipToInt(resolved);
+        if (ip == null) return false;
+    }
+    var p = __ipToInt(pattern);
+    var m = __ipToInt(mask);
+    if (p == null || m == null) return false;
+    for (var i = 0; i < 4; i++) {
+        var shift = Math.pow(256, 3 - i);
+        var mb = Math.floor(m / shift) % 256;
+        if ((Math.floor(ip / shift) % 256 & mb) != (Math.floor(p / shift) % 256 & mb)) {
+            return false;
+        }
+    }
+    return true;
+}
+
+function shExpMatch(str, shexp) {
+    var re = shexp
+        .replace(/[.+^${}()|[\]\\]/g, '\\$&')
+        .replace(/\*/g, '.*')
+        .replace(/\?/g, '.');
+    return new RegExp('^' + re + '$').test(str);
+}
+
+function __stripGmt(args) {
+    var list = Array.prototype.slice.call(args);
+    var gmt = list.length > 0 && list[list.length - 1] == 'GMT';
+    if (gmt) list.pop();
+    return { list: list, gmt: gmt };
+}
+
+var __DAYS = ['SUN', 'MON', 'TUE', 'WED', 'THU', 'FRI', 'SAT'];
+var __MONTHS = ['JAN', 'FEB', 'MAR', 'APR', 'MAY', 'JUN',
+    'JUL', 'AUG', 'SEP', 'OCT', 'NOV', 'DEC'];
+
+function __inRange(start, value, end) {
+    return start <= end ? (start <= value && value <= end)
+        : (value >= start || value <= end);
+}
+
+function weekdayRange() {
+    var a = __stripGmt(arguments);
+    var now = new Date();
+    var today = a.gmt ? now.getUTCDay() : now.getDay();
+    var start = __DAYS.indexOf(a.list[0]);
+    if (start == -1) return false;
+    var end = a.list.length > 1 ? __DAYS.indexOf(a.list[1]) : start;
+    if (end == -1) return false;
+    return __inRange(start, today, end);
+}
+
+function dateRange() {
+    var a = __stripGmt(arguments);
+    var now = new Date();
+    var current = {
+        day: a.gmt ? now.getUTCDate() : now.getDate(),
+        month: a.gmt ? now.getUTCMonth() : now.getMonth(),
+        year: a.gmt ? now.getUTCFullYear() : now.getFullYear()
+    };
+    var parse = function (value) {
+        if (typeof value == 'string' && __MONTHS.indexOf(value) != -1) {
+            return { kind: 'month', value: __MONTHS.indexOf(value) };
+        }
+        var n = parseInt(value, 10);
+        return n > 31 ? { kind: 'year', value: n } : { kind: 'day', value: n };
+    };
+    var parts = a.list.map(parse);
+    if (parts.length == 1) {
+        return current[parts[0].kind] == parts[0].value;
+    }
+    if (parts.length % 2 != 0) return false;
+    var half = parts.length / 2;
+    var weight = { year: 10000, month: 100, day: 1 };
+    var key = function (list, source) {
+        var total = 0;
+        for (var i = 0; i < list.length; i++) {
+            total += (source ? source[list[i].kind] : list[i].value) * weight[list[i].kind];
+        }
+        return total;
+    };
+    var startParts = parts.slice(0, half);
+    var endParts = parts.slice(half);
+    var hasYear = startParts.some(function (p) { return p.kind == 'year'; });
+    var start = key(startParts);
+    var end = key(endParts);
+    var value = key(startParts, current);
+    return hasYear ? (start <= value && value <= end) : __inRange(start, value, end);
+}
+
+function timeRange() {
+    var a = __stripGmt(arguments);
+    var now = new Date();
+    var seconds = (a.gmt ? now.getUTCHours() : now.getHours()) * 3600 +
+        (a.gmt ? now.getUTCMinutes() : now.getMinutes()) * 60 +
+        (a.gmt ? now.getUTCSeconds() : now.getSeconds());
+    var n = a.list.map(function (v) { return parseInt(v, 10); });
+    var start, end;
+    if (n.length == 1) {
+        start = n[0] * 3600;
+        end = start + 3599;
+    } else if (n.length == 2) {
+        start = n[0] * 3600;
+        end = n[1] * 3600 + 3599;
+    } else if (n.length == 4) {
+        start = n[0] * 3600 + n[1] * 60;
+        end = n[2] * 3600 + n[3] * 60 + 59;
+    } else if (n.length == 6) {
+        start = n[0] * 3600 + n[1] * 60 + n[2];
+        end = n[3] * 3600 + n[4] * 60 + n[5];
+    } else {
+        return false;
+    }
+    return __inRange(start, seconds, end);
+}
+"#;
+
+/// Loads the PAC script, either inline or by fetching it. Fetched scripts
+/// are reused for `PAC_CACHE_TTL`, and fetching stops once `cancel_token`
+/// is cancelled.
+#[tracing::instrument(skip(source, cancel_token), level = "debug")]
+pub(crate) fn load_script(
+    source: &PacSource,
+    cancel_token: Option<&CancellationToken>,
+) -> Result<String> {
+    let url = match source {
+        PacSource::Script { script } => return Ok(script.clone()),
+        PacSource::Url { url } => url,
+    };
+
+    let mut scripts = SCRIPTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    scripts.retain(|_, (fetched, _)| fetched.elapsed() < PAC_CACHE_TTL);
+    if let Some((_, script)) = scripts.get(url) {
+        tracing::debug!(url = %url, "Using cached PAC script");
+        return Ok(script.clone());
+    }
+    drop(scripts);
+
+    let script = fetch_script(url, cancel_token)?;
+    SCRIPTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(url.clone(), (Instant::now(), script.clone()));
+    Ok(script)
+}
+
+fn fetch_script(url: &str, cancel_token: Option<&CancellationToken>) -> Result<String> {
+    tracing::debug!(url = %url, "Fetching PAC script");
+
+    let network_error = |message: &str, e: curl::Error| {
+        tracing::error!(error = %e, url = %url, "{}", message);
+        RelayError::Network {
+            message: message.into(),
+            cause: Some(e.to_string()),
+        }
+    };
+
+    let mut handle = Easy::new();
+    handle
+        .url(url)
+        .map_err(|e| network_error("Failed to set PAC URL", e))?;
+    handle
+        .follow_location(true)
+        .map_err(|e| network_error("Failed to set PAC redirect behavior", e))?;
+    handle
+        .timeout(PAC_FETCH_TIMEOUT)
+        .map_err(|e| network_error("Failed to set PAC fetch timeout", e))?;
+    // The PAC file itself must never be fetched through a proxy.
+    handle
+        .proxy("")
+        .map_err(|e| network_error("Failed to disable proxy for PAC fetch", e))?;
+
+    handle
+        .progress(true)
+        .map_err(|e| network_error("Failed to enable PAC progress callback", e))?;
+
+    let cancelled = || cancel_token.is_some_and(CancellationToken::is_cancelled);
+    let mut script = Vec::new();
+    {
+        let mut transfer = handle.transfer();
+        transfer
+            .write_function(|data| {
+                script.extend_from_slice(data);
+                Ok(data.len())
+            })
+            .map_err(|e| network_error("Failed to set PAC write callback", e))?;
+        transfer
+            .progress_function(|_, _, _, _| !cancelled())
+            .map_err(|e| network_error("Failed to set PAC progress callback", e))?;
+        transfer.perform().map_err(|e| {
+            if cancelled() {
+                tracing::info!(url = %url, "PAC fetch cancelled");
+                return RelayError::Abort {
+                    message: "Request cancelled while fetching the PAC script".into(),
+                };
+            }
+            network_error("Failed to fetch PAC script", e)
+        })?;
+    }
+
+    let status = handle
+        .response_code()
+        .map_err(|e| network_error("Failed to get PAC response code", e))?;
+
+    // `file://` URLs report 0, anything else must be a success.
+    if status != 0 && !(200..300).contains(&status) {
+        return Err(RelayError::Network {
+            message: format!("Failed to fetch PAC script: HTTP {}", status),
+            cause: None,
+        });
+    }
+
+    String::from_utf8(script).map_err(|e| RelayError::Parse {
+        message: "PAC script is not valid UTF-8".into(),
+        cause: Some(e.to_string()),
+    })
+}
+
+/// A runtime with the PAC helpers and one script loaded.
+struct Evaluator {
+    script: String,
+    context: Context,
+    /// When the running evaluation has to give up, checked by the
+    /// runtime's interrupt handler.
+    deadline: Arc<Mutex<Instant>>,
+    // NOTE: Declared last, the context has to be dropped before its runtime.
+    _runtime: Runtime,
+}
+
+fn pac_error(message: &str, e: rquickjs::Error) -> RelayError {
+    tracing::error!(error = %e, "{}", message);
+    RelayError::Parse {
+        message: message.into(),
+        cause: Some(e.to_string()),
+    }
+}
+
+impl Evaluator {
+    fn new(script: &str) -> Result<Self> {
+        let runtime = Runtime::new().map_err(|e| pac_error("Failed to start PAC runtime", e))?;
+        runtime.set_memory_limit(PAC_MEMORY_LIMIT);
+
+        let deadline = Arc::new(Mutex::new(Instant::now() + PAC_EVAL_TIMEOUT));
+        let interrupt = Arc::clone(&deadline);
+        runtime.set_interrupt_handler(Some(Box::new(move || {
+            Instant::now() > *interrupt.lock().unwrap_or_else(|p| p.into_inner())
+        })));
+
+        let context =
+            Context::full(&runtime).map_err(|e| pac_error("Failed to create PAC context", e))?;
+
+        context.with(|ctx| {
+            let globals = ctx.globals();
+
+            globals
+                .set(
+                    "dnsResolve",
+                    Function::new(ctx.clone(), |host: String| dns_resolve(&host))
+                        .map_err(|e| pac_error("Failed to register dnsResolve", e))?,
+                )
+                .map_err(|e| pac_error("Failed to register dnsResolve", e))?;
+
+            globals
+                .set(
+                    "myIpAddress",
+                    Function::new(ctx.clone(), my_ip_address)
+                        .map_err(|e| pac_error("Failed to register myIpAddress", e))?,
+                )
+                .map_err(|e| pac_error("Failed to register myIpAddress", e))?;
+
+            ctx.eval::<(), _>(PAC_PRELUDE)
+                .map_err(|e| pac_error("Failed to load PAC helpers", e))?;
+
+            ctx.eval::<(), _>(script)
+                .map_err(|e| pac_error("Failed to evaluate PAC script", e))
+        })?;
+
+        Ok(Self {
+            script: script.to_string(),
+            context,
+            deadline,
+            _runtime: runtime,
+        })
+    }
+
+    fn find_proxy_for_url(&self, url: &str, host: &str) -> Result<String> {
+        *self.deadline.lock().unwrap_or_else(|p| p.into_inner()) =
+            Instant::now() + PAC_EVAL_TIMEOUT;
+
+        self.context.with(|ctx| {
+            let find: Function = ctx
+                .globals()
+                .get("FindProxyForURL")
+                .map_err(|e| pac_error("PAC script does not define FindProxyForURL", e))?;
+
+            find.call::<_, String>((url, host))
+                .map_err(|e| pac_error("FindProxyForURL failed", e))
+        })
+    }
+}
+
+/// Runs `FindProxyForURL(url, host)` from `script` and returns its raw
+/// result, e.g. `"PROXY proxy.corp:8080; DIRECT"`.
+///
+/// The script is compiled once per thread and kept loaded until another one
+/// is evaluated there, or until an evaluation fails and may have left it in
+/// a bad state.
+#[tracing::instrument(skip(script), level = "debug")]
+pub(crate) fn find_proxy_for_url(script: &str, url: &str, host: &str) -> Result<String> {
+    EVALUATOR.with(|evaluator| {
+        let mut evaluator = evaluator.borrow_mut();
+
+        let loaded = match evaluator.take() {
+            Some(loaded) if loaded.script == script => loaded,
+            _ => {
+                tracing::debug!("Loading PAC script");
+                Evaluator::new(script)?
+            }
+        };
+
+        let result = loaded.find_proxy_for_url(url, host);
+        if result.is_ok() {
+            *evaluator = Some(loaded);
+        }
+        result
+    })
+}
+
+/// Picks the first usable entry of a PAC result and converts it to a
+/// proxy URL curl understands. `None` means connect directly.
+pub(crate) fn parse_pac_result(result: &str) -> Option<String> {
+    result.split(';').map(str::trim).find_map(|entry| {
+        let mut parts = entry.split_whitespace();
+        let kind = parts.next()?.to_ascii_uppercase();
+        let target = parts.next();
+
+        match (kind.as_str(), target) {
+            ("DIRECT", _) => Some(None),
+            ("PROXY", Some(target)) => Some(Some(format!("http://{}", target))),
+            ("HTTPS", Some(target)) => Some(Some(format!("https://{}", target))),
+            ("SOCKS" | "SOCKS4", Some(target)) => Some(Some(format!("socks4://{}", target))),
+            ("SOCKS5", Some(target)) => Some(Some(format!("socks5://{}", target))),
+            _ => {
+                tracing::warn!(entry = %entry, "Skipping unrecognized PAC result entry");
+                None
+            }
+        }
+    })?
+}
+
+fn dns_resolve(host: &str) -> Option<String> {
+    (host, 0)
+        .to_socket_addrs()
+        .ok()?
+        .find(|addr| addr.is_ipv4())
+        .map(|addr| addr.ip().to_string())
+}
+
+fn my_ip_address() -> String {
+    // Connecting a UDP socket sends nothing, it only makes the OS pick the
+    // outbound interface, which is what PAC scripts expect here.
+    UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| {
+            socket.connect("192.0.2.1:80")?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|_| "127.0.0.1".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pac_result() {
+        assert_eq!(
+            parse_pac_result("PROXY proxy.corp:8080; DIRECT"),
+            Some("http://proxy.corp:8080".to_string())
+        );
+        assert_eq!(
+            parse_pac_result("SOCKS5 127.0.0.1:1080"),
+            Some("socks5://127.0.0.1:1080".to_string())
+        );
+        assert_eq!(parse_pac_result("DIRECT"), None);
+        assert_eq!(
+            parse_pac_result("BOGUS; HTTPS secure.corp:443"),
+            Some("https://secure.corp:443".to_string())
+        );
+    }
+
+    #[test]
+    fn test_find_proxy_for_url() {
+        let script = r#"
+            function FindProxyForURL(url, host) {
+                if (isPlainHostName(host) || dnsDomainIs(host, ".internal.example")) {
+                    return "DIRECT";
+                }
+                if (shExpMatch(host, "*.example.com")) {
+                    return "PROXY proxy.example.com:3128";
+                }
+                if (isInNet("10.1.2.3", "10.0.0.0", "255.0.0.0")) {
+                    return "SOCKS5 socks.example.com:1080";
+                }
+                return "DIRECT";
+            }
+        "#;
+
+        let result = |url: &str, host: &str| find_proxy_for_url(script, url, host).unwrap();
+
+        assert_eq!(result("http://intranet/", "intranet"), "DIRECT");
+        assert_eq!(
+            result("https://git.internal.example/", "git.internal.example"),
+            "DIRECT"
+        );
+        assert_eq!(
+            result("https://api.example.com/v1", "api.example.com"),
+            "PROXY proxy.example.com:3128"
+        );
+        assert_eq!(
+            result("https://hoppscotch.io/", "hoppscotch.io"),
+            "SOCKS5 socks.example.com:1080"
+        );
+    }
+
+    #[test]
+    fn test_find_proxy_for_url_times_out() {
+        let script = "function FindProxyForURL(url, host) { while (true) {} }";
+        assert!(find_proxy_for_url(script, "http://a.b/", "a.b").is_err());
+    }
+
+    #[test]
+    fn test_fetched_scripts_are_cached() {
+        let path = std::env::temp_dir().join(format!("relay-pac-{}.js", std::process::id()));
+        std::fs::write(&path, "// first").unwrap();
+        let source = PacSource::Url {
+            url: format!("file://{}", path.display()),
+        };
+
+        assert_eq!(load_script(&source, None).unwrap(), "// first");
+        std::fs::write(&path, "// second").unwrap();
+        assert_eq!(load_script(&source, None).unwrap(), "// first");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_fetch_stops_when_cancelled() {
+        // NOTE: Accepts but never answers, so only cancelling ends the fetch.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let source = PacSource::Url {
+            url: format!("http://{}/proxy.pac", listener.local_addr().unwrap()),
+        };
+        let cancel_token = CancellationToken::new();
+        cancel_token.cancel();
+
+        let started = Instant::now();
+        let result = load_script(&source, Some(&cancel_token));
+
+        assert!(matches!(result, Err(RelayError::Abort { .. })));
+        assert!(started.elapsed() < PAC_FETCH_TIMEOUT);
+    }
+
+    #[test]
+    fn test_slow_scripts_only_hold_up_their_own_thread() {
+        let slow = r#"
+            function FindProxyForURL(url, host) {
+                var end = Date.now() + 1000;
+                while (Date.now() < end) {}
+                return "DIRECT";
+            }
+        "#;
+        let fast = "function FindProxyForURL(url, host) { return \"DIRECT\"; }";
+
+        let (started_sender, started) = std::sync::mpsc::channel();
+        let slow = std::thread::spawn(move || {
+            let _ = started_sender.send(());
+            find_proxy_for_url(slow, "http://a.b/", "a.b")
+        });
+        started.recv().unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+
+        let start = Instant::now();
+        assert_eq!(
+            find_proxy_for_url(fast, "http://c.d/", "c.d").unwrap(),
+            "DIRECT"
+        );
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(slow.join().unwrap().unwrap(), "DIRECT");
+    }
+
+    #[test]
+    fn test_evaluator_keeps_the_script_loaded() {
+        let script = r#"
+            var calls = 0;
+            function FindProxyForURL(url, host) {
+                calls++;
+                return "PROXY proxy" + calls + ".example.com:3128";
+            }
+        "#;
+        let evaluator = Evaluator::new(script).unwrap();
+
+        for calls in 1..=3 {
+            assert_eq!(
+                evaluator.find_proxy_for_url("http://a.b/", "a.b").unwrap(),
+                format!("PROXY proxy{}.example.com:3128", calls)
+            );
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use std::{env, net::IpAddr};
+
+use curl::easy::{Auth, Easy};
+use ipnet::IpNet;
+use tokio_util::sync::CancellationToken;
+use url::{Host, Url};
+
+use crate::{
+    error::{RelayError, Result},
+    interop::{ProxyAuth, ProxyAuthScheme, ProxyConfig},
+    pac,
+    security::SecurityHandler,
+};
+
+pub(crate) struct ProxyHandler<'a> {
+    handle: &'a mut Easy,
+    cancel_token: Option<&'a CancellationToken>,
+}
+
+impl<'a> ProxyHandler<'a> {
+    pub(crate) fn new(handle: &'a mut Easy) -> Self {
+        Self {
+            handle,
+            cancel_token: None,
+        }
+    }
+
+    /// Stops fetching a PAC script once `cancel_token` is cancelled.
+    pub(crate) fn with_cancel_token(mut self, cancel_token: Option<&'a CancellationToken>) -> Self {
+        self.cancel_token = cancel_token;
+        self
+    }
+
+    /// Resolves which proxy `url` should go through and configures the
+    /// handle for it. Returns the proxy that was applied with any inline
+    /// credentials stripped, `None` meaning the request connects directly.
+    #[tracing::instrument(skip(self, proxy), level = "debug")]
+    pub(crate) fn configure(&mut self, proxy: &ProxyConfig, url: &str) -> Result<Option<String>> {
+        let target = Url::parse(url).map_err(|e| {
+            tracing::error!(error = %e, "Failed to parse URL for proxy resolution");
+            RelayError::Parse {
+                message: "Failed to parse URL for proxy resolution".into(),
+                cause: Some(e.to_string()),
+            }
+        })?;
+
+        let resolved = resolve(proxy, &target, self.cancel_token)?;
+
+        let Some(ref proxy_url) = resolved else {
+            // An empty string disables the proxy outright, including any
+            // that libcurl would otherwise pick up from the environment.
+            tracing::debug!("Connecting directly");
+            self.handle.proxy("").map_err(|e| RelayError::Network {
+                message: "Failed to disable proxy".into(),
+                cause: Some(e.to_string()),
+            })?;
+            return Ok(None);
+        };
+
+        let proxy_url = if proxy.remote_dns.unwrap_or(false) {
+            with_remote_dns(proxy_url)
+        } else {
+            proxy_url.clone()
+        };
+
+        tracing::debug!(proxy_url = %proxy_url, "Using proxy");
+
+        self.handle
+            .proxy(&proxy_url)
+            .map_err(|e| RelayError::Network {
+                message: "Failed to set proxy".into(),
+                cause: Some(e.to_string()),
+            })?;
+
+        self.configure_auth(proxy.auth.as_ref())?;
+
+        if let Some(ref security) = proxy.security {
+            tracing::trace!("Configuring proxy TLS settings");
+            SecurityHandler::new(self.handle).configure_proxy(security)?;
+        }
+
+        Ok(Some(without_credentials(&proxy_url)))
+    }
+
+    fn configure_auth(&mut self, auth: Option<&ProxyAuth>) -> Result<()> {
+        let scheme = auth
+            .and_then(|auth| auth.scheme)
+            .unwrap_or(ProxyAuthScheme::Any);
+
+        let mut curl_auth = Auth::new();
+        match scheme {
+            ProxyAuthScheme::Any => curl_auth.auto(true),
+            ProxyAuthScheme::Basic => curl_auth.basic(true),
+            ProxyAuthScheme::Digest => curl_auth.digest(true),
+            ProxyAuthScheme::Ntlm => curl_auth.ntlm(true),
+        };
+
+        tracing::debug!(scheme = ?scheme, "Setting proxy authentication scheme");
+        self.handle
+            .proxy_auth(&curl_auth)
+            .map_err(|e| RelayError::Network {
+                message: format!("Failed to set proxy authentication scheme to {:?}", scheme),
+                cause: Some(e.to_string()),
+            })?;
+
+        let Some(auth) = auth else {
+            return Ok(());
+        };
+
+        // A username on its own is valid, token-style proxies expect
+        // exactly that, so only the empty fields are skipped.
+        if !auth.username.trim().is_empty() {
+            self.handle
+                .proxy_username(&auth.username)
+                .map_err(|e| RelayError::Network {
+                    message: "Failed to set proxy username".into(),
+                    cause: Some(e.to_string()),
+                })?;
+        }
+
+        if !auth.password.is_empty() {
+            self.handle
+                .proxy_password(&auth.password)
+                .map_err(|e| RelayError::Network {
+                    message: "Failed to set proxy password".into(),
+                    cause: Some(e.to_string()),
+                })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Maps a failed transfer to a proxy error when the proxy is the part that
+/// failed, `None` when the failure is not attributable to it.
+///
+/// A non-2xx CONNECT response is the proxy refusing the tunnel. Connection
+/// and TLS failures before any CONNECT response are the proxy too, since
+/// with a proxy configured curl never connects to the target directly.
+pub(crate) fn classify_error(handle: &mut Easy, error: &curl::Error) -> Option<RelayError> {
+    let connect_code = handle.http_connectcode().unwrap_or(0);
+
+    if connect_code == 407 {
+        return Some(RelayError::ProxyAuth {
+            message: "Proxy rejected the CONNECT request with 407 Proxy Authentication Required"
+                .into(),
+            cause: Some(error.to_string()),
+        });
+    }
+
+    if connect_code != 0 && !(200..300).contains(&connect_code) {
+        return Some(RelayError::Proxy {
+            message: format!("Proxy rejected the CONNECT request with {}", connect_code),
+            cause: Some(error.to_string()),
+        });
+    }
+
+    let tunnel_established = (200..300).contains(&connect_code);
+
+    if error.is_couldnt_resolve_proxy()
+        || is_curle_proxy(error)
+        || (!tunnel_established && error.is_couldnt_connect())
+    {
+        return Some(RelayError::Proxy {
+            message: "Failed to connect to proxy".into(),
+            cause: Some(error.to_string()),
+        });
+    }
+
+    if !tunnel_established
+        && (error.is_ssl_connect_error()
+            || error.is_peer_failed_verification()
+            || error.is_ssl_cacert())
+    {
+        return Some(RelayError::Proxy {
+            message: "TLS handshake with proxy failed".into(),
+            cause: Some(error.to_string()),
+        });
+    }
+
+    None
+}
+
+/// `CURLE_PROXY` (97), returned for SOCKS and other proxy handshake
+/// failures since curl 7.73. The `curl` crate has no helper for it, and
+/// `CURLcode` is signed on some platforms, hence the untyped literal.
+fn is_curle_proxy(error: &curl::Error) -> bool {
+    error.code() == 97
+}
+
+/// Switches SOCKS URLs to the variants that resolve host names on the
+/// proxy side.
+fn with_remote_dns(proxy_url: &str) -> String {
+    let lower = proxy_url.to_ascii_lowercase();
+    for (local, remote) in [("socks5://", "socks5h://"), ("socks4://", "socks4a://")] {
+        if lower.starts_with(local) {
+            return format!("{}{}", remote, &proxy_url[local.len()..]);
+        }
+    }
+    proxy_url.to_string()
+}
+
+fn without_credentials(proxy_url: &str) -> String {
+    match Url::parse(proxy_url) {
+        Ok(mut parsed) if parsed.has_authority() => {
+            let _ = parsed.set_username("");
+            let _ = parsed.set_password(None);
+            parsed.to_string()
+        }
+        _ => proxy_url.to_string(),
+    }
+}
+
+/// Proxy selection, in order of precedence: the bypass list, a PAC
+/// script, the explicit URL, then the environment if enabled.
+fn resolve(
+    proxy: &ProxyConfig,
+    target: &Url,
+    cancel_token: Option<&CancellationToken>,
+) -> Result<Option<String>> {
+    let host = target.host();
+
+    if let Some(ref no_proxy) = proxy.no_proxy {
+        if is_bypassed(no_proxy.iter().map(String::as_str), host.as_ref()) {
+            tracing::debug!("Target matches proxy bypass list");
+            return Ok(None);
+        }
+    }
+
+    if let Some(ref source) = proxy.pac {
+        let script = pac::load_script(source, cancel_token)?;
+        let host = target.host_str().unwrap_or_default();
+        let result = pac::find_proxy_for_url(&script, target.as_str(), host)?;
+        tracing::debug!(result = %result, "PAC script evaluated");
+        return Ok(pac::parse_pac_result(&result));
+    }
+
+    if let Some(ref url) = proxy.url {
+        if !url.trim().is_empty() {
+            return Ok(Some(url.clone()));
+        }
+    }
+
+    if proxy.from_env.unwrap_or(false) {
+        return Ok(from_env(target));
+    }
+
+    Ok(None)
+}
+
+/// Reads the conventional proxy variables, lowercase first as curl and
+/// most tools do.
+fn from_env(target: &Url) -> Option<String> {
+    let var = |name: &str| {
+        env::var(name.to_lowercase())
+            .or_else(|_| env::var(name.to_uppercase()))
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+    };
+
+    if let Some(no_proxy) = var("no_proxy") {
+        if is_bypassed(no_proxy.split(','), target.host().as_ref()) {
+            tracing::debug!("Target matches NO_PROXY from environment");
+            return None;
+        }
+    }
+
+    let scheme_var = match target.scheme() {
+        "https" => "https_proxy",
+        _ => "http_proxy",
+    };
+
+    var(scheme_var).or_else(|| var("all_proxy"))
+}
+
+/// NO_PROXY-style matching. Entries are `*`, host names (which also match
+/// their subdomains, with or without a leading `.` or `*.`), IP addresses
+/// and CIDR ranges.
+fn is_bypassed<'e>(entries: impl IntoIterator<Item = &'e str>, host: Option<&Host<&str>>) -> bool {
+    let Some(host) = host else {
+        return false;
+    };
+
+    let ip = match host {
+        Host::Ipv4(ip) => Some(IpAddr::V4(*ip)),
+        Host::Ipv6(ip) => Some(IpAddr::V6(*ip)),
+        Host::Domain(_) => None,
+    };
+
+    entries
+        .into_iter()
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            if entry == "*" {
+                return true;
+            }
+
+            if let Ok(net) = entry.parse::<IpNet>() {
+                return ip.is_some_and(|ip| net.contains(&ip));
+            }
+
+            let entry_ip = entry.trim_start_matches('[').trim_end_matches(']');
+            if let Ok(entry_ip) = entry_ip.parse::<IpAddr>() {
+                return ip == Some(entry_ip);
+            }
+
+            let Host::Domain(domain) = host else {
+                return false;
+            };
+
+            let suffix = entry
+                .trim_start_matches("*.")
+                .trim_start_matches('.')
+                .to_ascii_lowercase();
+            let domain = domain.to_ascii_lowercase();
+
+            domain == suffix || domain.ends_with(&format!(".{}", suffix))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bypassed(entries: &[&str], url: &str) -> bool {
+        let url = Url::parse(url).unwrap();
+        is_bypassed(entries.iter().copied(), url.host().as_ref())
+    }
+
+    #[test]
+    fn test_bypass_hosts_and_suffixes() {
+        let entries = [
+            "localhost",
+            ".internal.example",
+            "*.corp.example",
+            "example.org",
+        ];
+
+        assert!(bypassed(&entries, "http://localhost:3000/"));
+        assert!(bypassed(&entries, "https://git.internal.example/"));
+        assert!(bypassed(&entries, "https://a.b.corp.example/"));
+        assert!(bypassed(&entries, "https://example.org/"));
+        assert!(bypassed(&entries, "https://api.EXAMPLE.org/"));
+        assert!(!bypassed(&entries, "https://notexample.org/"));
+        assert!(!bypassed(&entries, "https://hoppscotch.io/"));
+    }
+
+    #[test]
+    fn test_bypass_ips_and_cidrs() {
+        let entries = ["10.0.0.0/8", "192.168.1.20", "::1", "fd00::/8"];
+
+        assert!(bypassed(&entries, "http://10.20.30.40/"));
+        assert!(bypassed(&entries, "http://192.168.1.20:8080/"));
+        assert!(!bypassed(&entries, "http://192.168.1.21/"));
+        assert!(bypassed(&entries, "http://[::1]/"));
+        assert!(bypassed(&entries, "http://[fd12::1]/"));
+        assert!(!bypassed(&entries, "http://ten.example/"));
+    }
+
+    #[test]
+    fn test_with_remote_dns() {
+        assert_eq!(
+            with_remote_dns("socks5://127.0.0.1:1080"),
+            "socks5h://127.0.0.1:1080"
+        );
+        assert_eq!(
+            with_remote_dns("SOCKS4://proxy:1080"),
+            "socks4a://proxy:1080"
+        );
+        assert_eq!(
+            with_remote_dns("socks5h://proxy:1080"),
+            "socks5h://proxy:1080"
+        );
+        assert_eq!(with_remote_dns("http://proxy:3128"), "http://proxy:3128");
+    }
+
+    #[test]
+    fn test_bypass_wildcard() {
+        assert!(bypassed(&["*"], "https://anything.example/"));
+        assert!(!bypassed(&[""], "https://anything.example/"));
+    }
+}
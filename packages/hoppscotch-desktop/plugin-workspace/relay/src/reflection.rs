@@ -0,0 +1,248 @@
+use std::{collections::HashMap, sync::atomic::AtomicBool, sync::mpsc};
+
+use bytes::Bytes;
+use prost::Message;
+use prost_reflect::{prost_types::FileDescriptorProto, DescriptorPool};
+
+use crate::{
+    error::{RelayError, Result},
+    grpc::{exchange, frame},
+    interop::GrpcRequest,
+};
+
+const V1: &str = "/grpc.reflection.v1.ServerReflection/ServerReflectionInfo";
+const V1ALPHA: &str = "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo";
+/// `UNIMPLEMENTED`, what servers without a reflection version answer.
+const UNIMPLEMENTED: u32 = 12;
+
+// NOTE: The subset of `grpc/reflection/v1/reflection.proto` the relay uses,
+// which `v1alpha` shares field for field.
+
+#[derive(Clone, PartialEq, Message)]
+struct ServerReflectionRequest {
+    #[prost(string, tag = "1")]
+    host: String,
+    #[prost(oneof = "MessageRequest", tags = "3, 4, 7")]
+    message_request: Option<MessageRequest>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+enum MessageRequest {
+    #[prost(string, tag = "3")]
+    FileByFilename(String),
+    #[prost(string, tag = "4")]
+    FileContainingSymbol(String),
+    #[prost(string, tag = "7")]
+    ListServices(String),
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ServerReflectionResponse {
+    #[prost(oneof = "MessageResponse", tags = "4, 6, 7")]
+    message_response: Option<MessageResponse>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+#[allow(clippy::enum_variant_names)]
+enum MessageResponse {
+    #[prost(message, tag = "4")]
+    FileDescriptorResponse(FileDescriptorResponse),
+    #[prost(message, tag = "6")]
+    ListServicesResponse(ListServiceResponse),
+    #[prost(message, tag = "7")]
+    ErrorResponse(ErrorResponse),
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FileDescriptorResponse {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    file_descriptor_proto: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ListServiceResponse {
+    #[prost(message, repeated, tag = "1")]
+    service: Vec<ServiceResponse>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ServiceResponse {
+    #[prost(string, tag = "1")]
+    name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ErrorResponse {
+    #[prost(int32, tag = "1")]
+    error_code: i32,
+    #[prost(string, tag = "2")]
+    error_message: String,
+}
+
+/// Builds a pool from the files the server reports for `request.service`,
+/// or for all of its services when unset, with their imports.
+pub(crate) fn load(request: &GrpcRequest) -> Result<DescriptorPool> {
+    let mut client = Client { request, path: V1 };
+
+    let services = if request.service.is_empty() {
+        client.list_services()?
+    } else {
+        vec![request.service.clone()]
+    };
+
+    let mut files = HashMap::new();
+    for service in services {
+        client.add_files(MessageRequest::FileContainingSymbol(service), &mut files)?;
+    }
+
+    // NOTE: Servers usually send the imports along, but aren't required to.
+    loop {
+        let missing: Vec<String> = files
+            .values()
+            .flat_map(|file: &FileDescriptorProto| file.dependency.iter())
+            .filter(|name| !files.contains_key(*name))
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            break;
+        }
+
+        let known = files.len();
+        for name in missing {
+            client.add_files(MessageRequest::FileByFilename(name), &mut files)?;
+        }
+        if files.len() == known {
+            break;
+        }
+    }
+
+    let mut pool = DescriptorPool::new();
+    pool.add_file_descriptor_protos(files.into_values())
+        .map_err(|e| {
+            tracing::error!(error = %e, "Invalid descriptors from server reflection");
+            RelayError::Parse {
+                message: "Server reflection returned invalid descriptors".into(),
+                cause: Some(e.to_string()),
+            }
+        })?;
+
+    Ok(pool)
+}
+
+struct Client<'a> {
+    request: &'a GrpcRequest,
+    path: &'static str,
+}
+
+impl Client<'_> {
+    fn list_services(&mut self) -> Result<Vec<String>> {
+        match self.query(MessageRequest::ListServices(String::new()))? {
+            MessageResponse::ListServicesResponse(response) => Ok(response
+                .service
+                .into_iter()
+                .map(|service| service.name)
+                .collect()),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    fn add_files(
+        &mut self,
+        query: MessageRequest,
+        files: &mut HashMap<String, FileDescriptorProto>,
+    ) -> Result<()> {
+        let MessageResponse::FileDescriptorResponse(response) = self.query(query)? else {
+            return Err(unexpected_response());
+        };
+
+        for data in response.file_descriptor_proto {
+            let file = FileDescriptorProto::decode(data.as_slice()).map_err(|e| {
+                tracing::error!(error = %e, "Invalid file descriptor from server reflection");
+                RelayError::Parse {
+                    message: "Server reflection returned an invalid file descriptor".into(),
+                    cause: Some(e.to_string()),
+                }
+            })?;
+            files.insert(file.name().to_string(), file);
+        }
+
+        Ok(())
+    }
+
+    /// Sends one request on its own stream, switching to `v1alpha` for
+    /// servers that only have that.
+    fn query(&mut self, query: MessageRequest) -> Result<MessageResponse> {
+        tracing::debug!(query = ?query, path = self.path, "Querying server reflection");
+
+        let message = ServerReflectionRequest {
+            host: String::new(),
+            message_request: Some(query.clone()),
+        };
+        let (_, no_more) = mpsc::channel();
+        let mut responses = Vec::new();
+
+        let exchange = exchange(
+            self.request,
+            self.path,
+            vec![frame(&message.encode_to_vec())],
+            no_more,
+            &AtomicBool::new(false),
+            |data: Bytes| {
+                responses.push(data);
+                Ok(true)
+            },
+        )?;
+
+        if exchange.status.code == UNIMPLEMENTED && self.path == V1 {
+            tracing::debug!("Server has no v1 reflection, trying v1alpha");
+            self.path = V1ALPHA;
+            return self.query(query);
+        }
+
+        if exchange.status.code == UNIMPLEMENTED {
+            return Err(RelayError::UnsupportedFeature {
+                feature: "grpc-reflection".into(),
+                message: "The server doesn't support reflection, use a FileDescriptorSet instead"
+                    .into(),
+                relay: "curl".into(),
+            });
+        }
+
+        if exchange.status.code != 0 {
+            tracing::error!(status = ?exchange.status, "Server reflection failed");
+            return Err(RelayError::Network {
+                message: "Server reflection failed".into(),
+                cause: exchange.status.message,
+            });
+        }
+
+        let response = responses
+            .first()
+            .map(|data| ServerReflectionResponse::decode(data.as_ref()))
+            .transpose()
+            .map_err(|e| RelayError::Parse {
+                message: "Invalid server reflection response".into(),
+                cause: Some(e.to_string()),
+            })?
+            .and_then(|response| response.message_response);
+
+        match response {
+            Some(MessageResponse::ErrorResponse(error)) => {
+                tracing::error!(code = error.error_code, message = %error.error_message, "Server reflection error");
+                Err(RelayError::Parse {
+                    message: "Server reflection couldn't resolve the query".into(),
+                    cause: Some(error.error_message),
+                })
+            }
+            Some(response) => Ok(response),
+            None => Err(unexpected_response()),
+        }
+    }
+}
+
+fn unexpected_response() -> RelayError {
+    RelayError::Parse {
+        message: "Unexpected server reflection response".into(),
+        cause: None,
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     sync::{
         atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
         Arc,
     },
     time::SystemTime,
@@ -8,23 +9,42 @@ use std::{
 
 use curl::easy::Easy;
 use dashmap::DashMap;
+use encoding_rs::{Encoding, UTF_8};
 use http::StatusCode;
+use tokio::sync::oneshot;
 use tokio_util::sync::CancellationToken;
 
 use crate::{
+    assertion, cassette, charset,
     error::{RelayError, Result},
     interop::{Request, Response},
+    limit,
     request::CurlRequest,
     response::ResponseHandler,
+    retry,
     transfer::TransferHandler,
+    util::{negotiated_version, phase_timings, CANCEL_POLL_INTERVAL},
 };
 
 lazy_static::lazy_static! {
-    static ref ACTIVE_REQUESTS: DashMap<i64, Arc<AtomicBool>> = DashMap::new();
+    pub(crate) static ref ACTIVE_REQUESTS: DashMap<i64, Arc<AtomicBool>> = DashMap::new();
 }
 
-#[tracing::instrument(skip(request), fields(request_id = request.id), level = "debug")]
-fn execute_request(request: &Request, cancel_token: &CancellationToken) -> Result<Response> {
+pub(crate) fn execute_request(
+    request: &Request,
+    cancel_token: &CancellationToken,
+) -> Result<Response> {
+    send(&mut Easy::new(), request, cancel_token)
+}
+
+/// Sends `request` on `handle`. A handle that sent before is reset first but
+/// keeps its open connections, which callers sending many requests reuse.
+#[tracing::instrument(skip(handle, request, cancel_token), fields(request_id = request.id), level = "debug")]
+pub(crate) fn send(
+    handle: &mut Easy,
+    request: &Request,
+    cancel_token: &CancellationToken,
+) -> Result<Response> {
     tracing::info!(
         method = %request.method,
         url = %request.url,
@@ -32,11 +52,16 @@ fn execute_request(request: &Request, cancel_token: &CancellationToken) -> Resul
     );
 
     let id = request.id;
-    let mut handle = Easy::new();
+    handle.reset();
     let start_time = SystemTime::now();
 
-    let mut curl_request = CurlRequest::new(&mut handle, request);
+    let mut curl_request = CurlRequest::new(handle, request).with_cancel_token(cancel_token);
     curl_request.prepare()?;
+    let proxy = curl_request.proxy().map(str::to_owned);
+    let compression = curl_request.compression().cloned();
+    let key_log_file = curl_request
+        .key_log_file()
+        .map(|path| path.to_string_lossy().into_owned());
 
     tracing::debug!(request = ?request, "Full request details before sending");
 
@@ -56,8 +81,14 @@ fn execute_request(request: &Request, cancel_token: &CancellationToken) -> Resul
             cause: Some(e.to_string()),
         })?;
 
-    let mut transfer_handler = TransferHandler::new();
-    transfer_handler.handle_transfer(&mut handle, cancel_token)?;
+    let options = request.meta.as_ref().and_then(|m| m.options.as_ref());
+    let mut transfer_handler = TransferHandler::new()
+        .with_max_body_size(
+            options.and_then(|o| o.max_body_size),
+            options.and_then(|o| o.body_limit_mode).unwrap_or_default(),
+        )
+        .with_save_to(options.and_then(|o| o.save_to.as_deref()))?;
+    transfer_handler.handle_transfer(handle, cancel_token, proxy.is_some())?;
 
     let status = handle.response_code().map_err(|e| {
         tracing::error!(error = %e, "Failed to get response code");
@@ -75,6 +106,17 @@ fn execute_request(request: &Request, cancel_token: &CancellationToken) -> Resul
         }
     })?;
 
+    let primary_ip = handle.primary_ip().ok().flatten().map(str::to_owned);
+    let primary_port = handle.primary_port().ok().filter(|port| *port != 0);
+    let phases = phase_timings(handle);
+
+    // NOTE: The server can settle on an older version than requested,
+    // e.g. HTTP/2 falling back to HTTP/1.1 when ALPN doesn't offer `h2`.
+    let version = negotiated_version(handle).unwrap_or(request.version);
+
+    let truncated = transfer_handler.truncated();
+    let saved_to = transfer_handler.saved_body();
+    let output = transfer_handler.take_output();
     let (body, headers) = transfer_handler.into_parts();
 
     tracing::info!(
@@ -87,7 +129,15 @@ fn execute_request(request: &Request, cancel_token: &CancellationToken) -> Resul
     // NOTE: If this fails, something has gone very wrong.
     let status_code = StatusCode::from_u16(status).unwrap();
 
-    ResponseHandler::new(
+    if proxy.is_some() && status_code == StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+        tracing::warn!("Proxy responded with 407");
+        return Err(RelayError::ProxyAuth {
+            message: "Proxy responded with 407 Proxy Authentication Required".into(),
+            cause: None,
+        });
+    }
+
+    let mut response = ResponseHandler::new(
         id,
         headers,
         body,
@@ -95,13 +145,78 @@ fn execute_request(request: &Request, cancel_token: &CancellationToken) -> Resul
         header_size,
         start_time,
         SystemTime::now(),
-        request.version.clone(),
+        version,
     )
-    .build()
+    .build()?;
+
+    response.meta.tls_key_log = key_log_file;
+    response.meta.proxy = proxy;
+    response.meta.primary_ip = primary_ip;
+    response.meta.primary_port = primary_port;
+    response.meta.compression = compression;
+    response.meta.truncated = truncated.then_some(true);
+    response.meta.phases = phases;
+
+    // NOTE: The body is only a preview here, sizes are the file's.
+    if let Some(saved) = &saved_to {
+        response.meta.size.body = saved.size;
+        response.meta.size.total = response.meta.size.headers + saved.size;
+    }
+    response.meta.saved_to = saved_to;
+
+    let decode_text = options.and_then(|o| o.decode_text).unwrap_or(false);
+
+    if decode_text {
+        let encoding = response
+            .body
+            .charset
+            .as_deref()
+            .and_then(|name| Encoding::for_label(name.as_bytes()))
+            .or_else(|| charset::is_textual(response.body.kind).then_some(UTF_8));
+
+        if let Some(encoding) = encoding {
+            response.body.text = Some(charset::decode(&response.body.body, encoding));
+        }
+    }
+
+    // NOTE: Only a response that made it this far replaces the file, any
+    // earlier return drops `output` and with it the partial body.
+    if let Some(output) = output {
+        output.persist()?;
+    }
+
+    Ok(response)
 }
 
 #[tracing::instrument(skip(request), fields(request_id = request.id), level = "debug")]
 pub async fn execute(request: Request) -> Result<Response> {
+    // NOTE: `run` blocks for as long as the request queues for a host limit
+    // or backs off between retries, so it gets its own thread rather than
+    // an executor worker that `cancel` may need meanwhile.
+    let (sender, receiver) = oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(run(request));
+    });
+
+    receiver.await.unwrap_or_else(|_| {
+        tracing::error!("Request thread panicked");
+        Err(RelayError::Network {
+            message: "Request thread panicked".into(),
+            cause: None,
+        })
+    })
+}
+
+/// Blocking body of `execute`, for callers that already run on their own
+/// thread.
+pub(crate) fn run(request: Request) -> Result<Response> {
+    if let Some(result) = cassette::replay(&request) {
+        return result.map(|mut response| {
+            assertion::evaluate(&request, &mut response);
+            response
+        });
+    }
+
     let request_id = request.id;
     let cancelled = Arc::new(AtomicBool::new(false));
 
@@ -116,17 +231,44 @@ pub async fn execute(request: Request) -> Result<Response> {
     let cancel_token = CancellationToken::new();
     let cancel_token_clone = cancel_token.clone();
     let cancelled_clone = Arc::clone(&cancelled);
+    let (sender, receiver) = mpsc::channel();
 
     let handle = std::thread::spawn(move || {
-        let result = execute_request(&request, &cancel_token);
-        if cancel_token_clone.is_cancelled() {
-            cancelled_clone.store(true, Ordering::SeqCst);
+        let result = retry::execute(&request, &cancelled_clone, || {
+            let (_permit, queued) = limit::acquire(&request.url, &cancelled_clone)?;
+            execute_request(&request, &cancel_token_clone).map(|mut response| {
+                response.meta.queued = queued.map(|queued| queued.as_millis() as u64);
+                response
+            })
+        });
+        if !cancelled_clone.load(Ordering::SeqCst) {
+            if let Ok(ref response) = result {
+                cassette::record(&request, response);
+            }
         }
-        result
+        let _ = sender.send(result.map(|mut response| {
+            assertion::evaluate(&request, &mut response);
+            response
+        }));
     });
 
-    let result = match handle.join() {
-        Ok(result) => {
+    // NOTE: `cancel` only sets `cancelled`, which curl never sees. Handing
+    // it on to the token is what stops a transfer that is under way.
+    let result = loop {
+        match receiver.recv_timeout(CANCEL_POLL_INTERVAL) {
+            Ok(result) => break Some(result),
+            Err(RecvTimeoutError::Timeout) => {
+                if cancelled.load(Ordering::SeqCst) {
+                    cancel_token.cancel();
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break None,
+        }
+    };
+    let _ = handle.join();
+
+    let result = match result {
+        Some(result) => {
             if cancelled.load(Ordering::SeqCst) {
                 tracing::info!("Request was cancelled by user");
                 Err(RelayError::Abort {
@@ -137,7 +279,7 @@ pub async fn execute(request: Request) -> Result<Response> {
                 result
             }
         }
-        Err(_) => {
+        None => {
             tracing::error!("Request thread panicked");
             Err(RelayError::Network {
                 message: "Request thread panicked".into(),
@@ -173,3 +315,166 @@ pub async fn cancel(request_id: i64) -> Result<()> {
         })
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::UnixListener,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_unix_socket() {
+        let path = std::env::temp_dir().join(format!("relay-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+
+            let body = request_line.trim_end();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "url": "http://localhost/v1.43/containers/json",
+            "method": "GET",
+            "version": "HTTP/1.1",
+            "meta": { "options": { "unixSocket": path.to_str().unwrap() } }
+        }))
+        .unwrap();
+
+        let response = execute_request(&request, &CancellationToken::new()).unwrap();
+        server.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.version, http::Version::HTTP_11);
+        assert_eq!(
+            response.body.body.as_ref(),
+            b"GET /v1.43/containers/json HTTP/1.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel_while_waiting_to_retry() {
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "id": 47,
+            "url": "http://127.0.0.1:9/refused",
+            "method": "GET",
+            "version": "HTTP/1.1",
+            "meta": { "options": { "retry": { "initialDelay": 60000, "jitter": false } } }
+        }))
+        .unwrap();
+
+        // NOTE: The test runtime has a single worker, so this only finishes
+        // if `execute` leaves it free for `cancel`.
+        let started = std::time::Instant::now();
+        let pending = tokio::spawn(execute(request));
+        while !ACTIVE_REQUESTS.contains_key(&47) {
+            tokio::task::yield_now().await;
+        }
+        cancel(47).await.unwrap();
+
+        let error = pending.await.unwrap().unwrap_err();
+        assert!(matches!(error, RelayError::Abort { .. }));
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_body_limit_and_save_to() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("relay-test-limit-{}.sock", std::process::id()));
+        let output = dir.join(format!("relay-test-limit-{}.bin", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let body = "0123456789".repeat(1000);
+
+        let served = body.clone();
+        let server = std::thread::spawn(move || {
+            for _ in 0..4 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let _ = write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    served.len(),
+                    served
+                );
+            }
+        });
+
+        let request = |options: serde_json::Value| -> Request {
+            let mut options = options;
+            options["unixSocket"] = path.to_str().unwrap().into();
+            serde_json::from_value(serde_json::json!({
+                "id": 1,
+                "url": "http://localhost/large",
+                "method": "GET",
+                "version": "HTTP/1.1",
+                "meta": { "options": options }
+            }))
+            .unwrap()
+        };
+
+        let response = execute_request(
+            &request(serde_json::json!({ "maxBodySize": 100 })),
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert_eq!(response.body.body.as_ref(), &body.as_bytes()[..100]);
+        assert_eq!(response.meta.truncated, Some(true));
+
+        let error = execute_request(
+            &request(serde_json::json!({ "maxBodySize": 100, "bodyLimitMode": "error" })),
+            &CancellationToken::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, RelayError::BodyTooLarge { limit: 100, .. }));
+
+        crate::output::set_save_dir(Some(dir.clone()));
+        let error = execute_request(
+            &request(serde_json::json!({
+                "saveTo": output.to_str().unwrap(),
+                "maxBodySize": 100,
+                "bodyLimitMode": "error"
+            })),
+            &CancellationToken::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, RelayError::BodyTooLarge { .. }));
+        assert!(!output.exists());
+
+        let response = execute_request(
+            &request(serde_json::json!({ "saveTo": output.to_str().unwrap() })),
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        server.join().unwrap();
+        let saved = std::fs::read(&output).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&output);
+
+        assert_eq!(saved, body.as_bytes());
+        assert_eq!(response.body.body.len(), 4096);
+        assert_eq!(response.meta.size.body, body.len() as u64);
+        assert_eq!(response.meta.truncated, None);
+        assert_eq!(response.meta.saved_to.map(|saved| saved.size), Some(10000));
+    }
+}
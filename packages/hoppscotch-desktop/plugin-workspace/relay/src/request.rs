@@ -1,19 +1,27 @@
 use curl::easy::Easy;
-use std::{collections::HashMap, ops::Not};
+use std::{collections::HashMap, path::PathBuf};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
     auth::AuthHandler,
     content::ContentHandler,
+    dns::DnsHandler,
     error::{RelayError, Result},
     header::HeadersBuilder,
-    interop::{ApiKeyLocation, AuthType, Request},
-    security::SecurityHandler,
-    util::ToCurlVersion,
+    interop::{ApiKeyLocation, AuthType, CompressionInfo, Request},
+    multipart,
+    proxy::ProxyHandler,
+    security::{self, SecurityHandler},
+    util::{set_http_version, ToCurlVersion},
 };
 
 pub(crate) struct CurlRequest<'a> {
     handle: &'a mut Easy,
     request: &'a Request,
+    proxy: Option<String>,
+    compression: Option<CompressionInfo>,
+    key_log_file: Option<PathBuf>,
+    cancel_token: Option<&'a CancellationToken>,
 }
 
 impl<'a> CurlRequest<'a> {
@@ -24,7 +32,36 @@ impl<'a> CurlRequest<'a> {
             method = %request.method,
             "Creating new curl request"
         );
-        Self { handle, request }
+        Self {
+            handle,
+            request,
+            proxy: None,
+            compression: None,
+            key_log_file: None,
+            cancel_token: None,
+        }
+    }
+
+    /// Lets `prepare` give up on work of its own, like fetching a PAC
+    /// script, once `cancel_token` is cancelled.
+    pub(crate) fn with_cancel_token(mut self, cancel_token: &'a CancellationToken) -> Self {
+        self.cancel_token = Some(cancel_token);
+        self
+    }
+
+    /// The proxy chosen during `prepare`, if any.
+    pub(crate) fn proxy(&self) -> Option<&str> {
+        self.proxy.as_deref()
+    }
+
+    /// How the body was compressed during `prepare`, if it was.
+    pub(crate) fn compression(&self) -> Option<&CompressionInfo> {
+        self.compression.as_ref()
+    }
+
+    /// The file `prepare` set the TLS secrets to be written to, if any.
+    pub(crate) fn key_log_file(&self) -> Option<&PathBuf> {
+        self.key_log_file.as_ref()
     }
 
     #[tracing::instrument(skip(self), fields(request_id = self.request.id), level = "debug")]
@@ -93,15 +130,17 @@ impl<'a> CurlRequest<'a> {
         }
         */
 
-        self.handle
-            .http_version(self.request.version.to_curl_version())
-            .map_err(|e| {
-                tracing::error!(error = %e, "Failed to set HTTP version");
-                RelayError::Network {
-                    message: "Failed to set HTTP version".into(),
-                    cause: Some(e.to_string()),
-                }
-            })?;
+        let version_mode = self
+            .request
+            .meta
+            .as_ref()
+            .and_then(|m| m.options.as_ref())
+            .and_then(|o| o.version_mode)
+            .unwrap_or_default();
+
+        tracing::debug!(version = ?self.request.version, mode = ?version_mode, "Setting HTTP version");
+        let version = self.request.version.to_curl_version(version_mode)?;
+        set_http_version(self.handle, version)?;
 
         // NOTE: `""` corresponds to accept all,
         // see: https://curl.se/libcurl/c/CURLOPT_ACCEPT_ENCODING.html
@@ -195,20 +234,61 @@ impl<'a> CurlRequest<'a> {
             })?;
         }
 
+        if let Some(ref name) = options.abstract_unix_socket {
+            self.set_abstract_unix_socket(name)?;
+        } else if let Some(ref path) = options.unix_socket {
+            tracing::debug!(path = %path, "Connecting through Unix socket");
+            self.handle.unix_socket(path).map_err(|e| {
+                tracing::error!(error = %e, "Failed to set Unix socket");
+                RelayError::Network {
+                    message: format!("Failed to set Unix socket: {}", path),
+                    cause: Some(e.to_string()),
+                }
+            })?;
+        }
+
         tracing::debug!("Basic request parameters set successfully");
         Ok(())
     }
 
+    #[cfg(target_os = "linux")]
+    fn set_abstract_unix_socket(&mut self, name: &str) -> Result<()> {
+        tracing::debug!(name = %name, "Connecting through abstract Unix socket");
+        self.handle
+            .abstract_unix_socket(name.as_bytes())
+            .map_err(|e| {
+                tracing::error!(error = %e, "Failed to set abstract Unix socket");
+                RelayError::Network {
+                    message: format!("Failed to set abstract Unix socket: {}", name),
+                    cause: Some(e.to_string()),
+                }
+            })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_abstract_unix_socket(&mut self, _name: &str) -> Result<()> {
+        Err(RelayError::UnsupportedFeature {
+            feature: "Abstract Unix sockets".into(),
+            message: "Abstract Unix sockets are only available on Linux".into(),
+            relay: "curl".into(),
+        })
+    }
+
     #[tracing::instrument(skip(self), fields(request_id = self.request.id), level = "debug")]
     pub(crate) fn prepare(&mut self) -> Result<()> {
         tracing::debug!("Preparing request");
         self.setup_basics()?;
 
+        let options = self.request.meta.as_ref().and_then(|m| m.options.as_ref());
+        DnsHandler::new(self.handle).configure(options)?;
+
         let mut headers = HashMap::new();
 
         if let Some(ref content) = self.request.content {
             tracing::trace!(content_type = ?content, "Setting request content");
-            ContentHandler::new(self.handle, &mut headers).set_content(content)?;
+            self.compression = ContentHandler::new(self.handle, &mut headers)
+                .with_compression(options.and_then(|o| o.body_compression))
+                .set_content(content)?;
         }
 
         if let Some(ref auth) = self.request.auth {
@@ -225,44 +305,44 @@ impl<'a> CurlRequest<'a> {
             SecurityHandler::new(self.handle).configure(security)?;
         }
 
-        if let Some(ref proxy) = self.request.proxy {
-            tracing::trace!(proxy_url = %proxy.url, "Setting up proxy");
-
-            self.handle
-                .proxy(&proxy.url)
-                .map_err(|e| RelayError::Network {
-                    message: "Failed to set proxy".into(),
-                    cause: Some(e.to_string()),
-                })?;
-
-            self.handle
-                .proxy_auth(curl::easy::Auth::new().auto(true))
-                .map_err(|e| RelayError::Network {
-                    message: "Failed to set proxy authentication to auto".into(),
-                    cause: Some(e.to_string()),
-                })?;
+        if let Some(path) = security::key_log_file() {
+            SecurityHandler::new(self.handle).configure_key_log(&path)?;
+            self.key_log_file = Some(path);
+        }
 
-            if let Some(ref auth) = proxy.auth {
-                if (auth.username.trim().is_empty() || auth.password.trim().is_empty()).not() {
-                    self.handle.proxy_username(&auth.username).map_err(|e| {
-                        RelayError::Network {
-                            message: "Failed to set proxy username".into(),
-                            cause: Some(e.to_string()),
-                        }
-                    })?;
-
-                    self.handle.proxy_password(&auth.password).map_err(|e| {
-                        RelayError::Network {
-                            message: "Failed to set proxy password".into(),
-                            cause: Some(e.to_string()),
-                        }
-                    })?;
-                }
-            }
+        if let Some(ref proxy) = self.request.proxy {
+            tracing::trace!(proxy_url = ?proxy.url, "Setting up proxy");
+            self.proxy = ProxyHandler::new(self.handle)
+                .with_cancel_token(self.cancel_token)
+                .configure(proxy, &self.request.url)?;
         }
 
         if let Some(ref request_headers) = self.request.headers {
-            headers.extend(request_headers.clone());
+            let mut request_headers = request_headers.clone();
+
+            // NOTE: A multipart body only parses with the boundary it was
+            // built with. An explicit `Content-Type` keeps its media type but
+            // gets that boundary, or none for curl's form API, which appends
+            // its own.
+            let derived_type = headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case("content-type"))
+                .map(|(_, value)| value.clone())
+                .filter(|value| value.to_ascii_lowercase().starts_with("multipart/"));
+            if let Some(derived_type) = derived_type {
+                let boundary = multipart::boundary(&derived_type);
+                request_headers
+                    .iter_mut()
+                    .filter(|(key, _)| key.eq_ignore_ascii_case("content-type"))
+                    .for_each(|(_, value)| {
+                        *value = multipart::with_boundary(value, boundary.as_deref())
+                    });
+            }
+
+            // Explicit headers win over the ones derived from the content and
+            // auth, whatever their casing.
+            headers.retain(|key, _| !request_headers.keys().any(|k| k.eq_ignore_ascii_case(key)));
+            headers.extend(request_headers);
             HeadersBuilder::new(self.handle).add_headers(Some(&headers))?;
         } else if !headers.is_empty() {
             HeadersBuilder::new(self.handle).add_headers(Some(&headers))?;
@@ -1,15 +1,18 @@
 use std::{collections::HashMap, str::FromStr, time::SystemTime};
 
 use bytes::Bytes;
+use encoding_rs::Encoding;
 use http::{StatusCode, Version};
 use mime::Mime;
 use time::OffsetDateTime;
 
 use crate::{
+    charset,
     error::{RelayError, Result},
     interop::{
         Cookie, MediaType, Response, ResponseBody, ResponseMeta, SameSite, SizeInfo, TimingInfo,
     },
+    media,
 };
 
 pub(crate) struct ResponseHandler {
@@ -68,9 +71,16 @@ impl ResponseHandler {
 
         let cookies = self.parse_cookies();
 
+        let charset = self.detect_charset();
+        let mime = self.content_type().map(str::to_string);
+
         let body = ResponseBody {
             body: self.body,
+            kind: media::classify(&media_type),
             media_type,
+            mime,
+            charset: charset.map(|encoding| encoding.name().to_string()),
+            text: None,
         };
 
         Ok(Response {
@@ -80,7 +90,11 @@ impl ResponseHandler {
             version: self.version,
             cookies,
             headers: self.headers,
-            meta: ResponseMeta { timing, size },
+            meta: ResponseMeta {
+                timing,
+                size,
+                ..Default::default()
+            },
             body,
         })
     }
@@ -148,45 +162,33 @@ impl ResponseHandler {
         }
     }
 
+    fn content_type(&self) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("content-type"))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The `Content-Type` essence, kept as `MediaType::Other` when there's no
+    /// variant for it, else a type sniffed from the body.
     fn determine_media_type(&self) -> MediaType {
         tracing::trace!("Determining response content type");
 
-        self.headers
-            .iter()
-            .find_map(|(k, v)| {
-                if k.to_lowercase() == "content-type" {
-                    v.parse::<Mime>()
-                        .ok()
-                        .and_then(|mime| match (mime.type_(), mime.subtype()) {
-                            (mime::APPLICATION, mime::JSON) => Some(MediaType::Json),
-                            (mime::APPLICATION, mime::XML) => Some(MediaType::Xml),
-                            (mime::APPLICATION, mime::OCTET_STREAM) => Some(MediaType::OctetStream),
-                            (mime::TEXT, mime::PLAIN) => Some(MediaType::TextPlain),
-                            (mime::TEXT, mime::HTML) => Some(MediaType::TextHtml),
-                            (mime::TEXT, mime::CSS) => Some(MediaType::TextCss),
-                            (mime::TEXT, mime::CSV) => Some(MediaType::TextCsv),
-                            (mime::TEXT, mime::XML) => Some(MediaType::TextXml),
-                            (mime::APPLICATION, mime::WWW_FORM_URLENCODED) => {
-                                Some(MediaType::FormUrlEncoded)
-                            }
-                            (mime::APPLICATION, name) if name == "ld+json" => {
-                                Some(MediaType::JsonLd)
-                            }
-                            (mime::MULTIPART, name) if name == "form-data" => {
-                                Some(MediaType::MultipartFormData)
-                            }
-                            _ => None,
-                        })
-                } else {
-                    None
-                }
+        self.content_type()
+            .and_then(|value| value.parse::<Mime>().ok())
+            .and_then(|mime| MediaType::from_str(mime.essence_str()).ok())
+            .or_else(|| {
+                infer::get(&self.body).and_then(|kind| MediaType::from_str(kind.mime_type()).ok())
             })
-            .or(infer::get(&self.body)
-                .map(|kind| MediaType::from_str(kind.mime_type()).ok())
-                .flatten())
             .unwrap_or(MediaType::TextPlain)
     }
 
+    fn detect_charset(&self) -> Option<&'static Encoding> {
+        let charset = charset::detect(self.content_type(), &self.body);
+        tracing::debug!(charset = ?charset.map(Encoding::name), "Detected response charset");
+        charset
+    }
+
     fn calculate_timing(&self) -> Result<TimingInfo> {
         let start_ms = self
             .start_time
@@ -0,0 +1,450 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::AtomicBool,
+    time::{Duration, Instant},
+};
+
+use http::Method;
+use time::{format_description::well_known::Rfc2822, OffsetDateTime};
+
+use crate::{
+    error::{RelayError, Result},
+    interop::{Request, Response, RetryAttempt, RetryErrorKind, RetryPolicy},
+    util,
+};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_STATUSES: [u16; 6] = [408, 429, 500, 502, 503, 504];
+const DEFAULT_ERRORS: [RetryErrorKind; 2] = [RetryErrorKind::Network, RetryErrorKind::Timeout];
+const DEFAULT_INITIAL_DELAY: u64 = 500;
+const DEFAULT_MAX_DELAY: u64 = 30_000;
+const DEFAULT_MULTIPLIER: f64 = 2.0;
+
+/// Sends the request through `send` until it succeeds, fails in a way the
+/// request's retry policy doesn't cover, or runs out of attempts. Without a
+/// policy this is a single `send`.
+///
+/// Once `cancelled` is set, waiting for the next attempt stops and the
+/// request fails with `RelayError::Abort`.
+pub(crate) fn execute<F>(request: &Request, cancelled: &AtomicBool, mut send: F) -> Result<Response>
+where
+    F: FnMut() -> Result<Response>,
+{
+    let Some(policy) = request
+        .meta
+        .as_ref()
+        .and_then(|meta| meta.options.as_ref())
+        .and_then(|options| options.retry.as_ref())
+    else {
+        return send();
+    };
+
+    let max_attempts = if retries_method(policy, &request.method) {
+        policy.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1)
+    } else {
+        tracing::debug!(method = %request.method, "Not retrying a non-idempotent method");
+        1
+    };
+
+    let mut attempts = Vec::new();
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let started = Instant::now();
+        let result = send();
+        let duration = started.elapsed().as_millis() as u64;
+
+        let delay = (attempt < max_attempts)
+            .then(|| retry_delay(policy, attempt, &result, OffsetDateTime::now_utc()))
+            .flatten();
+        attempts.push(RetryAttempt {
+            status: result
+                .as_ref()
+                .ok()
+                .map(|response| response.status.as_u16()),
+            error: result.as_ref().err().map(ToString::to_string),
+            duration,
+            delay: delay.map(|delay| delay.as_millis() as u64),
+        });
+
+        match delay {
+            Some(delay) => {
+                tracing::info!(
+                    attempt,
+                    delay_ms = delay.as_millis() as u64,
+                    "Retrying request"
+                );
+                if util::wait(delay, cancelled) {
+                    tracing::info!("Retry cancelled");
+                    return Err(RelayError::Abort {
+                        message: "Request cancelled while waiting to retry".into(),
+                    });
+                }
+            }
+            None => {
+                return result.map(|mut response| {
+                    response.meta.attempts = Some(attempts);
+                    response
+                })
+            }
+        }
+    }
+}
+
+/// Idempotent methods per RFC 9110, plus anything else when the policy
+/// allows it.
+fn retries_method(policy: &RetryPolicy, method: &Method) -> bool {
+    policy.retry_non_idempotent.unwrap_or(false)
+        || matches!(
+            *method,
+            Method::GET
+                | Method::HEAD
+                | Method::OPTIONS
+                | Method::TRACE
+                | Method::PUT
+                | Method::DELETE
+        )
+}
+
+/// How long to wait before attempt `attempt + 1`, `None` when `result`
+/// shouldn't be retried.
+fn retry_delay(
+    policy: &RetryPolicy,
+    attempt: u32,
+    result: &Result<Response>,
+    now: OffsetDateTime,
+) -> Option<Duration> {
+    let max_delay = Duration::from_millis(policy.max_delay.unwrap_or(DEFAULT_MAX_DELAY));
+
+    match result {
+        Ok(response) => {
+            let status = response.status.as_u16();
+            let retryable = match policy.retry_on_status {
+                Some(ref statuses) => statuses.contains(&status),
+                None => DEFAULT_STATUSES.contains(&status),
+            };
+            if !retryable {
+                return None;
+            }
+
+            let retry_after = policy
+                .respect_retry_after
+                .unwrap_or(true)
+                .then(|| {
+                    response
+                        .headers
+                        .iter()
+                        .find(|(name, _)| name.eq_ignore_ascii_case("retry-after"))
+                        .and_then(|(_, value)| retry_after(value, now))
+                })
+                .flatten();
+            match retry_after {
+                Some(delay) if delay > max_delay => {
+                    tracing::info!(
+                        retry_after_ms = delay.as_millis() as u64,
+                        "Retry-After exceeds the maximum delay, not retrying"
+                    );
+                    None
+                }
+                Some(delay) => Some(delay),
+                None => Some(backoff(policy, attempt, max_delay)),
+            }
+        }
+        Err(error) => {
+            let kind = error_kind(error)?;
+            let retryable = match policy.retry_on_errors {
+                Some(ref kinds) => kinds.contains(&kind),
+                None => DEFAULT_ERRORS.contains(&kind),
+            };
+            retryable.then(|| backoff(policy, attempt, max_delay))
+        }
+    }
+}
+
+/// `initial_delay * multiplier^(attempt - 1)` capped at `max_delay`, with
+/// jitter applied after the cap so capped delays still spread out.
+fn backoff(policy: &RetryPolicy, attempt: u32, max_delay: Duration) -> Duration {
+    let initial = policy.initial_delay.unwrap_or(DEFAULT_INITIAL_DELAY) as f64;
+    let multiplier = policy.multiplier.unwrap_or(DEFAULT_MULTIPLIER).max(1.0);
+    let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+
+    let delay = (initial * multiplier.powi(exponent)).min(max_delay.as_millis() as f64);
+    let delay = if policy.jitter.unwrap_or(true) {
+        delay * (0.5 + random_fraction() / 2.0)
+    } else {
+        delay
+    };
+    Duration::from_millis(delay as u64)
+}
+
+/// A value in `[0, 1)`. `RandomState` is seeded per instance, which is
+/// plenty for spreading out retries.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Parses `Retry-After` as delay-seconds or an HTTP-date, dates in the past
+/// meaning no wait.
+fn retry_after(value: &str, now: OffsetDateTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = OffsetDateTime::parse(value, &Rfc2822).ok()?;
+    Some(Duration::try_from(date - now).unwrap_or(Duration::ZERO))
+}
+
+fn error_kind(error: &RelayError) -> Option<RetryErrorKind> {
+    match error {
+        RelayError::Network { .. } => Some(RetryErrorKind::Network),
+        RelayError::Timeout { .. } => Some(RetryErrorKind::Timeout),
+        RelayError::Certificate { .. } => Some(RetryErrorKind::Certificate),
+        RelayError::Parse { .. } => Some(RetryErrorKind::Parse),
+        RelayError::Proxy { .. } => Some(RetryErrorKind::Proxy),
+        RelayError::ProxyAuth { .. } => Some(RetryErrorKind::ProxyAuth),
+        RelayError::BodyTooLarge { .. } => Some(RetryErrorKind::BodyTooLarge),
+        RelayError::UnsupportedFeature { .. }
+        | RelayError::Abort { .. }
+        | RelayError::CassetteMiss { .. }
+        | RelayError::InvalidBatch { .. }
+        | RelayError::InvalidLoadTest { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use http::StatusCode;
+    use time::macros::datetime;
+
+    use super::*;
+    use crate::interop::{RequestMeta, RequestOptions};
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            initial_delay: Some(1),
+            jitter: Some(false),
+            ..Default::default()
+        }
+    }
+
+    fn request(method: Method, policy: Option<RetryPolicy>) -> Request {
+        Request {
+            meta: Some(RequestMeta {
+                options: Some(RequestOptions {
+                    retry: policy,
+                    ..Default::default()
+                }),
+            }),
+            ..Request::test(method, "https://example.com")
+        }
+    }
+
+    fn response(status: u16, headers: &[(&str, &str)]) -> Response {
+        Response::test(StatusCode::from_u16(status).unwrap(), headers, b"")
+    }
+
+    fn network_error() -> RelayError {
+        RelayError::Network {
+            message: "Connection reset by peer".into(),
+            cause: None,
+        }
+    }
+
+    #[test]
+    fn retries_until_success_and_records_attempts() {
+        let mut outcomes = vec![
+            Ok(response(200, &[])),
+            Ok(response(503, &[])),
+            Err(network_error()),
+        ];
+        let cancelled = AtomicBool::new(false);
+
+        let response = execute(&request(Method::GET, Some(policy())), &cancelled, || {
+            outcomes.pop().unwrap()
+        })
+        .unwrap();
+        let attempts = response.meta.attempts.unwrap();
+
+        assert_eq!(attempts.len(), 3);
+        assert_eq!(attempts[0].status, None);
+        assert_eq!(
+            attempts[0].error.as_deref(),
+            Some("Network error: Connection reset by peer")
+        );
+        assert_eq!(attempts[0].delay, Some(1));
+        assert_eq!(attempts[1].status, Some(503));
+        assert_eq!(attempts[1].delay, Some(2));
+        assert_eq!(attempts[2].status, Some(200));
+        assert_eq!(attempts[2].delay, None);
+    }
+
+    #[test]
+    fn stops_after_max_attempts() {
+        let mut sent = 0;
+        let cancelled = AtomicBool::new(false);
+        let policy = RetryPolicy {
+            max_attempts: Some(2),
+            ..policy()
+        };
+
+        let response = execute(&request(Method::GET, Some(policy)), &cancelled, || {
+            sent += 1;
+            Ok(response(502, &[]))
+        })
+        .unwrap();
+
+        assert_eq!(sent, 2);
+        assert_eq!(response.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(response.meta.attempts.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn only_retries_idempotent_methods_unless_allowed() {
+        let cancelled = AtomicBool::new(false);
+        let mut sent = 0;
+        execute(&request(Method::POST, Some(policy())), &cancelled, || {
+            sent += 1;
+            Ok(response(503, &[]))
+        })
+        .unwrap();
+        assert_eq!(sent, 1);
+
+        let mut sent = 0;
+        let policy = RetryPolicy {
+            retry_non_idempotent: Some(true),
+            ..policy()
+        };
+        execute(&request(Method::POST, Some(policy)), &cancelled, || {
+            sent += 1;
+            Ok(response(503, &[]))
+        })
+        .unwrap();
+        assert_eq!(sent, 3);
+    }
+
+    #[test]
+    fn leaves_requests_without_a_policy_alone() {
+        let cancelled = AtomicBool::new(false);
+        let mut sent = 0;
+        let response = execute(&request(Method::GET, None), &cancelled, || {
+            sent += 1;
+            Ok(response(503, &[]))
+        })
+        .unwrap();
+
+        assert_eq!(sent, 1);
+        assert!(response.meta.attempts.is_none());
+    }
+
+    #[test]
+    fn does_not_retry_other_statuses_and_errors() {
+        let now = OffsetDateTime::now_utc();
+        let policy = policy();
+
+        assert!(retry_delay(&policy, 1, &Ok(response(404, &[])), now).is_none());
+        let abort = RelayError::Abort {
+            message: "Request cancelled by user".into(),
+        };
+        assert!(retry_delay(&policy, 1, &Err(abort), now).is_none());
+
+        let policy = RetryPolicy {
+            retry_on_status: Some(vec![404]),
+            retry_on_errors: Some(vec![RetryErrorKind::Timeout]),
+            ..policy
+        };
+        assert!(retry_delay(&policy, 1, &Ok(response(404, &[])), now).is_some());
+        assert!(retry_delay(&policy, 1, &Ok(response(503, &[])), now).is_none());
+        assert!(retry_delay(&policy, 1, &Err(network_error()), now).is_none());
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_max_delay() {
+        let policy = RetryPolicy {
+            initial_delay: Some(100),
+            max_delay: Some(1000),
+            jitter: Some(false),
+            ..Default::default()
+        };
+        let max = Duration::from_millis(1000);
+        let delays: Vec<u64> = (1..=5)
+            .map(|attempt| backoff(&policy, attempt, max).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000]);
+
+        let jittered = RetryPolicy {
+            jitter: None,
+            ..policy
+        };
+        for _ in 0..20 {
+            let delay = backoff(&jittered, 3, max).as_millis();
+            assert!((200..=400).contains(&delay), "{}", delay);
+        }
+    }
+
+    #[test]
+    fn honours_retry_after() {
+        let now = datetime!(2024-01-01 12:00:00 UTC);
+        let policy = policy();
+
+        let seconds = Ok(response(429, &[("retry-after", "3")]));
+        assert_eq!(
+            retry_delay(&policy, 1, &seconds, now),
+            Some(Duration::from_secs(3))
+        );
+
+        let date = Ok(response(
+            503,
+            &[("Retry-After", "Mon, 01 Jan 2024 12:00:10 GMT")],
+        ));
+        assert_eq!(
+            retry_delay(&policy, 1, &date, now),
+            Some(Duration::from_secs(10))
+        );
+
+        let past = Ok(response(
+            503,
+            &[("Retry-After", "Mon, 01 Jan 2024 11:00:00 GMT")],
+        ));
+        assert_eq!(retry_delay(&policy, 1, &past, now), Some(Duration::ZERO));
+
+        let too_long = Ok(response(503, &[("Retry-After", "3600")]));
+        assert_eq!(retry_delay(&policy, 1, &too_long, now), None);
+
+        let ignored = RetryPolicy {
+            respect_retry_after: Some(false),
+            ..policy
+        };
+        assert_eq!(
+            retry_delay(&ignored, 1, &too_long, now),
+            Some(Duration::from_millis(1))
+        );
+    }
+
+    #[test]
+    fn cancelling_stops_waiting() {
+        let cancelled = AtomicBool::new(false);
+        let policy = RetryPolicy {
+            initial_delay: Some(60_000),
+            max_delay: Some(60_000),
+            ..policy()
+        };
+        let mut sent = 0;
+
+        let started = Instant::now();
+        let result = execute(&request(Method::GET, Some(policy)), &cancelled, || {
+            sent += 1;
+            cancelled.store(true, Ordering::SeqCst);
+            Ok(response(503, &[]))
+        });
+
+        assert_eq!(sent, 1);
+        assert!(matches!(result, Err(RelayError::Abort { .. })));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -1,13 +1,55 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
+
 use bytes::Bytes;
-use curl::easy::Easy;
+use curl::easy::{Easy, Handler};
 
-use openssl::pkcs12::Pkcs12;
+use openssl::{pkcs12::Pkcs12, pkey::PKey, ssl::SslContextBuilder, x509::X509};
+use rumqttc::tokio_native_tls::native_tls::{self, Certificate, Identity, TlsConnector};
 
 use crate::{
     error::{RelayError, Result},
     interop::{CertificateConfig, CertificateType, SecurityConfig},
 };
 
+lazy_static::lazy_static! {
+    /// Where the TLS secrets of HTTP requests are appended to, set by the
+    /// host app. Key logging is off while it is unset.
+    static ref KEY_LOG_FILE: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Makes every HTTP request append its TLS secrets to `path` in NSS key log
+/// format, what `SSLKEYLOGFILE` does for browsers, so packet captures can be
+/// decrypted in Wireshark. `None` turns it off again.
+///
+/// This is the host app's call, requests can't turn key logging on.
+pub fn set_key_log_file(path: Option<PathBuf>) {
+    tracing::info!(path = ?path, "Updating TLS key log file");
+
+    match KEY_LOG_FILE.write() {
+        Ok(mut guard) => *guard = path,
+        Err(poisoned) => *poisoned.into_inner() = path,
+    }
+}
+
+/// Returns the file TLS secrets are written to, `None` when they aren't.
+pub fn key_log_file() -> Option<PathBuf> {
+    match KEY_LOG_FILE.read() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+/// curl-rust's stock callbacks, to run the default `SSL_CTX` setup from a
+/// custom one.
+struct DefaultHandler;
+
+impl Handler for DefaultHandler {}
+
 pub(crate) struct SecurityHandler<'a> {
     handle: &'a mut Easy,
 }
@@ -51,6 +93,87 @@ impl<'a> SecurityHandler<'a> {
         Ok(())
     }
 
+    /// Same as `configure` but for the TLS connection to an `https://`
+    /// proxy.
+    #[tracing::instrument(skip(self), level = "debug")]
+    pub(crate) fn configure_proxy(&mut self, security: &SecurityConfig) -> Result<()> {
+        tracing::info!("Configuring proxy security settings");
+
+        if let Some(verify) = security.verify_peer {
+            tracing::debug!(verify = verify, "Setting proxy SSL verify peer");
+            self.handle.proxy_ssl_verify_peer(verify).map_err(|e| {
+                tracing::error!(error = %e, "Failed to set proxy SSL verify peer");
+                RelayError::Certificate {
+                    message: "Failed to set proxy SSL verify peer".into(),
+                    cause: Some(e.to_string()),
+                }
+            })?;
+        }
+
+        if let Some(verify) = security.verify_host {
+            tracing::debug!(verify = verify, "Setting proxy SSL verify host");
+            self.handle.proxy_ssl_verify_host(verify).map_err(|e| {
+                tracing::error!(error = %e, "Failed to set proxy SSL verify host");
+                RelayError::Certificate {
+                    message: "Failed to set proxy SSL verify host".into(),
+                    cause: Some(e.to_string()),
+                }
+            })?;
+        }
+
+        let Some(ref certs) = security.certificates else {
+            return Ok(());
+        };
+
+        if let Some(ref client_cert) = certs.client {
+            let (cert, key) = match client_cert {
+                CertificateType::Pem { cert, key } => (cert.to_vec(), key.to_vec()),
+                CertificateType::Pfx { data, password } => Self::pfx_to_pem(data, password)?,
+            };
+            self.configure_proxy_pem_certificate(&cert, &key)?;
+        }
+
+        if let Some(ref ca_certs) = certs.ca {
+            tracing::debug!(count = ca_certs.len(), "Setting proxy CA certificates");
+            self.handle
+                .proxy_ssl_cainfo_blob(&ca_bundle(ca_certs))
+                .map_err(|e| {
+                    tracing::error!(error = %e, "Failed to set proxy CA certificates");
+                    RelayError::Certificate {
+                        message: "Failed to set proxy CA certificates".into(),
+                        cause: Some(e.to_string()),
+                    }
+                })?;
+        }
+
+        Ok(())
+    }
+
+    fn configure_proxy_pem_certificate(&mut self, cert: &[u8], key: &[u8]) -> Result<()> {
+        let certificate_error = |message: &str, e: curl::Error| {
+            tracing::error!(error = %e, "{}", message);
+            RelayError::Certificate {
+                message: message.into(),
+                cause: Some(e.to_string()),
+            }
+        };
+
+        self.handle
+            .proxy_sslcert_type("PEM")
+            .map_err(|e| certificate_error("Failed to set proxy certificate type", e))?;
+        self.handle
+            .proxy_sslcert_blob(cert)
+            .map_err(|e| certificate_error("Failed to set proxy client certificate", e))?;
+        self.handle
+            .proxy_sslkey_type("PEM")
+            .map_err(|e| certificate_error("Failed to set proxy key type", e))?;
+        self.handle
+            .proxy_sslkey_blob(key)
+            .map_err(|e| certificate_error("Failed to set proxy client key", e))?;
+
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self), level = "debug")]
     fn configure_certificates(&mut self, certs: &CertificateConfig) -> Result<()> {
         if let Some(ref client_cert) = certs.client {
@@ -114,6 +237,11 @@ impl<'a> SecurityHandler<'a> {
     }
 
     fn configure_pfx_certificate(&mut self, data: &[u8], password: &str) -> Result<()> {
+        let (cert_pem, key_pem) = Self::pfx_to_pem(data, password)?;
+        self.configure_pem_certificate(&cert_pem, &key_pem)
+    }
+
+    fn pfx_to_pem(data: &[u8], password: &str) -> Result<(Vec<u8>, Vec<u8>)> {
         let pkcs12 = Pkcs12::from_der(data).map_err(|e| {
             tracing::error!(error = %e, "Failed to parse PKCS#12 data");
             RelayError::Certificate {
@@ -147,7 +275,7 @@ impl<'a> SecurityHandler<'a> {
                 }
             })?;
 
-            self.configure_pem_certificate(&cert_pem, &key_pem)
+            Ok((cert_pem, key_pem))
         } else {
             tracing::error!("PKCS#12 file missing certificate or private key");
             Err(RelayError::Certificate {
@@ -158,16 +286,326 @@ impl<'a> SecurityHandler<'a> {
     }
 
     fn configure_ca_certificates(&mut self, ca_certs: &[Bytes]) -> Result<()> {
-        for (index, cert) in ca_certs.iter().enumerate() {
-            tracing::debug!(cert_index = index, "Setting CA certificate");
-            self.handle.ssl_cainfo_blob(cert).map_err(|e| {
-                tracing::error!(error = %e, cert_index = index, "Failed to set CA certificate");
+        tracing::debug!(count = ca_certs.len(), "Setting CA certificates");
+        self.handle
+            .ssl_cainfo_blob(&ca_bundle(ca_certs))
+            .map_err(|e| {
+                tracing::error!(error = %e, "Failed to set CA certificates");
                 RelayError::Certificate {
-                    message: format!("Failed to set CA certificate at index {}", index),
+                    message: "Failed to set CA certificates".into(),
                     cause: Some(e.to_string()),
                 }
             })?;
-        }
         Ok(())
     }
+
+    /// Writes the TLS secrets of every connection made by this handle to
+    /// `path`, see `set_key_log_file`.
+    pub(crate) fn configure_key_log(&mut self, path: &Path) -> Result<()> {
+        tracing::warn!(path = %path.display(), "TLS key logging enabled, secrets will be written to disk");
+
+        let file = Self::open_key_log(path).map_err(|e| {
+            tracing::error!(error = %e, path = %path.display(), "Failed to open TLS key log file");
+            RelayError::Network {
+                message: format!("Failed to open TLS key log file: {}", path.display()),
+                cause: Some(e.to_string()),
+            }
+        })?;
+        let file = Arc::new(Mutex::new(file));
+
+        self.handle
+            .ssl_ctx_function(move |ctx| {
+                // NOTE: Setting a callback replaces curl-rust's default one,
+                // which adds the system certificate store on Windows, so that
+                // still runs first.
+                DefaultHandler.ssl_ctx(ctx)?;
+
+                // SAFETY: curl hands us the `SSL_CTX` it is about to use for
+                // this connection and keeps ownership of it. The builder only
+                // borrows it to register the callback and is forgotten so the
+                // context is not freed here. The callback itself lives in the
+                // context's ex data and is dropped together with it.
+                let mut builder =
+                    unsafe { SslContextBuilder::from_ptr(ctx as *mut openssl_sys::SSL_CTX) };
+
+                let file = Arc::clone(&file);
+                builder.set_keylog_callback(move |_, line| {
+                    if let Ok(mut file) = file.lock() {
+                        if let Err(e) = writeln!(file, "{}", line) {
+                            tracing::warn!(error = %e, "Failed to write TLS key log line");
+                        }
+                    }
+                });
+
+                std::mem::forget(builder);
+                Ok(())
+            })
+            .map_err(|e| {
+                tracing::error!(error = %e, "Failed to set SSL context callback");
+                RelayError::Network {
+                    message: "Failed to set TLS key log callback".into(),
+                    cause: Some(e.to_string()),
+                }
+            })?;
+
+        Ok(())
+    }
+
+    fn open_key_log(path: &Path) -> std::io::Result<File> {
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+
+        // Key log lines are session secrets, keep them private to the user.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        options.open(path)
+    }
+}
+
+/// Joins the PEM bundles into one, each CA blob given to curl replaces the
+/// previous one.
+fn ca_bundle(ca_certs: &[Bytes]) -> Vec<u8> {
+    let mut bundle = Vec::new();
+    for cert in ca_certs {
+        bundle.extend_from_slice(cert);
+        if !cert.ends_with(b"\n") {
+            bundle.push(b'\n');
+        }
+    }
+    bundle
+}
+
+/// Builds a `native-tls` connector with the same settings `SecurityHandler`
+/// gives curl, for the protocols that don't go through curl. Their TLS
+/// secrets aren't written to the key log file.
+#[tracing::instrument(skip(security), level = "debug")]
+pub(crate) fn tls_connector(security: Option<&SecurityConfig>) -> Result<TlsConnector> {
+    let mut builder = TlsConnector::builder();
+    let Some(security) = security else {
+        return build_connector(&builder);
+    };
+
+    if security.verify_peer == Some(false) {
+        tracing::debug!("Disabling certificate verification");
+        builder.danger_accept_invalid_certs(true);
+    }
+    if security.verify_host == Some(false) {
+        tracing::debug!("Disabling hostname verification");
+        builder.danger_accept_invalid_hostnames(true);
+    }
+
+    let Some(ref certs) = security.certificates else {
+        return build_connector(&builder);
+    };
+
+    if let Some(ref client_cert) = certs.client {
+        let (cert, key) = match client_cert {
+            CertificateType::Pem { cert, key } => (cert.to_vec(), pkcs8_key(key)?),
+            CertificateType::Pfx { data, password } => SecurityHandler::pfx_to_pem(data, password)?,
+        };
+        let identity = Identity::from_pkcs8(&cert, &key).map_err(|e| {
+            tracing::error!(error = %e, "Failed to load client certificate");
+            RelayError::Certificate {
+                message: "Failed to load client certificate".into(),
+                cause: Some(e.to_string()),
+            }
+        })?;
+        builder.identity(identity);
+    }
+
+    for (index, bundle) in certs.ca.iter().flatten().enumerate() {
+        let certs = X509::stack_from_pem(bundle).map_err(|e| {
+            tracing::error!(error = %e, cert_index = index, "Failed to parse CA certificate");
+            RelayError::Certificate {
+                message: format!("Failed to parse CA certificate at index {}", index),
+                cause: Some(e.to_string()),
+            }
+        })?;
+        for cert in certs {
+            let cert = cert
+                .to_der()
+                .map_err(|e| e.to_string())
+                .and_then(|der| Certificate::from_der(&der).map_err(|e| e.to_string()))
+                .map_err(|e| {
+                    tracing::error!(error = %e, cert_index = index, "Failed to load CA certificate");
+                    RelayError::Certificate {
+                        message: format!("Failed to load CA certificate at index {}", index),
+                        cause: Some(e),
+                    }
+                })?;
+            builder.add_root_certificate(cert);
+        }
+    }
+
+    build_connector(&builder)
+}
+
+fn build_connector(builder: &native_tls::TlsConnectorBuilder) -> Result<TlsConnector> {
+    builder.build().map_err(|e| {
+        tracing::error!(error = %e, "Failed to build TLS connector");
+        RelayError::Certificate {
+            message: "Failed to build TLS connector".into(),
+            cause: Some(e.to_string()),
+        }
+    })
+}
+
+/// `native-tls` only takes PKCS#8 keys, while curl also accepts the
+/// traditional RSA and EC encodings.
+fn pkcs8_key(key: &[u8]) -> Result<Vec<u8>> {
+    PKey::private_key_from_pem(key)
+        .and_then(|key| key.private_key_to_pem_pkcs8())
+        .map_err(|e| {
+            tracing::error!(error = %e, "Failed to read client key");
+            RelayError::Certificate {
+                message: "Failed to read client key".into(),
+                cause: Some(e.to_string()),
+            }
+        })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{io::Read, net::TcpListener, thread};
+
+    use openssl::{
+        asn1::Asn1Time,
+        hash::MessageDigest,
+        pkey::Private,
+        rsa::Rsa,
+        ssl::{SslAcceptor, SslMethod},
+        x509::{extension::SubjectAlternativeName, X509NameBuilder},
+    };
+    use tokio_util::sync::CancellationToken;
+
+    use super::*;
+    use crate::interop::Request;
+
+    /// A self-signed certificate for `127.0.0.1`, valid for a day.
+    pub(crate) fn certificate(name: &str) -> (X509, PKey<Private>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_text("CN", name).unwrap();
+        let subject = subject.build();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&subject).unwrap();
+        cert.set_issuer_name(&subject).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        let san = SubjectAlternativeName::new()
+            .ip("127.0.0.1")
+            .build(&cert.x509v3_context(None, None))
+            .unwrap();
+        cert.append_extension(san).unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+
+        (cert.build(), key)
+    }
+
+    /// Answers one request over TLS with `cert` with an empty 200, returns
+    /// its `https://` URL.
+    fn tls_server(cert: &X509, key: &PKey<Private>) -> (String, thread::JoinHandle<()>) {
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(cert).unwrap();
+        acceptor.set_private_key(key).unwrap();
+        let acceptor = acceptor.build();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("https://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let Ok(mut stream) = acceptor.accept(stream) else {
+                return;
+            };
+            let mut buffer = [0; 4096];
+            let _ = stream.read(&mut buffer);
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        });
+
+        (url, server)
+    }
+
+    #[test]
+    fn trusts_every_ca_certificate() {
+        let (other, _) = certificate("other");
+        let (cert, key) = certificate("server");
+        let ca = [cert.to_pem().unwrap(), other.to_pem().unwrap()];
+
+        let (url, server) = tls_server(&cert, &key);
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "id": 28,
+            "url": url,
+            "method": "GET",
+            "version": "HTTP/1.1",
+            "security": { "certificates": { "ca": ca } }
+        }))
+        .unwrap();
+        let response = crate::relay::execute_request(&request, &CancellationToken::new());
+        server.join().unwrap();
+        assert_eq!(response.unwrap().status, 200);
+
+        // NOTE: The proxy answers the request itself, curl sends plain HTTP
+        // requests to it with the absolute URL.
+        let (proxy, server) = tls_server(&cert, &key);
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "id": 29,
+            "url": "http://example.invalid/",
+            "method": "GET",
+            "version": "HTTP/1.1",
+            "proxy": {
+                "url": proxy,
+                "security": { "certificates": { "ca": ca } }
+            }
+        }))
+        .unwrap();
+        let response = crate::relay::execute_request(&request, &CancellationToken::new());
+        server.join().unwrap();
+        assert_eq!(response.unwrap().status, 200);
+    }
+
+    #[test]
+    fn writes_tls_secrets_to_the_key_log_file() {
+        let (cert, key) = certificate("server");
+        let (url, server) = tls_server(&cert, &key);
+
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "id": 26,
+            "url": url,
+            "method": "GET",
+            "version": "HTTP/1.1",
+            "security": {
+                "certificates": {
+                    "ca": [cert.to_pem().unwrap()]
+                }
+            }
+        }))
+        .unwrap();
+
+        let path = std::env::temp_dir().join(format!("relay-keylog-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        set_key_log_file(Some(path.clone()));
+        let response = crate::relay::execute_request(&request, &CancellationToken::new());
+        set_key_log_file(None);
+        server.join().unwrap();
+
+        let response = response.unwrap();
+        assert_eq!(response.meta.tls_key_log.as_deref(), path.to_str());
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(
+            log.lines().any(|line| line.starts_with("CLIENT_")),
+            "{}",
+            log
+        );
+    }
 }
@@ -0,0 +1,2727 @@
+use std::collections::{BTreeSet, HashSet};
+use std::time::Duration;
+
+use http::Version;
+use serde_json::{ser::PrettyFormatter, Serializer, Value};
+
+use crate::{
+    curl_command::{self, CA_CERT_FILE, CLIENT_CERT_FILE, CLIENT_KEY_FILE, CLIENT_P12_FILE},
+    interop::{
+        ApiKeyLocation, AuthType, CertificateType, ContentType, FormData, FormValue, MediaType,
+        Request, RequestOptions, SnippetTarget, VersionMode,
+    },
+};
+
+// NOTE: Features a target can't express are listed in a comment at the top
+// of its snippet rather than silently dropped.
+const HTTP_1_0: &str = "HTTP/1.0";
+const HTTP_2: &str = "HTTP/2";
+const HTTP_2_ONLY: &str = "HTTP/2 without fallback";
+const HTTP_3: &str = "HTTP/3";
+const DIGEST: &str = "digest auth";
+const AWS: &str = "AWS SigV4 auth";
+const PEM_CERT: &str = "PEM client certificates";
+const PKCS12_CERT: &str = "PKCS#12 client certificates";
+const CUSTOM_CA: &str = "custom CA certificates";
+const INSECURE: &str = "skipping certificate verification";
+const PROXY: &str = "proxies";
+const SOCKS_PROXY: &str = "SOCKS proxies";
+const PROXY_AUTH: &str = "proxy credentials";
+const NO_PROXY: &str = "proxy exclusions";
+const UNIX_SOCKET: &str = "Unix sockets";
+const RESOLVE: &str = "pinned host addresses";
+const MAX_REDIRECTS: &str = "redirect limits";
+const NO_KEEP_ALIVE: &str = "disabling keep-alive";
+const PART_TYPES: &str = "multipart part content types";
+const PART_HEADERS: &str = "multipart part headers";
+const RESTRICTED_HEADERS: &str = "restricted headers like Host";
+const CONNECT_TO: &str = "connection redirects";
+const BODY_COMPRESSION: &str = "request body compression";
+const PROXY_TLS: &str = "proxy TLS settings";
+const PAC: &str = "PAC proxy scripts";
+
+/// Boundary of the multipart bodies targets without multipart support
+/// assemble by hand.
+const BOUNDARY: &str = "----HoppscotchFormBoundary";
+
+/// Writes `request` out as code for `target` that sends the same request.
+///
+/// Like `curl_command::export`, certificates and binary bodies with a
+/// `filename` refer to files next to the snippet. Unset options keep the
+/// target's defaults, and anything the target can't express is named in a
+/// comment at the top instead of being left out silently.
+pub fn generate(request: &Request, target: SnippetTarget) -> String {
+    let plan = Plan::new(request);
+    let (comment, library, (code, unsupported)) = match target {
+        SnippetTarget::Curl => return curl_command::export(request),
+        SnippetTarget::PythonRequests => ("#", "requests", python_requests(&plan)),
+        SnippetTarget::PythonHttpx => ("#", "httpx", python_httpx(&plan)),
+        SnippetTarget::JavascriptFetch => ("//", "fetch", javascript_fetch(&plan)),
+        SnippetTarget::JavascriptAxios => ("//", "axios", javascript_axios(&plan)),
+        SnippetTarget::GoNetHttp => ("//", "net/http", go_net_http(&plan)),
+        SnippetTarget::RustReqwest => ("//", "reqwest", rust_reqwest(&plan)),
+        SnippetTarget::JavaHttpClient => ("//", "java.net.http", java_http_client(&plan)),
+        SnippetTarget::PowershellRestMethod => {
+            ("#", "Invoke-RestMethod", powershell_rest_method(&plan))
+        }
+    };
+
+    let mut seen = HashSet::new();
+    let unsupported: Vec<&str> = unsupported
+        .into_iter()
+        .filter(|feature| seen.insert(*feature))
+        .collect();
+    if unsupported.is_empty() {
+        return code;
+    }
+
+    format!(
+        "{} Not expressible with {}: {}.\n{}",
+        comment,
+        library,
+        unsupported.join(", "),
+        code
+    )
+}
+
+/// A generator's code and the features it left out.
+type Generated = (String, Vec<&'static str>);
+
+/// The request reduced to what the generators work from, with query auth
+/// in the URL and header-borne auth among the headers.
+struct Plan<'a> {
+    method: &'a str,
+    url: String,
+    version: Option<&'static str>,
+    /// Sorted by name, without `Content-Type`.
+    headers: Vec<(String, String)>,
+    content_type: Option<String>,
+    body: Body<'a>,
+    auth: Auth<'a>,
+    tls: Tls<'a>,
+    proxy: Option<Proxy<'a>>,
+    options: RequestOptions,
+    /// Features none of the generators can express.
+    common: Vec<&'static str>,
+}
+
+enum Body<'a> {
+    None,
+    Text(&'a str),
+    Json(&'a Value),
+    Bytes(&'a [u8]),
+    File(&'a str),
+    Form(&'a FormData),
+}
+
+enum Auth<'a> {
+    None,
+    Basic {
+        username: &'a str,
+        password: &'a str,
+    },
+    Digest {
+        username: &'a str,
+        password: &'a str,
+    },
+    Aws {
+        access_key: &'a str,
+        secret_key: &'a str,
+        region: &'a str,
+        service: &'a str,
+        session_token: Option<&'a str>,
+    },
+}
+
+#[derive(Default)]
+struct Tls<'a> {
+    insecure: bool,
+    client: Option<ClientCert<'a>>,
+    ca: bool,
+}
+
+enum ClientCert<'a> {
+    Pem,
+    Pkcs12 { password: &'a str },
+}
+
+struct Proxy<'a> {
+    url: String,
+    credentials: Option<(&'a str, &'a str)>,
+    no_proxy: &'a [String],
+}
+
+impl<'a> Plan<'a> {
+    fn new(request: &'a Request) -> Self {
+        let mut common = Vec::new();
+        let mut headers: Vec<(String, String)> = request
+            .headers
+            .iter()
+            .flatten()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        let has_header = |headers: &[(String, String)], name: &str| {
+            headers.iter().any(|(n, _)| n.eq_ignore_ascii_case(name))
+        };
+
+        let mut query: Vec<(String, String)> = request
+            .params
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        query.sort();
+
+        let mut auth = Auth::None;
+        match request.auth {
+            Some(AuthType::Basic {
+                ref username,
+                ref password,
+            }) => auth = Auth::Basic { username, password },
+            Some(AuthType::Digest {
+                ref username,
+                ref password,
+                ..
+            }) => auth = Auth::Digest { username, password },
+            Some(AuthType::Bearer { ref token })
+            | Some(AuthType::OAuth2 {
+                access_token: Some(ref token),
+                ..
+            }) if !has_header(&headers, "authorization") => {
+                headers.push(("Authorization".into(), format!("Bearer {}", token)));
+            }
+            Some(AuthType::ApiKey {
+                ref key,
+                ref value,
+                location: ApiKeyLocation::Header,
+            }) => headers.push((key.clone(), value.clone())),
+            Some(AuthType::ApiKey {
+                ref key,
+                ref value,
+                location: ApiKeyLocation::Query,
+            }) => query.push((key.clone(), value.clone())),
+            Some(AuthType::Aws {
+                ref access_key,
+                ref secret_key,
+                ref region,
+                ref service,
+                ref session_token,
+                ..
+            }) => {
+                auth = Auth::Aws {
+                    access_key,
+                    secret_key,
+                    region,
+                    service,
+                    session_token: session_token.as_deref(),
+                }
+            }
+            _ => {}
+        }
+
+        let explicit_type = headers
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map(|index| headers.remove(index).1);
+
+        let (body, media_type) = match request.content {
+            None => (Body::None, None),
+            Some(ContentType::Text {
+                ref content,
+                ref media_type,
+            })
+            | Some(ContentType::Xml {
+                ref content,
+                ref media_type,
+            })
+            | Some(ContentType::Urlencoded {
+                ref content,
+                ref media_type,
+            }) => (Body::Text(content), Some(media_type)),
+            Some(ContentType::Json {
+                ref content,
+                ref media_type,
+            }) => (Body::Json(content), Some(media_type)),
+            Some(ContentType::Binary {
+                ref content,
+                ref media_type,
+                ref filename,
+            }) => {
+                let body = match (filename, std::str::from_utf8(content)) {
+                    (Some(filename), _) => Body::File(filename),
+                    (None, Ok(text)) if !text.chars().any(binary_char) => Body::Text(text),
+                    (None, _) => Body::Bytes(content),
+                };
+                (body, Some(media_type))
+            }
+            // NOTE: Libraries write the `Content-Type` of multipart bodies
+            // themselves since it carries the boundary.
+            Some(ContentType::Form { ref content, .. })
+            | Some(ContentType::Multipart { ref content, .. }) => (Body::Form(content), None),
+        };
+        let content_type = match body {
+            Body::None | Body::Form(_) => None,
+            _ => explicit_type.or(media_type.map(MediaType::to_string)),
+        };
+
+        headers.sort_by(|(a, a_value), (b, b_value)| {
+            a.to_ascii_lowercase()
+                .cmp(&b.to_ascii_lowercase())
+                .then_with(|| a_value.cmp(b_value))
+        });
+
+        let mut tls = Tls::default();
+        if let Some(ref security) = request.security {
+            tls.insecure =
+                security.verify_peer == Some(false) || security.verify_host == Some(false);
+            if let Some(ref certificates) = security.certificates {
+                tls.client = match certificates.client {
+                    Some(CertificateType::Pem { .. }) => Some(ClientCert::Pem),
+                    Some(CertificateType::Pfx { ref password, .. }) => {
+                        Some(ClientCert::Pkcs12 { password })
+                    }
+                    None => None,
+                };
+                tls.ca = certificates.ca.as_ref().is_some_and(|ca| !ca.is_empty());
+            }
+        }
+
+        // NOTE: TLS settings only apply to `https://` URLs, like with curl.
+        let url = curl_command::with_query(&request.url, &query);
+        if !is_https(&url) {
+            tls = Tls::default();
+        }
+
+        let mut proxy = None;
+        if let Some(ref config) = request.proxy {
+            match config.url {
+                Some(ref url) => {
+                    let url = match (config.remote_dns, url.split_once("://")) {
+                        (Some(true), Some(("socks5", rest))) => format!("socks5h://{}", rest),
+                        (Some(true), Some(("socks4", rest))) => format!("socks4a://{}", rest),
+                        _ => url.clone(),
+                    };
+                    proxy = Some(Proxy {
+                        url,
+                        credentials: config
+                            .auth
+                            .as_ref()
+                            .map(|auth| (auth.username.as_str(), auth.password.as_str())),
+                        no_proxy: config.no_proxy.as_deref().unwrap_or_default(),
+                    });
+                }
+                None if config.pac.is_some() => common.push(PAC),
+                None => {}
+            }
+            if config.security.is_some() {
+                common.push(PROXY_TLS);
+            }
+        }
+
+        let options = request
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.options.clone())
+            .unwrap_or_default();
+        if options
+            .connect_to
+            .as_ref()
+            .is_some_and(|entries| !entries.is_empty())
+        {
+            common.push(CONNECT_TO);
+        }
+        if options.body_compression.is_some() {
+            common.push(BODY_COMPRESSION);
+        }
+
+        let version = match (request.version, options.version_mode.unwrap_or_default()) {
+            (Version::HTTP_10, _) => Some(HTTP_1_0),
+            (Version::HTTP_2, VersionMode::Fallback) => Some(HTTP_2),
+            (Version::HTTP_2, VersionMode::Only) => Some(HTTP_2_ONLY),
+            (Version::HTTP_3, _) => Some(HTTP_3),
+            _ => None,
+        };
+
+        Self {
+            method: request.method.as_str(),
+            url,
+            version,
+            headers,
+            content_type,
+            body,
+            auth,
+            tls,
+            proxy,
+            options,
+            common,
+        }
+    }
+
+    /// Headers with `Content-Type` sorted in, for targets that send it as
+    /// an ordinary header.
+    fn all_headers(&self) -> Vec<(String, String)> {
+        let mut headers = self.headers.clone();
+        if let Some(ref content_type) = self.content_type {
+            let index = headers
+                .iter()
+                .position(|(name, _)| name.to_ascii_lowercase().as_str() > "content-type")
+                .unwrap_or(headers.len());
+            headers.insert(index, ("Content-Type".into(), content_type.clone()));
+        }
+        headers
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.options.timeout.map(Duration::from_millis)
+    }
+
+    /// `Some(false)` to stop at the first response, `Some(true)` to follow,
+    /// `None` for the target's default.
+    fn follow_redirects(&self) -> Option<bool> {
+        self.options.follow_redirects
+    }
+
+    /// The redirect limit, if redirects are followed.
+    fn max_redirects(&self) -> Option<u32> {
+        match self.options.follow_redirects {
+            Some(true) => self.options.max_redirects,
+            _ => None,
+        }
+    }
+
+    /// The socket path, abstract names with their leading NUL byte.
+    fn unix_socket(&self) -> Option<String> {
+        match (
+            &self.options.abstract_unix_socket,
+            &self.options.unix_socket,
+        ) {
+            (Some(name), _) => Some(format!("\0{}", name)),
+            (None, Some(path)) => Some(path.clone()),
+            (None, None) => None,
+        }
+    }
+
+    fn resolves(&self) -> bool {
+        self.options
+            .resolve
+            .as_ref()
+            .is_some_and(|entries| !entries.is_empty())
+    }
+
+    fn keep_alive_off(&self) -> bool {
+        self.options.keep_alive == Some(false)
+    }
+
+    fn is_https(&self) -> bool {
+        is_https(&self.url)
+    }
+}
+
+impl Proxy<'_> {
+    /// The proxy URL with its credentials in the user info.
+    fn url_with_credentials(&self) -> String {
+        let Some((username, password)) = self.credentials else {
+            return self.url.clone();
+        };
+        match url::Url::parse(&self.url) {
+            Ok(mut url) => {
+                let _ = url.set_username(username);
+                let _ = url.set_password(Some(password));
+                url.to_string().trim_end_matches('/').to_string()
+            }
+            Err(_) => self.url.clone(),
+        }
+    }
+
+    fn scheme(&self) -> &str {
+        self.url
+            .split_once("://")
+            .map_or("http", |(scheme, _)| scheme)
+    }
+
+    fn is_socks(&self) -> bool {
+        self.scheme().starts_with("socks")
+    }
+
+    /// Host and port, the port defaulting by scheme.
+    fn host_port(&self) -> (String, u16) {
+        match url::Url::parse(&self.url) {
+            Ok(url) => (
+                url.host_str().unwrap_or_default().to_string(),
+                url.port_or_known_default().unwrap_or(1080),
+            ),
+            Err(_) => (self.url.clone(), 1080),
+        }
+    }
+}
+
+fn parts(form: &FormData) -> impl Iterator<Item = (&str, &FormValue)> {
+    form.iter()
+        .flat_map(|(name, values)| values.iter().map(move |value| (name.as_str(), value)))
+}
+
+fn has_part_headers(form: &FormData) -> bool {
+    parts(form).any(|(_, value)| match value {
+        FormValue::Text { headers, .. } | FormValue::File { headers, .. } => {
+            headers.as_ref().is_some_and(|headers| !headers.is_empty())
+        }
+    })
+}
+
+/// Part headers sorted by name, for targets that can send them.
+fn part_headers(value: &FormValue) -> Vec<(&str, &str)> {
+    let (FormValue::Text { headers, .. } | FormValue::File { headers, .. }) = value;
+    let mut headers: Vec<(&str, &str)> = headers
+        .iter()
+        .flatten()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    headers.sort();
+    headers
+}
+
+fn is_https(url: &str) -> bool {
+    url.get(..8)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("https://"))
+}
+
+fn binary_char(c: char) -> bool {
+    c.is_control() && !matches!(c, '\n' | '\r' | '\t')
+}
+
+fn seconds(duration: Duration) -> String {
+    duration.as_secs_f64().to_string()
+}
+
+/// Quotes `value` C-style with `quote`, writing control characters other
+/// than `\n`, `\r` and `\t` through `control`.
+fn escape(value: &str, quote: char, control: impl Fn(char) -> String) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push(quote);
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c == quote => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c.is_control() => escaped.push_str(&control(c)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push(quote);
+    escaped
+}
+
+fn hex_escape(c: char) -> String {
+    match c as u32 {
+        code @ 0..=0x7f => format!("\\x{:02x}", code),
+        code => format!("\\u{:04x}", code),
+    }
+}
+
+fn unicode_escape(c: char) -> String {
+    format!("\\u{:04x}", c as u32)
+}
+
+/// A double-quoted byte string for the `b"..."` and `[]byte("...")`
+/// literals of Python, Rust and Go.
+fn byte_string(bytes: &[u8]) -> String {
+    let mut escaped = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'\\' => escaped.push_str("\\\\"),
+            b'"' => escaped.push_str("\\\""),
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            b'\t' => escaped.push_str("\\t"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn pretty_json(value: &Value, indent: &str) -> String {
+    let mut out = Vec::new();
+    let mut serializer =
+        Serializer::with_formatter(&mut out, PrettyFormatter::with_indent(indent.as_bytes()));
+    serde::Serialize::serialize(value, &mut serializer).unwrap_or_default();
+    String::from_utf8(out).unwrap_or_default()
+}
+
+/// Indents every line of `text` but the first by `pad`.
+fn indent_rest(text: &str, pad: &str) -> String {
+    text.replace('\n', &format!("\n{}", pad))
+}
+
+/// A multi-line `{ ... }` or `[ ... ]` with one entry per line and a
+/// trailing comma, indented `depth` levels of `unit`.
+fn block(open: &str, close: &str, entries: &[String], unit: &str, depth: usize) -> String {
+    if entries.is_empty() {
+        return format!("{}{}", open, close);
+    }
+    let pad = unit.repeat(depth + 1);
+    let mut out = format!("{}\n", open);
+    for entry in entries {
+        out.push_str(&format!("{}{},\n", pad, entry));
+    }
+    out.push_str(&unit.repeat(depth));
+    out.push_str(close);
+    out
+}
+
+fn py_str(value: &str) -> String {
+    escape(value, '"', hex_escape)
+}
+
+/// `value` as a Python literal.
+fn py_value(value: &Value, depth: usize) -> String {
+    match value {
+        Value::Null => "None".into(),
+        Value::Bool(true) => "True".into(),
+        Value::Bool(false) => "False".into(),
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => py_str(text),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(|item| py_value(item, depth + 1)).collect();
+            block("[", "]", &items, "    ", depth)
+        }
+        Value::Object(map) => {
+            let entries: Vec<String> = map
+                .iter()
+                .map(|(key, value)| format!("{}: {}", py_str(key), py_value(value, depth + 1)))
+                .collect();
+            block("{", "}", &entries, "    ", depth)
+        }
+    }
+}
+
+fn py_dict(entries: &[(String, String)]) -> String {
+    let entries: Vec<String> = entries
+        .iter()
+        .map(|(key, value)| format!("{}: {}", py_str(key), py_str(value)))
+        .collect();
+    block("{", "}", &entries, "    ", 0)
+}
+
+/// The `files=` list shared by requests and httpx.
+fn py_files(form: &FormData) -> String {
+    let entries: Vec<String> = parts(form)
+        .map(|(name, value)| {
+            let mut fields = match value {
+                FormValue::Text {
+                    value,
+                    content_type,
+                    ..
+                } => {
+                    let mut fields = vec!["None".to_string(), py_str(value)];
+                    if let Some(content_type) = content_type {
+                        fields.push(py_str(content_type));
+                    }
+                    fields
+                }
+                FormValue::File {
+                    filename,
+                    content_type,
+                    ..
+                } => vec![
+                    py_str(filename),
+                    format!("open({}, \"rb\")", py_str(filename)),
+                    py_str(&content_type.to_string()),
+                ],
+            };
+            let headers = part_headers(value);
+            if !headers.is_empty() {
+                if fields.len() == 2 {
+                    fields.push("None".into());
+                }
+                let headers: Vec<String> = headers
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", py_str(name), py_str(value)))
+                    .collect();
+                fields.push(format!("{{{}}}", headers.join(", ")));
+            }
+            format!("({}, ({}))", py_str(name), fields.join(", "))
+        })
+        .collect();
+    block("[", "]", &entries, "    ", 0)
+}
+
+/// Writes a Python call with one argument per line once it has keyword
+/// arguments.
+fn py_call(function: &str, args: &[String], depth: usize) -> String {
+    if args.len() <= 1 {
+        return format!("{}({})", function, args.join(", "));
+    }
+    block(&format!("{}(", function), ")", args, "    ", depth)
+}
+
+fn py_method(method: &str) -> Option<&'static str> {
+    Some(match method {
+        "GET" => "get",
+        "POST" => "post",
+        "PUT" => "put",
+        "PATCH" => "patch",
+        "DELETE" => "delete",
+        "HEAD" => "head",
+        "OPTIONS" => "options",
+        _ => return None,
+    })
+}
+
+/// The definitions before the request and the arguments describing its
+/// body, shared by requests and httpx which differ in the raw body one.
+fn py_body(plan: &Plan, raw: &str, lines: &mut Vec<String>, args: &mut Vec<String>) {
+    match plan.body {
+        Body::None => {}
+        Body::Text(text) => {
+            lines.push(format!("{} = {}", raw, py_str(text)));
+            args.push(format!("{}={}", raw, raw));
+        }
+        Body::Json(value) => {
+            lines.push(format!("payload = {}", py_value(value, 0)));
+            args.push("json=payload".into());
+        }
+        Body::Bytes(bytes) => {
+            lines.push(format!("{} = b{}", raw, byte_string(bytes)));
+            args.push(format!("{}={}", raw, raw));
+        }
+        Body::File(filename) => {
+            lines.push(format!("{} = open({}, \"rb\")", raw, py_str(filename)));
+            args.push(format!("{}={}", raw, raw));
+        }
+        Body::Form(form) => {
+            lines.push(format!("files = {}", py_files(form)));
+            args.push("files=files".into());
+        }
+    }
+}
+
+fn python_requests(plan: &Plan) -> Generated {
+    let mut unsupported = plan.common.clone();
+    let mut imports = vec!["import requests".to_string()];
+    let mut lines = vec![format!("url = {}", py_str(&plan.url))];
+    let mut args = vec!["url".to_string()];
+
+    let headers = plan.all_headers();
+    if !headers.is_empty() {
+        lines.push(format!("headers = {}", py_dict(&headers)));
+        args.push("headers=headers".into());
+    }
+    py_body(plan, "data", &mut lines, &mut args);
+
+    match plan.auth {
+        Auth::None => {}
+        Auth::Basic { username, password } => {
+            args.push(format!("auth=({}, {})", py_str(username), py_str(password)));
+        }
+        Auth::Digest { username, password } => {
+            imports.push("from requests.auth import HTTPDigestAuth".into());
+            args.push(format!(
+                "auth=HTTPDigestAuth({}, {})",
+                py_str(username),
+                py_str(password)
+            ));
+        }
+        Auth::Aws {
+            access_key,
+            secret_key,
+            region,
+            service,
+            session_token,
+        } => {
+            imports.push("from requests_aws4auth import AWS4Auth".into());
+            let mut auth_args = vec![
+                py_str(access_key),
+                py_str(secret_key),
+                py_str(region),
+                py_str(service),
+            ];
+            if let Some(token) = session_token {
+                auth_args.push(format!("session_token={}", py_str(token)));
+            }
+            args.push(format!("auth=AWS4Auth({})", auth_args.join(", ")));
+        }
+    }
+
+    match plan.tls.client {
+        Some(ClientCert::Pem) => args.push(format!(
+            "cert=({}, {})",
+            py_str(CLIENT_CERT_FILE),
+            py_str(CLIENT_KEY_FILE)
+        )),
+        Some(ClientCert::Pkcs12 { .. }) => unsupported.push(PKCS12_CERT),
+        None => {}
+    }
+    if plan.tls.insecure {
+        args.push("verify=False".into());
+    } else if plan.tls.ca {
+        args.push(format!("verify={}", py_str(CA_CERT_FILE)));
+    }
+
+    if let Some(ref proxy) = plan.proxy {
+        let url = py_str(&proxy.url_with_credentials());
+        let mut entries = vec![format!("\"http\": {}", url), format!("\"https\": {}", url)];
+        if !proxy.no_proxy.is_empty() {
+            entries.push(format!(
+                "\"no_proxy\": {}",
+                py_str(&proxy.no_proxy.join(","))
+            ));
+        }
+        lines.push(format!(
+            "proxies = {}",
+            block("{", "}", &entries, "    ", 0)
+        ));
+        args.push("proxies=proxies".into());
+    }
+
+    if let Some(timeout) = plan.timeout() {
+        args.push(format!("timeout={}", seconds(timeout)));
+    }
+    match plan.follow_redirects() {
+        Some(true) => args.push("allow_redirects=True".into()),
+        Some(false) => args.push("allow_redirects=False".into()),
+        None => {}
+    }
+    if plan.max_redirects().is_some() {
+        unsupported.push(MAX_REDIRECTS);
+    }
+
+    unsupported.extend(plan.version);
+    if plan.unix_socket().is_some() {
+        unsupported.push(UNIX_SOCKET);
+    }
+    if plan.resolves() {
+        unsupported.push(RESOLVE);
+    }
+    if plan.keep_alive_off() {
+        unsupported.push(NO_KEEP_ALIVE);
+    }
+
+    let call = match py_method(plan.method) {
+        Some(method) => py_call(&format!("requests.{}", method), &args, 0),
+        None => {
+            args.insert(0, py_str(plan.method));
+            py_call("requests.request", &args, 0)
+        }
+    };
+
+    let code = format!(
+        "{}\n\n{}\n\nresponse = {}\n\nprint(response.status_code)\nprint(response.text)\n",
+        imports.join("\n"),
+        lines.join("\n"),
+        call
+    );
+    (code, unsupported)
+}
+
+fn python_httpx(plan: &Plan) -> Generated {
+    let mut unsupported = plan.common.clone();
+    let mut lines = vec![format!("url = {}", py_str(&plan.url))];
+    let mut args = vec!["url".to_string()];
+    // Connection settings, which go on the transport when there is one.
+    let mut connection = Vec::new();
+    let mut client = Vec::new();
+
+    let headers = plan.all_headers();
+    if !headers.is_empty() {
+        lines.push(format!("headers = {}", py_dict(&headers)));
+        args.push("headers=headers".into());
+    }
+    py_body(plan, "content", &mut lines, &mut args);
+
+    match plan.auth {
+        Auth::None => {}
+        Auth::Basic { username, password } => {
+            args.push(format!("auth=({}, {})", py_str(username), py_str(password)));
+        }
+        Auth::Digest { username, password } => {
+            args.push(format!(
+                "auth=httpx.DigestAuth({}, {})",
+                py_str(username),
+                py_str(password)
+            ));
+        }
+        Auth::Aws { .. } => unsupported.push(AWS),
+    }
+
+    match plan.version {
+        Some(HTTP_2) => connection.push("http2=True".to_string()),
+        Some(HTTP_2_ONLY) => {
+            connection.push("http1=False".to_string());
+            connection.push("http2=True".to_string());
+        }
+        Some(version) => unsupported.push(version),
+        None => {}
+    }
+
+    if plan.tls.insecure {
+        connection.push("verify=False".into());
+    } else if plan.tls.ca {
+        connection.push(format!("verify={}", py_str(CA_CERT_FILE)));
+    }
+    match plan.tls.client {
+        Some(ClientCert::Pem) => connection.push(format!(
+            "cert=({}, {})",
+            py_str(CLIENT_CERT_FILE),
+            py_str(CLIENT_KEY_FILE)
+        )),
+        Some(ClientCert::Pkcs12 { .. }) => unsupported.push(PKCS12_CERT),
+        None => {}
+    }
+    if plan.keep_alive_off() {
+        connection.push("limits=httpx.Limits(max_keepalive_connections=0)".into());
+    }
+
+    if let Some(ref proxy) = plan.proxy {
+        client.push(format!("proxy={}", py_str(&proxy.url_with_credentials())));
+        if !proxy.no_proxy.is_empty() {
+            unsupported.push(NO_PROXY);
+        }
+    }
+    if let Some(timeout) = plan.timeout() {
+        client.push(format!("timeout={}", seconds(timeout)));
+    }
+    match plan.follow_redirects() {
+        Some(true) => client.push("follow_redirects=True".into()),
+        Some(false) => client.push("follow_redirects=False".into()),
+        None => {}
+    }
+    if let Some(max) = plan.max_redirects() {
+        client.push(format!("max_redirects={}", max));
+    }
+
+    match plan.unix_socket() {
+        Some(path) => {
+            connection.insert(0, format!("uds={}", py_str(&path)));
+            client.push(format!(
+                "transport={}",
+                py_call("httpx.HTTPTransport", &connection, 1)
+            ));
+        }
+        None => {
+            client.splice(0..0, connection);
+        }
+    }
+    if plan.resolves() {
+        unsupported.push(RESOLVE);
+    }
+
+    let method = py_method(plan.method);
+    if method.is_none() {
+        args.insert(0, py_str(plan.method));
+    }
+    let function = method.unwrap_or("request");
+
+    let request = if client.is_empty() {
+        format!(
+            "response = {}",
+            py_call(&format!("httpx.{}", function), &args, 0)
+        )
+    } else {
+        format!(
+            "with {} as client:\n    response = {}",
+            block("httpx.Client(", ")", &client, "    ", 0),
+            py_call(&format!("client.{}", function), &args, 1)
+        )
+    };
+
+    let code = format!(
+        "import httpx\n\n{}\n\n{}\n\nprint(response.status_code)\nprint(response.text)\n",
+        lines.join("\n"),
+        request
+    );
+    (code, unsupported)
+}
+
+fn js_str(value: &str) -> String {
+    escape(value, '"', unicode_escape)
+}
+
+fn js_object(entries: &[(String, String)], depth: usize) -> String {
+    let entries: Vec<String> = entries
+        .iter()
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect();
+    block("{", "}", &entries, "  ", depth)
+}
+
+/// Header entries as JavaScript, Basic credentials encoded at runtime.
+fn js_headers(plan: &Plan, basic: bool) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = plan
+        .all_headers()
+        .into_iter()
+        .map(|(name, value)| (name, js_str(&value)))
+        .collect();
+    if let (true, Auth::Basic { username, password }) = (basic, &plan.auth) {
+        let credentials = js_str(&format!("{}:{}", username, password));
+        headers.push((
+            "Authorization".into(),
+            format!("`Basic ${{btoa({})}}`", credentials),
+        ));
+        headers.sort_by_key(|(name, _)| name.to_ascii_lowercase());
+    }
+    headers
+        .into_iter()
+        .map(|(name, value)| (js_str(&name), value))
+        .collect()
+}
+
+/// Builds `form` for fetch and axios, file parts read with Node's
+/// `openAsBlob`.
+fn js_form(form: &FormData, unsupported: &mut Vec<&'static str>) -> (Vec<String>, bool) {
+    let mut lines = vec!["const form = new FormData();".to_string()];
+    let mut reads_files = false;
+    for (name, value) in parts(form) {
+        let value = match value {
+            FormValue::Text {
+                value,
+                content_type: None,
+                ..
+            } => js_str(value),
+            FormValue::Text {
+                value,
+                content_type: Some(content_type),
+                ..
+            } => format!(
+                "new Blob([{}], {{ type: {} }})",
+                js_str(value),
+                js_str(content_type)
+            ),
+            FormValue::File {
+                filename,
+                content_type,
+                ..
+            } => {
+                reads_files = true;
+                format!(
+                    "await openAsBlob({}, {{ type: {} }}), {}",
+                    js_str(filename),
+                    js_str(&content_type.to_string()),
+                    js_str(filename)
+                )
+            }
+        };
+        lines.push(format!("form.append({}, {});", js_str(name), value));
+    }
+    if has_part_headers(form) {
+        unsupported.push(PART_HEADERS);
+    }
+    (lines, reads_files)
+}
+
+fn js_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(u8::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn javascript_fetch(plan: &Plan) -> Generated {
+    let mut unsupported = plan.common.clone();
+    let mut imports = Vec::new();
+    let mut lines = Vec::new();
+    let mut init = Vec::new();
+
+    if plan.method != "GET" {
+        init.push(("method".to_string(), js_str(plan.method)));
+    }
+
+    match plan.auth {
+        Auth::Digest { .. } => unsupported.push(DIGEST),
+        Auth::Aws { .. } => unsupported.push(AWS),
+        Auth::None | Auth::Basic { .. } => {}
+    }
+    let headers = js_headers(plan, true);
+    if !headers.is_empty() {
+        init.push(("headers".into(), js_object(&headers, 1)));
+    }
+
+    match plan.body {
+        Body::None => {}
+        Body::Text(text) => init.push(("body".into(), js_str(text))),
+        Body::Json(value) => init.push((
+            "body".into(),
+            format!(
+                "JSON.stringify({})",
+                indent_rest(&pretty_json(value, "  "), "  ")
+            ),
+        )),
+        Body::Bytes(bytes) => init.push((
+            "body".into(),
+            format!("new Uint8Array([{}])", js_bytes(bytes)),
+        )),
+        Body::File(filename) => {
+            imports.push("import { openAsBlob } from \"node:fs\";");
+            init.push((
+                "body".into(),
+                format!("await openAsBlob({})", js_str(filename)),
+            ));
+        }
+        Body::Form(form) => {
+            let (form_lines, reads_files) = js_form(form, &mut unsupported);
+            if reads_files {
+                imports.push("import { openAsBlob } from \"node:fs\";");
+            }
+            lines.extend(form_lines);
+            init.push(("body".into(), "form".into()));
+        }
+    }
+
+    if plan.follow_redirects() == Some(false) {
+        init.push(("redirect".into(), js_str("manual")));
+    }
+    if plan.max_redirects().is_some() {
+        unsupported.push(MAX_REDIRECTS);
+    }
+    if let Some(timeout) = plan.timeout() {
+        init.push((
+            "signal".into(),
+            format!("AbortSignal.timeout({})", timeout.as_millis()),
+        ));
+    }
+
+    unsupported.extend(plan.version);
+    match plan.tls.client {
+        Some(ClientCert::Pem) => unsupported.push(PEM_CERT),
+        Some(ClientCert::Pkcs12 { .. }) => unsupported.push(PKCS12_CERT),
+        None => {}
+    }
+    if plan.tls.ca {
+        unsupported.push(CUSTOM_CA);
+    }
+    if plan.tls.insecure {
+        unsupported.push(INSECURE);
+    }
+    if plan.proxy.is_some() {
+        unsupported.push(PROXY);
+    }
+    if plan.unix_socket().is_some() {
+        unsupported.push(UNIX_SOCKET);
+    }
+    if plan.resolves() {
+        unsupported.push(RESOLVE);
+    }
+    if plan.keep_alive_off() {
+        unsupported.push(NO_KEEP_ALIVE);
+    }
+
+    let call = if init.is_empty() {
+        format!("fetch({})", js_str(&plan.url))
+    } else {
+        format!("fetch({}, {})", js_str(&plan.url), js_object(&init, 0))
+    };
+
+    let mut code = String::new();
+    if !imports.is_empty() {
+        code.push_str(&format!("{}\n\n", imports.join("\n")));
+    }
+    if !lines.is_empty() {
+        code.push_str(&format!("{}\n\n", lines.join("\n")));
+    }
+    code.push_str(&format!(
+        "const response = await {};\n\nconsole.log(response.status);\nconsole.log(await response.text());\n",
+        call
+    ));
+    (code, unsupported)
+}
+
+fn javascript_axios(plan: &Plan) -> Generated {
+    let mut unsupported = plan.common.clone();
+    let mut imports = vec!["import axios from \"axios\";".to_string()];
+    let mut lines = Vec::new();
+    let mut config = vec![
+        ("method".to_string(), js_str(&plan.method.to_lowercase())),
+        ("url".to_string(), js_str(&plan.url)),
+    ];
+    let mut reads_sync = false;
+
+    let headers = js_headers(plan, false);
+    if !headers.is_empty() {
+        config.push(("headers".into(), js_object(&headers, 1)));
+    }
+
+    match plan.body {
+        Body::None => {}
+        Body::Text(text) => config.push(("data".into(), js_str(text))),
+        Body::Json(value) => {
+            config.push(("data".into(), indent_rest(&pretty_json(value, "  "), "  ")))
+        }
+        Body::Bytes(bytes) => {
+            config.push(("data".into(), format!("Buffer.from([{}])", js_bytes(bytes))))
+        }
+        Body::File(filename) => {
+            reads_sync = true;
+            config.push(("data".into(), format!("readFileSync({})", js_str(filename))));
+        }
+        Body::Form(form) => {
+            let (form_lines, reads_files) = js_form(form, &mut unsupported);
+            if reads_files {
+                imports.push("import { openAsBlob } from \"node:fs\";".into());
+            }
+            lines.extend(form_lines);
+            config.push(("data".into(), "form".into()));
+        }
+    }
+
+    match plan.auth {
+        Auth::None => {}
+        Auth::Basic { username, password } => config.push((
+            "auth".into(),
+            format!(
+                "{{ username: {}, password: {} }}",
+                js_str(username),
+                js_str(password)
+            ),
+        )),
+        Auth::Digest { .. } => unsupported.push(DIGEST),
+        Auth::Aws { .. } => unsupported.push(AWS),
+    }
+
+    if let Some(timeout) = plan.timeout() {
+        config.push(("timeout".into(), timeout.as_millis().to_string()));
+    }
+    match (plan.follow_redirects(), plan.max_redirects()) {
+        (Some(false), _) => config.push(("maxRedirects".into(), "0".into())),
+        (_, Some(max)) => config.push(("maxRedirects".into(), max.to_string())),
+        _ => {}
+    }
+
+    if let Some(ref proxy) = plan.proxy {
+        if proxy.is_socks() {
+            unsupported.push(SOCKS_PROXY);
+        } else {
+            let (host, port) = proxy.host_port();
+            let mut entries = vec![
+                ("protocol".to_string(), js_str(proxy.scheme())),
+                ("host".to_string(), js_str(&host)),
+                ("port".to_string(), port.to_string()),
+            ];
+            if let Some((username, password)) = proxy.credentials {
+                entries.push((
+                    "auth".into(),
+                    format!(
+                        "{{ username: {}, password: {} }}",
+                        js_str(username),
+                        js_str(password)
+                    ),
+                ));
+            }
+            config.push(("proxy".into(), js_object(&entries, 1)));
+        }
+        if !proxy.no_proxy.is_empty() {
+            unsupported.push(NO_PROXY);
+        }
+    }
+
+    let mut agent = Vec::new();
+    match plan.tls.client {
+        Some(ClientCert::Pem) => {
+            agent.push((
+                "cert".to_string(),
+                format!("readFileSync({})", js_str(CLIENT_CERT_FILE)),
+            ));
+            agent.push((
+                "key".to_string(),
+                format!("readFileSync({})", js_str(CLIENT_KEY_FILE)),
+            ));
+        }
+        Some(ClientCert::Pkcs12 { password }) => {
+            agent.push((
+                "pfx".to_string(),
+                format!("readFileSync({})", js_str(CLIENT_P12_FILE)),
+            ));
+            agent.push(("passphrase".to_string(), js_str(password)));
+        }
+        None => {}
+    }
+    if plan.tls.ca {
+        agent.push((
+            "ca".into(),
+            format!("readFileSync({})", js_str(CA_CERT_FILE)),
+        ));
+    }
+    if plan.tls.insecure {
+        agent.push(("rejectUnauthorized".into(), "false".into()));
+    }
+    if plan.keep_alive_off() {
+        agent.push(("keepAlive".into(), "false".into()));
+    }
+    if !agent.is_empty() {
+        reads_sync |= agent
+            .iter()
+            .any(|(_, value)| value.starts_with("readFileSync"));
+        let (module, key) = if plan.is_https() {
+            ("https", "httpsAgent")
+        } else {
+            ("http", "httpAgent")
+        };
+        imports.push(format!("import {} from \"node:{}\";", module, module));
+        config.push((
+            key.into(),
+            format!("new {}.Agent({})", module, js_object(&agent, 1)),
+        ));
+    }
+
+    if let Some(path) = plan.unix_socket() {
+        config.push(("socketPath".into(), js_str(&path)));
+    }
+
+    unsupported.extend(plan.version);
+    if plan.resolves() {
+        unsupported.push(RESOLVE);
+    }
+
+    if reads_sync {
+        match imports.iter().position(|line| line.contains("openAsBlob")) {
+            Some(index) => {
+                imports[index] = "import { openAsBlob, readFileSync } from \"node:fs\";".into()
+            }
+            None => imports.push("import { readFileSync } from \"node:fs\";".into()),
+        }
+    }
+
+    imports[1..].sort_by_key(|line| line.rsplit(' ').next().unwrap_or_default().to_string());
+
+    let mut code = format!("{}\n\n", imports.join("\n"));
+    if !lines.is_empty() {
+        code.push_str(&format!("{}\n\n", lines.join("\n")));
+    }
+    code.push_str(&format!(
+        "const response = await axios.request({});\n\nconsole.log(response.status);\nconsole.log(response.data);\n",
+        js_object(&config, 0)
+    ));
+    (code, unsupported)
+}
+
+fn go_str(value: &str) -> String {
+    escape(value, '"', hex_escape)
+}
+
+/// A raw string literal where it reads better and is possible.
+fn go_text(value: &str) -> String {
+    if value.contains('"') && !value.contains('`') && !value.contains('\r') {
+        format!("`{}`", value)
+    } else {
+        go_str(value)
+    }
+}
+
+/// Keyed composite literal fields, aligned the way gofmt does.
+fn go_fields(fields: &[(String, String)], depth: usize) -> String {
+    let width = fields.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    let pad = "\t".repeat(depth + 1);
+    fields
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}{}:{}{},\n",
+                pad,
+                key,
+                " ".repeat(width - key.len() + 1),
+                value
+            )
+        })
+        .collect()
+}
+
+fn go_method(method: &str) -> String {
+    match method {
+        "GET" => "http.MethodGet".into(),
+        "HEAD" => "http.MethodHead".into(),
+        "POST" => "http.MethodPost".into(),
+        "PUT" => "http.MethodPut".into(),
+        "PATCH" => "http.MethodPatch".into(),
+        "DELETE" => "http.MethodDelete".into(),
+        "CONNECT" => "http.MethodConnect".into(),
+        "OPTIONS" => "http.MethodOptions".into(),
+        "TRACE" => "http.MethodTrace".into(),
+        method => go_str(method),
+    }
+}
+
+/// Writes Go statements, tracking imports and which variables exist so
+/// assignments use `:=` only where they declare something.
+struct Go {
+    imports: BTreeSet<&'static str>,
+    declared: HashSet<&'static str>,
+    lines: Vec<String>,
+}
+
+impl Go {
+    fn line(&mut self, line: impl Into<String>) {
+        self.lines.push(format!("\t{}", line.into()));
+    }
+
+    fn assign(&mut self, names: &[&'static str], value: impl std::fmt::Display) {
+        let op = if names.iter().all(|name| self.declared.contains(name)) {
+            "="
+        } else {
+            ":="
+        };
+        self.declared.extend(names);
+        self.line(format!("{} {} {}", names.join(", "), op, value));
+    }
+
+    /// Assigns a value and an error, failing on the error.
+    fn try_assign(&mut self, names: &[&'static str], value: impl std::fmt::Display) {
+        let mut names = names.to_vec();
+        names.push("err");
+        self.assign(&names, value);
+        self.check();
+    }
+
+    fn check(&mut self) {
+        self.imports.insert("log");
+        self.line("if err != nil {");
+        self.line("\tlog.Fatal(err)");
+        self.line("}");
+    }
+
+    fn try_call(&mut self, call: impl std::fmt::Display) {
+        self.imports.insert("log");
+        self.line(format!("if err := {}; err != nil {{", call));
+        self.line("\tlog.Fatal(err)");
+        self.line("}");
+    }
+
+    fn try_write(&mut self, call: impl std::fmt::Display) {
+        self.imports.insert("log");
+        self.line(format!("if _, err := {}; err != nil {{", call));
+        self.line("\tlog.Fatal(err)");
+        self.line("}");
+    }
+
+    fn blank(&mut self) {
+        if self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+}
+
+fn go_part(go: &mut Go, disposition: String, content_type: Option<String>, value: &FormValue) {
+    let mut header = vec![(
+        "\"Content-Disposition\"".to_string(),
+        format!("{{{}}}", go_str(&disposition)),
+    )];
+    if let Some(content_type) = content_type {
+        header.push((
+            "\"Content-Type\"".into(),
+            format!("{{{}}}", go_str(&content_type)),
+        ));
+    }
+    for (name, value) in part_headers(value) {
+        header.push((go_str(name), format!("{{{}}}", go_str(value))));
+    }
+    go.imports.insert("net/textproto");
+    go.try_assign(
+        &["part"],
+        format!(
+            "form.CreatePart(textproto.MIMEHeader{{\n{}\t}})",
+            go_fields(&header, 1)
+        ),
+    );
+}
+
+fn go_net_http(plan: &Plan) -> Generated {
+    let mut unsupported = plan.common.clone();
+    let mut go = Go {
+        imports: BTreeSet::from(["fmt", "io", "log", "net/http"]),
+        declared: HashSet::new(),
+        lines: Vec::new(),
+    };
+
+    let body = match plan.body {
+        Body::None => "nil",
+        Body::Text(text) => {
+            go.imports.insert("strings");
+            go.assign(&["body"], format!("strings.NewReader({})", go_text(text)));
+            "body"
+        }
+        Body::Json(value) => {
+            go.imports.insert("strings");
+            let json = serde_json::to_string(value).unwrap_or_default();
+            go.assign(&["body"], format!("strings.NewReader({})", go_text(&json)));
+            "body"
+        }
+        Body::Bytes(bytes) => {
+            go.imports.insert("bytes");
+            go.assign(
+                &["body"],
+                format!("bytes.NewReader([]byte({}))", byte_string(bytes)),
+            );
+            "body"
+        }
+        Body::File(filename) => {
+            go.imports.insert("os");
+            go.try_assign(&["body"], format!("os.Open({})", go_str(filename)));
+            go.line("defer body.Close()");
+            "body"
+        }
+        Body::Form(form) => {
+            go.imports.extend(["bytes", "mime/multipart"]);
+            go.assign(&["body"], "new(bytes.Buffer)");
+            go.assign(&["form"], "multipart.NewWriter(body)");
+            for (name, value) in parts(form) {
+                let name = name.replace('"', "\\\"");
+                match value {
+                    FormValue::Text {
+                        value: text,
+                        content_type: None,
+                        headers,
+                    } if headers.as_ref().is_none_or(|headers| headers.is_empty()) => {
+                        go.try_call(format!(
+                            "form.WriteField({}, {})",
+                            go_str(&name),
+                            go_str(text)
+                        ));
+                    }
+                    FormValue::Text {
+                        value: text,
+                        content_type,
+                        ..
+                    } => {
+                        go_part(
+                            &mut go,
+                            format!("form-data; name=\"{}\"", name),
+                            content_type.clone(),
+                            value,
+                        );
+                        go.try_write(format!("io.WriteString(part, {})", go_str(text)));
+                    }
+                    FormValue::File {
+                        filename,
+                        content_type,
+                        ..
+                    } => {
+                        go.imports.insert("os");
+                        go.try_assign(&["file"], format!("os.Open({})", go_str(filename)));
+                        go.line("defer file.Close()");
+                        go_part(
+                            &mut go,
+                            format!(
+                                "form-data; name=\"{}\"; filename=\"{}\"",
+                                name,
+                                filename.replace('"', "\\\"")
+                            ),
+                            Some(content_type.to_string()),
+                            value,
+                        );
+                        go.try_write("io.Copy(part, file)");
+                    }
+                }
+            }
+            go.try_call("form.Close()");
+            "body"
+        }
+    };
+    go.blank();
+
+    go.try_assign(
+        &["req"],
+        format!(
+            "http.NewRequest({}, {}, {})",
+            go_method(plan.method),
+            go_str(&plan.url),
+            body
+        ),
+    );
+    for (name, value) in plan.all_headers() {
+        go.line(format!(
+            "req.Header.Set({}, {})",
+            go_str(&name),
+            go_str(&value)
+        ));
+    }
+    if let Body::Form(_) = plan.body {
+        go.line("req.Header.Set(\"Content-Type\", form.FormDataContentType())");
+    }
+    match plan.auth {
+        Auth::None => {}
+        Auth::Basic { username, password } => {
+            go.line(format!(
+                "req.SetBasicAuth({}, {})",
+                go_str(username),
+                go_str(password)
+            ));
+        }
+        Auth::Digest { .. } => unsupported.push(DIGEST),
+        Auth::Aws { .. } => unsupported.push(AWS),
+    }
+
+    let mut tls_fields = Vec::new();
+    match plan.tls.client {
+        Some(ClientCert::Pem) => {
+            go.blank();
+            go.imports.insert("crypto/tls");
+            go.try_assign(
+                &["cert"],
+                format!(
+                    "tls.LoadX509KeyPair({}, {})",
+                    go_str(CLIENT_CERT_FILE),
+                    go_str(CLIENT_KEY_FILE)
+                ),
+            );
+            tls_fields.push((
+                "Certificates".to_string(),
+                "[]tls.Certificate{cert}".to_string(),
+            ));
+        }
+        Some(ClientCert::Pkcs12 { .. }) => unsupported.push(PKCS12_CERT),
+        None => {}
+    }
+    if plan.tls.ca {
+        go.blank();
+        go.imports.extend(["crypto/x509", "os"]);
+        go.try_assign(&["ca"], format!("os.ReadFile({})", go_str(CA_CERT_FILE)));
+        go.assign(&["pool"], "x509.NewCertPool()");
+        go.line("pool.AppendCertsFromPEM(ca)");
+        tls_fields.push(("RootCAs".into(), "pool".into()));
+    }
+    if plan.tls.insecure {
+        tls_fields.push(("InsecureSkipVerify".into(), "true".into()));
+    }
+
+    let mut transport = Vec::new();
+    if let Some(ref proxy) = plan.proxy {
+        if proxy.scheme().starts_with("socks4") {
+            unsupported.push(SOCKS_PROXY);
+        } else {
+            go.blank();
+            go.imports.insert("net/url");
+            go.try_assign(
+                &["proxyURL"],
+                format!("url.Parse({})", go_str(&proxy.url_with_credentials())),
+            );
+            transport.push(("Proxy".to_string(), "http.ProxyURL(proxyURL)".to_string()));
+        }
+        if !proxy.no_proxy.is_empty() {
+            unsupported.push(NO_PROXY);
+        }
+    }
+    if !tls_fields.is_empty() {
+        go.blank();
+        go.imports.insert("crypto/tls");
+        go.lines.push(format!(
+            "\ttlsConfig := &tls.Config{{\n{}\t}}",
+            go_fields(&tls_fields, 1)
+        ));
+        transport.push(("TLSClientConfig".into(), "tlsConfig".into()));
+    }
+    if plan.keep_alive_off() {
+        transport.push(("DisableKeepAlives".into(), "true".into()));
+    }
+    match plan.version {
+        Some(HTTP_2) if !transport.is_empty() => {
+            transport.push(("ForceAttemptHTTP2".into(), "true".into()))
+        }
+        Some(HTTP_2) => {}
+        Some(version) => unsupported.push(version),
+        None => {}
+    }
+    let unix_socket = plan.unix_socket();
+
+    let mut client = Vec::new();
+    if let Some(timeout) = plan.timeout() {
+        go.imports.insert("time");
+        let value = if timeout.subsec_millis() == 0 {
+            format!("{} * time.Second", timeout.as_secs())
+        } else {
+            format!("{} * time.Millisecond", timeout.as_millis())
+        };
+        client.push(("Timeout".to_string(), value));
+    }
+    let transport_var = unix_socket.is_some() || transport.len() > 1;
+    if !transport.is_empty() || unix_socket.is_some() {
+        if transport_var {
+            go.blank();
+            go.lines.push(format!(
+                "\ttransport := &http.Transport{{\n{}\t}}",
+                go_fields(&transport, 1)
+            ));
+            if let Some(ref path) = unix_socket {
+                go.imports.extend(["context", "net"]);
+                // NOTE: Go reads a leading `@` as an abstract socket name.
+                let path = path.replacen('\0', "@", 1);
+                go.line("transport.DialContext = func(ctx context.Context, _, _ string) (net.Conn, error) {");
+                go.line(format!(
+                    "\treturn (&net.Dialer{{}}).DialContext(ctx, \"unix\", {})",
+                    go_str(&path)
+                ));
+                go.line("}");
+            }
+            client.push(("Transport".into(), "transport".into()));
+        } else {
+            client.push((
+                "Transport".into(),
+                format!("&http.Transport{{{}: {}}}", transport[0].0, transport[0].1),
+            ));
+        }
+    }
+
+    let redirect = match (plan.follow_redirects(), plan.max_redirects()) {
+        (Some(false), _) => Some("\treturn http.ErrUseLastResponse".to_string()),
+        (_, Some(max)) => Some(format!(
+            "\tif len(via) >= {} {{\n\t\t\treturn http.ErrUseLastResponse\n\t\t}}\n\t\treturn nil",
+            max
+        )),
+        _ => None,
+    };
+    if plan.resolves() {
+        unsupported.push(RESOLVE);
+    }
+
+    go.blank();
+    let client_name = if client.is_empty() && redirect.is_none() {
+        "http.DefaultClient"
+    } else {
+        if client.is_empty() {
+            go.line("client := &http.Client{}");
+        } else {
+            go.lines.push(format!(
+                "\tclient := &http.Client{{\n{}\t}}",
+                go_fields(&client, 1)
+            ));
+        }
+        if let Some(redirect) = redirect {
+            go.line("client.CheckRedirect = func(req *http.Request, via []*http.Request) error {");
+            go.line(redirect);
+            go.line("}");
+        }
+        go.blank();
+        "client"
+    };
+
+    go.try_assign(&["res"], format!("{}.Do(req)", client_name));
+    go.line("defer res.Body.Close()");
+    go.blank();
+    go.try_assign(&["data"], "io.ReadAll(res.Body)");
+    go.line("fmt.Println(res.Status)");
+    go.line("fmt.Println(string(data))");
+
+    let imports: String = go
+        .imports
+        .iter()
+        .map(|import| format!("\t\"{}\"\n", import))
+        .collect();
+    let code = format!(
+        "package main\n\nimport (\n{})\n\nfunc main() {{\n{}\n}}\n",
+        imports,
+        go.lines.join("\n")
+    );
+    (code, unsupported)
+}
+
+fn rust_str(value: &str) -> String {
+    format!("{:?}", value)
+}
+
+fn rust_method(method: &str) -> Option<&'static str> {
+    Some(match method {
+        "GET" => "get",
+        "POST" => "post",
+        "PUT" => "put",
+        "PATCH" => "patch",
+        "DELETE" => "delete",
+        "HEAD" => "head",
+        _ => return None,
+    })
+}
+
+fn rust_reqwest(plan: &Plan) -> Generated {
+    let mut unsupported = plan.common.clone();
+    let mut builder = Vec::new();
+
+    if plan.tls.insecure {
+        builder.push(".danger_accept_invalid_certs(true)".to_string());
+    }
+    match plan.tls.client {
+        Some(ClientCert::Pem) => builder.push(format!(
+            ".identity(reqwest::Identity::from_pkcs8_pem(\n            &std::fs::read({})?,\n            &std::fs::read({})?,\n        )?)",
+            rust_str(CLIENT_CERT_FILE),
+            rust_str(CLIENT_KEY_FILE)
+        )),
+        Some(ClientCert::Pkcs12 { password }) => builder.push(format!(
+            ".identity(reqwest::Identity::from_pkcs12_der(\n            &std::fs::read({})?,\n            {},\n        )?)",
+            rust_str(CLIENT_P12_FILE),
+            rust_str(password)
+        )),
+        None => {}
+    }
+    if plan.tls.ca {
+        builder.push(format!(
+            ".add_root_certificate(reqwest::Certificate::from_pem(&std::fs::read({})?)?)",
+            rust_str(CA_CERT_FILE)
+        ));
+    }
+
+    if let Some(ref proxy) = plan.proxy {
+        let mut value = format!("reqwest::Proxy::all({})?", rust_str(&proxy.url));
+        if let Some((username, password)) = proxy.credentials {
+            value.push_str(&format!(
+                "\n                .basic_auth({}, {})",
+                rust_str(username),
+                rust_str(password)
+            ));
+        }
+        if !proxy.no_proxy.is_empty() {
+            value.push_str(&format!(
+                "\n                .no_proxy(reqwest::NoProxy::from_string({}))",
+                rust_str(&proxy.no_proxy.join(","))
+            ));
+        }
+        if value.contains('\n') {
+            builder.push(format!(".proxy(\n            {},\n        )", value));
+        } else {
+            builder.push(format!(".proxy({})", value));
+        }
+    }
+
+    match plan.version {
+        Some(HTTP_2) => {}
+        Some(HTTP_2_ONLY) => builder.push(".http2_prior_knowledge()".into()),
+        Some(version) => unsupported.push(version),
+        None => {}
+    }
+    if let Some(timeout) = plan.timeout() {
+        builder.push(format!(
+            ".timeout(std::time::Duration::from_millis({}))",
+            timeout.as_millis()
+        ));
+    }
+    match (plan.follow_redirects(), plan.max_redirects()) {
+        (Some(false), _) => builder.push(".redirect(reqwest::redirect::Policy::none())".into()),
+        (_, Some(max)) => builder.push(format!(
+            ".redirect(reqwest::redirect::Policy::limited({}))",
+            max
+        )),
+        _ => {}
+    }
+    if plan.keep_alive_off() {
+        builder.push(".pool_max_idle_per_host(0)".into());
+    }
+    for entry in plan.options.resolve.iter().flatten() {
+        let addresses: Vec<String> = entry
+            .addresses
+            .iter()
+            .map(|address| {
+                let address = address.trim_start_matches('[').trim_end_matches(']');
+                let address = if address.contains(':') {
+                    format!("[{}]:{}", address, entry.port)
+                } else {
+                    format!("{}:{}", address, entry.port)
+                };
+                format!("{}.parse()?", rust_str(&address))
+            })
+            .collect();
+        builder.push(format!(
+            ".resolve_to_addrs({}, &[{}])",
+            rust_str(&entry.host),
+            addresses.join(", ")
+        ));
+    }
+    if plan.unix_socket().is_some() {
+        unsupported.push(UNIX_SOCKET);
+    }
+
+    let mut lines = Vec::new();
+    let mut chain = Vec::new();
+    match rust_method(plan.method) {
+        Some(method) => chain.push(format!(".{}({})", method, rust_str(&plan.url))),
+        None => {
+            let method = match plan.method {
+                "OPTIONS" | "CONNECT" | "TRACE" => format!("reqwest::Method::{}", plan.method),
+                method => format!(
+                    "reqwest::Method::from_bytes({})?",
+                    byte_string(method.as_bytes()).replacen('"', "b\"", 1)
+                ),
+            };
+            chain.push(format!(".request({}, {})", method, rust_str(&plan.url)));
+        }
+    }
+    for (name, value) in plan.all_headers() {
+        chain.push(format!(
+            ".header({}, {})",
+            rust_str(&name),
+            rust_str(&value)
+        ));
+    }
+    match plan.auth {
+        Auth::None => {}
+        Auth::Basic { username, password } => chain.push(format!(
+            ".basic_auth({}, Some({}))",
+            rust_str(username),
+            rust_str(password)
+        )),
+        Auth::Digest { .. } => unsupported.push(DIGEST),
+        Auth::Aws { .. } => unsupported.push(AWS),
+    }
+    match plan.body {
+        Body::None => {}
+        Body::Text(text) => chain.push(format!(".body({})", rust_str(text))),
+        Body::Json(value) => chain.push(format!(
+            ".json(&serde_json::json!({}))",
+            indent_rest(&pretty_json(value, "    "), "        ")
+        )),
+        Body::Bytes(bytes) => chain.push(format!(".body(b{}.to_vec())", byte_string(bytes))),
+        Body::File(filename) => chain.push(format!(
+            ".body(tokio::fs::read({}).await?)",
+            rust_str(filename)
+        )),
+        Body::Form(form) => {
+            let mut form_chain = vec!["reqwest::multipart::Form::new()".to_string()];
+            for (name, value) in parts(form) {
+                form_chain.push(match value {
+                    FormValue::Text {
+                        value,
+                        content_type: None,
+                        ..
+                    } => format!(".text({}, {})", rust_str(name), rust_str(value)),
+                    FormValue::Text {
+                        value,
+                        content_type: Some(content_type),
+                        ..
+                    } => format!(
+                        ".part(\n            {},\n            reqwest::multipart::Part::text({}).mime_str({})?,\n        )",
+                        rust_str(name),
+                        rust_str(value),
+                        rust_str(content_type)
+                    ),
+                    FormValue::File {
+                        filename,
+                        content_type,
+                        ..
+                    } => format!(
+                        ".part(\n            {},\n            reqwest::multipart::Part::bytes(tokio::fs::read({}).await?)\n                .file_name({})\n                .mime_str({})?,\n        )",
+                        rust_str(name),
+                        rust_str(filename),
+                        rust_str(filename),
+                        rust_str(&content_type.to_string())
+                    ),
+                });
+            }
+            if has_part_headers(form) {
+                unsupported.push(PART_HEADERS);
+            }
+            lines.push(format!("    let form = {};", form_chain.join("\n        ")));
+            chain.push(".multipart(form)".into());
+        }
+    }
+    chain.push(".send()".into());
+    chain.push(".await?".into());
+
+    let client = if builder.is_empty() {
+        "    let client = reqwest::Client::new();".to_string()
+    } else {
+        format!(
+            "    let client = reqwest::Client::builder()\n        {}\n        .build()?;",
+            builder.join("\n        ")
+        )
+    };
+    let mut body = vec![client];
+    if !lines.is_empty() {
+        body.push(lines.join("\n"));
+    }
+    body.push(format!(
+        "    let response = client\n        {};",
+        chain.join("\n        ")
+    ));
+    body.push(
+        "    println!(\"{}\", response.status());\n    println!(\"{}\", response.text().await?);"
+            .into(),
+    );
+    body.push("    Ok(())".into());
+
+    let code = format!(
+        "#[tokio::main]\nasync fn main() -> Result<(), Box<dyn std::error::Error>> {{\n{}\n}}\n",
+        body.join("\n\n")
+    );
+    (code, unsupported)
+}
+
+fn java_str(value: &str) -> String {
+    escape(value, '"', unicode_escape)
+}
+
+/// Headers the JDK refuses to let requests set.
+fn java_restricted(name: &str) -> bool {
+    ["connection", "content-length", "expect", "host", "upgrade"]
+        .contains(&name.to_ascii_lowercase().as_str())
+}
+
+fn java_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| {
+            if byte < 0x80 {
+                byte.to_string()
+            } else {
+                format!("(byte) 0x{:02x}", byte)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn java_http_client(plan: &Plan) -> Generated {
+    let mut unsupported = plan.common.clone();
+    let mut imports = BTreeSet::from([
+        "java.net.URI",
+        "java.net.http.HttpClient",
+        "java.net.http.HttpRequest",
+        "java.net.http.HttpResponse",
+    ]);
+    let mut lines: Vec<String> = Vec::new();
+    let mut client = Vec::new();
+    let mut request = vec![format!(".uri(URI.create({}))", java_str(&plan.url))];
+
+    match plan.version {
+        None => client.push(".version(HttpClient.Version.HTTP_1_1)".to_string()),
+        Some(HTTP_2) => client.push(".version(HttpClient.Version.HTTP_2)".to_string()),
+        Some(version) => unsupported.push(version),
+    }
+    if plan.follow_redirects() == Some(true) {
+        client.push(".followRedirects(HttpClient.Redirect.NORMAL)".into());
+    }
+    if plan.max_redirects().is_some() {
+        unsupported.push(MAX_REDIRECTS);
+    }
+
+    let mut key_managers = "null";
+    let mut trust_managers = "null";
+    match plan.tls.client {
+        Some(ClientCert::Pem) => unsupported.push(PEM_CERT),
+        Some(ClientCert::Pkcs12 { password }) => {
+            imports.extend([
+                "java.io.FileInputStream",
+                "java.io.InputStream",
+                "java.security.KeyStore",
+                "javax.net.ssl.KeyManagerFactory",
+            ]);
+            let password = java_str(password);
+            lines.push("KeyStore keyStore = KeyStore.getInstance(\"PKCS12\");".into());
+            lines.push(format!(
+                "try (InputStream in = new FileInputStream({})) {{",
+                java_str(CLIENT_P12_FILE)
+            ));
+            lines.push(format!(
+                "    keyStore.load(in, {}.toCharArray());",
+                password
+            ));
+            lines.push("}".into());
+            lines.push("KeyManagerFactory keyManagers = KeyManagerFactory.getInstance(KeyManagerFactory.getDefaultAlgorithm());".into());
+            lines.push(format!(
+                "keyManagers.init(keyStore, {}.toCharArray());",
+                password
+            ));
+            lines.push(String::new());
+            key_managers = "keyManagers.getKeyManagers()";
+        }
+        None => {}
+    }
+    if plan.tls.ca {
+        imports.extend([
+            "java.io.FileInputStream",
+            "java.io.InputStream",
+            "java.security.KeyStore",
+            "java.security.cert.Certificate",
+            "java.security.cert.CertificateFactory",
+            "javax.net.ssl.TrustManagerFactory",
+        ]);
+        lines.push("KeyStore trustStore = KeyStore.getInstance(KeyStore.getDefaultType());".into());
+        lines.push("trustStore.load(null, null);".into());
+        lines.push(format!(
+            "try (InputStream in = new FileInputStream({})) {{",
+            java_str(CA_CERT_FILE)
+        ));
+        lines.push("    int index = 0;".into());
+        lines.push("    for (Certificate certificate : CertificateFactory.getInstance(\"X.509\").generateCertificates(in)) {".into());
+        lines
+            .push("        trustStore.setCertificateEntry(\"ca-\" + index++, certificate);".into());
+        lines.push("    }".into());
+        lines.push("}".into());
+        lines.push("TrustManagerFactory trustManagers = TrustManagerFactory.getInstance(TrustManagerFactory.getDefaultAlgorithm());".into());
+        lines.push("trustManagers.init(trustStore);".into());
+        lines.push(String::new());
+        trust_managers = "trustManagers.getTrustManagers()";
+    }
+    if key_managers != "null" || trust_managers != "null" {
+        imports.insert("javax.net.ssl.SSLContext");
+        lines.push("SSLContext sslContext = SSLContext.getInstance(\"TLS\");".into());
+        lines.push(format!(
+            "sslContext.init({}, {}, null);",
+            key_managers, trust_managers
+        ));
+        lines.push(String::new());
+        client.push(".sslContext(sslContext)".into());
+    }
+    if plan.tls.insecure {
+        unsupported.push(INSECURE);
+    }
+
+    if let Some(ref proxy) = plan.proxy {
+        if proxy.is_socks() {
+            unsupported.push(SOCKS_PROXY);
+        } else {
+            imports.extend(["java.net.InetSocketAddress", "java.net.ProxySelector"]);
+            let (host, port) = proxy.host_port();
+            client.push(format!(
+                ".proxy(ProxySelector.of(new InetSocketAddress({}, {})))",
+                java_str(&host),
+                port
+            ));
+        }
+        if proxy.credentials.is_some() {
+            unsupported.push(PROXY_AUTH);
+        }
+        if !proxy.no_proxy.is_empty() {
+            unsupported.push(NO_PROXY);
+        }
+    }
+
+    if let Some(timeout) = plan.timeout() {
+        imports.insert("java.time.Duration");
+        request.push(format!(
+            ".timeout(Duration.ofMillis({}))",
+            timeout.as_millis()
+        ));
+    }
+
+    let mut headers: Vec<(String, String)> = plan
+        .all_headers()
+        .into_iter()
+        .filter(|(name, _)| {
+            let restricted = java_restricted(name);
+            if restricted {
+                unsupported.push(RESTRICTED_HEADERS);
+            }
+            !restricted
+        })
+        .map(|(name, value)| (name, java_str(&value)))
+        .collect();
+    match plan.auth {
+        Auth::None => {}
+        Auth::Basic { username, password } => {
+            imports.extend(["java.nio.charset.StandardCharsets", "java.util.Base64"]);
+            headers.push((
+                "Authorization".into(),
+                format!(
+                    "\"Basic \" + Base64.getEncoder().encodeToString({}.getBytes(StandardCharsets.UTF_8))",
+                    java_str(&format!("{}:{}", username, password))
+                ),
+            ));
+            headers.sort_by_key(|(name, _)| name.to_ascii_lowercase());
+        }
+        Auth::Digest { .. } => unsupported.push(DIGEST),
+        Auth::Aws { .. } => unsupported.push(AWS),
+    }
+
+    let publisher = match plan.body {
+        Body::None => None,
+        Body::Text(text) => Some(format!(
+            "HttpRequest.BodyPublishers.ofString({})",
+            java_str(text)
+        )),
+        Body::Json(value) => Some(format!(
+            "HttpRequest.BodyPublishers.ofString({})",
+            java_str(&serde_json::to_string(value).unwrap_or_default())
+        )),
+        Body::Bytes(bytes) => Some(format!(
+            "HttpRequest.BodyPublishers.ofByteArray(new byte[] {{ {} }})",
+            java_bytes(bytes)
+        )),
+        Body::File(filename) => {
+            imports.insert("java.nio.file.Path");
+            Some(format!(
+                "HttpRequest.BodyPublishers.ofFile(Path.of({}))",
+                java_str(filename)
+            ))
+        }
+        Body::Form(form) => {
+            // NOTE: The JDK has no multipart support, so the body is
+            // assembled by hand.
+            imports.extend([
+                "java.nio.charset.StandardCharsets",
+                "java.util.ArrayList",
+                "java.util.List",
+            ]);
+            lines.push(format!("String boundary = {};", java_str(BOUNDARY)));
+            lines.push("List<byte[]> parts = new ArrayList<>();".into());
+            for (name, value) in parts(form) {
+                let name = name.replace('"', "\\\"");
+                let mut head = match value {
+                    FormValue::Text { content_type, .. } => {
+                        let mut head = format!("\r\nContent-Disposition: form-data; name=\"{}\"\r\n", name);
+                        if let Some(content_type) = content_type {
+                            head.push_str(&format!("Content-Type: {}\r\n", content_type));
+                        }
+                        head
+                    }
+                    FormValue::File {
+                        filename,
+                        content_type,
+                        ..
+                    } => format!(
+                        "\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n",
+                        name,
+                        filename.replace('"', "\\\""),
+                        content_type
+                    ),
+                };
+                for (header, value) in part_headers(value) {
+                    head.push_str(&format!("{}: {}\r\n", header, value));
+                }
+                head.push_str("\r\n");
+                match value {
+                    FormValue::Text { value, .. } => {
+                        head.push_str(value);
+                        head.push_str("\r\n");
+                        lines.push(format!(
+                            "parts.add((\"--\" + boundary + {}).getBytes(StandardCharsets.UTF_8));",
+                            java_str(&head)
+                        ));
+                    }
+                    FormValue::File { filename, .. } => {
+                        imports.extend(["java.nio.file.Files", "java.nio.file.Path"]);
+                        lines.push(format!(
+                            "parts.add((\"--\" + boundary + {}).getBytes(StandardCharsets.UTF_8));",
+                            java_str(&head)
+                        ));
+                        lines.push(format!(
+                            "parts.add(Files.readAllBytes(Path.of({})));",
+                            java_str(filename)
+                        ));
+                        lines
+                            .push("parts.add(\"\\r\\n\".getBytes(StandardCharsets.UTF_8));".into());
+                    }
+                }
+            }
+            lines.push(
+                "parts.add((\"--\" + boundary + \"--\\r\\n\").getBytes(StandardCharsets.UTF_8));"
+                    .into(),
+            );
+            lines.push(String::new());
+            headers.push((
+                "Content-Type".into(),
+                "\"multipart/form-data; boundary=\" + boundary".into(),
+            ));
+            headers.sort_by_key(|(name, _)| name.to_ascii_lowercase());
+            Some("HttpRequest.BodyPublishers.ofByteArrays(parts)".into())
+        }
+    };
+    for (name, value) in headers {
+        request.push(format!(".header({}, {})", java_str(&name), value));
+    }
+    request.push(match (plan.method, publisher) {
+        ("GET", None) => ".GET()".into(),
+        ("DELETE", None) => ".DELETE()".into(),
+        ("POST", Some(publisher)) => format!(".POST({})", publisher),
+        ("PUT", Some(publisher)) => format!(".PUT({})", publisher),
+        (method, publisher) => format!(
+            ".method({}, {})",
+            java_str(method),
+            publisher.unwrap_or("HttpRequest.BodyPublishers.noBody()".into())
+        ),
+    });
+    request.push(".build()".into());
+
+    if plan.unix_socket().is_some() {
+        unsupported.push(UNIX_SOCKET);
+    }
+    if plan.resolves() {
+        unsupported.push(RESOLVE);
+    }
+    if plan.keep_alive_off() {
+        unsupported.push(NO_KEEP_ALIVE);
+    }
+
+    client.push(".build()".into());
+    let pad = "        ";
+    let mut body: Vec<String> = lines
+        .iter()
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", pad, line)
+            }
+        })
+        .collect();
+    body.push(format!(
+        "{}HttpClient client = HttpClient.newBuilder()\n{}    {};",
+        pad,
+        pad,
+        client.join(&format!("\n{}    ", pad))
+    ));
+    body.push(String::new());
+    body.push(format!(
+        "{}HttpRequest request = HttpRequest.newBuilder()\n{}    {};",
+        pad,
+        pad,
+        request.join(&format!("\n{}    ", pad))
+    ));
+    body.push(String::new());
+    body.push(format!(
+        "{}HttpResponse<String> response = client.send(request, HttpResponse.BodyHandlers.ofString());",
+        pad
+    ));
+    body.push(String::new());
+    body.push(format!("{}System.out.println(response.statusCode());", pad));
+    body.push(format!("{}System.out.println(response.body());", pad));
+
+    let imports: String = imports
+        .iter()
+        .map(|import| format!("import {};\n", import))
+        .collect();
+    let code = format!(
+        "{}\npublic class Main {{\n    public static void main(String[] args) throws Exception {{\n{}\n    }}\n}}\n",
+        imports,
+        body.join("\n")
+    );
+    (code, unsupported)
+}
+
+/// A single-quoted PowerShell string, which takes everything literally
+/// but its quotes.
+fn ps_str(value: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in value.chars() {
+        // NOTE: PowerShell also reads typographic single quotes as quotes.
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+fn ps_secure(value: &str) -> String {
+    format!(
+        "(ConvertTo-SecureString -String {} -AsPlainText -Force)",
+        ps_str(value)
+    )
+}
+
+fn ps_method(method: &str) -> Option<&'static str> {
+    Some(match method {
+        "GET" => "Get",
+        "HEAD" => "Head",
+        "POST" => "Post",
+        "PUT" => "Put",
+        "DELETE" => "Delete",
+        "TRACE" => "Trace",
+        "OPTIONS" => "Options",
+        "MERGE" => "Merge",
+        "PATCH" => "Patch",
+        _ => return None,
+    })
+}
+
+fn ps_table(entries: &[(String, String)], depth: usize) -> String {
+    if entries.is_empty() {
+        return "@{}".into();
+    }
+    let pad = "    ".repeat(depth + 1);
+    let mut out = String::from("@{\n");
+    for (key, value) in entries {
+        out.push_str(&format!("{}{} = {}\n", pad, key, value));
+    }
+    out.push_str(&"    ".repeat(depth));
+    out.push('}');
+    out
+}
+
+fn powershell_rest_method(plan: &Plan) -> Generated {
+    let mut unsupported = plan.common.clone();
+    let mut lines = Vec::new();
+    let mut params = vec![("Uri".to_string(), ps_str(&plan.url))];
+
+    match ps_method(plan.method) {
+        Some(method) => params.push(("Method".into(), ps_str(method))),
+        None => params.push(("CustomMethod".into(), ps_str(plan.method))),
+    }
+
+    let headers: Vec<(String, String)> = plan
+        .headers
+        .iter()
+        .map(|(name, value)| (ps_str(name), ps_str(value)))
+        .collect();
+    if !headers.is_empty() {
+        params.push(("Headers".into(), ps_table(&headers, 1)));
+    }
+    if let Some(ref content_type) = plan.content_type {
+        params.push(("ContentType".into(), ps_str(content_type)));
+    }
+
+    match plan.body {
+        Body::None => {}
+        Body::Text(text) => params.push(("Body".into(), ps_str(text))),
+        Body::Json(value) => params.push((
+            "Body".into(),
+            ps_str(&serde_json::to_string(value).unwrap_or_default()),
+        )),
+        Body::Bytes(bytes) => params.push((
+            "Body".into(),
+            format!(
+                "[byte[]] @({})",
+                bytes
+                    .iter()
+                    .map(|byte| format!("0x{:02x}", byte))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        )),
+        Body::File(filename) => params.push(("InFile".into(), ps_str(filename))),
+        Body::Form(form) => {
+            let mut fields = Vec::new();
+            for (name, values) in form {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|value| match value {
+                        FormValue::Text {
+                            value,
+                            content_type,
+                            ..
+                        } => {
+                            if content_type.is_some() {
+                                unsupported.push(PART_TYPES);
+                            }
+                            ps_str(value)
+                        }
+                        FormValue::File {
+                            filename,
+                            content_type,
+                            ..
+                        } => {
+                            if *content_type != MediaType::OctetStream {
+                                unsupported.push(PART_TYPES);
+                            }
+                            format!("(Get-Item -Path {})", ps_str(filename))
+                        }
+                    })
+                    .collect();
+                let value = match values.as_slice() {
+                    [value] => value.clone(),
+                    values => format!("@({})", values.join(", ")),
+                };
+                fields.push((ps_str(name), value));
+            }
+            if has_part_headers(form) {
+                unsupported.push(PART_HEADERS);
+            }
+            params.push(("Form".into(), ps_table(&fields, 1)));
+        }
+    }
+
+    match plan.auth {
+        Auth::None => {}
+        Auth::Basic { username, password } => {
+            lines.push(format!(
+                "$credential = [pscredential]::new({}, {})",
+                ps_str(username),
+                ps_secure(password)
+            ));
+            params.push(("Authentication".into(), ps_str("Basic")));
+            params.push(("Credential".into(), "$credential".into()));
+            if !plan.is_https() {
+                params.push(("AllowUnencryptedAuthentication".into(), "$true".into()));
+            }
+        }
+        Auth::Digest { .. } => unsupported.push(DIGEST),
+        Auth::Aws { .. } => unsupported.push(AWS),
+    }
+
+    match plan.tls.client {
+        Some(ClientCert::Pem) => {
+            lines.push(format!(
+                "$certificate = [System.Security.Cryptography.X509Certificates.X509Certificate2]::CreateFromPemFile({}, {})",
+                ps_str(CLIENT_CERT_FILE),
+                ps_str(CLIENT_KEY_FILE)
+            ));
+            params.push(("Certificate".into(), "$certificate".into()));
+        }
+        Some(ClientCert::Pkcs12 { password }) => {
+            lines.push(format!(
+                "$certificate = [System.Security.Cryptography.X509Certificates.X509Certificate2]::new({}, {})",
+                ps_str(CLIENT_P12_FILE),
+                ps_str(password)
+            ));
+            params.push(("Certificate".into(), "$certificate".into()));
+        }
+        None => {}
+    }
+    if plan.tls.ca {
+        unsupported.push(CUSTOM_CA);
+    }
+    if plan.tls.insecure {
+        params.push(("SkipCertificateCheck".into(), "$true".into()));
+    }
+
+    if let Some(ref proxy) = plan.proxy {
+        params.push(("Proxy".into(), ps_str(&proxy.url)));
+        if let Some((username, password)) = proxy.credentials {
+            lines.push(format!(
+                "$proxyCredential = [pscredential]::new({}, {})",
+                ps_str(username),
+                ps_secure(password)
+            ));
+            params.push(("ProxyCredential".into(), "$proxyCredential".into()));
+        }
+        if !proxy.no_proxy.is_empty() {
+            unsupported.push(NO_PROXY);
+        }
+    }
+
+    match plan.version {
+        Some(HTTP_1_0) => params.push(("HttpVersion".into(), ps_str("1.0"))),
+        Some(HTTP_2) => params.push(("HttpVersion".into(), ps_str("2.0"))),
+        Some(version) => unsupported.push(version),
+        None => {}
+    }
+    if let Some(timeout) = plan.timeout() {
+        let seconds = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        params.push(("TimeoutSec".into(), seconds.to_string()));
+    }
+    match (plan.follow_redirects(), plan.max_redirects()) {
+        (Some(false), _) => params.push(("MaximumRedirection".into(), "0".into())),
+        (_, Some(max)) => params.push(("MaximumRedirection".into(), max.to_string())),
+        _ => {}
+    }
+    if plan.keep_alive_off() {
+        params.push(("DisableKeepAlive".into(), "$true".into()));
+    }
+    if let Some(path) = plan.unix_socket() {
+        let path = match path.strip_prefix('\0') {
+            Some(name) => format!("(\"`0\" + {})", ps_str(name)),
+            None => ps_str(&path),
+        };
+        params.push((
+            "UnixSocket".into(),
+            format!(
+                "[System.Net.Sockets.UnixDomainSocketEndPoint]::new({})",
+                path
+            ),
+        ));
+    }
+    if plan.resolves() {
+        unsupported.push(RESOLVE);
+    }
+
+    let mut code = String::new();
+    if !lines.is_empty() {
+        code.push_str(&format!("{}\n", lines.join("\n")));
+    }
+    code.push_str(&format!(
+        "$params = {}\n\n$response = Invoke-RestMethod @params\n$response\n",
+        ps_table(&params, 0)
+    ));
+    (code, unsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bytes::Bytes;
+    use http::Method;
+
+    use super::*;
+    use crate::interop::{
+        CertificateConfig, ProxyAuth, ProxyConfig, RequestMeta, ResolveEntry, SecurityConfig,
+    };
+
+    const TARGETS: [(SnippetTarget, &str); 8] = [
+        (SnippetTarget::PythonRequests, "python_requests"),
+        (SnippetTarget::PythonHttpx, "python_httpx"),
+        (SnippetTarget::JavascriptFetch, "javascript_fetch"),
+        (SnippetTarget::JavascriptAxios, "javascript_axios"),
+        (SnippetTarget::GoNetHttp, "go_net_http"),
+        (SnippetTarget::RustReqwest, "rust_reqwest"),
+        (SnippetTarget::JavaHttpClient, "java_http_client"),
+        (
+            SnippetTarget::PowershellRestMethod,
+            "powershell_rest_method",
+        ),
+    ];
+
+    fn assert_snapshots(name: &str, request: &Request) {
+        for (target, target_name) in TARGETS {
+            insta::assert_snapshot!(
+                format!("{}_{}", name, target_name),
+                generate(request, target)
+            );
+        }
+    }
+
+    #[test]
+    fn json_with_basic_auth() {
+        let mut request = Request::test(Method::POST, "https://api.example.com/v1/items");
+        request.params = Some(HashMap::from([("draft".into(), "true".into())]));
+        request.headers = Some(HashMap::from([
+            ("Accept".into(), "application/json".into()),
+            ("X-Note".into(), "it's \"quoted\"".into()),
+        ]));
+        request.content = Some(ContentType::Json {
+            content: serde_json::json!({ "name": "widget", "tags": ["a", "b"], "stock": null, "active": true }),
+            media_type: MediaType::Json,
+        });
+        request.auth = Some(AuthType::Basic {
+            username: "alice".into(),
+            password: "s3cret".into(),
+        });
+        request.meta = Some(RequestMeta {
+            options: Some(RequestOptions {
+                timeout: Some(2500),
+                follow_redirects: Some(false),
+                ..Default::default()
+            }),
+        });
+
+        assert_snapshots("json_with_basic_auth", &request);
+    }
+
+    #[test]
+    fn multipart_with_certificates_and_proxy() {
+        let mut request = Request::test(Method::PUT, "https://uploads.example.com/files");
+        request.version = Version::HTTP_2;
+        request.content = Some(ContentType::Multipart {
+            content: vec![
+                (
+                    "note".into(),
+                    vec![FormValue::Text {
+                        value: "hello".into(),
+                        content_type: None,
+                        headers: None,
+                    }],
+                ),
+                (
+                    "meta".into(),
+                    vec![FormValue::Text {
+                        value: "{\"a\":1}".into(),
+                        content_type: Some("application/json".into()),
+                        headers: None,
+                    }],
+                ),
+                (
+                    "file".into(),
+                    vec![FormValue::File {
+                        filename: "photo.png".into(),
+                        content_type: MediaType::ImagePng,
+                        data: Bytes::new(),
+                        headers: None,
+                    }],
+                ),
+            ],
+            media_type: MediaType::MultipartFormData,
+            boundary: None,
+        });
+        request.auth = Some(AuthType::Bearer {
+            token: "token-123".into(),
+        });
+        request.security = Some(SecurityConfig {
+            certificates: Some(CertificateConfig {
+                client: Some(CertificateType::Pem {
+                    cert: Bytes::new(),
+                    key: Bytes::new(),
+                }),
+                ca: Some(vec![Bytes::new()]),
+            }),
+            verify_host: None,
+            verify_peer: None,
+        });
+        request.proxy = Some(ProxyConfig {
+            url: Some("http://proxy.internal:3128".into()),
+            auth: Some(ProxyAuth {
+                username: "proxy-user".into(),
+                password: "p@ss".into(),
+                scheme: None,
+            }),
+            no_proxy: None,
+            from_env: None,
+            pac: None,
+            remote_dns: None,
+            security: None,
+        });
+        request.meta = Some(RequestMeta {
+            options: Some(RequestOptions {
+                follow_redirects: Some(true),
+                max_redirects: Some(5),
+                ..Default::default()
+            }),
+        });
+
+        assert_snapshots("multipart_with_certificates_and_proxy", &request);
+    }
+
+    #[test]
+    fn binary_with_digest_and_unix_socket() {
+        let mut request = Request::test(Method::PATCH, "https://localhost/v1/blobs");
+        request.content = Some(ContentType::Binary {
+            content: Bytes::from_static(b"\x00\x01\xff\"\\"),
+            media_type: MediaType::OctetStream,
+            filename: None,
+        });
+        request.auth = Some(AuthType::Digest {
+            username: "bob".into(),
+            password: "pw".into(),
+            realm: None,
+            nonce: None,
+            opaque: None,
+            algorithm: None,
+            qop: None,
+            nc: None,
+            cnonce: None,
+        });
+        request.security = Some(SecurityConfig {
+            certificates: Some(CertificateConfig {
+                client: Some(CertificateType::Pfx {
+                    data: Bytes::new(),
+                    password: "pfx-pass".into(),
+                }),
+                ca: None,
+            }),
+            verify_host: Some(false),
+            verify_peer: Some(false),
+        });
+        request.meta = Some(RequestMeta {
+            options: Some(RequestOptions {
+                keep_alive: Some(false),
+                unix_socket: Some("/var/run/app.sock".into()),
+                resolve: Some(vec![ResolveEntry {
+                    host: "localhost".into(),
+                    port: 80,
+                    addresses: vec!["::1".into()],
+                }]),
+                ..Default::default()
+            }),
+        });
+
+        assert_snapshots("binary_with_digest_and_unix_socket", &request);
+    }
+
+    #[test]
+    fn curl_target_matches_export() {
+        let request = Request::test(Method::GET, "https://example.com");
+        assert_eq!(
+            generate(&request, SnippetTarget::Curl),
+            curl_command::export(&request)
+        );
+    }
+
+    #[test]
+    fn escapes_literals() {
+        assert_eq!(py_str("a\"b\\c\n\u{1}"), r#""a\"b\\c\n\x01""#);
+        assert_eq!(js_str("\u{1}é"), r#""\u0001é""#);
+        assert_eq!(go_text("{\"a\":1}"), "`{\"a\":1}`");
+        assert_eq!(ps_str("it's ‘x’"), "'it''s ‘‘x’’'");
+        assert_eq!(byte_string(b"\x00a\"\xff"), r#""\x00a\"\xff""#);
+        assert_eq!(java_bytes(&[1, 0xff]), "1, (byte) 0xff");
+    }
+}
@@ -0,0 +1,597 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use curl::easy::Easy;
+use mime::Mime;
+
+use crate::{
+    error::{RelayError, Result},
+    interop::{Request, SseEvent, SseMessage},
+    proxy,
+    relay::ACTIVE_REQUESTS,
+    request::CurlRequest,
+    util::wait,
+};
+
+/// Reconnection delay until the server sends `retry:`, the value browsers
+/// use.
+const DEFAULT_RETRY: Duration = Duration::from_millis(3000);
+/// `CURLOPTTYPE_LONG + 265`, not exported by `curl-sys`. Keeps a proxy's
+/// CONNECT replies out of the header callback.
+const CURLOPT_SUPPRESS_CONNECT_HEADERS: curl_sys::CURLoption = curl_sys::CURLOPTTYPE_LONG + 265;
+
+/// Incremental `text/event-stream` parser, following the HTML "event stream
+/// interpretation" algorithm. Chunks can split lines, and `\r\n` pairs,
+/// anywhere.
+#[derive(Debug, Default)]
+pub(crate) struct SseParser {
+    line: Vec<u8>,
+    pending_cr: bool,
+    started: bool,
+    event_type: String,
+    data: String,
+    last_event_id: String,
+    retry: Option<Duration>,
+}
+
+impl SseParser {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts over for a new connection, keeping the last event ID and
+    /// reconnection time.
+    pub(crate) fn reset(&mut self) {
+        *self = Self {
+            last_event_id: std::mem::take(&mut self.last_event_id),
+            retry: self.retry,
+            ..Self::default()
+        };
+    }
+
+    pub(crate) fn last_event_id(&self) -> Option<&str> {
+        Some(self.last_event_id.as_str()).filter(|id| !id.is_empty())
+    }
+
+    pub(crate) fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    pub(crate) fn feed(&mut self, mut chunk: &[u8]) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+
+        if self.pending_cr {
+            self.pending_cr = false;
+            chunk = chunk.strip_prefix(b"\n").unwrap_or(chunk);
+        }
+
+        while let Some(end) = chunk.iter().position(|b| *b == b'\r' || *b == b'\n') {
+            self.line.extend_from_slice(&chunk[..end]);
+            let line = std::mem::take(&mut self.line);
+            events.extend(self.process_line(&line));
+
+            let crlf = chunk[end] == b'\r';
+            chunk = &chunk[end + 1..];
+
+            if crlf {
+                match chunk.first() {
+                    Some(b'\n') => chunk = &chunk[1..],
+                    Some(_) => {}
+                    None => self.pending_cr = true,
+                }
+            }
+        }
+
+        self.line.extend_from_slice(chunk);
+        events
+    }
+
+    fn process_line(&mut self, mut line: &[u8]) -> Option<SseEvent> {
+        if !self.started {
+            self.started = true;
+            line = line.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(line);
+        }
+
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        if line.starts_with(b":") {
+            return None;
+        }
+
+        let line = String::from_utf8_lossy(line);
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line.as_ref(), ""),
+        };
+
+        match field {
+            "event" => self.event_type = value.to_string(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_event_id = value.to_string(),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                self.retry = value.parse().ok().map(Duration::from_millis);
+            }
+            _ => tracing::trace!(field = %field, "Ignoring event stream field"),
+        }
+
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event_type = std::mem::take(&mut self.event_type);
+        let mut data = std::mem::take(&mut self.data);
+
+        if data.is_empty() {
+            return None;
+        }
+        data.pop();
+
+        Some(SseEvent {
+            event: if event_type.is_empty() {
+                "message".to_string()
+            } else {
+                event_type
+            },
+            data,
+            id: self.last_event_id().map(str::to_string),
+        })
+    }
+}
+
+/// How a single connection ended.
+enum ConnectionEnd {
+    /// The server closed the stream or the connection dropped, reconnect.
+    Dropped(Option<RelayError>),
+    /// Cancelled, the subscriber stopped listening, or the server answered
+    /// `204 No Content`.
+    Closed,
+}
+
+/// Opens an event stream and passes each event to `on_message` until the
+/// subscription is cancelled with `relay::cancel(request.id)`, `on_message`
+/// returns `false`, or the server answers `204 No Content`. Dropped
+/// connections are reopened with `Last-Event-ID`.
+///
+/// Blocks for the lifetime of the subscription, so async callers should run
+/// it on a blocking thread.
+#[tracing::instrument(skip(request, on_message), fields(request_id = request.id), level = "debug")]
+pub fn subscribe<F>(request: Request, mut on_message: F) -> Result<()>
+where
+    F: FnMut(SseMessage) -> bool,
+{
+    tracing::info!(url = %request.url, "Opening event stream");
+
+    let request_id = request.id;
+    let cancelled = Arc::new(AtomicBool::new(false));
+    ACTIVE_REQUESTS.insert(request_id, Arc::clone(&cancelled));
+
+    let mut parser = SseParser::new();
+
+    let result = loop {
+        match connect(&request, &mut parser, &cancelled, &mut on_message) {
+            Ok(ConnectionEnd::Closed) => break Ok(()),
+            Err(e) => break Err(e),
+            Ok(ConnectionEnd::Dropped(error)) => {
+                let delay = parser.retry().unwrap_or(DEFAULT_RETRY);
+                tracing::info!(delay_ms = delay.as_millis() as u64, error = ?error, "Event stream dropped, reconnecting");
+
+                let reconnecting = SseMessage::Reconnecting {
+                    delay_ms: delay.as_millis() as u64,
+                    last_event_id: parser.last_event_id().map(str::to_string),
+                    cause: error.map(|e| e.to_string()),
+                };
+                if !on_message(reconnecting) || wait(delay, &cancelled) {
+                    break Ok(());
+                }
+                parser.reset();
+            }
+        }
+    };
+
+    ACTIVE_REQUESTS.remove(&request_id);
+    tracing::info!("Event stream closed");
+    result
+}
+
+fn connect<F>(
+    request: &Request,
+    parser: &mut SseParser,
+    cancelled: &AtomicBool,
+    on_message: &mut F,
+) -> Result<ConnectionEnd>
+where
+    F: FnMut(SseMessage) -> bool,
+{
+    let mut request = request.clone();
+    let headers = request.headers.get_or_insert_with(HashMap::new);
+    headers.retain(|name, _| !name.eq_ignore_ascii_case("last-event-id"));
+    if !headers
+        .keys()
+        .any(|name| name.eq_ignore_ascii_case("accept"))
+    {
+        headers.insert("Accept".to_string(), "text/event-stream".to_string());
+    }
+    headers.insert("Cache-Control".to_string(), "no-cache".to_string());
+    if let Some(id) = parser.last_event_id() {
+        headers.insert("Last-Event-ID".to_string(), id.to_string());
+    }
+
+    let mut handle = Easy::new();
+    let mut curl_request = CurlRequest::new(&mut handle, &request);
+    curl_request.prepare()?;
+    let proxied = curl_request.proxy().is_some();
+
+    handle.progress(true).map_err(|e| {
+        tracing::error!(error = %e, "Failed to enable progress callback");
+        RelayError::Network {
+            message: "Failed to enable progress callback".into(),
+            cause: Some(e.to_string()),
+        }
+    })?;
+
+    let code = unsafe {
+        curl_sys::curl_easy_setopt(
+            handle.raw(),
+            CURLOPT_SUPPRESS_CONNECT_HEADERS,
+            1 as std::os::raw::c_long,
+        )
+    };
+    if code != curl_sys::CURLE_OK {
+        let e = curl::Error::new(code);
+        tracing::error!(error = %e, "Failed to suppress CONNECT headers");
+        return Err(RelayError::Network {
+            message: "Failed to suppress CONNECT headers".into(),
+            cause: Some(e.to_string()),
+        });
+    }
+
+    let on_message = RefCell::new(on_message);
+    let opened = Cell::new(false);
+    let stopped = Cell::new(false);
+    let mut status = 0;
+    let mut response_headers = HashMap::new();
+    let mut failure = None;
+    let mut no_content = false;
+
+    let result = {
+        let mut transfer = handle.transfer();
+
+        transfer
+            .header_function(|line| {
+                let line = String::from_utf8_lossy(line);
+                let line = line.trim_end();
+
+                if line.starts_with("HTTP/") {
+                    status = line
+                        .split_whitespace()
+                        .nth(1)
+                        .and_then(|code| code.parse().ok())
+                        .unwrap_or_default();
+                    response_headers.clear();
+                } else if let Some((name, value)) = line.split_once(':') {
+                    response_headers.insert(name.trim().to_string(), value.trim().to_string());
+                } else if line.is_empty()
+                    && !(100..200).contains(&status)
+                    && !(300..400).contains(&status)
+                    && !matches!(status, 401 | 407)
+                {
+                    // NOTE: End of the final response's headers, interim,
+                    // followed redirect and auth challenge responses have
+                    // their own blocks.
+                    if status == 204 {
+                        no_content = true;
+                        return false;
+                    }
+
+                    let event_stream = response_headers
+                        .iter()
+                        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+                        .and_then(|(_, value)| value.parse::<Mime>().ok())
+                        .is_some_and(|mime| mime.essence_str() == mime::TEXT_EVENT_STREAM.as_ref());
+
+                    if status != 200 || !event_stream {
+                        tracing::error!(status, headers = ?response_headers, "Response is not an event stream");
+                        failure = Some(status);
+                        return false;
+                    }
+
+                    tracing::debug!("Event stream opened");
+                    opened.set(true);
+                    if !(on_message.borrow_mut())(SseMessage::Open {
+                        status,
+                        headers: response_headers.clone(),
+                    }) {
+                        stopped.set(true);
+                        return false;
+                    }
+                }
+                true
+            })
+            .map_err(|e| {
+                tracing::error!(error = %e, "Failed to set header callback");
+                RelayError::Network {
+                    message: "Failed to set header callback".into(),
+                    cause: Some(e.to_string()),
+                }
+            })?;
+
+        transfer
+            .write_function(|data| {
+                // NOTE: Bodies of the auth challenges curl answers.
+                if !opened.get() {
+                    return Ok(data.len());
+                }
+                for event in parser.feed(data) {
+                    tracing::trace!(event = %event.event, id = ?event.id, "Received event");
+                    if !(on_message.borrow_mut())(SseMessage::Event(event)) {
+                        stopped.set(true);
+                        return Ok(0);
+                    }
+                }
+                Ok(data.len())
+            })
+            .map_err(|e| {
+                tracing::error!(error = %e, "Failed to set write callback");
+                RelayError::Network {
+                    message: "Failed to set write callback".into(),
+                    cause: Some(e.to_string()),
+                }
+            })?;
+
+        transfer
+            .progress_function(|_, _, _, _| !cancelled.load(Ordering::SeqCst))
+            .map_err(|e| {
+                tracing::error!(error = %e, "Failed to set progress callback");
+                RelayError::Network {
+                    message: "Failed to set progress callback".into(),
+                    cause: Some(e.to_string()),
+                }
+            })?;
+
+        transfer.perform()
+    };
+
+    if stopped.get() || no_content || cancelled.load(Ordering::SeqCst) {
+        return Ok(ConnectionEnd::Closed);
+    }
+
+    // NOTE: A challenge curl couldn't answer is the final response.
+    if !opened.get() && matches!(status, 401 | 407) {
+        failure = Some(status);
+    }
+
+    if let Some(status) = failure {
+        return Err(RelayError::Network {
+            message: format!(
+                "Server responded with status {} instead of an event stream",
+                status
+            ),
+            cause: None,
+        });
+    }
+
+    Ok(ConnectionEnd::Dropped(result.err().map(|e| {
+        proxied
+            .then(|| proxy::classify_error(&mut handle, &e))
+            .flatten()
+            .unwrap_or_else(|| RelayError::Network {
+                message: "Event stream connection failed".into(),
+                cause: Some(e.to_string()),
+            })
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_all(parser: &mut SseParser, chunks: &[&[u8]]) -> Vec<SseEvent> {
+        chunks.iter().flat_map(|chunk| parser.feed(chunk)).collect()
+    }
+
+    #[test]
+    fn test_parse_split_chunks() {
+        let mut parser = SseParser::new();
+        let events = feed_all(
+            &mut parser,
+            &[
+                b"\xEF\xBB",
+                b"\xBF: comment\r",
+                b"\nevent: update\rda",
+                b"ta: one\r\ndata:two\n",
+                b"id: 7\n\n",
+                b"data\n\ndata: ignored without a blank line",
+            ],
+        );
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "update");
+        assert_eq!(events[0].data, "one\ntwo");
+        assert_eq!(events[0].id.as_deref(), Some("7"));
+        assert_eq!(events[1].event, "message");
+        assert_eq!(events[1].data, "");
+        assert_eq!(events[1].id.as_deref(), Some("7"));
+    }
+
+    #[test]
+    fn test_parse_id_and_retry() {
+        let mut parser = SseParser::new();
+        let events = parser.feed(b"retry: 1500\nretry: soon\nid: 3\n\nid\ndata: x\n\n");
+
+        assert_eq!(parser.retry(), Some(Duration::from_millis(1500)));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id, None);
+
+        parser.feed(b"id: 4\n\ndata: partial");
+        parser.reset();
+        assert_eq!(parser.last_event_id(), Some("4"));
+        assert_eq!(parser.retry(), Some(Duration::from_millis(1500)));
+        assert!(parser.feed(b"\n\n").is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_subscribe_reconnects_with_last_event_id() {
+        use std::{
+            io::{BufRead, BufReader, Write},
+            os::unix::net::UnixListener,
+        };
+
+        let path = std::env::temp_dir().join(format!("relay-test-sse-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = std::thread::spawn(move || {
+            let mut last_event_ids = Vec::new();
+
+            for body in ["retry: 10\nid: 1\ndata: a\n\n", "event: done\ndata: b\n\n"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    if let Some(id) = line.to_lowercase().strip_prefix("last-event-id: ") {
+                        last_event_ids.push(id.trim().to_string());
+                    }
+                    line.clear();
+                }
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n{}",
+                    body
+                )
+                .unwrap();
+            }
+
+            last_event_ids
+        });
+
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "id": 42,
+            "url": "http://localhost/events",
+            "method": "GET",
+            "version": "HTTP/1.1",
+            "meta": { "options": { "unixSocket": path.to_str().unwrap() } }
+        }))
+        .unwrap();
+
+        let mut messages = Vec::new();
+        subscribe(request, |message| {
+            let done = matches!(&message, SseMessage::Event(event) if event.event == "done");
+            messages.push(message);
+            !done
+        })
+        .unwrap();
+
+        let last_event_ids = server.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(last_event_ids, ["1"]);
+        assert!(matches!(&messages[0], SseMessage::Open { status: 200, .. }));
+        assert!(matches!(&messages[1], SseMessage::Event(event) if event.data == "a"));
+        assert!(matches!(
+            &messages[2],
+            SseMessage::Reconnecting { delay_ms: 10, last_event_id: Some(id), .. } if id == "1"
+        ));
+        assert!(matches!(&messages[3], SseMessage::Open { .. }));
+        assert!(
+            matches!(&messages[4], SseMessage::Event(event) if event.data == "b" && event.id.as_deref() == Some("1"))
+        );
+        assert!(!ACTIVE_REQUESTS.contains_key(&42));
+    }
+
+    #[test]
+    fn test_subscribe_through_a_tunnel_with_an_auth_challenge() {
+        use std::{
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+        };
+
+        use openssl::ssl::{SslAcceptor, SslMethod};
+
+        fn read_head(reader: &mut impl BufRead) -> Vec<String> {
+            let mut lines = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim_end().is_empty() {
+                    return lines;
+                }
+                lines.push(line.trim_end().to_string());
+            }
+        }
+
+        let (cert, key) = crate::security::tests::certificate("server");
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        let acceptor = acceptor.build();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            // The proxy stand-in opens the tunnel and is the server too.
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let connect = read_head(&mut reader).remove(0);
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .unwrap();
+
+            let mut reader = BufReader::new(acceptor.accept(reader.into_inner()).unwrap());
+            read_head(&mut reader);
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Digest realm=\"r\", nonce=\"n\"\r\nContent-Length: 16\r\n\r\ndata: denied\n\n\n\n")
+                .unwrap();
+
+            let authorization = read_head(&mut reader)
+                .into_iter()
+                .find(|line| line.to_lowercase().starts_with("authorization: digest"));
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\ndata: a\n\n")
+                .unwrap();
+
+            (connect, authorization)
+        });
+
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "id": 43,
+            "url": "https://127.0.0.1/events",
+            "method": "GET",
+            "version": "HTTP/1.1",
+            "auth": { "kind": "digest", "username": "user", "password": "secret" },
+            "security": { "certificates": { "ca": [cert.to_pem().unwrap()] } },
+            "proxy": { "url": proxy }
+        }))
+        .unwrap();
+
+        let mut messages = Vec::new();
+        subscribe(request, |message| {
+            let event = matches!(&message, SseMessage::Event(_));
+            messages.push(message);
+            !event
+        })
+        .unwrap();
+
+        let (connect, authorization) = server.join().unwrap();
+        assert!(connect.starts_with("CONNECT 127.0.0.1:443 "), "{}", connect);
+        assert!(authorization.is_some());
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(&messages[0], SseMessage::Open { status: 200, .. }));
+        assert!(matches!(&messages[1], SseMessage::Event(event) if event.data == "a"));
+    }
+}
@@ -1,14 +1,45 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+};
 
 use bytes::{Bytes, BytesMut};
 use curl::easy::Easy;
 use tokio_util::sync::CancellationToken;
 
-use crate::error::{RelayError, Result};
+use crate::{
+    error::{RelayError, Result},
+    interop::{BodyLimitMode, SavedBody},
+    output::{self, PendingFile},
+    proxy,
+};
+
+/// How much of a body saved to disk is kept in memory as a preview.
+const PREVIEW_SIZE: usize = 4096;
+
+/// Where received body bytes go. Bodies written to disk keep only the first
+/// `PREVIEW_SIZE` bytes in memory.
+enum BodySink {
+    Memory,
+    File {
+        writer: BufWriter<File>,
+        // NOTE: Declared after `writer` so the file is closed before an
+        // unpersisted one is removed.
+        pending: PendingFile,
+    },
+}
 
 pub(crate) struct TransferHandler {
     body: BytesMut,
     headers: HashMap<String, String>,
+    sink: BodySink,
+    max_body_size: Option<u64>,
+    limit_mode: BodyLimitMode,
+    received: u64,
+    truncated: bool,
+    exceeded: bool,
+    write_error: Option<std::io::Error>,
 }
 
 impl TransferHandler {
@@ -16,26 +47,116 @@ impl TransferHandler {
         Self {
             body: BytesMut::new(),
             headers: HashMap::new(),
+            sink: BodySink::Memory,
+            max_body_size: None,
+            limit_mode: BodyLimitMode::default(),
+            received: 0,
+            truncated: false,
+            exceeded: false,
+            write_error: None,
         }
     }
 
+    /// Caps the decoded body, which also bounds what a compressed response
+    /// can expand to.
+    pub(crate) fn with_max_body_size(mut self, limit: Option<u64>, mode: BodyLimitMode) -> Self {
+        self.max_body_size = limit;
+        self.limit_mode = mode;
+        self
+    }
+
+    /// Writes the body to `path` inside the save directory instead of
+    /// keeping it in memory. The file only replaces an existing one once
+    /// `take_output` persists it.
+    pub(crate) fn with_save_to(mut self, path: Option<&str>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(self);
+        };
+
+        let Some(dir) = output::save_dir() else {
+            tracing::error!(path = %path, "Saving bodies is disabled, no save directory is set");
+            return Err(RelayError::UnsupportedFeature {
+                feature: "save_to".into(),
+                message: "No directory to save response bodies to is configured".into(),
+                relay: "curl".into(),
+            });
+        };
+
+        let target = output::resolve_within(&dir, path)?;
+        let (pending, file) = PendingFile::create(target)?;
+
+        self.sink = BodySink::File {
+            writer: BufWriter::new(file),
+            pending,
+        };
+        Ok(self)
+    }
+
     #[tracing::instrument(skip(self, handle), level = "debug")]
     pub(crate) fn handle_transfer(
         &mut self,
         handle: &mut Easy,
         cancel_token: &CancellationToken,
+        proxied: bool,
     ) -> Result<()> {
         tracing::debug!("Setting up transfer handlers");
+        // NOTE: curl only calls the progress callback, which is where
+        // cancellation is checked, once progress meters are switched on.
+        handle.progress(true).map_err(|e| {
+            tracing::error!(error = %e, "Failed to enable progress callback");
+            RelayError::Network {
+                message: "Failed to enable progress callback".into(),
+                cause: Some(e.to_string()),
+            }
+        })?;
         let mut transfer = handle.transfer();
 
         let body = &mut self.body;
         let headers = &mut self.headers;
+        let sink = &mut self.sink;
+        let received = &mut self.received;
+        let truncated = &mut self.truncated;
+        let exceeded = &mut self.exceeded;
+        let write_error = &mut self.write_error;
+        let max_body_size = self.max_body_size;
+        let limit_mode = self.limit_mode;
 
         transfer
             .write_function(move |data| {
-                body.extend_from_slice(data);
                 tracing::trace!(bytes = data.len(), "Received response data chunk");
-                Ok(data.len())
+
+                let remaining = max_body_size.map_or(u64::MAX, |limit| limit - *received);
+                let accepted = &data[..data.len().min(remaining as usize)];
+
+                if accepted.len() < data.len() && limit_mode == BodyLimitMode::Error {
+                    tracing::warn!(limit = ?max_body_size, "Response body exceeded the limit");
+                    *exceeded = true;
+                    return Ok(0);
+                }
+
+                match sink {
+                    BodySink::Memory => body.extend_from_slice(accepted),
+                    BodySink::File { writer, .. } => {
+                        let preview = accepted.len().min(PREVIEW_SIZE.saturating_sub(body.len()));
+                        body.extend_from_slice(&accepted[..preview]);
+
+                        if let Err(e) = writer.write_all(accepted) {
+                            tracing::error!(error = %e, "Failed to write response body to file");
+                            *write_error = Some(e);
+                            return Ok(0);
+                        }
+                    }
+                }
+                *received += accepted.len() as u64;
+
+                // NOTE: Returning less than was passed in makes curl stop the
+                // transfer with `CURLE_WRITE_ERROR`, which is how the rest of
+                // the body is never downloaded.
+                if accepted.len() < data.len() {
+                    tracing::warn!(limit = ?max_body_size, "Response body truncated");
+                    *truncated = true;
+                }
+                Ok(accepted.len())
             })
             .map_err(|e| {
                 tracing::error!(error = %e, "Failed to set write callback");
@@ -98,18 +219,79 @@ impl TransferHandler {
             })?;
 
         tracing::debug!("Starting transfer");
-        transfer.perform().map_err(|e| {
-            tracing::error!(error = %e, "Failed to perform request");
-            RelayError::Network {
-                message: "Failed to perform request".into(),
+        let result = transfer.perform();
+        drop(transfer);
+
+        if let Some(e) = self.write_error.take() {
+            return Err(RelayError::Network {
+                message: "Failed to write response body to file".into(),
                 cause: Some(e.to_string()),
-            }
+            });
+        }
+
+        if self.exceeded {
+            return Err(RelayError::BodyTooLarge {
+                message: "Response body exceeded the maximum size".into(),
+                limit: self.max_body_size.unwrap_or_default(),
+            });
+        }
+
+        let result = match result {
+            Err(e) if self.truncated && e.is_write_error() => Ok(()),
+            result => result,
+        };
+
+        result.map_err(|e| {
+            tracing::error!(error = %e, "Failed to perform request");
+            proxied
+                .then(|| proxy::classify_error(handle, &e))
+                .flatten()
+                .unwrap_or_else(|| RelayError::Network {
+                    message: "Failed to perform request".into(),
+                    cause: Some(e.to_string()),
+                })
         })?;
 
+        if let BodySink::File { writer, .. } = &mut self.sink {
+            writer.flush().map_err(|e| {
+                tracing::error!(error = %e, "Failed to flush output file");
+                RelayError::Network {
+                    message: "Failed to write response body to file".into(),
+                    cause: Some(e.to_string()),
+                }
+            })?;
+        }
+
         tracing::debug!("Transfer completed successfully");
         Ok(())
     }
 
+    pub(crate) fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub(crate) fn saved_body(&self) -> Option<SavedBody> {
+        match &self.sink {
+            BodySink::Memory => None,
+            BodySink::File { pending, .. } => Some(SavedBody {
+                path: pending.target().to_string_lossy().into_owned(),
+                size: self.received,
+            }),
+        }
+    }
+
+    /// The body's file, to `persist` once the whole response checks out.
+    /// Files of failed transfers are removed with the handler.
+    pub(crate) fn take_output(&mut self) -> Option<PendingFile> {
+        match std::mem::replace(&mut self.sink, BodySink::Memory) {
+            BodySink::Memory => None,
+            BodySink::File { writer, pending } => {
+                drop(writer);
+                Some(pending)
+            }
+        }
+    }
+
     pub(crate) fn into_parts(self) -> (Bytes, HashMap<String, String>) {
         (self.body.into(), self.headers)
     }
@@ -1,15 +1,147 @@
+use std::{
+    os::raw::c_long,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use curl::easy::Easy;
+use http::Version;
+
+use crate::{
+    error::{RelayError, Result},
+    interop::{PhaseTimings, VersionMode},
+};
+
+// NOTE: Neither of these are exposed by `curl` or `curl-sys` yet,
+// see: https://curl.se/libcurl/c/CURLOPT_HTTP_VERSION.html
+// and: https://curl.se/libcurl/c/CURLINFO_HTTP_VERSION.html
+const CURL_HTTP_VERSION_3ONLY: c_long = 31;
+const CURLINFO_HTTP_VERSION: curl_sys::CURLINFO = curl_sys::CURLINFO_LONG + 46;
+
+/// How often `wait` and other loops blocked on work check for cancellation.
+pub(crate) const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 pub trait ToCurlVersion {
-    fn to_curl_version(self) -> curl::easy::HttpVersion;
+    fn to_curl_version(self, mode: VersionMode) -> Result<c_long>;
 }
 
-impl ToCurlVersion for http::Version {
-    fn to_curl_version(self) -> curl::easy::HttpVersion {
-        match self {
-            http::Version::HTTP_10 => curl::easy::HttpVersion::V10,
-            http::Version::HTTP_11 => curl::easy::HttpVersion::V11,
-            http::Version::HTTP_2 => curl::easy::HttpVersion::V2,
-            http::Version::HTTP_3 => curl::easy::HttpVersion::V3,
-            _ => panic!("Unsupported"),
+impl ToCurlVersion for Version {
+    fn to_curl_version(self, mode: VersionMode) -> Result<c_long> {
+        let version = match (self, mode) {
+            (Version::HTTP_10, _) => curl_sys::CURL_HTTP_VERSION_1_0 as c_long,
+            (Version::HTTP_11, _) => curl_sys::CURL_HTTP_VERSION_1_1 as c_long,
+            (Version::HTTP_2, VersionMode::Fallback) => curl_sys::CURL_HTTP_VERSION_2_0 as c_long,
+            (Version::HTTP_2, VersionMode::Only) => {
+                curl_sys::CURL_HTTP_VERSION_2_PRIOR_KNOWLEDGE as c_long
+            }
+            (Version::HTTP_3, VersionMode::Fallback) => curl_sys::CURL_HTTP_VERSION_3 as c_long,
+            (Version::HTTP_3, VersionMode::Only) => CURL_HTTP_VERSION_3ONLY,
+            (version, _) => {
+                return Err(RelayError::UnsupportedFeature {
+                    feature: format!("{:?}", version),
+                    message: "Only HTTP/1.0, HTTP/1.1, HTTP/2 and HTTP/3 are supported".into(),
+                    relay: "curl".into(),
+                })
+            }
+        };
+
+        Ok(version)
+    }
+}
+
+/// Sets `CURLOPT_HTTP_VERSION` directly, since `curl::easy::HttpVersion`
+/// has no variant for HTTP/3 without fallback.
+pub(crate) fn set_http_version(handle: &mut Easy, version: c_long) -> Result<()> {
+    let rc = unsafe {
+        curl_sys::curl_easy_setopt(handle.raw(), curl_sys::CURLOPT_HTTP_VERSION, version)
+    };
+
+    if rc == curl_sys::CURLE_OK {
+        return Ok(());
+    }
+
+    let error = curl::Error::new(rc);
+    tracing::error!(error = %error, version, "Failed to set HTTP version");
+    Err(RelayError::Network {
+        message: "Failed to set HTTP version".into(),
+        cause: Some(error.to_string()),
+    })
+}
+
+/// The HTTP version used for the last transfer on `handle`, `None` when curl
+/// doesn't know, for instance if no connection was made.
+pub(crate) fn negotiated_version(handle: &Easy) -> Option<Version> {
+    let mut version: c_long = 0;
+    let rc =
+        unsafe { curl_sys::curl_easy_getinfo(handle.raw(), CURLINFO_HTTP_VERSION, &mut version) };
+
+    if rc != curl_sys::CURLE_OK {
+        tracing::warn!(code = rc, "Failed to get negotiated HTTP version");
+        return None;
+    }
+
+    match version as i32 {
+        curl_sys::CURL_HTTP_VERSION_1_0 => Some(Version::HTTP_10),
+        curl_sys::CURL_HTTP_VERSION_1_1 => Some(Version::HTTP_11),
+        curl_sys::CURL_HTTP_VERSION_2_0 => Some(Version::HTTP_2),
+        curl_sys::CURL_HTTP_VERSION_3 => Some(Version::HTTP_3),
+        _ => None,
+    }
+}
+
+/// Phase timings of the last transfer on `handle`, `None` when curl can't
+/// report them.
+pub(crate) fn phase_timings(handle: &mut Easy) -> Option<PhaseTimings> {
+    let millis = |info: std::result::Result<Duration, curl::Error>| {
+        info.inspect_err(|e| tracing::warn!(error = %e, "Failed to get phase timing"))
+            .ok()
+            .map(|duration| duration.as_secs_f64() * 1000.0)
+    };
+
+    Some(PhaseTimings {
+        namelookup: millis(handle.namelookup_time())?,
+        connect: millis(handle.connect_time())?,
+        appconnect: millis(handle.appconnect_time())?,
+        pretransfer: millis(handle.pretransfer_time())?,
+        starttransfer: millis(handle.starttransfer_time())?,
+        total: millis(handle.total_time())?,
+        redirect: millis(handle.redirect_time())?,
+    })
+}
+
+/// Sleeps for `delay`, returning early with `true` when cancelled.
+pub(crate) fn wait(delay: Duration, cancelled: &AtomicBool) -> bool {
+    let deadline = Instant::now() + delay;
+
+    while Instant::now() < deadline {
+        if cancelled.load(Ordering::SeqCst) {
+            return true;
         }
+        std::thread::sleep(
+            CANCEL_POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())),
+        );
+    }
+
+    cancelled.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_curl_version() {
+        assert_eq!(
+            Version::HTTP_2.to_curl_version(VersionMode::Only).unwrap(),
+            curl_sys::CURL_HTTP_VERSION_2_PRIOR_KNOWLEDGE as c_long
+        );
+        assert_eq!(
+            Version::HTTP_3.to_curl_version(VersionMode::Only).unwrap(),
+            CURL_HTTP_VERSION_3ONLY
+        );
+        assert!(matches!(
+            Version::HTTP_09.to_curl_version(VersionMode::Fallback),
+            Err(RelayError::UnsupportedFeature { .. })
+        ));
     }
 }
@@ -0,0 +1,835 @@
+use std::{
+    collections::HashMap,
+    os::raw::{c_int, c_uint, c_void},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use curl::{
+    easy::Easy,
+    multi::{Multi, WaitFd},
+};
+use dashmap::{mapref::entry::Entry, DashMap};
+
+use crate::{
+    error::{RelayError, Result},
+    interop::{Request, WsEvent, WsFrame},
+    proxy,
+    relay::ACTIVE_REQUESTS,
+    request::CurlRequest,
+};
+
+/// `CURLINFO_SOCKET + 44`, not exported by `curl-sys`.
+const CURLINFO_ACTIVESOCKET: curl_sys::CURLINFO = 0x500000 + 44;
+/// Makes `perform` stop after the upgrade handshake, leaving the connection
+/// to `curl_ws_send` and `curl_ws_recv`.
+const CONNECT_ONLY_WEBSOCKET: std::os::raw::c_long = 2;
+/// `CURLOPTTYPE_LONG + 320`, not exported by `curl-sys`.
+const CURLOPT_WS_OPTIONS: curl_sys::CURLoption = curl_sys::CURLOPTTYPE_LONG + 320;
+/// Makes libcurl leave pings to the session, which answers them itself.
+const CURLWS_NOAUTOPONG: std::os::raw::c_long = 1 << 1;
+/// 8.14.0, the first libcurl with `CURLWS_NOAUTOPONG`. Older ones answer
+/// pings on their own, so those may never show up as events.
+const NOAUTOPONG_VERSION: u32 = 0x08_0e_00;
+
+const CURLWS_TEXT: c_int = 1 << 0;
+const CURLWS_BINARY: c_int = 1 << 1;
+const CURLWS_CONT: c_int = 1 << 2;
+const CURLWS_CLOSE: c_int = 1 << 3;
+const CURLWS_PING: c_int = 1 << 4;
+const CURLWS_PONG: c_int = 1 << 6;
+
+const RECV_BUFFER_SIZE: usize = 64 * 1024;
+/// How long a read waits before the outgoing queue is checked again.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// How long to wait for the server's close frame after sending ours.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// "Abnormal Closure", reported when the connection ended without a close
+/// frame, see RFC 6455 section 7.4.1.
+const ABNORMAL_CLOSURE: u16 = 1006;
+
+#[repr(C)]
+struct CurlWsFrame {
+    age: c_int,
+    flags: c_int,
+    offset: curl_sys::curl_off_t,
+    bytesleft: curl_sys::curl_off_t,
+    len: usize,
+}
+
+extern "C" {
+    fn curl_ws_recv(
+        curl: *mut curl_sys::CURL,
+        buffer: *mut c_void,
+        buflen: usize,
+        recv: *mut usize,
+        meta: *mut *const CurlWsFrame,
+    ) -> curl_sys::CURLcode;
+
+    fn curl_ws_send(
+        curl: *mut curl_sys::CURL,
+        buffer: *const c_void,
+        buflen: usize,
+        sent: *mut usize,
+        fragsize: curl_sys::curl_off_t,
+        flags: c_uint,
+    ) -> curl_sys::CURLcode;
+}
+
+lazy_static::lazy_static! {
+    static ref SESSIONS: DashMap<i64, Sender<WsFrame>> = DashMap::new();
+}
+
+/// Performs the upgrade handshake for `request.url` (`ws://` or `wss://`)
+/// with the request's headers, auth, security and proxy settings, then
+/// hands the connection to a background thread that passes every received
+/// frame to `on_event`. The session is addressed by `request.id` in `send`,
+/// `close` and `relay::cancel`, and ends with a `WsEvent::Close`.
+///
+/// Blocks until the handshake completes.
+#[tracing::instrument(skip(request, on_event), fields(request_id = request.id), level = "debug")]
+pub fn open<F>(request: Request, mut on_event: F) -> Result<()>
+where
+    F: FnMut(WsEvent) -> bool + Send + 'static,
+{
+    tracing::info!(url = %request.url, "Opening WebSocket");
+
+    let scheme = url::Url::parse(&request.url)
+        .map(|url| url.scheme().to_string())
+        .unwrap_or_default();
+    if scheme != "ws" && scheme != "wss" {
+        tracing::error!(url = %request.url, "Not a WebSocket URL");
+        return Err(RelayError::Parse {
+            message: "WebSocket URLs must use the ws:// or wss:// scheme".into(),
+            cause: Some(request.url.clone()),
+        });
+    }
+
+    let id = request.id;
+    // NOTE: Reserved up front so a concurrent `open` with the same id fails
+    // instead of replacing this session, and registered before `Open` is
+    // reported so frames can be sent from inside the callback.
+    let (sender, receiver) = mpsc::channel();
+    match SESSIONS.entry(id) {
+        Entry::Occupied(_) => {
+            return Err(RelayError::Network {
+                message: format!("WebSocket session {} is already open", id),
+                cause: None,
+            });
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(sender);
+        }
+    }
+
+    let auto_pong = auto_pong();
+    let (handle, socket, open) = match handshake(&request, auto_pong) {
+        Ok(handshake) => handshake,
+        Err(e) => {
+            SESSIONS.remove(&id);
+            return Err(e);
+        }
+    };
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    ACTIVE_REQUESTS.insert(id, Arc::clone(&cancelled));
+
+    if !on_event(open) {
+        SESSIONS.remove(&id);
+        ACTIVE_REQUESTS.remove(&id);
+        return Ok(());
+    }
+
+    std::thread::spawn(move || {
+        let mut session = Session {
+            handle,
+            socket,
+            multi: Multi::new(),
+            auto_pong,
+            buffer: vec![0; RECV_BUFFER_SIZE],
+            message: Vec::new(),
+            message_flags: 0,
+        };
+        let close = session.run(&receiver, &cancelled, &mut on_event);
+
+        SESSIONS.remove(&id);
+        ACTIVE_REQUESTS.remove(&id);
+        tracing::info!(code = ?close.0, reason = %close.1, "WebSocket closed");
+        on_event(WsEvent::Close {
+            code: close.0,
+            reason: close.1,
+        });
+    });
+
+    Ok(())
+}
+
+/// Performs the upgrade handshake, returning the connection and its `Open`
+/// event.
+fn handshake(
+    request: &Request,
+    auto_pong: bool,
+) -> Result<(Easy, curl_sys::curl_socket_t, WsEvent)> {
+    let mut handle = Easy::new();
+    let mut curl_request = CurlRequest::new(&mut handle, request);
+    curl_request.prepare()?;
+    let proxied = curl_request.proxy().is_some();
+
+    let code = unsafe {
+        let code = curl_sys::curl_easy_setopt(
+            handle.raw(),
+            curl_sys::CURLOPT_CONNECT_ONLY,
+            CONNECT_ONLY_WEBSOCKET,
+        );
+        if code == curl_sys::CURLE_OK && !auto_pong {
+            curl_sys::curl_easy_setopt(handle.raw(), CURLOPT_WS_OPTIONS, CURLWS_NOAUTOPONG)
+        } else {
+            code
+        }
+    };
+    if code != curl_sys::CURLE_OK {
+        let e = curl::Error::new(code);
+        tracing::error!(error = %e, "Failed to enable WebSocket mode");
+        return Err(RelayError::UnsupportedFeature {
+            feature: "websocket".into(),
+            message: format!("WebSocket mode is unavailable: {}", e),
+            relay: "curl".into(),
+        });
+    }
+
+    let headers = Arc::new(Mutex::new(HashMap::new()));
+    let response_headers = Arc::clone(&headers);
+    handle
+        .header_function(move |line| {
+            let line = String::from_utf8_lossy(line);
+            if let Some((name, value)) = line.split_once(':') {
+                if let Ok(mut headers) = response_headers.lock() {
+                    headers.insert(name.trim().to_string(), value.trim().to_string());
+                }
+            }
+            true
+        })
+        .map_err(|e| {
+            tracing::error!(error = %e, "Failed to set header callback");
+            RelayError::Network {
+                message: "Failed to set header callback".into(),
+                cause: Some(e.to_string()),
+            }
+        })?;
+
+    handle.perform().map_err(|e| {
+        tracing::error!(error = %e, "WebSocket handshake failed");
+        if e.is_unsupported_protocol() {
+            return RelayError::UnsupportedFeature {
+                feature: "websocket".into(),
+                message: "libcurl was built without WebSocket support".into(),
+                relay: "curl".into(),
+            };
+        }
+        proxied
+            .then(|| proxy::classify_error(&mut handle, &e))
+            .flatten()
+            .unwrap_or_else(|| RelayError::Network {
+                message: "WebSocket handshake failed".into(),
+                cause: Some(e.to_string()),
+            })
+    })?;
+
+    let status = handle.response_code().unwrap_or_default() as u16;
+    let socket = active_socket(&handle)?;
+    let headers = headers.lock().map(|h| h.clone()).unwrap_or_default();
+    let protocol = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("sec-websocket-protocol"))
+        .map(|(_, value)| value.clone());
+
+    tracing::info!(status, protocol = ?protocol, "WebSocket opened");
+    Ok((
+        handle,
+        socket,
+        WsEvent::Open {
+            status,
+            headers,
+            protocol,
+        },
+    ))
+}
+
+/// Queues a frame on an open session.
+#[tracing::instrument(skip(frame), level = "debug")]
+pub fn send(id: i64, frame: WsFrame) -> Result<()> {
+    let sent = SESSIONS
+        .get(&id)
+        .map(|session| session.send(frame).is_ok())
+        .unwrap_or(false);
+
+    if sent {
+        return Ok(());
+    }
+
+    tracing::warn!("WebSocket session not found");
+    Err(RelayError::Network {
+        message: "WebSocket session not found".into(),
+        cause: None,
+    })
+}
+
+/// Starts the closing handshake, the session ends once the server answers
+/// or after a few seconds.
+pub fn close(id: i64, code: Option<u16>, reason: Option<String>) -> Result<()> {
+    send(
+        id,
+        WsFrame::Close {
+            code,
+            reason: reason.unwrap_or_default(),
+        },
+    )
+}
+
+fn active_socket(handle: &Easy) -> Result<curl_sys::curl_socket_t> {
+    let mut socket: curl_sys::curl_socket_t = curl_sys::CURL_SOCKET_BAD;
+    let code = unsafe {
+        curl_sys::curl_easy_getinfo(
+            handle.raw(),
+            CURLINFO_ACTIVESOCKET,
+            &mut socket as *mut curl_sys::curl_socket_t,
+        )
+    };
+
+    if code != curl_sys::CURLE_OK || socket == curl_sys::CURL_SOCKET_BAD {
+        tracing::error!(code, "Failed to get the WebSocket connection");
+        return Err(RelayError::Network {
+            message: "Failed to get the WebSocket connection".into(),
+            cause: Some(curl::Error::new(code).to_string()),
+        });
+    }
+
+    Ok(socket)
+}
+
+/// Whether the linked libcurl answers pings itself.
+fn auto_pong() -> bool {
+    curl::Version::get().version_num() < NOAUTOPONG_VERSION
+}
+
+struct Session {
+    handle: Easy,
+    socket: curl_sys::curl_socket_t,
+    /// Only used to wait on the socket, it never holds any transfers.
+    multi: Multi,
+    /// Whether libcurl already answers pings, see `NOAUTOPONG_VERSION`.
+    auto_pong: bool,
+    /// Reused by every read, the socket is polled every `POLL_INTERVAL`.
+    buffer: Vec<u8>,
+    message: Vec<u8>,
+    message_flags: c_int,
+}
+
+impl Session {
+    /// Runs until either side closes, returning the close code and reason.
+    fn run<F>(
+        &mut self,
+        outgoing: &Receiver<WsFrame>,
+        cancelled: &AtomicBool,
+        on_event: &mut F,
+    ) -> (Option<u16>, String)
+    where
+        F: FnMut(WsEvent) -> bool,
+    {
+        let mut closing_since = None;
+
+        loop {
+            if cancelled.load(Ordering::SeqCst) {
+                return (Some(ABNORMAL_CLOSURE), "Cancelled".into());
+            }
+
+            if closing_since.is_some_and(|since: Instant| since.elapsed() > CLOSE_TIMEOUT) {
+                return (
+                    Some(ABNORMAL_CLOSURE),
+                    "Server did not answer the close frame".into(),
+                );
+            }
+
+            while let Ok(frame) = outgoing.try_recv() {
+                if matches!(frame, WsFrame::Close { .. }) {
+                    closing_since.get_or_insert_with(Instant::now);
+                }
+                if let Err(e) = self.send(&frame) {
+                    return (Some(ABNORMAL_CLOSURE), e.to_string());
+                }
+            }
+
+            match self.recv() {
+                Ok(Some(WsEvent::Close { code, reason })) => {
+                    if closing_since.is_none() {
+                        // NOTE: Echo the code back to complete the handshake.
+                        let _ = self.send(&WsFrame::Close {
+                            code,
+                            reason: String::new(),
+                        });
+                    }
+                    return (code, reason);
+                }
+                Ok(Some(event)) => {
+                    // NOTE: Older libcurl has already answered the ping.
+                    if let (WsEvent::Ping { data }, false) = (&event, self.auto_pong) {
+                        let pong = WsFrame::Pong { data: data.clone() };
+                        if let Err(e) = self.send(&pong) {
+                            return (Some(ABNORMAL_CLOSURE), e.to_string());
+                        }
+                    }
+                    if !on_event(event) {
+                        return (Some(ABNORMAL_CLOSURE), "Subscriber went away".into());
+                    }
+                }
+                Ok(None) => {
+                    if let Err(e) = self.wait(false) {
+                        return (Some(ABNORMAL_CLOSURE), e.to_string());
+                    }
+                }
+                Err(e) => return (Some(ABNORMAL_CLOSURE), e.to_string()),
+            }
+        }
+    }
+
+    /// Reads one chunk, returning an event once a whole message or control
+    /// frame arrived, `None` when more data is needed.
+    fn recv(&mut self) -> Result<Option<WsEvent>> {
+        let mut received = 0;
+        let mut meta: *const CurlWsFrame = std::ptr::null();
+
+        let code = unsafe {
+            curl_ws_recv(
+                self.handle.raw(),
+                self.buffer.as_mut_ptr().cast(),
+                self.buffer.len(),
+                &mut received,
+                &mut meta,
+            )
+        };
+
+        match code {
+            curl_sys::CURLE_OK => {}
+            curl_sys::CURLE_AGAIN => return Ok(None),
+            curl_sys::CURLE_GOT_NOTHING => {
+                return Ok(Some(WsEvent::Close {
+                    code: Some(ABNORMAL_CLOSURE),
+                    reason: "Connection closed without a close frame".into(),
+                }));
+            }
+            code => {
+                let e = curl::Error::new(code);
+                tracing::error!(error = %e, "Failed to receive WebSocket frame");
+                return Err(RelayError::Network {
+                    message: "Failed to receive WebSocket frame".into(),
+                    cause: Some(e.to_string()),
+                });
+            }
+        }
+
+        // SAFETY: `curl_ws_recv` points `meta` at its own frame state on
+        // success, valid until the next call on this handle.
+        let Some(meta) = (unsafe { meta.as_ref() }) else {
+            return Ok(None);
+        };
+        let data = &self.buffer[..received];
+        let complete = meta.bytesleft == 0;
+
+        if meta.flags & (CURLWS_CLOSE | CURLWS_PING | CURLWS_PONG) != 0 {
+            // NOTE: Control frames are at most 125 bytes and never
+            // fragmented, so they arrive in one read.
+            let event = if meta.flags & CURLWS_CLOSE != 0 {
+                let code = (data.len() >= 2).then(|| u16::from_be_bytes([data[0], data[1]]));
+                let reason =
+                    String::from_utf8_lossy(data.get(2..).unwrap_or_default()).into_owned();
+                WsEvent::Close { code, reason }
+            } else if meta.flags & CURLWS_PING != 0 {
+                WsEvent::Ping {
+                    data: Bytes::copy_from_slice(data),
+                }
+            } else {
+                WsEvent::Pong {
+                    data: Bytes::copy_from_slice(data),
+                }
+            };
+            return Ok(Some(event));
+        }
+
+        if self.message.is_empty() && self.message_flags == 0 {
+            self.message_flags = meta.flags & (CURLWS_TEXT | CURLWS_BINARY);
+        }
+        self.message.extend_from_slice(data);
+
+        if !complete || meta.flags & CURLWS_CONT != 0 {
+            return Ok(None);
+        }
+
+        let message = std::mem::take(&mut self.message);
+        let flags = std::mem::take(&mut self.message_flags);
+
+        Ok(Some(if flags & CURLWS_TEXT != 0 {
+            WsEvent::Text {
+                data: String::from_utf8_lossy(&message).into_owned(),
+            }
+        } else {
+            WsEvent::Binary {
+                data: Bytes::from(message),
+            }
+        }))
+    }
+
+    fn send(&mut self, frame: &WsFrame) -> Result<()> {
+        let (payload, flags) = match frame {
+            WsFrame::Text { data } => (data.as_bytes().to_vec(), CURLWS_TEXT),
+            WsFrame::Binary { data } => (data.to_vec(), CURLWS_BINARY),
+            WsFrame::Ping { data } => (data.to_vec(), CURLWS_PING),
+            WsFrame::Pong { data } => (data.to_vec(), CURLWS_PONG),
+            WsFrame::Close { code, reason } => {
+                let mut payload = Vec::new();
+                if let Some(code) = code {
+                    payload.extend_from_slice(&code.to_be_bytes());
+                    payload.extend_from_slice(reason.as_bytes());
+                }
+                (payload, CURLWS_CLOSE)
+            }
+        };
+
+        let mut offset = 0;
+        loop {
+            let mut sent = 0;
+            let remaining = &payload[offset..];
+            let code = unsafe {
+                curl_ws_send(
+                    self.handle.raw(),
+                    remaining.as_ptr().cast(),
+                    remaining.len(),
+                    &mut sent,
+                    0,
+                    flags as c_uint,
+                )
+            };
+
+            match code {
+                curl_sys::CURLE_OK => offset += sent,
+                curl_sys::CURLE_AGAIN => self.wait(true)?,
+                code => {
+                    let e = curl::Error::new(code);
+                    tracing::error!(error = %e, "Failed to send WebSocket frame");
+                    return Err(RelayError::Network {
+                        message: "Failed to send WebSocket frame".into(),
+                        cause: Some(e.to_string()),
+                    });
+                }
+            }
+
+            if offset >= payload.len() {
+                tracing::trace!(bytes = payload.len(), flags, "Sent WebSocket frame");
+                return Ok(());
+            }
+        }
+    }
+
+    /// Waits for the socket to become readable, or writable, for at most
+    /// `POLL_INTERVAL`.
+    fn wait(&self, write: bool) -> Result<()> {
+        let mut fd = WaitFd::new();
+        fd.set_fd(self.socket);
+        if write {
+            fd.poll_on_write(true);
+        } else {
+            fd.poll_on_read(true);
+        }
+
+        self.multi
+            .wait(&mut [fd], POLL_INTERVAL)
+            .map(|_| ())
+            .map_err(|e| {
+                tracing::error!(error = %e, "Failed to wait on WebSocket connection");
+                RelayError::Network {
+                    message: "Failed to wait on WebSocket connection".into(),
+                    cause: Some(e.to_string()),
+                }
+            })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        os::unix::net::{UnixListener, UnixStream},
+        path::PathBuf,
+        thread::JoinHandle,
+    };
+
+    use super::*;
+
+    type Server = BufReader<UnixStream>;
+
+    fn read_frame(stream: &mut impl Read) -> (u8, Vec<u8>) {
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).unwrap();
+        let len = match header[1] & 0x7F {
+            126 => {
+                let mut len = [0u8; 2];
+                stream.read_exact(&mut len).unwrap();
+                u16::from_be_bytes(len) as usize
+            }
+            len => len as usize,
+        };
+        let mut mask = [0u8; 4];
+        stream.read_exact(&mut mask).unwrap();
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).unwrap();
+        payload
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b ^= mask[i % 4]);
+        (header[0], payload)
+    }
+
+    /// An unmasked server frame, `header` being FIN, RSV and opcode.
+    fn write_frame(stream: &mut impl Write, header: u8, payload: &[u8]) {
+        let mut frame = vec![header];
+        match payload.len() {
+            len @ 0..=125 => frame.push(len as u8),
+            len @ 126..=0xFFFF => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        stream.write_all(&frame).unwrap();
+    }
+
+    /// Accepts one upgrade on a Unix socket named after `name` and hands
+    /// the connection to `script`.
+    fn server<T: Send + 'static>(
+        name: &str,
+        script: impl FnOnce(&mut Server) -> T + Send + 'static,
+    ) -> (PathBuf, JoinHandle<T>) {
+        let path = std::env::temp_dir().join(format!(
+            "relay-test-ws-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            // NOTE: curl doesn't verify `Sec-WebSocket-Accept`.
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: x\r\nSec-WebSocket-Protocol: chat\r\n\r\n")
+                .unwrap();
+            script(&mut reader)
+        });
+
+        (path, handle)
+    }
+
+    /// Opens session `id` through the socket at `path`, checks the `Open`
+    /// event and returns the ones after it.
+    fn connect(id: i64, path: &std::path::Path) -> impl Fn() -> WsEvent {
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "url": "ws://localhost/chat",
+            "method": "GET",
+            "version": "HTTP/1.1",
+            "meta": { "options": { "unixSocket": path.to_str().unwrap() } }
+        }))
+        .unwrap();
+
+        let (events_sender, events) = mpsc::channel();
+        open(request, move |event| events_sender.send(event).is_ok()).unwrap();
+
+        // NOTE: Pings answered by libcurl itself may or may not be reported.
+        let next = move || loop {
+            match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+                WsEvent::Ping { .. } if auto_pong() => continue,
+                event => return event,
+            }
+        };
+        assert!(
+            matches!(next(), WsEvent::Open { status: 101, protocol: Some(p), .. } if p == "chat")
+        );
+        next
+    }
+
+    #[test]
+    fn test_websocket_echo_and_close() {
+        let (path, server) = server("echo", |server| {
+            let (opcode, payload) = read_frame(server);
+            assert_eq!(opcode, 0x82);
+            write_frame(server.get_mut(), 0x82, &payload);
+
+            loop {
+                let (opcode, payload) = read_frame(server);
+                if opcode == 0x88 {
+                    write_frame(server.get_mut(), 0x88, &payload);
+                    return payload;
+                }
+            }
+        });
+        let next = connect(7, &path);
+
+        send(
+            7,
+            WsFrame::Binary {
+                data: Bytes::from_static(&[1, 2, 3]),
+            },
+        )
+        .unwrap();
+        assert!(matches!(next(), WsEvent::Binary { data } if data.as_ref() == [1, 2, 3]));
+
+        close(7, Some(1000), Some("bye".into())).unwrap();
+        assert!(matches!(next(), WsEvent::Close { code: Some(1000), reason } if reason == "bye"));
+
+        assert_eq!(server.join().unwrap(), b"\x03\xe8bye");
+        let _ = std::fs::remove_file(&path);
+        assert!(send(
+            7,
+            WsFrame::Text {
+                data: "late".into()
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_websocket_fragmented_messages() {
+        let large = vec![b'x'; RECV_BUFFER_SIZE * 2 + 1];
+        let expected = String::from_utf8(large.clone()).unwrap();
+
+        let (path, server) = server("fragments", move |server| {
+            // A text message in two fragments, then a binary one in three
+            // with a ping between them, which control frames may do.
+            write_frame(server.get_mut(), 0x01, b"hel");
+            write_frame(server.get_mut(), 0x80, b"lo");
+            write_frame(server.get_mut(), 0x02, &[1]);
+            write_frame(server.get_mut(), 0x89, b"p");
+            write_frame(server.get_mut(), 0x00, &[2]);
+            write_frame(server.get_mut(), 0x80, &[3]);
+            // Larger than one read.
+            write_frame(server.get_mut(), 0x81, &large);
+
+            let pong = read_frame(server);
+            write_frame(server.get_mut(), 0x88, b"\x03\xe8");
+            read_frame(server);
+            pong
+        });
+        let next = connect(3901, &path);
+
+        assert!(matches!(next(), WsEvent::Text { data } if data == "hello"));
+        if !auto_pong() {
+            assert!(matches!(next(), WsEvent::Ping { data } if data.as_ref() == b"p"));
+        }
+        assert!(matches!(next(), WsEvent::Binary { data } if data.as_ref() == [1, 2, 3]));
+        assert!(matches!(next(), WsEvent::Text { data } if data == expected));
+        assert!(matches!(
+            next(),
+            WsEvent::Close {
+                code: Some(1000),
+                ..
+            }
+        ));
+
+        assert_eq!(server.join().unwrap(), (0x8A, b"p".to_vec()));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_websocket_ping_pong() {
+        let (path, server) = server("ping", |server| {
+            let ping = read_frame(server);
+            write_frame(server.get_mut(), 0x8A, &ping.1);
+
+            // NOTE: Older libcurl only flushes its own pong along with the
+            // next frame it hands over.
+            write_frame(server.get_mut(), 0x89, b"from server");
+            write_frame(server.get_mut(), 0x88, b"");
+            let pong = read_frame(server);
+            read_frame(server);
+            (ping, pong)
+        });
+        let next = connect(3902, &path);
+
+        send(
+            3902,
+            WsFrame::Ping {
+                data: Bytes::from_static(b"from client"),
+            },
+        )
+        .unwrap();
+        assert!(matches!(next(), WsEvent::Pong { data } if data.as_ref() == b"from client"));
+        if !auto_pong() {
+            assert!(matches!(next(), WsEvent::Ping { data } if data.as_ref() == b"from server"));
+        }
+        assert!(matches!(next(), WsEvent::Close { code: None, .. }));
+
+        let (ping, pong) = server.join().unwrap();
+        assert_eq!(ping, (0x89, b"from client".to_vec()));
+        assert_eq!(pong, (0x8A, b"from server".to_vec()));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_websocket_duplicate_and_failed_opens() {
+        let (path, server) = server("duplicate", read_frame);
+        let next = connect(3904, &path);
+
+        let request = |path: &std::path::Path| -> Request {
+            serde_json::from_value(serde_json::json!({
+                "id": 3904,
+                "url": "ws://localhost/chat",
+                "method": "GET",
+                "version": "HTTP/1.1",
+                "meta": { "options": { "unixSocket": path.to_str().unwrap() } }
+            }))
+            .unwrap()
+        };
+        assert!(open(request(&path), |_| true).is_err());
+        close(3904, None, None).unwrap();
+        assert_eq!(server.join().unwrap().0, 0x88);
+        assert!(matches!(next(), WsEvent::Close { .. }));
+        let _ = std::fs::remove_file(&path);
+
+        // A failed handshake releases the id again.
+        let missing = path.with_extension("missing");
+        assert!(open(request(&missing), |_| true).is_err());
+        assert!(!SESSIONS.contains_key(&3904));
+    }
+
+    #[test]
+    fn test_websocket_server_close() {
+        let (path, server) = server("close", |server| {
+            write_frame(server.get_mut(), 0x88, b"\x03\xe9going away");
+            read_frame(server)
+        });
+        let next = connect(3903, &path);
+
+        assert!(
+            matches!(next(), WsEvent::Close { code: Some(1001), reason } if reason == "going away")
+        );
+
+        // The code is echoed back to complete the closing handshake.
+        assert_eq!(server.join().unwrap(), (0x88, b"\x03\xe9".to_vec()));
+        let _ = std::fs::remove_file(&path);
+        assert!(close(3903, None, None).is_err());
+    }
+}
@@ -1,4 +1,26 @@
-const COMMANDS: &[&str] = &["execute", "cancel", "subscribe"];
+const COMMANDS: &[&str] = &[
+    "execute",
+    "execute_batch",
+    "load_test",
+    "cancel",
+    "subscribe",
+    "set_host_overrides",
+    "get_host_overrides",
+    "set_host_limits",
+    "get_host_limits",
+    "websocket_open",
+    "websocket_send",
+    "websocket_close",
+    "mqtt_connect",
+    "mqtt_subscribe",
+    "mqtt_unsubscribe",
+    "mqtt_publish",
+    "mqtt_disconnect",
+    "generate_snippet",
+    "export_har",
+    "set_cassette",
+    "get_cassette",
+];
 
 fn main() {
     tauri_plugin::Builder::new(COMMANDS)
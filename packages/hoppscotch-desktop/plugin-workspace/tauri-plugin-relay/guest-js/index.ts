@@ -1,4 +1,4 @@
-import { invoke } from '@tauri-apps/api/core'
+import { invoke, Channel } from '@tauri-apps/api/core'
 
 export type Method =
   | "GET"     // Retrieve resource
@@ -13,6 +13,14 @@ export type Method =
 
 export type Version = "HTTP/1.0" | "HTTP/1.1" | "HTTP/2.0" | "HTTP/3.0"
 
+// "fallback" lets HTTP/2 and HTTP/3 downgrade, "only" forbids it, which for
+// HTTP/2 on cleartext connections means h2c with prior knowledge.
+export type VersionMode = "fallback" | "only"
+
+export type BodyCompression = "gzip" | "deflate" | "br" | "zstd"
+
+export type BodyLimitMode = "truncate" | "error"
+
 export type StatusCode =
     | 100  // Continue
     | 101  // Switching Protocols
@@ -79,8 +87,8 @@ export type StatusCode =
     | 511  // Network Auth Required
 
 export type FormDataValue =
-    | { kind: "text"; value: string }
-    | { kind: "file"; filename: string; contentType: string; data: Uint8Array }
+    | { kind: "text"; value: string; contentType?: string; headers?: Record<string, string> }
+    | { kind: "file"; filename: string; contentType: string; data: Uint8Array; headers?: Record<string, string> }
 
 export type FormData = [string, FormDataValue[]][]
 
@@ -95,22 +103,30 @@ export enum MediaType {
     TEXT_XML = "text/xml",
     APPLICATION_FORM = "application/x-www-form-urlencoded",
     APPLICATION_OCTET = "application/octet-stream",
-    MULTIPART_FORM = "multipart/form-data"
+    MULTIPART_FORM = "multipart/form-data",
+    MULTIPART_MIXED = "multipart/mixed",
+    MULTIPART_RELATED = "multipart/related"
 }
 
 export type ContentType =
-    | { kind: "text"; content: string; mediaType: MediaType.TEXT_PLAIN | MediaType.TEXT_HTML | MediaType.TEXT_CSS | MediaType.TEXT_CSV }
-    | { kind: "json"; content: unknown; mediaType: MediaType.APPLICATION_JSON | MediaType.APPLICATION_LD_JSON }
-    | { kind: "xml"; content: string; mediaType: MediaType.APPLICATION_XML | MediaType.TEXT_XML }
+    | { kind: "text"; content: string; mediaType: MediaType.TEXT_PLAIN | MediaType.TEXT_HTML | MediaType.TEXT_CSS | MediaType.TEXT_CSV | string }
+    | { kind: "json"; content: unknown; mediaType: MediaType.APPLICATION_JSON | MediaType.APPLICATION_LD_JSON | string }
+    | { kind: "xml"; content: string; mediaType: MediaType.APPLICATION_XML | MediaType.TEXT_XML | string }
     | { kind: "form"; content: FormData; mediaType: MediaType.APPLICATION_FORM }
     | { kind: "binary"; content: Uint8Array; mediaType: MediaType.APPLICATION_OCTET | string; filename?: string }
-    | { kind: "multipart"; content: FormData; mediaType: MediaType.MULTIPART_FORM }
-    | { kind: "urlencoded"; content: string; mediaType: MediaType.APPLICATION_FORM }
+    | { kind: "multipart"; content: FormData; mediaType: MediaType.MULTIPART_FORM | MediaType.MULTIPART_MIXED | MediaType.MULTIPART_RELATED; boundary?: string }
+    | { kind: "urlencoded"; content: string; mediaType: MediaType.APPLICATION_FORM | string }
     | { kind: "stream"; content: ReadableStream; mediaType: string }
 
+export type MediaKind = "json" | "xml" | "text" | "image" | "binary"
+
 export interface ResponseBody {
     body: Uint8Array
     mediaType: MediaType | string
+    mime?: string
+    kind: MediaKind
+    charset?: string
+    text?: string
 }
 
 export type AuthType =
@@ -187,6 +203,80 @@ export type CertificateType =
     password: string
   }
 
+export type PacSource =
+  | { kind: "url"; url: string }
+  | { kind: "script"; script: string }
+
+export interface SecurityConfig {
+  certificates?: {
+    client?: CertificateType
+    ca?: Array<Uint8Array>
+  }
+  verifyHost?: boolean
+  verifyPeer?: boolean
+}
+
+export interface ProxyConfig {
+  // `http://`, `https://`, `socks4://`, `socks4a://`, `socks5://` or `socks5h://`
+  url?: string
+  auth?: {
+    username: string
+    password?: string
+    scheme?: "any" | "basic" | "digest" | "ntlm"
+  }
+  noProxy?: string[]
+  fromEnv?: boolean
+  pac?: PacSource
+  remoteDns?: boolean
+  security?: SecurityConfig
+}
+
+export interface ResolveEntry {
+  host: string
+  port: number
+  addresses: string[]
+}
+
+export interface ConnectToEntry {
+  host?: string
+  port?: number
+  connectHost?: string
+  connectPort?: number
+}
+
+export interface HostOverrides {
+  resolve?: ResolveEntry[]
+  connectTo?: ConnectToEntry[]
+}
+
+// Shared by every request to matching hosts, requests over the limit wait their turn.
+// `host` is a pattern like `*.example.com`, only the first matching limit applies.
+export interface HostLimit {
+  host: string
+  // Requests per second, fractions allowed.
+  rate?: number
+  // Token bucket size, defaults to `rate` rounded up.
+  burst?: number
+  maxConcurrent?: number
+}
+
+export type CassetteMode = "record" | "replay"
+
+export interface CassetteMatching {
+  body?: boolean
+  headers?: boolean
+  // Header names left out when `headers` is set, on top of the redacted ones.
+  ignoreHeaders?: string[]
+}
+
+export interface CassetteConfig {
+  mode: CassetteMode
+  path: string
+  matching?: CassetteMatching
+  // Headers written as "[REDACTED]" besides Authorization, Proxy-Authorization, Cookie and Set-Cookie.
+  redactHeaders?: string[]
+}
+
 export interface RequestOptions {
   timeout?: number
   followRedirects?: boolean
@@ -194,6 +284,76 @@ export interface RequestOptions {
   decompress?: boolean
   cookies?: boolean
   keepAlive?: boolean
+  resolve?: ResolveEntry[]
+  connectTo?: ConnectToEntry[]
+  unixSocket?: string
+  abstractUnixSocket?: string
+  versionMode?: VersionMode
+  bodyCompression?: BodyCompression
+  decodeText?: boolean
+  maxBodySize?: number
+  bodyLimitMode?: BodyLimitMode
+  // Relative to the host's save directory (the downloads folder on desktop), or absolute inside it.
+  saveTo?: string
+  retry?: RetryPolicy
+  // Checked against the final response, outcomes land in `meta.assertions`.
+  assertions?: Assertion[]
+}
+
+export type RetryErrorKind =
+  | "network"
+  | "timeout"
+  | "certificate"
+  | "parse"
+  | "proxy"
+  | "proxy_auth"
+  | "body_too_large"
+
+// Unset fields fall back to 3 attempts, 408/429/500/502/503/504, network and
+// timeout errors, 500ms doubling up to 30s with jitter, and honouring Retry-After.
+// Only idempotent methods are retried unless `retryNonIdempotent` is set.
+export interface RetryPolicy {
+  maxAttempts?: number
+  retryOnStatus?: number[]
+  retryOnErrors?: RetryErrorKind[]
+  initialDelay?: number
+  maxDelay?: number
+  multiplier?: number
+  jitter?: boolean
+  respectRetryAfter?: boolean
+  retryNonIdempotent?: boolean
+}
+
+export interface RetryAttempt {
+  status?: number
+  error?: string
+  duration: number
+  delay?: number
+}
+
+// Body checks see the body as returned, only a preview with `saveTo`.
+export type Assertion =
+  | { kind: "status"; equals: number }
+  // Both bounds inclusive.
+  | { kind: "statusRange"; min: number; max: number }
+  // Case-insensitive name, `matches` is a regex the value has to match.
+  | { kind: "header"; name: string; matches?: string }
+  // Milliseconds, exclusive.
+  | { kind: "responseTime"; below: number }
+  | { kind: "bodyContains"; text: string }
+  // Several matches are compared as an array of them.
+  | { kind: "jsonPath"; path: string; equals: unknown }
+  | { kind: "arrayLength"; path: string; equals: number }
+  // Compared with the expression's XPath 1.0 string value.
+  | { kind: "xpath"; path: string; equals: string }
+
+export interface AssertionResult {
+  assertion: Assertion
+  passed: boolean
+  // null when there was nothing to compare, e.g. a missing header.
+  actual: unknown | null
+  // Why the assertion couldn't be checked, it fails then.
+  error?: string
 }
 
 export interface RequestMeta {
@@ -210,22 +370,9 @@ export interface Request {
   content?: ContentType
   auth?: AuthType
 
-  security?: {
-    certificates?: {
-      client?: CertificateType
-      ca?: Array<Uint8Array>
-    }
-    verifyHost?: boolean
-    verifyPeer?: boolean
-  }
+  security?: SecurityConfig
 
-  proxy?: {
-    url: string
-    auth?: {
-      username: string
-      password: string
-    }
-  }
+  proxy?: ProxyConfig
 
   meta?: RequestMeta
 }
@@ -258,6 +405,38 @@ export interface Response {
       body: number
       total: number
     }
+    tlsKeyLog?: string
+    proxy?: string
+    primaryIp?: string
+    primaryPort?: number
+    compression?: {
+      encoding: BodyCompression
+      originalSize: number
+      compressedSize: number
+    }
+    truncated?: boolean
+    savedTo?: {
+      path: string
+      size: number
+    }
+    // Milliseconds from the start of the transfer until each phase ended, 0 for skipped phases.
+    phases?: {
+      namelookup: number
+      connect: number
+      appconnect: number
+      pretransfer: number
+      starttransfer: number
+      total: number
+      redirect: number
+    }
+    // Set when the response was replayed from a cassette.
+    replayed?: boolean
+    // Every attempt in order when the request had a retry policy, the last one being this response.
+    attempts?: RetryAttempt[]
+    // Milliseconds spent waiting for a host limit, not included in `timing`.
+    queued?: number
+    // One per `RequestOptions.assertions` entry, in the same order.
+    assertions?: AssertionResult[]
   }
 }
 
@@ -274,7 +453,13 @@ export type RelayError =
   | { kind: "timeout"; message: string; phase?: "connect" | "tls" | "response" }
   | { kind: "certificate"; message: string; cause?: unknown }
   | { kind: "parse"; message: string; cause?: unknown }
+  | { kind: "proxy"; message: string; cause?: unknown }
+  | { kind: "proxy_auth"; message: string; cause?: unknown }
+  | { kind: "body_too_large"; message: string; limit: number }
   | { kind: "abort"; message: string }
+  | { kind: "cassette_miss"; message: string; method: string; url: string }
+  | { kind: "invalid_batch"; message: string }
+  | { kind: "invalid_load_test"; message: string }
 
 export type RequestResult =
   | { kind: 'success'; response: Response }
@@ -284,6 +469,281 @@ export async function execute(request: Request): Promise<RequestResult> {
   return await invoke<RequestResult>('plugin:relay|execute', { request })
 }
 
+export interface BatchItem {
+  request: Request
+  // Ids of requests that have to succeed before this one starts.
+  dependsOn?: number[]
+}
+
+export interface Batch {
+  // Passed to `cancel` to cancel the whole batch, must not clash with request ids.
+  id: number
+  items: BatchItem[]
+  // Requests in flight at once, defaults to 6.
+  concurrency?: number
+  // Starts no further requests once one fails, defaults to false.
+  stopOnFailure?: boolean
+}
+
+export type SkipReason = "dependencyFailed" | "stopped" | "cancelled"
+
+// One per item, in the same order. Only relay errors count as failures.
+export type BatchResult =
+  | { kind: "success"; id: number; response: Response }
+  | { kind: "error"; id: number; error: RelayError }
+  | { kind: "skipped"; id: number; reason: SkipReason }
+
+export async function executeBatch(batch: Batch): Promise<BatchResult[]> {
+  return await invoke<BatchResult[]>('plugin:relay|execute_batch', { batch })
+}
+
+export interface LoadTest {
+  // Passed to `cancel` to stop the test, must not clash with request ids.
+  id: number
+  // Sent as is, skipping cassettes, host limits and retries.
+  request: Request
+  // At least one of `iterations` and `duration` (ms) is required, the test ends at whichever comes first.
+  iterations?: number
+  duration?: number
+  // Requests in flight at once, defaults to 1.
+  concurrency?: number
+  // Requests started per second, as fast as they complete when unset.
+  rate?: number
+  // Milliseconds between progress reports, defaults to 1000.
+  reportInterval?: number
+}
+
+// Latencies in milliseconds, over requests that got a response. With a
+// `rate` they count from when a request was due, percentiles are within 1%.
+export interface LatencySummary {
+  min: number
+  mean: number
+  p50: number
+  p90: number
+  p99: number
+  max: number
+}
+
+export interface HistogramBucket {
+  // Inclusive upper bound in milliseconds.
+  le: number
+  count: number
+}
+
+export interface LoadTestStats {
+  completed: number
+  failed: number
+  elapsed: number
+  // Completed requests per second.
+  throughput: number
+  latency: LatencySummary | null
+  histogram: HistogramBucket[]
+  // Keyed by status code.
+  statuses: Record<string, number>
+  // Keyed by `RelayError` kind.
+  errors: Record<string, number>
+}
+
+export type LoadTestResult =
+  | { kind: "finished"; stats: LoadTestStats }
+  | { kind: "error"; error: RelayError }
+
+// Resolves with the final statistics, `onProgress` gets interim ones every `reportInterval`.
+export async function loadTest(
+  test: LoadTest,
+  onProgress: (stats: LoadTestStats) => void
+): Promise<LoadTestResult> {
+  const channel = new Channel<LoadTestStats>()
+  channel.onmessage = onProgress
+  return await invoke<LoadTestResult>('plugin:relay|load_test', { test, onProgress: channel })
+}
+
 export async function cancel(requestId: number): Promise<void> {
   return await invoke<void>('plugin:relay|cancel', { requestId })
 }
+
+export type SseEvent = {
+  // "message" unless the server sent an `event:` field
+  event: string
+  data: string
+  id: string | null
+}
+
+export type SseMessage =
+  | { kind: "open"; status: number; headers: Record<string, string> }
+  | ({ kind: "event" } & SseEvent)
+  | { kind: "reconnecting"; delayMs: number; lastEventId: string | null; cause: string | null }
+
+export type SubscribeResult =
+  | { kind: "closed" }
+  | { kind: "error"; error: RelayError }
+
+// Resolves once the stream ends, cancel with `cancel(request.id)`.
+export async function subscribe(
+  request: Request,
+  onMessage: (message: SseMessage) => void
+): Promise<SubscribeResult> {
+  const channel = new Channel<SseMessage>()
+  channel.onmessage = onMessage
+  return await invoke<SubscribeResult>('plugin:relay|subscribe', { request, onMessage: channel })
+}
+
+export type WsFrame =
+  | { kind: "text"; data: string }
+  | { kind: "binary"; data: Uint8Array }
+  | { kind: "ping"; data?: Uint8Array }
+  | { kind: "pong"; data?: Uint8Array }
+  | { kind: "close"; code?: number; reason?: string }
+
+export type WsEvent =
+  | { kind: "open"; status: number; headers: Record<string, string>; protocol: string | null }
+  | { kind: "text"; data: string }
+  | { kind: "binary"; data: Uint8Array }
+  | { kind: "ping"; data: Uint8Array }
+  | { kind: "pong"; data: Uint8Array }
+  // 1006 when the connection dropped without a close frame
+  | { kind: "close"; code: number | null; reason: string }
+
+export type WebSocketOpenResult =
+  | { kind: "open" }
+  | { kind: "error"; error: RelayError }
+
+// Resolves after the handshake, `request.id` identifies the socket afterwards.
+export async function websocketOpen(
+  request: Request,
+  onEvent: (event: WsEvent) => void
+): Promise<WebSocketOpenResult> {
+  const channel = new Channel<WsEvent>()
+  channel.onmessage = onEvent
+  return await invoke<WebSocketOpenResult>('plugin:relay|websocket_open', { request, onEvent: channel })
+}
+
+export async function websocketSend(id: number, frame: WsFrame): Promise<void> {
+  return await invoke<void>('plugin:relay|websocket_send', { request: { id, frame } })
+}
+
+export async function websocketClose(id: number, code?: number, reason?: string): Promise<void> {
+  return await invoke<void>('plugin:relay|websocket_close', { request: { id, code, reason } })
+}
+
+export type MqttQoS = 0 | 1 | 2
+
+export interface MqttMessage {
+  topic: string
+  payload: Uint8Array
+  qos?: MqttQoS
+  retain?: boolean
+}
+
+export interface MqttRequest {
+  id: number
+  // mqtt:// or tcp:// for plain TCP, mqtts:// or ssl:// for TLS
+  url: string
+  version?: "3.1.1" | "5"
+  // defaults to `hoppscotch-<id>`
+  clientId?: string
+  username?: string
+  password?: string
+  // seconds, defaults to 60
+  keepAlive?: number
+  cleanStart?: boolean
+  lastWill?: MqttMessage
+  security?: SecurityConfig
+}
+
+export type MqttEvent =
+  | { kind: "connected"; sessionPresent: boolean }
+  // null where the broker refused the filter
+  | { kind: "subscribed"; granted: (MqttQoS | null)[] }
+  | { kind: "unsubscribed" }
+  | ({ kind: "message" } & Required<MqttMessage>)
+  // reason is null when the session was closed by `mqttDisconnect`
+  | { kind: "disconnected"; reason: string | null }
+
+export type MqttConnectResult =
+  | { kind: "connected" }
+  | { kind: "error"; error: RelayError }
+
+// Resolves once the broker accepted the connection, `request.id` identifies the session afterwards.
+export async function mqttConnect(
+  request: MqttRequest,
+  onEvent: (event: MqttEvent) => void
+): Promise<MqttConnectResult> {
+  const channel = new Channel<MqttEvent>()
+  channel.onmessage = onEvent
+  return await invoke<MqttConnectResult>('plugin:relay|mqtt_connect', { request, onEvent: channel })
+}
+
+export async function mqttSubscribe(id: number, topic: string, qos: MqttQoS = 0): Promise<void> {
+  return await invoke<void>('plugin:relay|mqtt_subscribe', { request: { id, topic, qos } })
+}
+
+export async function mqttUnsubscribe(id: number, topic: string): Promise<void> {
+  return await invoke<void>('plugin:relay|mqtt_unsubscribe', { request: { id, topic } })
+}
+
+export async function mqttPublish(id: number, message: MqttMessage): Promise<void> {
+  return await invoke<void>('plugin:relay|mqtt_publish', { request: { id, message } })
+}
+
+export async function mqttDisconnect(id: number): Promise<void> {
+  return await invoke<void>('plugin:relay|mqtt_disconnect', { id })
+}
+
+export async function setHostOverrides(overrides: HostOverrides): Promise<void> {
+  return await invoke<void>('plugin:relay|set_host_overrides', { overrides })
+}
+
+export async function getHostOverrides(): Promise<HostOverrides> {
+  return await invoke<HostOverrides>('plugin:relay|get_host_overrides')
+}
+
+export async function setHostLimits(limits: HostLimit[]): Promise<void> {
+  return await invoke<void>('plugin:relay|set_host_limits', { limits })
+}
+
+export async function getHostLimits(): Promise<HostLimit[]> {
+  return await invoke<HostLimit[]>('plugin:relay|get_host_limits')
+}
+
+export type SnippetTarget =
+  | "curl"
+  | "pythonRequests"
+  | "pythonHttpx"
+  | "javascriptFetch"
+  | "javascriptAxios"
+  | "goNetHttp"
+  | "rustReqwest"
+  | "javaHttpClient"
+  | "powershellRestMethod"
+
+// Features the target can't express are listed in a comment at the top of the snippet.
+export async function generateSnippet(request: Request, target: SnippetTarget): Promise<string> {
+  return await invoke<string>('plugin:relay|generate_snippet', { request: { request, target } })
+}
+
+export interface HarExchange {
+  request: Request
+  response: Response
+}
+
+// HAR 1.2, see http://www.softwareishard.com/blog/har-12-spec/ for the entry layout.
+export interface Har {
+  log: {
+    version: string
+    creator: { name: string; version: string }
+    entries: Record<string, unknown>[]
+  }
+}
+
+export async function exportHar(exchanges: HarExchange[]): Promise<Har> {
+  return await invoke<Har>('plugin:relay|export_har', { exchanges })
+}
+
+export async function setCassette(cassette: CassetteConfig | null): Promise<void> {
+  return await invoke<void>('plugin:relay|set_cassette', { cassette })
+}
+
+export async function getCassette(): Promise<CassetteConfig | null> {
+  return await invoke<CassetteConfig | null>('plugin:relay|get_cassette')
+}
@@ -1,5 +1,5 @@
 use crate::{models::*, RelayExt, Result};
-use tauri::{command, AppHandle, Runtime};
+use tauri::{command, ipc::Channel, AppHandle, Runtime};
 
 #[command]
 pub(crate) async fn execute<R: Runtime>(
@@ -21,6 +21,29 @@ pub(crate) async fn execute<R: Runtime>(
     response
 }
 
+#[command]
+pub(crate) async fn execute_batch<R: Runtime>(
+    app: AppHandle<R>,
+    batch: ExecuteBatchRequest,
+) -> Result<ExecuteBatchResponse> {
+    tracing::debug!(
+        id = batch.id,
+        count = batch.items.len(),
+        "Received execute_batch command"
+    );
+    app.relay().execute_batch(batch).await
+}
+
+#[command]
+pub(crate) async fn load_test<R: Runtime>(
+    app: AppHandle<R>,
+    test: LoadTestRequest,
+    on_progress: Channel<LoadTestProgress>,
+) -> Result<LoadTestResponse> {
+    tracing::debug!(id = test.id, url = %test.request.url, "Received load_test command");
+    app.relay().load_test(test, on_progress).await
+}
+
 #[command]
 pub(crate) async fn cancel<R: Runtime>(
     app: AppHandle<R>,
@@ -36,3 +59,173 @@ pub(crate) async fn cancel<R: Runtime>(
 
     response
 }
+
+#[command]
+pub(crate) async fn subscribe<R: Runtime>(
+    app: AppHandle<R>,
+    request: SubscribeRequest,
+    on_message: Channel<SubscribeMessage>,
+) -> Result<SubscribeResponse> {
+    tracing::debug!(?request, "Received subscribe command");
+    let response = app.relay().subscribe(request, on_message).await;
+
+    match &response {
+        Ok(_) => tracing::info!("Subscribe command completed successfully"),
+        Err(e) => tracing::error!(?e, "Subscribe command failed"),
+    }
+
+    response
+}
+
+#[command]
+pub(crate) async fn websocket_open<R: Runtime>(
+    app: AppHandle<R>,
+    request: WebSocketOpenRequest,
+    on_event: Channel<WebSocketEvent>,
+) -> Result<WebSocketOpenResponse> {
+    tracing::debug!(?request, "Received websocket_open command");
+    let response = app.relay().websocket_open(request, on_event).await;
+
+    match &response {
+        Ok(_) => tracing::info!("WebSocket open command completed successfully"),
+        Err(e) => tracing::error!(?e, "WebSocket open command failed"),
+    }
+
+    response
+}
+
+#[command]
+pub(crate) async fn websocket_send<R: Runtime>(
+    app: AppHandle<R>,
+    request: WebSocketSendRequest,
+) -> Result<()> {
+    tracing::debug!(id = request.id, "Received websocket_send command");
+    app.relay().websocket_send(request)
+}
+
+#[command]
+pub(crate) async fn websocket_close<R: Runtime>(
+    app: AppHandle<R>,
+    request: WebSocketCloseRequest,
+) -> Result<()> {
+    tracing::debug!(?request, "Received websocket_close command");
+    app.relay().websocket_close(request)
+}
+
+#[command]
+pub(crate) async fn mqtt_connect<R: Runtime>(
+    app: AppHandle<R>,
+    request: MqttConnectRequest,
+    on_event: Channel<MqttEvent>,
+) -> Result<MqttConnectResponse> {
+    tracing::debug!(id = request.id, "Received mqtt_connect command");
+    let response = app.relay().mqtt_connect(request, on_event).await;
+
+    match &response {
+        Ok(_) => tracing::info!("MQTT connect command completed successfully"),
+        Err(e) => tracing::error!(?e, "MQTT connect command failed"),
+    }
+
+    response
+}
+
+#[command]
+pub(crate) async fn mqtt_subscribe<R: Runtime>(
+    app: AppHandle<R>,
+    request: MqttSubscribeRequest,
+) -> Result<()> {
+    tracing::debug!(?request, "Received mqtt_subscribe command");
+    app.relay().mqtt_subscribe(request)
+}
+
+#[command]
+pub(crate) async fn mqtt_unsubscribe<R: Runtime>(
+    app: AppHandle<R>,
+    request: MqttUnsubscribeRequest,
+) -> Result<()> {
+    tracing::debug!(?request, "Received mqtt_unsubscribe command");
+    app.relay().mqtt_unsubscribe(request)
+}
+
+#[command]
+pub(crate) async fn mqtt_publish<R: Runtime>(
+    app: AppHandle<R>,
+    request: MqttPublishRequest,
+) -> Result<()> {
+    tracing::debug!(id = request.id, topic = %request.message.topic, "Received mqtt_publish command");
+    app.relay().mqtt_publish(request)
+}
+
+#[command]
+pub(crate) async fn mqtt_disconnect<R: Runtime>(
+    app: AppHandle<R>,
+    id: MqttDisconnectRequest,
+) -> Result<()> {
+    tracing::debug!(id, "Received mqtt_disconnect command");
+    app.relay().mqtt_disconnect(id)
+}
+
+#[command]
+pub(crate) async fn set_host_overrides<R: Runtime>(
+    app: AppHandle<R>,
+    overrides: SetHostOverridesRequest,
+) -> Result<()> {
+    tracing::debug!(?overrides, "Received set_host_overrides command");
+    app.relay().set_host_overrides(overrides)
+}
+
+#[command]
+pub(crate) async fn get_host_overrides<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<HostOverridesResponse> {
+    tracing::debug!("Received get_host_overrides command");
+    app.relay().host_overrides()
+}
+
+#[command]
+pub(crate) async fn set_host_limits<R: Runtime>(
+    app: AppHandle<R>,
+    limits: SetHostLimitsRequest,
+) -> Result<()> {
+    tracing::debug!(?limits, "Received set_host_limits command");
+    app.relay().set_host_limits(limits)
+}
+
+#[command]
+pub(crate) async fn get_host_limits<R: Runtime>(app: AppHandle<R>) -> Result<HostLimitsResponse> {
+    tracing::debug!("Received get_host_limits command");
+    app.relay().host_limits()
+}
+
+#[command]
+pub(crate) async fn generate_snippet<R: Runtime>(
+    app: AppHandle<R>,
+    request: GenerateSnippetRequest,
+) -> Result<GenerateSnippetResponse> {
+    tracing::debug!(id = request.request.id, target = ?request.target, "Received generate_snippet command");
+    app.relay().generate_snippet(request)
+}
+
+#[command]
+pub(crate) async fn export_har<R: Runtime>(
+    app: AppHandle<R>,
+    exchanges: ExportHarRequest,
+) -> Result<ExportHarResponse> {
+    tracing::debug!(count = exchanges.len(), "Received export_har command");
+    app.relay().export_har(exchanges)
+}
+
+#[command]
+pub(crate) async fn set_cassette<R: Runtime>(
+    app: AppHandle<R>,
+    cassette: SetCassetteRequest,
+) -> Result<()> {
+    tracing::debug!(?cassette, "Received set_cassette command");
+    app.relay().set_cassette(cassette)
+}
+
+#[command]
+pub(crate) async fn get_cassette<R: Runtime>(app: AppHandle<R>) -> Result<CassetteResponse> {
+    tracing::debug!("Received get_cassette command");
+    app.relay().cassette()
+}
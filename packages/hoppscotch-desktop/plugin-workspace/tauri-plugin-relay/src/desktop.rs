@@ -1,12 +1,61 @@
 use crate::{models::*, Result};
 use serde::de::DeserializeOwned;
-use tauri::{plugin::PluginApi, AppHandle, Runtime};
+use tauri::{ipc::Channel, plugin::PluginApi, AppHandle, Manager, Runtime};
+use tauri_plugin_store::StoreExt;
+
+/// Store file for relay settings that outlive restarts, the app has to
+/// register `tauri-plugin-store` before this plugin.
+const RELAY_STORE: &str = "relay.json";
+const HOST_OVERRIDES: &str = "hostOverrides";
+const HOST_LIMITS: &str = "hostLimits";
+/// Cassettes can only be read and written inside this folder of the app's
+/// data folder.
+const CASSETTE_DIR: &str = "cassettes";
 
 pub fn init<R: Runtime, C: DeserializeOwned>(
     app: &AppHandle<R>,
     _api: PluginApi<R, C>,
 ) -> Result<Relay<R>> {
     tracing::debug!("Initializing Relay for desktop platform");
+
+    // NOTE: Requests can only save bodies inside the user's downloads
+    // folder, never pick an arbitrary file to overwrite.
+    match app.path().download_dir() {
+        Ok(dir) => relay::set_save_dir(Some(dir)),
+        Err(e) => tracing::warn!(error = %e, "No downloads folder, saving bodies is disabled"),
+    }
+
+    // NOTE: TLS key logging is only ever turned on from the environment the
+    // app was started in, like browsers do, never by a request.
+    if let Some(path) = std::env::var_os("SSLKEYLOGFILE") {
+        relay::set_key_log_file(Some(path.into()));
+    }
+
+    // NOTE: Host overrides and limits live in the relay itself, so they
+    // only need to be handed over once on startup, same as the agent does.
+    match app.store(RELAY_STORE) {
+        Ok(store) => {
+            if let Some(overrides) = store
+                .get(HOST_OVERRIDES)
+                .and_then(|val| serde_json::from_value::<relay::HostOverrides>(val).ok())
+            {
+                tracing::debug!("Restoring host overrides from store");
+                relay::set_host_overrides(overrides);
+            }
+            if let Some(limits) = store
+                .get(HOST_LIMITS)
+                .and_then(|val| serde_json::from_value::<Vec<relay::HostLimit>>(val).ok())
+            {
+                tracing::debug!("Restoring host limits from store");
+                relay::set_host_limits(limits);
+            }
+        }
+        Err(e) => tracing::warn!(
+            error = %e,
+            "No relay store, host overrides and limits won't persist"
+        ),
+    }
+
     Ok(Relay(app.clone()))
 }
 
@@ -28,6 +77,56 @@ impl<R: Runtime> Relay<R> {
         }
     }
 
+    /// Runs on a blocking thread, the batch is cancelled through `cancel`
+    /// with its id.
+    pub async fn execute_batch(&self, batch: ExecuteBatchRequest) -> Result<ExecuteBatchResponse> {
+        tracing::debug!(id = batch.id, "Executing batch");
+
+        tauri::async_runtime::spawn_blocking(move || relay::batch::execute(batch))
+            .await
+            .map_err(|e| {
+                tracing::error!(error = %e, "Batch thread panicked");
+                relay::error::RelayError::Network {
+                    message: "Batch thread panicked".into(),
+                    cause: Some(e.to_string()),
+                }
+            })?
+            .map_err(Into::into)
+    }
+
+    /// Resolves with the final statistics once the test ends, interim ones
+    /// arrive on `on_progress`. The test is cancelled through `cancel` with
+    /// its id.
+    pub async fn load_test(
+        &self,
+        test: LoadTestRequest,
+        on_progress: Channel<LoadTestProgress>,
+    ) -> Result<LoadTestResponse> {
+        tracing::debug!(id = test.id, "Running load test");
+
+        let result = tauri::async_runtime::spawn_blocking(move || {
+            relay::load_test::run(test, move |stats| on_progress.send(stats.clone()).is_ok())
+        })
+        .await;
+
+        match result {
+            Ok(Ok(stats)) => Ok(LoadTestResponse::Finished { stats }),
+            Ok(Err(error)) => {
+                tracing::error!(?error, "Load test failed");
+                Ok(LoadTestResponse::Error { error })
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Load test thread panicked");
+                Ok(LoadTestResponse::Error {
+                    error: relay::error::RelayError::Network {
+                        message: "Load test thread panicked".into(),
+                        cause: Some(e.to_string()),
+                    },
+                })
+            }
+        }
+    }
+
     pub async fn cancel(&self, request_id: CancelRequest) -> Result<CancelResponse> {
         tracing::debug!(?request_id, "Cancelling request");
 
@@ -39,4 +138,202 @@ impl<R: Runtime> Relay<R> {
         tracing::debug!("Request cancelled successfully");
         Ok(())
     }
+
+    /// Streams events to `on_message` until the subscription is cancelled
+    /// through `cancel` or the frontend drops the channel.
+    pub async fn subscribe(
+        &self,
+        request: SubscribeRequest,
+        on_message: Channel<SubscribeMessage>,
+    ) -> Result<SubscribeResponse> {
+        tracing::debug!(?request, "Subscribing to event stream");
+
+        let result = tauri::async_runtime::spawn_blocking(move || {
+            relay::subscribe(request, |message| on_message.send(message).is_ok())
+        })
+        .await;
+
+        match result {
+            Ok(Ok(())) => {
+                tracing::debug!("Event stream closed");
+                Ok(SubscribeResponse::Closed)
+            }
+            Ok(Err(error)) => {
+                tracing::error!(?error, "Event stream failed");
+                Ok(SubscribeResponse::Error { error })
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Subscription thread panicked");
+                Ok(SubscribeResponse::Error {
+                    error: relay::error::RelayError::Network {
+                        message: "Subscription thread panicked".into(),
+                        cause: Some(e.to_string()),
+                    },
+                })
+            }
+        }
+    }
+
+    /// Resolves once the handshake is done, events keep arriving on
+    /// `on_event` until a `close` event.
+    pub async fn websocket_open(
+        &self,
+        request: WebSocketOpenRequest,
+        on_event: Channel<WebSocketEvent>,
+    ) -> Result<WebSocketOpenResponse> {
+        tracing::debug!(?request, "Opening WebSocket");
+
+        let result = tauri::async_runtime::spawn_blocking(move || {
+            relay::websocket::open(request, move |event| on_event.send(event).is_ok())
+        })
+        .await;
+
+        match result {
+            Ok(Ok(())) => {
+                tracing::debug!("WebSocket opened");
+                Ok(WebSocketOpenResponse::Open)
+            }
+            Ok(Err(error)) => {
+                tracing::error!(?error, "WebSocket handshake failed");
+                Ok(WebSocketOpenResponse::Error { error })
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "WebSocket thread panicked");
+                Ok(WebSocketOpenResponse::Error {
+                    error: relay::error::RelayError::Network {
+                        message: "WebSocket thread panicked".into(),
+                        cause: Some(e.to_string()),
+                    },
+                })
+            }
+        }
+    }
+
+    pub fn websocket_send(&self, request: WebSocketSendRequest) -> Result<()> {
+        relay::websocket::send(request.id, request.frame).map_err(Into::into)
+    }
+
+    pub fn websocket_close(&self, request: WebSocketCloseRequest) -> Result<()> {
+        relay::websocket::close(request.id, request.code, request.reason).map_err(Into::into)
+    }
+
+    /// Resolves once the broker accepted the connection, events keep
+    /// arriving on `on_event` until a `disconnected` event.
+    pub async fn mqtt_connect(
+        &self,
+        request: MqttConnectRequest,
+        on_event: Channel<MqttEvent>,
+    ) -> Result<MqttConnectResponse> {
+        tracing::debug!(id = request.id, url = %request.url, "Connecting to MQTT broker");
+
+        let result = tauri::async_runtime::spawn_blocking(move || {
+            relay::mqtt::connect(request, move |event| on_event.send(event).is_ok())
+        })
+        .await;
+
+        match result {
+            Ok(Ok(())) => {
+                tracing::debug!("MQTT connected");
+                Ok(MqttConnectResponse::Connected)
+            }
+            Ok(Err(error)) => {
+                tracing::error!(?error, "MQTT connection failed");
+                Ok(MqttConnectResponse::Error { error })
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "MQTT thread panicked");
+                Ok(MqttConnectResponse::Error {
+                    error: relay::error::RelayError::Network {
+                        message: "MQTT thread panicked".into(),
+                        cause: Some(e.to_string()),
+                    },
+                })
+            }
+        }
+    }
+
+    pub fn mqtt_subscribe(&self, request: MqttSubscribeRequest) -> Result<()> {
+        relay::mqtt::subscribe(request.id, request.topic, request.qos).map_err(Into::into)
+    }
+
+    pub fn mqtt_unsubscribe(&self, request: MqttUnsubscribeRequest) -> Result<()> {
+        relay::mqtt::unsubscribe(request.id, request.topic).map_err(Into::into)
+    }
+
+    pub fn mqtt_publish(&self, request: MqttPublishRequest) -> Result<()> {
+        relay::mqtt::publish(request.id, request.message).map_err(Into::into)
+    }
+
+    pub fn mqtt_disconnect(&self, id: MqttDisconnectRequest) -> Result<()> {
+        relay::mqtt::disconnect(id).map_err(Into::into)
+    }
+
+    /// Replaces the global host overrides and persists them so they
+    /// survive restarts.
+    pub fn set_host_overrides(&self, overrides: SetHostOverridesRequest) -> Result<()> {
+        tracing::debug!(?overrides, "Setting global host overrides");
+
+        let store = self.0.store(RELAY_STORE)?;
+        store.set(HOST_OVERRIDES, serde_json::to_value(&overrides)?);
+        store.save()?;
+
+        relay::set_host_overrides(overrides);
+        Ok(())
+    }
+
+    pub fn host_overrides(&self) -> Result<HostOverridesResponse> {
+        Ok(relay::host_overrides())
+    }
+
+    /// Replaces the global host limits and persists them so they survive
+    /// restarts.
+    pub fn set_host_limits(&self, limits: SetHostLimitsRequest) -> Result<()> {
+        tracing::debug!(?limits, "Setting global host limits");
+
+        let store = self.0.store(RELAY_STORE)?;
+        store.set(HOST_LIMITS, serde_json::to_value(&limits)?);
+        store.save()?;
+
+        relay::set_host_limits(limits);
+        Ok(())
+    }
+
+    pub fn host_limits(&self) -> Result<HostLimitsResponse> {
+        Ok(relay::host_limits())
+    }
+
+    pub fn generate_snippet(
+        &self,
+        request: GenerateSnippetRequest,
+    ) -> Result<GenerateSnippetResponse> {
+        Ok(relay::snippet::generate(&request.request, request.target))
+    }
+
+    pub fn export_har(&self, exchanges: ExportHarRequest) -> Result<ExportHarResponse> {
+        Ok(relay::har::export(&exchanges))
+    }
+
+    /// Cassette paths are taken from the app's cassette folder, with the
+    /// same rules as saved bodies.
+    pub fn set_cassette(&self, cassette: SetCassetteRequest) -> Result<()> {
+        tracing::debug!(?cassette, "Setting cassette");
+
+        let cassette = match cassette {
+            Some(mut config) => {
+                let dir = self.0.path().app_data_dir()?.join(CASSETTE_DIR);
+                std::fs::create_dir_all(&dir)?;
+                config.path = relay::resolve_within(&dir, &config.path)?
+                    .to_string_lossy()
+                    .into_owned();
+                Some(config)
+            }
+            None => None,
+        };
+
+        relay::set_cassette(cassette).map_err(Into::into)
+    }
+
+    pub fn cassette(&self) -> Result<CassetteResponse> {
+        Ok(relay::cassette())
+    }
 }
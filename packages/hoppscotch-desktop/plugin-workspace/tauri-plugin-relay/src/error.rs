@@ -8,6 +8,12 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Relay(#[from] relay::error::RelayError),
+    #[error(transparent)]
+    Tauri(#[from] tauri::Error),
+    #[error(transparent)]
+    Store(#[from] tauri_plugin_store::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
     #[cfg(mobile)]
     #[error(transparent)]
     PluginInvoke(#[from] tauri::plugin::mobile::PluginInvokeError),
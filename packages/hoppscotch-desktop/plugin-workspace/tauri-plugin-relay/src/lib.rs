@@ -38,7 +38,26 @@ pub fn init<R: Runtime>() -> TauriPlugin<R> {
     Builder::new("relay")
         .invoke_handler(tauri::generate_handler![
             commands::execute,
-            commands::cancel
+            commands::execute_batch,
+            commands::load_test,
+            commands::cancel,
+            commands::subscribe,
+            commands::websocket_open,
+            commands::websocket_send,
+            commands::websocket_close,
+            commands::mqtt_connect,
+            commands::mqtt_subscribe,
+            commands::mqtt_unsubscribe,
+            commands::mqtt_publish,
+            commands::mqtt_disconnect,
+            commands::set_host_overrides,
+            commands::get_host_overrides,
+            commands::set_host_limits,
+            commands::get_host_limits,
+            commands::generate_snippet,
+            commands::export_har,
+            commands::set_cassette,
+            commands::get_cassette
         ])
         .setup(|app, api| {
             tracing::info!("Setting up relay plugin");
@@ -1,4 +1,12 @@
-use relay::{error::RelayError, Request as RelayRequest, Response as RelayResponse};
+use relay::{
+    batch::{Batch, BatchResult},
+    error::RelayError,
+    har::{Exchange as HarExchange, Har},
+    load_test::{LoadTest, LoadTestStats},
+    CassetteConfig, HostLimit, HostOverrides, MqttEvent as RelayMqttEvent, MqttMessage,
+    MqttRequest, Request as RelayRequest, Response as RelayResponse, SnippetTarget, SseMessage,
+    WsEvent, WsFrame,
+};
 use serde::{Deserialize, Serialize};
 
 pub type RunRequest = RelayRequest;
@@ -12,6 +20,127 @@ pub enum ExecuteResponse {
     Error { error: RelayError },
 }
 
+pub type ExecuteBatchRequest = Batch;
+
+pub type ExecuteBatchResponse = Vec<BatchResult>;
+
+pub type LoadTestRequest = LoadTest;
+
+pub type LoadTestProgress = LoadTestStats;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum LoadTestResponse {
+    #[serde(rename = "finished")]
+    Finished { stats: LoadTestStats },
+    #[serde(rename = "error")]
+    Error { error: RelayError },
+}
+
 pub type CancelRequest = i64;
 
 pub type CancelResponse = ();
+
+pub type SetHostOverridesRequest = HostOverrides;
+
+pub type HostOverridesResponse = HostOverrides;
+
+pub type SetHostLimitsRequest = Vec<HostLimit>;
+
+pub type HostLimitsResponse = Vec<HostLimit>;
+
+pub type SubscribeRequest = RelayRequest;
+
+pub type SubscribeMessage = SseMessage;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum SubscribeResponse {
+    #[serde(rename = "closed")]
+    Closed,
+    #[serde(rename = "error")]
+    Error { error: RelayError },
+}
+
+pub type WebSocketOpenRequest = RelayRequest;
+
+pub type WebSocketEvent = WsEvent;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum WebSocketOpenResponse {
+    #[serde(rename = "open")]
+    Open,
+    #[serde(rename = "error")]
+    Error { error: RelayError },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketSendRequest {
+    pub id: i64,
+    pub frame: WsFrame,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketCloseRequest {
+    pub id: i64,
+    pub code: Option<u16>,
+    pub reason: Option<String>,
+}
+
+pub type MqttConnectRequest = MqttRequest;
+
+pub type MqttEvent = RelayMqttEvent;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum MqttConnectResponse {
+    #[serde(rename = "connected")]
+    Connected,
+    #[serde(rename = "error")]
+    Error { error: RelayError },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MqttSubscribeRequest {
+    pub id: i64,
+    pub topic: String,
+    #[serde(default)]
+    pub qos: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MqttUnsubscribeRequest {
+    pub id: i64,
+    pub topic: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MqttPublishRequest {
+    pub id: i64,
+    pub message: MqttMessage,
+}
+
+pub type MqttDisconnectRequest = i64;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateSnippetRequest {
+    pub request: RelayRequest,
+    pub target: SnippetTarget,
+}
+
+pub type GenerateSnippetResponse = String;
+
+pub type ExportHarRequest = Vec<HarExchange>;
+
+pub type ExportHarResponse = Har;
+
+pub type SetCassetteRequest = Option<CassetteConfig>;
+
+pub type CassetteResponse = Option<CassetteConfig>;